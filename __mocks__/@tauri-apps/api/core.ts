@@ -44,6 +44,12 @@ defaultReturns.set('set_book_status', null)
 defaultReturns.set('set_snip_status', null)
 defaultReturns.set('bulk_set_snip_status', null)
 defaultReturns.set('get_snip_status_counts', {})
+defaultReturns.set('get_clip_annotations', null)
+defaultReturns.set('get_last_position', null)
+defaultReturns.set('record_reading_position', null)
+defaultReturns.set('record_jump', null)
+defaultReturns.set('navigate_back', null)
+defaultReturns.set('navigate_forward', null)
 
 /**
  * Set a return value for a specific command name.
@@ -0,0 +1,827 @@
+//! Local MCP server exposing the library to coding agents.
+//!
+//! Speaks JSON-RPC 2.0 over HTTP POST on `127.0.0.1` (MCP's "streamable
+//! HTTP" transport, answering with plain JSON). Only tools on the user's
+//! allowlist are listed or callable; the server is off until enabled.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::commands::{get_note_inner, list_directories_inner, scan_textbooks, set_note_inner, DbState};
+use crate::json_storage::read_json;
+use crate::models::{AgentServerConfig, AgentServerStatus, AgentToolInfo, Directory, Snip};
+use crate::note_store;
+use crate::pdf_commands::{request, PdfState};
+use crate::pdf_engine::PdfRequest;
+use crate::pdf_models::TextMode;
+use crate::snip_commands::{create_snip, list_snips, SNIPS_FILE};
+
+const CONFIG_FILE: &str = "agent-server.json";
+const DEFAULT_PORT: u16 = 7823;
+const PROTOCOL_VERSION: &str = "2025-03-26";
+/// Largest request body accepted.
+const MAX_BODY: usize = 4 * 1024 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Width snips are rendered at when the agent doesn't ask for one.
+const SNIP_WIDTH: i32 = 1200;
+/// Widest page render an agent may ask for.
+const MAX_SNIP_WIDTH: i32 = 4096;
+
+struct ToolSpec {
+    name: &'static str,
+    description: &'static str,
+    writes: bool,
+    schema: fn() -> Value,
+}
+
+const TOOLS: &[ToolSpec] = &[
+    ToolSpec {
+        name: "list_textbooks",
+        description: "List the documents in the library with their slug, title, directory and path.",
+        writes: false,
+        schema: || json!({ "type": "object", "properties": {} }),
+    },
+    ToolSpec {
+        name: "search_document",
+        description: "Search a document's text. Returns matching pages with surrounding text.",
+        writes: false,
+        schema: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Full path of the document" },
+                    "query": { "type": "string" }
+                },
+                "required": ["path", "query"]
+            })
+        },
+    },
+    ToolSpec {
+        name: "extract_page_text",
+        description: "Text of one page (1-based): raw, with formulas as LaTeX (\"math\"), or in reading order without headers and footers (\"reading\").",
+        writes: false,
+        schema: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Full path of the document" },
+                    "page": { "type": "integer", "minimum": 1 },
+                    "mode": { "type": "string", "enum": ["plain", "math", "reading"] }
+                },
+                "required": ["path", "page"]
+            })
+        },
+    },
+    ToolSpec {
+        name: "get_note",
+        description: "The reader's note on a page of a book, if any.",
+        writes: false,
+        schema: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "slug": { "type": "string" },
+                    "page": { "type": "integer" }
+                },
+                "required": ["slug", "page"]
+            })
+        },
+    },
+    ToolSpec {
+        name: "set_note",
+        description: "Write the note on a page of a book, replacing what is there. Empty content deletes it.",
+        writes: true,
+        schema: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "slug": { "type": "string" },
+                    "page": { "type": "integer" },
+                    "content": { "type": "string" },
+                    "format": { "type": "string", "default": "markdown" }
+                },
+                "required": ["slug", "page", "content"]
+            })
+        },
+    },
+    ToolSpec {
+        name: "list_snips",
+        description: "Snips (saved page regions) of a book.",
+        writes: false,
+        schema: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "dir_path": { "type": "string", "description": "Library directory of the book" },
+                    "slug": { "type": "string" }
+                },
+                "required": ["dir_path", "slug"]
+            })
+        },
+    },
+    ToolSpec {
+        name: "create_snip",
+        description: "Save a region of a page as a snip. Coordinates are fractions of the page, from the top left.",
+        writes: true,
+        schema: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "dir_path": { "type": "string" },
+                    "slug": { "type": "string" },
+                    "path": { "type": "string", "description": "Full path of the document" },
+                    "page": { "type": "integer", "minimum": 1 },
+                    "label": { "type": "string" },
+                    "x": { "type": "number" },
+                    "y": { "type": "number" },
+                    "width": { "type": "number" },
+                    "height": { "type": "number" }
+                },
+                "required": ["dir_path", "slug", "path", "page", "label", "x", "y", "width", "height"]
+            })
+        },
+    },
+    ToolSpec {
+        name: "render_snip",
+        description: "Render a snip as a JPEG image.",
+        writes: false,
+        schema: || {
+            json!({
+                "type": "object",
+                "properties": {
+                    "dir_path": { "type": "string" },
+                    "id": { "type": "string" },
+                    "width": { "type": "integer", "minimum": 1, "maximum": MAX_SNIP_WIDTH, "description": "Pixel width of the page render" }
+                },
+                "required": ["dir_path", "id"]
+            })
+        },
+    },
+];
+
+impl Default for AgentServerConfig {
+    /// Off, and read-only when turned on.
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            allowed_tools: TOOLS.iter().filter(|t| !t.writes).map(|t| t.name.to_string()).collect(),
+        }
+    }
+}
+
+/// What a tool call returns to the agent.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ToolOutput {
+    Text(String),
+    Jpeg(Vec<u8>),
+}
+
+/// Carries out tool calls; the app's implementation goes through the
+/// database and render workers.
+pub(crate) trait Library: Send + Sync {
+    fn call(&self, tool: &str, args: Value) -> Result<ToolOutput, String>;
+}
+
+fn rpc_result(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn rpc_error(id: &Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn tool_result(output: Result<ToolOutput, String>) -> Value {
+    match output {
+        Ok(ToolOutput::Text(text)) => json!({ "content": [{ "type": "text", "text": text }] }),
+        Ok(ToolOutput::Jpeg(bytes)) => json!({
+            "content": [{
+                "type": "image",
+                "data": base64::engine::general_purpose::STANDARD.encode(bytes),
+                "mimeType": "image/jpeg"
+            }]
+        }),
+        Err(e) => json!({ "content": [{ "type": "text", "text": e }], "isError": true }),
+    }
+}
+
+/// Answer one JSON-RPC message. Notifications get no reply.
+pub(crate) fn handle_message(msg: &Value, allowed: &[String], library: &dyn Library) -> Option<Value> {
+    let id = msg.get("id")?;
+    let params = msg.get("params").cloned().unwrap_or(Value::Null);
+    let is_allowed = |name: &str| allowed.iter().any(|a| a == name);
+
+    let reply = match msg.get("method").and_then(Value::as_str).unwrap_or_default() {
+        "initialize" => rpc_result(
+            id,
+            json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "axiomatic", "version": env!("CARGO_PKG_VERSION") }
+            }),
+        ),
+        "ping" => rpc_result(id, json!({})),
+        "tools/list" => {
+            let tools: Vec<Value> = TOOLS
+                .iter()
+                .filter(|t| is_allowed(t.name))
+                .map(|t| json!({ "name": t.name, "description": t.description, "inputSchema": (t.schema)() }))
+                .collect();
+            rpc_result(id, json!({ "tools": tools }))
+        }
+        "tools/call" => {
+            let name = params.get("name").and_then(Value::as_str).unwrap_or_default();
+            let args = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+            if !TOOLS.iter().any(|t| t.name == name) {
+                rpc_error(id, -32602, format!("Unknown tool: {}", name))
+            } else if !is_allowed(name) {
+                rpc_result(
+                    id,
+                    tool_result(Err(format!("Tool '{}' is not allowed in Axiomatic's agent settings", name))),
+                )
+            } else {
+                rpc_result(id, tool_result(library.call(name, args)))
+            }
+        }
+        method => rpc_error(id, -32601, format!("Method not found: {}", method)),
+    };
+    Some(reply)
+}
+
+fn args<T: DeserializeOwned>(value: Value) -> Result<T, String> {
+    serde_json::from_value(value).map_err(|e| format!("Invalid arguments: {}", e))
+}
+
+fn to_text<T: serde::Serialize>(value: &T) -> Result<ToolOutput, String> {
+    serde_json::to_string_pretty(value)
+        .map(ToolOutput::Text)
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Deserialize)]
+struct DocArgs {
+    path: String,
+    #[serde(default)]
+    query: String,
+    #[serde(default)]
+    page: u32,
+    #[serde(default)]
+    mode: Option<TextMode>,
+}
+
+#[derive(Deserialize)]
+struct NoteArgs {
+    slug: String,
+    page: i64,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    format: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SnipArgs {
+    dir_path: String,
+    #[serde(default)]
+    slug: String,
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    width: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct NewSnipArgs {
+    dir_path: String,
+    slug: String,
+    path: String,
+    page: i64,
+    label: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+fn outside_library(path: &str) -> String {
+    format!("Not in the library: {}", path)
+}
+
+/// The library directory `dir_path` names, as stored.
+fn library_dir(dirs: &[Directory], dir_path: &str) -> Result<String, String> {
+    let canonical = std::fs::canonicalize(dir_path).map_err(|_| outside_library(dir_path))?;
+    dirs.iter()
+        .find(|d| std::fs::canonicalize(&d.path).is_ok_and(|p| p == canonical))
+        .map(|d| d.path.clone())
+        .ok_or_else(|| outside_library(dir_path))
+}
+
+/// Canonical form of `path` if it lies inside a library directory.
+fn library_file(dirs: &[Directory], path: &str) -> Result<String, String> {
+    let canonical = std::fs::canonicalize(path).map_err(|_| outside_library(path))?;
+    dirs.iter()
+        .filter_map(|d| std::fs::canonicalize(&d.path).ok())
+        .any(|d| canonical.starts_with(d))
+        .then(|| canonical.to_string_lossy().into_owned())
+        .ok_or_else(|| outside_library(path))
+}
+
+/// Check the `dir_path` and `path` arguments of a call against the
+/// library, so agents can't read or write anywhere else on the machine.
+fn confine_args(mut args: Value, dirs: &[Directory]) -> Result<Value, String> {
+    if let Some(obj) = args.as_object_mut() {
+        if let Some(Value::String(dir_path)) = obj.get_mut("dir_path") {
+            *dir_path = library_dir(dirs, dir_path)?;
+        }
+        if let Some(Value::String(path)) = obj.get_mut("path") {
+            *path = library_file(dirs, path)?;
+        }
+    }
+    Ok(args)
+}
+
+/// Crop a rendered page to a snip's region.
+fn crop_snip(page_jpeg: &[u8], snip: &Snip) -> Result<Vec<u8>, String> {
+    let page = image::load_from_memory_with_format(page_jpeg, image::ImageFormat::Jpeg)
+        .map_err(|e| format!("Failed to decode page: {}", e))?;
+    let (w, h) = (page.width() as f64, page.height() as f64);
+    let x = (snip.x.clamp(0.0, 1.0) * w) as u32;
+    let y = (snip.y.clamp(0.0, 1.0) * h) as u32;
+    let width = ((snip.width * w) as u32).clamp(1, page.width().saturating_sub(x).max(1));
+    let height = ((snip.height * h) as u32).clamp(1, page.height().saturating_sub(y).max(1));
+    let mut buf = Vec::new();
+    page.crop_imm(x, y, width, height)
+        .to_rgb8()
+        .write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf, 85))
+        .map_err(|e| format!("Failed to encode snip: {}", e))?;
+    Ok(buf)
+}
+
+struct AppLibrary {
+    app: AppHandle,
+}
+
+impl AppLibrary {
+    fn pdf(&self) -> State<'_, PdfState> {
+        self.app.state::<PdfState>()
+    }
+
+    fn with_db<T>(&self, f: impl FnOnce(&rusqlite::Connection) -> Result<T, String>) -> Result<T, String> {
+        let state = self.app.state::<DbState>();
+        let conn = state.0.lock().map_err(|e| e.to_string())?;
+        f(&conn)
+    }
+
+    fn render_snip(&self, a: SnipArgs, dirs: &[Directory]) -> Result<ToolOutput, String> {
+        let snips: Vec<Snip> = read_json(&a.dir_path, SNIPS_FILE);
+        let snip = snips
+            .into_iter()
+            .find(|s| s.id == a.id)
+            .ok_or_else(|| format!("Snip not found: {}", a.id))?;
+        let path = library_file(dirs, &snip.full_path)?;
+        let pdf = self.pdf();
+        let page = request(&pdf.sender, |tx| PdfRequest::RenderPage {
+            path,
+            page: snip.page as u32,
+            width: a.width.unwrap_or(SNIP_WIDTH).clamp(1, MAX_SNIP_WIDTH),
+            dpr: 1.0,
+            generation: pdf.generation.load(Ordering::Relaxed),
+            tx,
+        })?;
+        crop_snip(&page, &snip).map(ToolOutput::Jpeg)
+    }
+}
+
+impl Library for AppLibrary {
+    fn call(&self, tool: &str, value: Value) -> Result<ToolOutput, String> {
+        let writes = TOOLS.iter().any(|t| t.name == tool && t.writes);
+        let arguments = writes.then(|| value.clone());
+        let dirs = self.with_db(list_directories_inner)?;
+        let value = confine_args(value, &dirs)?;
+        let output = match tool {
+            "list_textbooks" => {
+                let cached = self.with_db(|conn| crate::scan_index::cached_textbooks_inner(conn, &dirs))?;
+                let books: Vec<_> = cached
+                    .into_iter()
+                    .flat_map(|(dir, books)| books.unwrap_or_else(|| scan_textbooks(&[dir])))
+                    .collect();
+                to_text(&books)
+            }
+            "search_document" => {
+                let a: DocArgs = args(value)?;
+                let results = request(&self.pdf().sender, |tx| PdfRequest::SearchDocument {
+                    path: a.path,
+                    query: a.query,
+                    tx,
+                })?;
+                to_text(&results)
+            }
+            "extract_page_text" => {
+                let a: DocArgs = args(value)?;
+                request(&self.pdf().sender, |tx| PdfRequest::ExtractPageText {
+                    path: a.path,
+                    page: a.page,
+                    mode: a.mode.unwrap_or_default(),
+                    region: None,
+                    tx,
+                })
+                .map(ToolOutput::Text)
+            }
+            "get_note" => {
+                let a: NoteArgs = args(value)?;
+                let note = self.with_db(|conn| get_note_inner(&*note_store::open(conn, &a.slug)?, &a.slug, a.page))?;
+                Ok(ToolOutput::Text(note.map(|n| n.content).unwrap_or_default()))
+            }
+            "set_note" => {
+                let a: NoteArgs = args(value)?;
+                let format = a.format.as_deref().unwrap_or("markdown");
+                self.with_db(|conn| set_note_inner(&*note_store::open(conn, &a.slug)?, &a.slug, a.page, &a.content, format))?;
+                Ok(ToolOutput::Text("Saved".into()))
+            }
+            "list_snips" => {
+                let a: SnipArgs = args(value)?;
+                to_text(&list_snips(a.dir_path, a.slug)?)
+            }
+            "create_snip" => {
+                let a: NewSnipArgs = args(value)?;
+                let snip = create_snip(a.dir_path, a.slug, a.path, a.page, a.label, a.x, a.y, a.width, a.height)?;
+                to_text(&snip)
+            }
+            "render_snip" => self.render_snip(args(value)?, &dirs),
+            _ => Err(format!("Unknown tool: {}", tool)),
+        }?;
+        // Let open views pick up what the agent changed
+        if let Some(arguments) = arguments {
+            let _ = self.app.emit("agent-library-changed", json!({ "tool": tool, "arguments": arguments }));
+        }
+        Ok(output)
+    }
+}
+
+struct HttpRequest {
+    method: String,
+    /// Lowercased names with their values.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+fn read_request(stream: &TcpStream) -> Result<HttpRequest, String> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let method = line.split_whitespace().next().unwrap_or_default().to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let length: usize = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, v)| v.parse().ok())
+        .unwrap_or(0);
+    if length > MAX_BODY {
+        return Err("Request too large".into());
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok(HttpRequest { method, headers, body })
+}
+
+fn write_response(mut stream: &TcpStream, status: &str, body: Option<&Value>) {
+    let body = body.map(|b| b.to_string()).unwrap_or_default();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+/// Browsers may reach localhost too; only requests without an `Origin` or
+/// from a local page are served, which keeps web pages from driving it.
+fn origin_allowed(headers: &[(String, String)]) -> bool {
+    headers.iter().filter(|(name, _)| name == "origin").all(|(_, origin)| {
+        let host = origin.split("://").nth(1).unwrap_or_default();
+        let host = host.rsplit_once(':').map_or(host, |(h, _)| h);
+        matches!(host, "localhost" | "127.0.0.1" | "[::1]")
+    })
+}
+
+fn serve_connection(stream: TcpStream, config: &Mutex<AgentServerConfig>, library: &dyn Library) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let HttpRequest { method, headers, body } = match read_request(&stream) {
+        Ok(request) => request,
+        Err(e) => return write_response(&stream, "400 Bad Request", Some(&json!({ "error": e }))),
+    };
+    if !origin_allowed(&headers) {
+        return write_response(&stream, "403 Forbidden", None);
+    }
+    if method != "POST" {
+        return write_response(&stream, "405 Method Not Allowed", None);
+    }
+    let msg: Value = match serde_json::from_slice(&body) {
+        Ok(msg) => msg,
+        Err(e) => {
+            let reply = rpc_error(&Value::Null, -32700, format!("Parse error: {}", e));
+            return write_response(&stream, "200 OK", Some(&reply));
+        }
+    };
+    let allowed = config.lock().map(|c| c.allowed_tools.clone()).unwrap_or_default();
+    match handle_message(&msg, &allowed, library) {
+        Some(reply) => write_response(&stream, "200 OK", Some(&reply)),
+        None => write_response(&stream, "202 Accepted", None),
+    }
+}
+
+/// Accept connections until `stop` is set, one thread per connection.
+fn serve(
+    listener: TcpListener,
+    config: Arc<Mutex<AgentServerConfig>>,
+    library: Arc<dyn Library>,
+    stop: Arc<AtomicBool>,
+) {
+    if let Err(e) = listener.set_nonblocking(true) {
+        log::warn!("agent server: {}", e);
+        return;
+    }
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = stream.set_nonblocking(false);
+                let (config, library) = (Arc::clone(&config), Arc::clone(&library));
+                std::thread::spawn(move || serve_connection(stream, &config, library.as_ref()));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => log::warn!("agent server: {}", e),
+        }
+    }
+}
+
+struct Running {
+    port: u16,
+    stop: Arc<AtomicBool>,
+}
+
+/// The agent server and its settings. Allowlist changes apply to the next
+/// request; enabling, disabling or moving the port restarts the listener.
+pub struct AgentServer {
+    config: Arc<Mutex<AgentServerConfig>>,
+    config_path: PathBuf,
+    library: Arc<dyn Library>,
+    running: Mutex<Option<Running>>,
+}
+
+impl AgentServer {
+    pub fn start(app: &AppHandle, app_data: &Path) -> Self {
+        let config_path = app_data.join(CONFIG_FILE);
+        let config = std::fs::read_to_string(&config_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        let server = Self {
+            config: Arc::new(Mutex::new(config)),
+            config_path,
+            library: Arc::new(AppLibrary { app: app.clone() }),
+            running: Mutex::new(None),
+        };
+        if let Err(e) = server.apply() {
+            log::warn!("agent server: {}", e);
+        }
+        server
+    }
+
+    /// Start or stop the listener to match the config.
+    fn apply(&self) -> Result<(), String> {
+        let config = self.config.lock().map_err(|e| e.to_string())?.clone();
+        let mut running = self.running.lock().map_err(|e| e.to_string())?;
+        if let Some(r) = running.as_ref() {
+            if config.enabled && r.port == config.port {
+                return Ok(());
+            }
+            r.stop.store(true, Ordering::Relaxed);
+            *running = None;
+        }
+        if !config.enabled {
+            return Ok(());
+        }
+        let listener = TcpListener::bind(("127.0.0.1", config.port))
+            .map_err(|e| format!("Cannot listen on port {}: {}", config.port, e))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let (cfg, library, flag) = (Arc::clone(&self.config), Arc::clone(&self.library), Arc::clone(&stop));
+        std::thread::Builder::new()
+            .name("agent-server".into())
+            .spawn(move || serve(listener, cfg, library, flag))
+            .map_err(|e| e.to_string())?;
+        log::info!("agent server listening on 127.0.0.1:{}", config.port);
+        *running = Some(Running { port: config.port, stop });
+        Ok(())
+    }
+
+    fn status(&self) -> Result<AgentServerStatus, String> {
+        let config = self.config.lock().map_err(|e| e.to_string())?.clone();
+        let url = self
+            .running
+            .lock()
+            .map_err(|e| e.to_string())?
+            .as_ref()
+            .map(|r| format!("http://127.0.0.1:{}/mcp", r.port));
+        let tools = TOOLS
+            .iter()
+            .map(|t| AgentToolInfo {
+                name: t.name.into(),
+                description: t.description.into(),
+                writes: t.writes,
+            })
+            .collect();
+        Ok(AgentServerStatus { config, url, tools })
+    }
+}
+
+#[tauri::command]
+pub fn get_agent_server(state: State<'_, AgentServer>) -> Result<AgentServerStatus, String> {
+    state.status()
+}
+
+/// Save the agent server settings and start or stop it accordingly.
+#[tauri::command]
+pub fn set_agent_server_config(
+    config: AgentServerConfig,
+    state: State<'_, AgentServer>,
+) -> Result<AgentServerStatus, String> {
+    if let Some(unknown) = config.allowed_tools.iter().find(|n| !TOOLS.iter().any(|t| t.name == n.as_str())) {
+        return Err(format!("Unknown tool: {}", unknown));
+    }
+    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    std::fs::write(&state.config_path, json).map_err(|e| format!("Failed to save agent settings: {}", e))?;
+    *state.config.lock().map_err(|e| e.to_string())? = config;
+    state.apply()?;
+    state.status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeLibrary;
+
+    impl Library for FakeLibrary {
+        fn call(&self, tool: &str, args: Value) -> Result<ToolOutput, String> {
+            match tool {
+                "extract_page_text" => Ok(ToolOutput::Text(format!("page {}", args["page"]))),
+                "render_snip" => Ok(ToolOutput::Jpeg(vec![0xff, 0xd8])),
+                _ => Err("boom".into()),
+            }
+        }
+    }
+
+    fn rpc(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params })
+    }
+
+    #[test]
+    fn tools_follow_the_allowlist() {
+        let allowed = AgentServerConfig::default().allowed_tools;
+        assert!(allowed.contains(&"render_snip".to_string()));
+        assert!(!allowed.contains(&"set_note".to_string()));
+
+        let init = handle_message(&rpc("initialize", json!({})), &allowed, &FakeLibrary).unwrap();
+        assert_eq!(init["result"]["serverInfo"]["name"], "axiomatic");
+        let note = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert!(handle_message(&note, &allowed, &FakeLibrary).is_none());
+
+        let list = handle_message(&rpc("tools/list", json!({})), &allowed, &FakeLibrary).unwrap();
+        let names: Vec<&str> = list["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names.len(), allowed.len());
+        assert!(!names.contains(&"create_snip"));
+
+        let call = |name: &str, args: Value| {
+            handle_message(&rpc("tools/call", json!({ "name": name, "arguments": args })), &allowed, &FakeLibrary)
+                .unwrap()
+        };
+        let text = call("extract_page_text", json!({ "page": 3 }));
+        assert_eq!(text["result"]["content"][0]["text"], "page 3");
+        let image = call("render_snip", json!({}));
+        assert_eq!(image["result"]["content"][0]["data"], "/9g=");
+        let denied = call("set_note", json!({}));
+        assert_eq!(denied["result"]["isError"], true);
+        assert_eq!(call("rm_rf", json!({}))["error"]["code"], -32602);
+        assert_eq!(
+            handle_message(&rpc("resources/list", json!({})), &allowed, &FakeLibrary).unwrap()["error"]["code"],
+            -32601
+        );
+    }
+
+    #[test]
+    fn serves_json_rpc_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = Arc::new(Mutex::new(AgentServerConfig::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        let handle = std::thread::spawn(move || serve(listener, config, Arc::new(FakeLibrary), flag));
+
+        let post = |body: &str, origin: Option<&str>| {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let origin = origin.map(|o| format!("Origin: {}\r\n", o)).unwrap_or_default();
+            write!(
+                stream,
+                "POST /mcp HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: {}\r\n\r\n{}",
+                origin,
+                body.len(),
+                body
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let body = rpc("tools/call", json!({ "name": "extract_page_text", "arguments": { "page": 7 } })).to_string();
+        let response = post(&body, Some("http://localhost:1420"));
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("page 7"));
+        assert!(post(&body, Some("https://evil.example")).starts_with("HTTP/1.1 403"));
+        assert!(post(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#, None).starts_with("HTTP/1.1 202"));
+
+        stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn calls_are_confined_to_the_library() {
+        let root = tempfile::tempdir().unwrap();
+        let library = root.path().join("books");
+        let elsewhere = root.path().join("private");
+        std::fs::create_dir_all(library.join("sub")).unwrap();
+        std::fs::create_dir_all(&elsewhere).unwrap();
+        std::fs::write(library.join("sub/a.pdf"), b"%PDF").unwrap();
+        std::fs::write(elsewhere.join("b.pdf"), b"%PDF").unwrap();
+        let dirs = vec![Directory {
+            id: 1,
+            path: library.to_string_lossy().into_owned(),
+            label: "books".into(),
+            added_at: String::new(),
+        }];
+
+        let inside = library.join("sub/../sub/a.pdf");
+        let ok = confine_args(json!({ "path": inside, "page": 1 }), &dirs).unwrap();
+        let expected = std::fs::canonicalize(library.join("sub/a.pdf")).unwrap();
+        assert_eq!(ok["path"], &*expected.to_string_lossy());
+        assert_eq!(ok["page"], 1);
+
+        let outside = json!({ "path": elsewhere.join("b.pdf"), "query": "x" });
+        assert!(confine_args(outside, &dirs).unwrap_err().starts_with("Not in the library"));
+        let escape = json!({ "path": library.join("../private/b.pdf") });
+        assert!(confine_args(escape, &dirs).is_err());
+        assert!(confine_args(json!({ "path": library.join("missing.pdf") }), &dirs).is_err());
+
+        let snip_dir = json!({ "dir_path": library.join("sub/.."), "slug": "a" });
+        assert_eq!(confine_args(snip_dir, &dirs).unwrap()["dir_path"], dirs[0].path);
+        // Snips can only go into the library directories themselves
+        assert!(confine_args(json!({ "dir_path": elsewhere, "slug": "a" }), &dirs).is_err());
+        assert!(confine_args(json!({ "dir_path": library.join("sub"), "slug": "a" }), &dirs).is_err());
+    }
+
+    #[test]
+    fn crop_snip_cuts_the_region() {
+        let page = image::RgbImage::from_pixel(200, 100, image::Rgb([255, 255, 255]));
+        let mut jpeg = Vec::new();
+        image::DynamicImage::ImageRgb8(page)
+            .write_with_encoder(image::codecs::jpeg::JpegEncoder::new(&mut jpeg))
+            .unwrap();
+        let snip: Snip = serde_json::from_value(json!({
+            "id": "s", "slug": "b", "full_path": "/b.pdf", "page": 1, "label": "",
+            "x": 0.5, "y": 0.2, "width": 0.9, "height": 0.5, "created_at": ""
+        }))
+        .unwrap();
+        let cropped = image::load_from_memory(&crop_snip(&jpeg, &snip).unwrap()).unwrap();
+        // Clamped to the page edge
+        assert_eq!((cropped.width(), cropped.height()), (100, 50));
+    }
+}
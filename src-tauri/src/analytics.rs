@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone};
+use tauri::State;
+
+use crate::commands::{get_db, list_directories_inner, DbState};
+use crate::history_commands::{import_legacy_history, reading_logs, reading_stretches, Stretch};
+use crate::json_storage::read_json;
+use crate::models::{
+    ChapterTime, Snip, SolidificationPoint, StatsGroup, StatsGroupBy, StatsRange, StudySession, StudyStats,
+};
+use crate::outlines::{book_outline_inner, get_custom_outline};
+use crate::pdf_commands::PdfState;
+use crate::pdf_models::OutlineEntry;
+use crate::session_commands::SESSIONS_FILE;
+use crate::snip_commands::SNIPS_FILE;
+
+/// Title for reading before a book's first outline entry.
+const FRONT_MATTER: &str = "Front matter";
+
+/// Everything the statistics are computed from, gathered across libraries.
+#[derive(Default)]
+pub struct StudyData {
+    pub sessions: Vec<StudySession>,
+    /// Reading stretches by book slug.
+    pub reading: Vec<(String, Stretch)>,
+    pub snips: Vec<Snip>,
+    /// Outline per book slug, for the chapter breakdown.
+    pub outlines: HashMap<String, Vec<OutlineEntry>>,
+}
+
+fn local_day<Tz: TimeZone>(at: &DateTime<Tz>) -> NaiveDate {
+    at.with_timezone(&Local).date_naive()
+}
+
+fn parse_day(timestamp: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(timestamp).ok().map(|t| local_day(&t))
+}
+
+fn period_key(day: NaiveDate, group_by: StatsGroupBy) -> String {
+    match group_by {
+        StatsGroupBy::Day => day.format("%Y-%m-%d").to_string(),
+        // Books are charted over weeks
+        StatsGroupBy::Week | StatsGroupBy::Book => {
+            let week = day.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }
+    }
+}
+
+struct DayRange {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+impl DayRange {
+    fn parse(range: &StatsRange) -> Result<Self, String> {
+        let day = |s: &Option<String>| {
+            s.as_deref()
+                .map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").map_err(|e| format!("Invalid date '{}': {}", d, e)))
+                .transpose()
+        };
+        Ok(Self {
+            from: day(&range.from)?,
+            to: day(&range.to)?,
+        })
+    }
+
+    fn contains(&self, day: NaiveDate) -> bool {
+        self.from.map_or(true, |f| day >= f) && self.to.map_or(true, |t| day <= t)
+    }
+}
+
+/// Longest run of consecutive days, and the run ending today or yesterday.
+fn streaks(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (i64, i64) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut prev: Option<NaiveDate> = None;
+    for &day in days {
+        run = if prev.and_then(|p| p.succ_opt()) == Some(day) { run + 1 } else { 1 };
+        longest = longest.max(run);
+        prev = Some(day);
+    }
+    let mut current = 0;
+    let mut day = if days.contains(&today) { Some(today) } else { today.pred_opt() };
+    while let Some(d) = day.filter(|d| days.contains(d)) {
+        current += 1;
+        day = d.pred_opt();
+    }
+    (current, longest)
+}
+
+/// Top-level outline entry a page belongs to: the last one starting on or
+/// before it.
+fn chapter_of(outline: &[OutlineEntry], page: i64) -> Option<&OutlineEntry> {
+    outline
+        .iter()
+        .filter(|e| e.page.is_some_and(|p| p as i64 <= page))
+        .max_by_key(|e| e.page)
+}
+
+fn group_mut(groups: &mut BTreeMap<String, StatsGroup>, key: String) -> &mut StatsGroup {
+    groups.entry(key.clone()).or_insert_with(|| StatsGroup {
+        key,
+        ..Default::default()
+    })
+}
+
+pub fn compute_stats(data: &StudyData, range: &StatsRange, group_by: StatsGroupBy, today: NaiveDate) -> Result<StudyStats, String> {
+    let range = DayRange::parse(range)?;
+    let mut groups: BTreeMap<String, StatsGroup> = BTreeMap::new();
+    let mut active_days = BTreeSet::new();
+    let mut stats = StudyStats::default();
+
+    for session in &data.sessions {
+        let Some(day) = parse_day(&session.started_at) else { continue };
+        active_days.insert(day);
+        if !range.contains(day) {
+            continue;
+        }
+        let minutes = session.duration_minutes as f64;
+        stats.total_minutes += minutes;
+        stats.total_sessions += 1;
+        if group_by == StatsGroupBy::Book {
+            // A session spent on several books counts for each, time split evenly
+            let share = minutes / session.books.len().max(1) as f64;
+            for book in &session.books {
+                let g = group_mut(&mut groups, book.slug.clone());
+                g.minutes += share;
+                g.sessions += 1;
+            }
+        } else {
+            let g = group_mut(&mut groups, period_key(day, group_by));
+            g.minutes += minutes;
+            g.sessions += 1;
+        }
+    }
+
+    let key_for = |day: NaiveDate, slug: &str| match group_by {
+        StatsGroupBy::Book => slug.to_string(),
+        _ => period_key(day, group_by),
+    };
+    let mut chapters: BTreeMap<(String, Option<u32>, String), ChapterTime> = BTreeMap::new();
+    let (mut reading_pages, mut reading_minutes) = (0.0, 0.0);
+    for (slug, stretch) in &data.reading {
+        let day = local_day(&stretch.at);
+        active_days.insert(day);
+        if !range.contains(day) {
+            continue;
+        }
+        reading_pages += stretch.pages;
+        reading_minutes += stretch.minutes;
+        let g = group_mut(&mut groups, key_for(day, slug));
+        g.reading_minutes += stretch.minutes;
+        g.pages_read += stretch.pages;
+
+        if let Some(outline) = data.outlines.get(slug) {
+            let chapter = chapter_of(outline, stretch.page);
+            let title = chapter.map(|c| c.title.clone()).unwrap_or_else(|| FRONT_MATTER.to_string());
+            let page = chapter.and_then(|c| c.page);
+            let entry = chapters
+                .entry((slug.clone(), page, title.clone()))
+                .or_insert_with(|| ChapterTime {
+                    slug: slug.clone(),
+                    title,
+                    page,
+                    minutes: 0.0,
+                    pages_read: 0.0,
+                });
+            entry.minutes += stretch.minutes;
+            entry.pages_read += stretch.pages;
+        }
+    }
+
+    // Solidification: cumulative over all snips, reported for periods in range
+    let mut events: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+    let mut before = (0i64, 0i64);
+    for snip in &data.snips {
+        let created = parse_day(&snip.created_at);
+        let solid = snip.solid_at.as_deref().and_then(parse_day);
+        for (day, solidified) in [(created, false), (solid, true)] {
+            let Some(day) = day else { continue };
+            let counter = if range.from.is_some_and(|f| day < f) {
+                &mut before
+            } else if range.contains(day) {
+                events.entry(period_key(day, group_by)).or_default()
+            } else {
+                continue;
+            };
+            if solidified {
+                counter.1 += 1;
+            } else {
+                counter.0 += 1;
+            }
+            if range.contains(day) {
+                let g = group_mut(&mut groups, key_for(day, &snip.slug));
+                if solidified {
+                    g.snips_solidified += 1;
+                } else {
+                    g.snips_created += 1;
+                }
+            }
+        }
+    }
+    let (mut created, mut solidified) = before;
+    for (period, (c, s)) in events {
+        created += c;
+        solidified += s;
+        stats.solidification.push(SolidificationPoint {
+            period,
+            created,
+            solidified,
+            rate: (created > 0).then(|| solidified as f64 / created as f64),
+        });
+    }
+
+    let pace = |pages: f64, minutes: f64| (minutes >= 1.0).then(|| pages / (minutes / 60.0));
+    stats.groups = groups
+        .into_values()
+        .map(|mut g| {
+            g.pages_per_hour = pace(g.pages_read, g.reading_minutes);
+            g
+        })
+        .collect();
+    stats.pages_per_hour = pace(reading_pages, reading_minutes);
+    (stats.current_streak, stats.longest_streak) = streaks(&active_days, today);
+    stats.chapters = chapters.into_values().collect();
+    Ok(stats)
+}
+
+/// Study statistics across all libraries: totals and aggregates grouped by
+/// day, week or book, streaks, reading pace, snip solidification over time
+/// and reading time per chapter.
+#[tauri::command]
+pub async fn get_study_stats(
+    range: StatsRange,
+    group_by: StatsGroupBy,
+    db: State<'_, DbState>,
+    pdf: State<'_, PdfState>,
+) -> Result<StudyStats, String> {
+    let (books, logs) = {
+        let conn = get_db(&db)?;
+        let dirs = list_directories_inner(&conn)?;
+        for dir in &dirs {
+            import_legacy_history(&conn, &dir.path)?;
+        }
+        (crate::scan_index::cached_textbooks_inner(&conn, &dirs)?, reading_logs(&conn)?)
+    };
+    let sender = pdf.sender.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut data = StudyData::default();
+        let mut seen_sessions = HashSet::new();
+        let paths: HashMap<String, (String, String)> = books
+            .iter()
+            .flat_map(|(dir, textbooks)| {
+                textbooks
+                    .iter()
+                    .flatten()
+                    .map(|b| (b.slug.clone(), (dir.path.clone(), b.full_path.clone())))
+            })
+            .collect();
+        for (dir, _) in &books {
+            // A session spanning libraries is logged in each of them
+            let sessions: Vec<StudySession> = read_json(&dir.path, SESSIONS_FILE);
+            data.sessions
+                .extend(sessions.into_iter().filter(|s| seen_sessions.insert(s.id.clone())));
+            let snips: Vec<Snip> = read_json(&dir.path, SNIPS_FILE);
+            data.snips.extend(snips);
+        }
+        let dir_of_slug = |slug: &str| {
+            let id = slug.split_once('_')?.0.parse::<i64>().ok()?;
+            books.iter().find(|(dir, _)| dir.id == id).map(|(dir, _)| dir.path.clone())
+        };
+        for (slug, log) in logs {
+            data.reading
+                .extend(reading_stretches(&log).into_iter().map(|s| (slug.clone(), s)));
+            let outline = match paths.get(&slug) {
+                Some((dir_path, full_path)) => {
+                    book_outline_inner(&sender, dir_path.clone(), slug.clone(), full_path.clone())
+                }
+                // A book no longer on disk keeps its custom outline in its directory
+                None => match dir_of_slug(&slug) {
+                    Some(dir_path) => get_custom_outline(dir_path, slug.clone())
+                        .map(|o| o.map(|o| o.entries).unwrap_or_default()),
+                    None => Ok(Vec::new()),
+                },
+            };
+            data.outlines.insert(slug, outline.unwrap_or_default());
+        }
+        compute_stats(&data, &range, group_by, Local::now().date_naive())
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::StudySessionBook;
+
+    fn session(id: &str, started_at: &str, minutes: i64, slugs: &[&str]) -> StudySession {
+        StudySession {
+            id: id.into(),
+            started_at: started_at.into(),
+            ended_at: started_at.into(),
+            duration_minutes: minutes,
+            books: slugs
+                .iter()
+                .map(|s| StudySessionBook { slug: s.to_string(), dir_path: "/lib".into() })
+                .collect(),
+        }
+    }
+
+    fn stretch(at: &str, page: i64, minutes: f64, pages: f64) -> Stretch {
+        Stretch { at: DateTime::parse_from_rfc3339(at).unwrap(), page, minutes, pages }
+    }
+
+    fn snip(slug: &str, created_at: &str, solid_at: Option<&str>) -> Snip {
+        Snip {
+            id: created_at.into(),
+            slug: slug.into(),
+            full_path: "/lib/a.pdf".into(),
+            page: 1,
+            label: "s".into(),
+            x: 0.0,
+            y: 0.0,
+            width: 0.1,
+            height: 0.1,
+            created_at: created_at.into(),
+            tags: vec![],
+            status: if solid_at.is_some() { "solid" } else { "open" }.into(),
+            solid_at: solid_at.map(String::from),
+        }
+    }
+
+    fn chapter(title: &str, page: u32) -> OutlineEntry {
+        OutlineEntry { title: title.into(), page: Some(page), view: None, children: vec![] }
+    }
+
+    fn data() -> StudyData {
+        StudyData {
+            sessions: vec![
+                session("a", "2025-03-03T12:00:00Z", 30, &["algebra"]),
+                session("b", "2025-03-04T12:00:00Z", 60, &["algebra", "topology"]),
+                session("c", "2025-03-05T12:00:00Z", 20, &["topology"]),
+                session("d", "2025-03-12T12:00:00Z", 40, &["algebra"]),
+            ],
+            reading: vec![
+                ("algebra".into(), stretch("2025-03-03T12:00:00Z", 1, 20.0, 4.0)),
+                ("algebra".into(), stretch("2025-03-04T12:00:00Z", 12, 40.0, 6.0)),
+                ("algebra".into(), stretch("2025-03-12T12:00:00Z", 30, 30.0, 5.0)),
+            ],
+            snips: vec![
+                snip("algebra", "2025-02-20T12:00:00Z", Some("2025-03-04T12:00:00Z")),
+                snip("algebra", "2025-03-03T12:00:00Z", None),
+                snip("algebra", "2025-03-12T12:00:00Z", Some("2025-03-12T13:00:00Z")),
+            ],
+            outlines: HashMap::from([(
+                "algebra".to_string(),
+                vec![chapter("Groups", 5), chapter("Rings", 25)],
+            )]),
+        }
+    }
+
+    fn range(from: &str, to: &str) -> StatsRange {
+        StatsRange { from: Some(from.into()), to: Some(to.into()) }
+    }
+
+    #[test]
+    fn groups_by_week_day_and_book() {
+        let today = NaiveDate::from_ymd_opt(2025, 3, 13).unwrap();
+        let weeks = compute_stats(&data(), &StatsRange::default(), StatsGroupBy::Week, today).unwrap();
+        let keys: Vec<&str> = weeks.groups.iter().map(|g| g.key.as_str()).collect();
+        assert_eq!(keys, vec!["2025-W08", "2025-W10", "2025-W11"]);
+        assert_eq!(weeks.groups[1].minutes, 110.0);
+        assert_eq!(weeks.groups[1].sessions, 3);
+        assert_eq!(weeks.groups[1].pages_per_hour, Some(10.0));
+        assert_eq!(weeks.total_minutes, 150.0);
+        assert_eq!(weeks.pages_per_hour, Some(10.0));
+
+        let days = compute_stats(&data(), &range("2025-03-04", "2025-03-05"), StatsGroupBy::Day, today).unwrap();
+        assert_eq!(days.groups.len(), 2);
+        assert_eq!(days.total_sessions, 2);
+        assert_eq!(days.groups[0].snips_solidified, 1);
+
+        let books = compute_stats(&data(), &StatsRange::default(), StatsGroupBy::Book, today).unwrap();
+        let topology = books.groups.iter().find(|g| g.key == "topology").unwrap();
+        assert_eq!((topology.minutes, topology.sessions), (50.0, 2));
+        let algebra = books.groups.iter().find(|g| g.key == "algebra").unwrap();
+        assert_eq!(algebra.snips_created, 3);
+    }
+
+    #[test]
+    fn streaks_and_solidification() {
+        let stats = compute_stats(
+            &data(),
+            &range("2025-03-01", "2025-03-31"),
+            StatsGroupBy::Week,
+            NaiveDate::from_ymd_opt(2025, 3, 13).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(stats.longest_streak, 3);
+        // Studied yesterday, not yet today
+        assert_eq!(stats.current_streak, 1);
+
+        // The February snip counts towards the running totals
+        let points: Vec<(String, i64, i64)> = stats
+            .solidification
+            .iter()
+            .map(|p| (p.period.clone(), p.created, p.solidified))
+            .collect();
+        assert_eq!(points, vec![("2025-W10".into(), 2, 1), ("2025-W11".into(), 3, 2)]);
+        assert_eq!(stats.solidification[1].rate, Some(2.0 / 3.0));
+
+        let (current, longest) = streaks(&BTreeSet::new(), NaiveDate::from_ymd_opt(2025, 3, 13).unwrap());
+        assert_eq!((current, longest), (0, 0));
+    }
+
+    #[test]
+    fn time_per_chapter_from_outline() {
+        let today = NaiveDate::from_ymd_opt(2025, 3, 13).unwrap();
+        let stats = compute_stats(&data(), &StatsRange::default(), StatsGroupBy::Book, today).unwrap();
+        let chapters: Vec<(&str, f64, f64)> = stats
+            .chapters
+            .iter()
+            .map(|c| (c.title.as_str(), c.minutes, c.pages_read))
+            .collect();
+        assert_eq!(chapters, vec![(FRONT_MATTER, 20.0, 4.0), ("Groups", 40.0, 6.0), ("Rings", 30.0, 5.0)]);
+
+        assert!(compute_stats(&data(), &range("March", "2025-03-31"), StatsGroupBy::Day, today).is_err());
+    }
+}
@@ -0,0 +1,806 @@
+//! Full backup and restore: one zip archive with the database, every library
+//! directory's `.axiomatic/` folder and a manifest.
+//!
+//! Archive layout:
+//! - `manifest.json`: [`BackupManifest`]
+//! - `axiomatic.db`: a consistent copy of the database, note images included
+//! - `dirs/{id}/...`: the `.axiomatic/` files of the directory with that id,
+//!   its notes store as a consistent copy like the database
+//!
+//! Slugs embed the directory id (`{id}_{stem}`). When a directory is restored
+//! under another id, its slugs are rewritten in the database rows and JSON
+//! files being restored.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use rusqlite::Connection;
+use serde_json::Value;
+use tauri::State;
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::commands::{add_directory_inner, get_db, list_directories_inner, DbState, SLUG_KEYED_FILES};
+use crate::library_watcher::LibraryWatcher;
+use crate::models::{BackupDirectory, BackupManifest, BackupMode, BackupReport, Directory};
+use crate::note_revisions;
+use crate::note_store::{self, STORE_FILE};
+use crate::snip_commands::now_iso8601;
+
+const FORMAT: &str = "axiomatic-backup";
+const BACKUP_VERSION: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+const DATABASE: &str = "axiomatic.db";
+const DIRS: &str = "dirs";
+/// JSON fields holding a book slug or a library directory path.
+const SLUG_FIELDS: &[&str] = &["slug", "sourceSlug", "bookSlug"];
+const PATH_FIELDS: &[&str] = &["dirPath", "sourceDirPath"];
+
+fn zip_err(e: impl std::fmt::Display) -> String {
+    format!("Backup archive error: {}", e)
+}
+
+fn count(conn: &Connection, table: &str) -> Result<i64, String> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
+/// A scratch file next to the system temp dir, removed on drop.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("axiomatic-{}-{}", uuid::Uuid::new_v4(), name)))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+pub(crate) fn export_backup_inner(conn: &Connection, output: &Path) -> Result<BackupManifest, String> {
+    let dirs = list_directories_inner(conn)?;
+    let snapshot = TempFile::new(DATABASE);
+    conn.execute("VACUUM INTO ?1", [snapshot.0.to_string_lossy()])
+        .map_err(|e| format!("Failed to snapshot database: {}", e))?;
+
+    let file = File::create(output).map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, path: &Path| -> Result<(), String> {
+        zip.start_file(name, options).map_err(zip_err)?;
+        let mut src = File::open(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        std::io::copy(&mut src, &mut zip).map_err(zip_err)?;
+        Ok(())
+    };
+
+    add(DATABASE, &snapshot.0)?;
+    let mut notes = count(conn, "notes")?;
+    let mut note_images = count(conn, "note_images")?;
+    let mut revisions = count(conn, "note_revisions")?;
+    let mut files = 0;
+    for dir in &dirs {
+        if let Some(store) = note_store::dir_store(dir)? {
+            let snapshot = TempFile::new(STORE_FILE);
+            store
+                .execute("VACUUM INTO ?1", [snapshot.0.to_string_lossy()])
+                .map_err(|e| format!("Failed to snapshot the notes of {}: {}", dir.path, e))?;
+            add(&format!("{}/{}/{}", DIRS, dir.id, STORE_FILE), &snapshot.0)?;
+            notes += count(&store, "notes")?;
+            note_images += count(&store, "note_images")?;
+            revisions += count(&store, "note_revisions")?;
+        }
+        let root = Path::new(&dir.path).join(".axiomatic");
+        for entry in WalkDir::new(&root).into_iter().flatten().filter(|e| e.file_type().is_file()) {
+            let Ok(rel) = entry.path().strip_prefix(&root) else {
+                continue;
+            };
+            if is_store_file(rel) {
+                continue;
+            }
+            let rel: Vec<_> = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+            add(&format!("{}/{}/{}", DIRS, dir.id, rel.join("/")), entry.path())?;
+            files += 1;
+        }
+    }
+
+    let manifest = BackupManifest {
+        format: FORMAT.into(),
+        version: BACKUP_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").into(),
+        created_at: now_iso8601(),
+        schema_version: crate::db::get_current_version(conn).map_err(|e| e.to_string())?,
+        directories: dirs
+            .into_iter()
+            .map(|d| BackupDirectory {
+                id: d.id,
+                path: d.path,
+                label: d.label,
+            })
+            .collect(),
+        notes,
+        note_images,
+        note_revisions: revisions,
+        files,
+    };
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    zip.start_file(MANIFEST, options).map_err(zip_err)?;
+    std::io::Write::write_all(&mut zip, &json).map_err(zip_err)?;
+    zip.finish().map_err(zip_err)?;
+    Ok(manifest)
+}
+
+/// The notes store of a directory, or its journal, relative to
+/// `.axiomatic/`.
+fn is_store_file(rel: &Path) -> bool {
+    rel.to_str()
+        .and_then(|name| name.strip_prefix(STORE_FILE))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+}
+
+/// Rewrites slugs of directories restored under a different id, and
+/// remembers which slugs it changed.
+struct SlugMap {
+    ids: HashMap<i64, i64>,
+    renamed: RefCell<HashSet<String>>,
+}
+
+impl SlugMap {
+    fn apply(&self, slug: String) -> String {
+        let renamed = slug.split_once('_').and_then(|(prefix, rest)| {
+            let new_id = self.ids.get(&prefix.parse::<i64>().ok()?)?;
+            (new_id.to_string() != prefix).then(|| format!("{}_{}", new_id, rest))
+        });
+        match renamed {
+            Some(new) => {
+                self.renamed.borrow_mut().insert(slug);
+                new
+            }
+            None => slug,
+        }
+    }
+}
+
+/// Rewrite slugs and directory paths inside a restored JSON file.
+fn remap_json(value: &mut Value, slugs: &SlugMap, paths: &HashMap<String, String>, slug_keyed: bool) {
+    match value {
+        Value::Object(map) => {
+            if slug_keyed {
+                *map = std::mem::take(map).into_iter().map(|(k, v)| (slugs.apply(k), v)).collect();
+            }
+            for (key, v) in map.iter_mut() {
+                match v {
+                    Value::String(s) if SLUG_FIELDS.contains(&key.as_str()) => *s = slugs.apply(std::mem::take(s)),
+                    Value::String(s) if PATH_FIELDS.contains(&key.as_str()) => {
+                        if let Some(path) = paths.get(s.as_str()) {
+                            *s = path.clone();
+                        }
+                    }
+                    _ => remap_json(v, slugs, paths, false),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                remap_json(item, slugs, paths, false);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Add what the backup has and the local file lacks: missing keys of an
+/// object, and array items whose `id` (or value) isn't present yet.
+fn merge_json(local: &mut Value, backup: Value) {
+    match (local, backup) {
+        (Value::Object(local), Value::Object(backup)) => {
+            for (k, v) in backup {
+                local.entry(k).or_insert(v);
+            }
+        }
+        (Value::Array(local), Value::Array(backup)) => {
+            for item in backup {
+                let present = match item.get("id") {
+                    Some(id) => local.iter().any(|l| l.get("id") == Some(id)),
+                    None => local.contains(&item),
+                };
+                if !present {
+                    local.push(item);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn rows(backup: &Connection, sql: &str) -> Result<Vec<Vec<rusqlite::types::Value>>, String> {
+    let mut stmt = backup.prepare(sql).map_err(|e| e.to_string())?;
+    let n = stmt.column_count();
+    let rows = stmt
+        .query_map([], |row| (0..n).map(|i| row.get(i)).collect())
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string());
+    rows
+}
+
+/// Restore the app database's own tables, then its notes.
+fn restore_tables(
+    conn: &Connection,
+    backup: &Connection,
+    mode: BackupMode,
+    slugs: &SlugMap,
+    report: &mut BackupReport,
+) -> Result<(), String> {
+    let slug_of = |v: &rusqlite::types::Value| match v {
+        rusqlite::types::Value::Text(s) => rusqlite::types::Value::Text(slugs.apply(s.clone())),
+        other => other.clone(),
+    };
+    let exec = |sql: &str, params: &[rusqlite::types::Value]| -> Result<usize, String> {
+        conn.execute(sql, rusqlite::params_from_iter(params)).map_err(|e| e.to_string())
+    };
+
+    if mode == BackupMode::Replace {
+        conn.execute_batch(
+            "DELETE FROM book_tags; DELETE FROM tags; DELETE FROM reading_log; DELETE FROM reading_stack;",
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for row in rows(backup, "SELECT name, color FROM tags")? {
+        exec("INSERT OR IGNORE INTO tags (name, color) VALUES (?1, ?2)", &row)?;
+    }
+    for mut row in rows(backup, "SELECT bt.book_slug, t.name FROM book_tags bt JOIN tags t ON t.id = bt.tag_id")? {
+        row[0] = slug_of(&row[0]);
+        exec(
+            "INSERT OR IGNORE INTO book_tags (book_slug, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+            &row,
+        )?;
+    }
+    // Reading logs merge entry by entry; back/forward stacks are not restored
+    for mut row in rows(backup, "SELECT slug, page, scroll_offset, zoom, timestamp, jump FROM reading_log ORDER BY id")? {
+        row[0] = slug_of(&row[0]);
+        exec(
+            "INSERT INTO reading_log (slug, page, scroll_offset, zoom, timestamp, jump)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6
+             WHERE NOT EXISTS (SELECT 1 FROM reading_log WHERE slug = ?1 AND timestamp = ?5 AND page = ?2)",
+            &row,
+        )?;
+    }
+    restore_notes(conn, backup, mode, slugs, report)
+}
+
+/// Restore notes, note images and highlights from `backup` (the app
+/// database or a directory store) into `conn` (either too).
+fn restore_notes(
+    conn: &Connection,
+    backup: &Connection,
+    mode: BackupMode,
+    slugs: &SlugMap,
+    report: &mut BackupReport,
+) -> Result<(), String> {
+    let slug_of = |v: &rusqlite::types::Value| match v {
+        rusqlite::types::Value::Text(s) => rusqlite::types::Value::Text(slugs.apply(s.clone())),
+        other => other.clone(),
+    };
+    let exec = |sql: &str, params: &[rusqlite::types::Value]| -> Result<usize, String> {
+        conn.execute(sql, rusqlite::params_from_iter(params)).map_err(|e| e.to_string())
+    };
+
+    if mode == BackupMode::Replace {
+        conn.execute_batch(
+            "DELETE FROM highlights; DELETE FROM note_images; DELETE FROM note_image_blobs; DELETE FROM notes;
+             DELETE FROM note_revisions;",
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    // Images keep their id where it is free here; notes linking to the
+    // others are relinked below
+    let mut stmt = backup
+        .prepare(
+            "SELECT i.id, i.note_slug, i.note_page, i.filename, i.created_at, b.data
+             FROM note_images i JOIN note_image_blobs b ON b.hash = i.hash",
+        )
+        .map_err(|e| e.to_string())?;
+    let images = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, Vec<u8>>(5)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let mut image_ids = HashMap::new();
+    for (id, slug, page, filename, created_at, data) in images {
+        let slug = slugs.apply(slug);
+        let hash = crate::note_images::put_blob(conn, &data).map_err(|e| e.to_string())?;
+        let taken: bool = conn
+            .query_row("SELECT EXISTS(SELECT 1 FROM note_images WHERE id = ?1)", [id], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        report.note_images += conn
+            .execute(
+                "INSERT OR IGNORE INTO note_images (id, note_slug, note_page, filename, hash, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![if taken { None } else { Some(id) }, slug, page, filename, hash, created_at],
+            )
+            .map_err(|e| e.to_string())?;
+        let new_id: i64 = conn
+            .query_row(
+                "SELECT id FROM note_images WHERE note_slug = ?1 AND note_page = ?2 AND filename = ?3",
+                rusqlite::params![slug, page, filename],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if new_id != id {
+            image_ids.insert(id, new_id);
+        }
+    }
+    // Contents of images that were already here
+    conn.execute("DELETE FROM note_image_blobs WHERE hash NOT IN (SELECT hash FROM note_images)", [])
+        .map_err(|e| e.to_string())?;
+
+    let relink = |content: &mut String| {
+        let rename = |slug: &str| Some(slugs.apply(slug.to_string())).filter(|new| new != slug);
+        if let Some(linked) = crate::note_links::rewrite_page_links(content, rename) {
+            *content = linked;
+        }
+        *content = crate::note_store::relink_images(content, &image_ids);
+    };
+
+    for mut row in rows(backup, "SELECT slug, page, anchor, content, format, updated_at FROM notes")? {
+        row[0] = slug_of(&row[0]);
+        if let rusqlite::types::Value::Text(content) = &mut row[3] {
+            relink(content);
+        }
+        // On conflict the newer note wins
+        report.notes += exec(
+            "INSERT INTO notes (slug, page, anchor, content, format, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(slug, page, anchor) DO UPDATE SET content = excluded.content, format = excluded.format,
+                 updated_at = excluded.updated_at
+             WHERE excluded.updated_at > notes.updated_at",
+            &row,
+        )?;
+    }
+    for mut row in rows(
+        backup,
+        "SELECT slug, page, x, y, width, height, color, note, text, group_id, created_at FROM highlights",
+    )? {
+        row[0] = slug_of(&row[0]);
+        report.highlights += exec(
+            "INSERT INTO highlights (slug, page, x, y, width, height, color, note, text, group_id, created_at)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11
+             WHERE NOT EXISTS (SELECT 1 FROM highlights WHERE slug = ?1 AND page = ?2 AND x = ?3
+                 AND y = ?4 AND width = ?5 AND height = ?6 AND group_id = ?10)",
+            &row,
+        )?;
+    }
+    for mut history in note_revisions::note_histories(backup)? {
+        history.slug = slugs.apply(history.slug);
+        for revision in &mut history.revisions {
+            relink(&mut revision.content);
+        }
+        report.note_revisions += note_revisions::merge_history(conn, &history)?;
+    }
+    crate::note_links::reindex(conn).map_err(|e| e.to_string())
+}
+
+/// `dirs/{id}/{rest}` of an archive entry.
+fn dir_entry(name: &Path) -> Option<(i64, PathBuf)> {
+    let mut components = name.components();
+    if components.next() != Some(Component::Normal(DIRS.as_ref())) {
+        return None;
+    }
+    let id = components.next()?.as_os_str().to_str()?.parse().ok()?;
+    let rest = components.as_path().to_path_buf();
+    (!rest.as_os_str().is_empty()).then_some((id, rest))
+}
+
+/// Restore a backup. Directories are matched by path, after `dir_map`
+/// (backed-up path to path on this machine); directories that don't exist
+/// here are skipped. Returns the report and the directories added.
+pub(crate) fn import_backup_inner(
+    conn: &Connection,
+    archive_path: &Path,
+    mode: BackupMode,
+    dir_map: &HashMap<String, String>,
+) -> Result<(BackupReport, Vec<Directory>), String> {
+    let file = File::open(archive_path).map_err(|e| format!("Failed to open {}: {}", archive_path.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(zip_err)?;
+    let manifest: BackupManifest = {
+        let entry = archive.by_name(MANIFEST).map_err(|_| "Not an Axiomatic backup: no manifest".to_string())?;
+        serde_json::from_reader(entry).map_err(|e| format!("Invalid backup manifest: {}", e))?
+    };
+    if manifest.format != FORMAT {
+        return Err("Not an Axiomatic backup".into());
+    }
+    let schema_version = crate::db::get_current_version(conn).map_err(|e| e.to_string())?;
+    if manifest.version > BACKUP_VERSION || manifest.schema_version > schema_version {
+        return Err(format!(
+            "This backup was made by a newer version of Axiomatic ({})",
+            manifest.app_version
+        ));
+    }
+
+    let mut report = BackupReport::default();
+    let local_dirs = list_directories_inner(conn)?;
+    let mut added = Vec::new();
+    let mut targets: HashMap<i64, Directory> = HashMap::new();
+    let mut paths = HashMap::new();
+    for d in &manifest.directories {
+        let path = dir_map.get(&d.path).unwrap_or(&d.path);
+        let local = match local_dirs.iter().find(|l| &l.path == path) {
+            Some(local) => local.clone(),
+            None if Path::new(path).is_dir() => {
+                let dir = add_directory_inner(conn, path, &d.label)?;
+                report.directories_added.push(path.clone());
+                added.push(dir.clone());
+                dir
+            }
+            None => {
+                report.directories_skipped.push(d.path.clone());
+                continue;
+            }
+        };
+        paths.insert(d.path.clone(), local.path.clone());
+        targets.insert(d.id, local);
+    }
+    let slugs = SlugMap {
+        ids: targets.iter().map(|(id, dir)| (*id, dir.id)).collect(),
+        renamed: RefCell::new(HashSet::new()),
+    };
+
+    // Restore the database through a migrated copy of the backed-up one
+    let snapshot = TempFile::new(DATABASE);
+    {
+        let mut entry = archive.by_name(DATABASE).map_err(zip_err)?;
+        let mut out = File::create(&snapshot.0).map_err(|e| e.to_string())?;
+        std::io::copy(&mut entry, &mut out).map_err(zip_err)?;
+    }
+    let backup = crate::db::init_db(&snapshot.0).map_err(|e| format!("Invalid backup database: {}", e))?;
+
+    // Directory stores are merged row by row like the database, into the
+    // directory's store if it keeps one here, else into the app database
+    let mut stores = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(zip_err)?;
+        let Some((id, rest)) = entry.enclosed_name().as_deref().and_then(dir_entry) else {
+            continue;
+        };
+        let Some(dir) = targets.get(&id) else {
+            continue;
+        };
+        if rest != Path::new(STORE_FILE) {
+            continue;
+        }
+        let file = TempFile::new(STORE_FILE);
+        let mut out = File::create(&file.0).map_err(|e| e.to_string())?;
+        std::io::copy(&mut entry, &mut out).map_err(zip_err)?;
+        let store = Connection::open(&file.0).map_err(|e| format!("Invalid backup of {}: {}", dir.path, e))?;
+        note_store::upgrade_store(&store)?;
+        stores.push((note_store::dir_store(dir)?, store, file));
+    }
+
+    conn.execute_batch("BEGIN TRANSACTION").map_err(|e| e.to_string())?;
+    let restored = restore_tables(conn, &backup, mode, &slugs, &mut report).and_then(|()| {
+        for (_, store, _) in stores.iter().filter(|(local, _, _)| local.is_none()) {
+            restore_notes(conn, store, BackupMode::Merge, &slugs, &mut report)?;
+        }
+        Ok(())
+    });
+    match restored {
+        Ok(()) => conn.execute_batch("COMMIT").map_err(|e| e.to_string())?,
+        Err(e) => {
+            conn.execute_batch("ROLLBACK").ok();
+            return Err(e);
+        }
+    }
+    drop(backup);
+    for (local, store, _) in &stores {
+        let Some(local) = local else {
+            continue;
+        };
+        let tx = local.unchecked_transaction().map_err(|e| e.to_string())?;
+        restore_notes(&tx, store, mode, &slugs, &mut report)?;
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(zip_err)?;
+        let Some((id, rest)) = entry.enclosed_name().as_deref().and_then(dir_entry) else {
+            continue;
+        };
+        let Some(dir) = targets.get(&id) else {
+            continue;
+        };
+        if is_store_file(&rest) {
+            continue;
+        }
+        let dest = Path::new(&dir.path).join(".axiomatic").join(&rest);
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).map_err(zip_err)?;
+
+        let is_json = rest.extension().is_some_and(|e| e == "json");
+        let bytes = match serde_json::from_slice::<Value>(&bytes) {
+            Ok(mut value) if is_json => {
+                let name = rest.to_string_lossy();
+                remap_json(&mut value, &slugs, &paths, SLUG_KEYED_FILES.contains(&name.as_ref()));
+                if mode == BackupMode::Merge {
+                    if let Some(mut local) = std::fs::read(&dest).ok().and_then(|b| serde_json::from_slice(&b).ok()) {
+                        merge_json(&mut local, value);
+                        value = local;
+                    }
+                }
+                serde_json::to_vec_pretty(&value).map_err(|e| e.to_string())?
+            }
+            _ if mode == BackupMode::Merge && dest.exists() => continue,
+            _ => bytes,
+        };
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(&dest, bytes).map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+        report.files += 1;
+    }
+
+    report.slugs_renamed = slugs.renamed.borrow().len();
+    Ok((report, added))
+}
+
+/// Write a backup of the database and every library's `.axiomatic/` folder.
+#[tauri::command]
+pub fn export_backup(output_path: String, state: State<'_, DbState>) -> Result<BackupManifest, String> {
+    let conn = get_db(&state)?;
+    export_backup_inner(&conn, Path::new(&output_path))
+}
+
+/// Restore a backup made with `export_backup`. `dir_map` relocates
+/// directories whose path differs on this machine.
+#[tauri::command]
+pub fn import_backup(
+    path: String,
+    mode: BackupMode,
+    dir_map: Option<HashMap<String, String>>,
+    state: State<'_, DbState>,
+    watcher: State<'_, LibraryWatcher>,
+) -> Result<BackupReport, String> {
+    let conn = get_db(&state)?;
+    let (report, added) = import_backup_inner(&conn, Path::new(&path), mode, &dir_map.unwrap_or_default())?;
+    for dir in &added {
+        watcher.watch(dir);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{get_note_inner, save_note_image_inner, set_note_inner};
+    use crate::json_storage::{read_json, write_json};
+    use crate::models::{NoteAnchor, NoteStorage, Snip};
+
+    struct Machine {
+        _tmp: tempfile::TempDir,
+        conn: Connection,
+        library: String,
+        root: PathBuf,
+    }
+
+    /// A database with one library directory, registered after `offset`
+    /// other ones so its id differs between machines.
+    fn machine(offset: usize) -> Machine {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let conn = crate::db::init_db(&root.join("axiomatic.db")).unwrap();
+        for i in 0..offset {
+            let other = root.join(format!("other{}", i));
+            std::fs::create_dir_all(&other).unwrap();
+            add_directory_inner(&conn, &other.to_string_lossy(), "other").unwrap();
+        }
+        let library = root.join("library");
+        std::fs::create_dir_all(&library).unwrap();
+        let library = library.to_string_lossy().to_string();
+        add_directory_inner(&conn, &library, "library").unwrap();
+        Machine { _tmp: tmp, conn, library, root }
+    }
+
+    fn slug(m: &Machine) -> String {
+        let id = list_directories_inner(&m.conn).unwrap().into_iter().find(|d| d.path == m.library).unwrap().id;
+        format!("{}_algebra", id)
+    }
+
+    fn snip(id: &str, slug: &str) -> Snip {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "slug": slug, "full_path": "/x.pdf", "page": 1, "label": id,
+            "x": 0.0, "y": 0.0, "width": 1.0, "height": 1.0, "created_at": ""
+        }))
+        .unwrap()
+    }
+
+    fn export(m: &Machine) -> PathBuf {
+        let s = slug(m);
+        set_note_inner(&m.conn, &s, 4, "Backed up", "markdown").unwrap();
+        save_note_image_inner(&m.conn, &s, 4, "fig.png", b"\x89PNG").unwrap();
+        write_json(&m.library, "snips.json", &vec![snip("s1", &s)]).unwrap();
+        let mut progress = serde_json::Map::new();
+        progress.insert(s.clone(), serde_json::json!({ "currentPage": 12, "totalPages": 300, "lastReadAt": "" }));
+        write_json(&m.library, "progress.json", &progress).unwrap();
+        let position = serde_json::from_value(serde_json::json!({ "page": 12, "scrollOffset": 0.5 })).unwrap();
+        crate::history_commands::record_reading_position_inner(&m.conn, &m.library, &s, position).unwrap();
+
+        let archive = m.root.join("backup.zip");
+        let manifest = export_backup_inner(&m.conn, &archive).unwrap();
+        assert_eq!((manifest.notes, manifest.note_images, manifest.files), (1, 1, 2));
+        archive
+    }
+
+    #[test]
+    fn restore_on_another_machine_renames_slugs() {
+        let source = machine(0);
+        let archive = export(&source);
+
+        // Same library path, but registered as the third directory
+        let target = machine(2);
+        let dir_map = HashMap::from([(source.library.clone(), target.library.clone())]);
+        let (report, added) = import_backup_inner(&target.conn, &archive, BackupMode::Merge, &dir_map).unwrap();
+        assert!(added.is_empty() && report.directories_skipped.is_empty());
+        assert_eq!((report.slugs_renamed, report.notes, report.note_images, report.files), (1, 1, 1, 2));
+
+        let s = slug(&target);
+        assert_eq!(get_note_inner(&target.conn, &s, 4).unwrap().unwrap().content, "Backed up");
+        let snips: Vec<Snip> = read_json(&target.library, "snips.json");
+        assert_eq!(snips[0].slug, s);
+        let progress: serde_json::Map<String, Value> = read_json(&target.library, "progress.json");
+        assert!(progress.contains_key(&s));
+        let last = crate::history_commands::get_last_position_inner(&target.conn, &target.library, &s).unwrap();
+        assert_eq!(last.map(|p| p.page), Some(12));
+    }
+
+    #[test]
+    fn merge_keeps_local_data_and_replace_overwrites() {
+        let m = machine(0);
+        let archive = export(&m);
+        let s = slug(&m);
+
+        // Changes after the backup
+        m.conn.execute("UPDATE notes SET updated_at = '2999-01-01 00:00:00'", []).unwrap();
+        set_note_inner(&m.conn, &s, 5, "Newer page", "markdown").unwrap();
+        m.conn.execute("UPDATE notes SET content = 'Edited', updated_at = '2999-01-01' WHERE page = 4", []).unwrap();
+        write_json(&m.library, "snips.json", &vec![snip("s2", &s)]).unwrap();
+
+        let (report, _) = import_backup_inner(&m.conn, &archive, BackupMode::Merge, &HashMap::new()).unwrap();
+        assert_eq!((report.notes, report.note_images), (0, 0));
+        assert_eq!(get_note_inner(&m.conn, &s, 4).unwrap().unwrap().content, "Edited");
+        let snips: Vec<Snip> = read_json(&m.library, "snips.json");
+        assert_eq!(snips.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), ["s2", "s1"]);
+
+        import_backup_inner(&m.conn, &archive, BackupMode::Replace, &HashMap::new()).unwrap();
+        assert_eq!(get_note_inner(&m.conn, &s, 4).unwrap().unwrap().content, "Backed up");
+        assert!(get_note_inner(&m.conn, &s, 5).unwrap().is_none());
+        let snips: Vec<Snip> = read_json(&m.library, "snips.json");
+        assert_eq!(snips.len(), 1);
+    }
+
+    #[test]
+    fn restored_notes_link_to_their_images() {
+        let source = machine(0);
+        let s = slug(&source);
+        let id = save_note_image_inner(&source.conn, &s, 1, "plot.png", b"plot").unwrap();
+        set_note_inner(&source.conn, &s, 1, &format!("![plot](axiomatic-image://{})", id), "markdown").unwrap();
+        let archive = source.root.join("backup.zip");
+        export_backup_inner(&source.conn, &archive).unwrap();
+        let image_of = |conn: &Connection| {
+            let content = get_note_inner(conn, &s, 1).unwrap().unwrap().content;
+            let id = content.trim_end_matches(')').rsplit('/').next().unwrap().parse().unwrap();
+            crate::commands::get_note_image_inner(conn, id).unwrap()
+        };
+
+        // An unrelated image already holds the id here
+        let target = machine(0);
+        let dir_map = HashMap::from([(source.library.clone(), target.library.clone())]);
+        assert_eq!(save_note_image_inner(&target.conn, "1_other", 1, "x.png", b"other").unwrap(), id);
+        import_backup_inner(&target.conn, &archive, BackupMode::Merge, &dir_map).unwrap();
+        assert_eq!(image_of(&target.conn), b"plot");
+
+        // Replace clears the images first, without resetting their ids
+        import_backup_inner(&target.conn, &archive, BackupMode::Replace, &dir_map).unwrap();
+        assert_eq!(image_of(&target.conn), b"plot");
+    }
+
+    #[test]
+    fn directory_stores_are_snapshotted_and_merged() {
+        let source = machine(0);
+        let s = slug(&source);
+        note_store::set_note_storage_inner(&source.conn, &source.library, NoteStorage::Directory).unwrap();
+        let store = note_store::open(&source.conn, &s).unwrap();
+        set_note_inner(&store, &s, 1, "In the store", "markdown").unwrap();
+        drop(store);
+        let archive = source.root.join("backup.zip");
+        let manifest = export_backup_inner(&source.conn, &archive).unwrap();
+        assert_eq!((manifest.notes, manifest.files), (1, 0));
+
+        // Into the app database of a machine keeping notes there
+        let target = machine(2);
+        let dir_map = HashMap::from([(source.library.clone(), target.library.clone())]);
+        let t = slug(&target);
+        import_backup_inner(&target.conn, &archive, BackupMode::Merge, &dir_map).unwrap();
+        assert_eq!(get_note_inner(&target.conn, &t, 1).unwrap().unwrap().content, "In the store");
+
+        // Into an existing store, keeping what is only there
+        note_store::set_note_storage_inner(&target.conn, &target.library, NoteStorage::Directory).unwrap();
+        let store = note_store::open(&target.conn, &t).unwrap();
+        set_note_inner(&store, &t, 1, "", "").unwrap();
+        set_note_inner(&store, &t, 2, "Local only", "markdown").unwrap();
+        drop(store);
+        let (report, _) = import_backup_inner(&target.conn, &archive, BackupMode::Merge, &dir_map).unwrap();
+        assert_eq!(report.notes, 1);
+        let store = note_store::open(&target.conn, &t).unwrap();
+        assert_eq!(get_note_inner(&store, &t, 1).unwrap().unwrap().content, "In the store");
+        assert_eq!(get_note_inner(&store, &t, 2).unwrap().unwrap().content, "Local only");
+        assert!(is_store_file(Path::new("notes.db-journal")) && !is_store_file(Path::new("notes.dbx")));
+    }
+
+    #[test]
+    fn note_history_round_trips() {
+        let source = machine(0);
+        let s = slug(&source);
+        set_note_inner(&source.conn, &s, 1, "First draft", "markdown").unwrap();
+        set_note_inner(&source.conn, &s, 1, "Second draft", "markdown").unwrap();
+        let archive = source.root.join("backup.zip");
+        assert_eq!(export_backup_inner(&source.conn, &archive).unwrap().note_revisions, 1);
+
+        let target = machine(2);
+        let dir_map = HashMap::from([(source.library.clone(), target.library.clone())]);
+        let t = slug(&target);
+        let history = |conn: &Connection| -> Vec<String> {
+            note_revisions::list_note_revisions_inner(conn, &t, 1, &NoteAnchor::Page)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.content)
+                .collect()
+        };
+        let (report, _) = import_backup_inner(&target.conn, &archive, BackupMode::Merge, &dir_map).unwrap();
+        assert_eq!(report.note_revisions, 1);
+        assert_eq!(history(&target.conn), ["First draft"]);
+        let (report, _) = import_backup_inner(&target.conn, &archive, BackupMode::Merge, &dir_map).unwrap();
+        assert_eq!(report.note_revisions, 0);
+
+        // Wiping the note keeps it as a revision; replace drops that history
+        set_note_inner(&target.conn, &t, 1, "", "").unwrap();
+        assert_eq!(history(&target.conn), ["Second draft", "First draft"]);
+        import_backup_inner(&target.conn, &archive, BackupMode::Replace, &dir_map).unwrap();
+        assert_eq!(history(&target.conn), ["First draft"]);
+        assert_eq!(get_note_inner(&target.conn, &t, 1).unwrap().unwrap().content, "Second draft");
+    }
+
+    #[test]
+    fn rejects_foreign_archives_and_skips_missing_dirs() {
+        let m = machine(0);
+        let bogus = m.root.join("bogus.zip");
+        let mut zip = ZipWriter::new(File::create(&bogus).unwrap());
+        zip.start_file("hello.txt", SimpleFileOptions::default()).unwrap();
+        zip.finish().unwrap();
+        let err = import_backup_inner(&m.conn, &bogus, BackupMode::Merge, &HashMap::new()).unwrap_err();
+        assert!(err.contains("no manifest"));
+
+        let archive = export(&m);
+        let target = machine(0);
+        let dir_map = HashMap::from([(m.library.clone(), "/nonexistent/library".to_string())]);
+        let (report, _) = import_backup_inner(&target.conn, &archive, BackupMode::Merge, &dir_map).unwrap();
+        assert_eq!(report.directories_skipped, vec![m.library.clone()]);
+        assert_eq!(report.files, 0);
+        assert_eq!(dir_entry(Path::new("dirs/3/snips.json")), Some((3, PathBuf::from("snips.json"))));
+        assert_eq!(dir_entry(Path::new("axiomatic.db")), None);
+    }
+}
@@ -0,0 +1,486 @@
+//! Headless command line. `axiomatic <command> ...` works on the library
+//! database, `.axiomatic/` files and PDF engine without opening a window.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use crossbeam_channel::Sender;
+use rusqlite::Connection;
+use walkdir::WalkDir;
+
+use crate::clip_commands::record_clip_provenance_inner;
+use crate::commands::{add_directory_inner, export_notes_for_book_inner, list_directories_inner};
+use crate::doc_backend::is_supported_document;
+use crate::json_storage::read_json;
+use crate::models::{Directory, Snip, Textbook};
+use crate::note_store;
+use crate::pdf_commands::request;
+use crate::pdf_engine::PdfRequest;
+use crate::scan_index::{cached_textbooks_inner, refresh_inner};
+use crate::snip_commands::SNIPS_FILE;
+
+/// Must match `identifier` in tauri.conf.json, which names the app data dir.
+const APP_IDENTIFIER: &str = "com.axiomatic.app";
+/// Flags that take no value.
+const BOOL_FLAGS: &[&str] = &["json"];
+
+const USAGE: &str = "Usage: axiomatic <command> [options]
+
+Commands:
+  books [--json]                          List the documents in the library
+  import <library-dir> <file|dir>...      Copy documents into a library directory
+  export-notes <slug> [-o FILE]           Export a book's notes as Markdown
+  snips [--dir DIR] [--slug SLUG] [--tag TAG] [--status STATUS] [--json]
+                                          List snips across the library
+  clip <book> <start-end> <out.pdf>       Save a page range as a new PDF
+  search <book> <query> [--json]          Search a document's text
+
+<book> is a document path or a slug from `axiomatic books`.
+
+Options:
+  --db PATH   Library database (default: the app's, or $AXIOMATIC_DB)
+
+PDFium is looked up next to the executable; set $AXIOMATIC_PDFIUM to override.
+";
+
+/// Whether the process was started as a CLI command rather than the app
+/// (which also takes a document path to open).
+pub fn is_cli_invocation(args: &[String]) -> bool {
+    let commands = ["books", "import", "export-notes", "snips", "clip", "search", "help", "--help"];
+    args.get(1).is_some_and(|a| commands.contains(&a.as_str()))
+}
+
+/// Run a command given the arguments after the program name; returns the
+/// process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let mut out = std::io::stdout().lock();
+    match parse(args).and_then(|opts| execute(&opts, &mut out)) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("axiomatic: {}", e);
+            1
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    command: String,
+    positional: Vec<String>,
+    values: HashMap<String, String>,
+    flags: HashSet<String>,
+}
+
+impl Options {
+    fn arg(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("missing <{}>\n\n{}", name, USAGE))
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    fn json(&self) -> bool {
+        self.flags.contains("json")
+    }
+}
+
+fn parse(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut iter = args.iter();
+    opts.command = iter.next().cloned().unwrap_or_else(|| "help".into());
+    while let Some(arg) = iter.next() {
+        let name = match arg.as_str() {
+            "-o" => "output",
+            a => match a.strip_prefix("--") {
+                Some(name) => name,
+                None => {
+                    opts.positional.push(arg.clone());
+                    continue;
+                }
+            },
+        };
+        if BOOL_FLAGS.contains(&name) {
+            opts.flags.insert(name.to_string());
+        } else {
+            let value = iter.next().ok_or_else(|| format!("--{} needs a value", name))?;
+            opts.values.insert(name.to_string(), value.clone());
+        }
+    }
+    Ok(opts)
+}
+
+fn db_path(opts: &Options) -> Result<PathBuf, String> {
+    if let Some(path) = opts.value("db") {
+        return Ok(PathBuf::from(path));
+    }
+    if let Some(path) = std::env::var_os("AXIOMATIC_DB") {
+        return Ok(PathBuf::from(path));
+    }
+    dirs::data_dir()
+        .map(|d| d.join(APP_IDENTIFIER).join("axiomatic.db"))
+        .ok_or_else(|| "cannot locate the app data directory; pass --db".into())
+}
+
+fn write_json<T: serde::Serialize>(out: &mut dyn Write, value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    writeln!(out, "{}", json).map_err(|e| e.to_string())
+}
+
+fn execute(opts: &Options, out: &mut dyn Write) -> Result<(), String> {
+    if matches!(opts.command.as_str(), "help" | "--help") {
+        return write!(out, "{}", USAGE).map_err(|e| e.to_string());
+    }
+    let conn = crate::db::init_db(&db_path(opts)?).map_err(|e| format!("cannot open database: {}", e))?;
+    let w = |out: &mut dyn Write, line: String| writeln!(out, "{}", line).map_err(|e| e.to_string());
+
+    match opts.command.as_str() {
+        "books" => {
+            let books = textbooks(&conn)?;
+            if opts.json() {
+                return write_json(out, &books);
+            }
+            for b in books {
+                w(out, format!("{}\t{}\t{}", b.slug, b.title, b.full_path))?;
+            }
+        }
+        "import" => {
+            let target = opts.arg(0, "library-dir")?;
+            let sources: Vec<&str> = opts.positional[1..].iter().map(String::as_str).collect();
+            if sources.is_empty() {
+                return Err(format!("nothing to import\n\n{}", USAGE));
+            }
+            let (copied, skipped) = import(&conn, Path::new(target), &sources)?;
+            w(out, format!("Imported {} document(s), skipped {} already present", copied, skipped))?;
+        }
+        "export-notes" => {
+            let slug = opts.arg(0, "slug")?;
+            let markdown = export_notes_for_book_inner(&*note_store::open(&conn, slug)?, slug)?;
+            match opts.value("output") {
+                Some(path) => std::fs::write(path, markdown).map_err(|e| format!("cannot write {}: {}", path, e))?,
+                None => write!(out, "{}", markdown).map_err(|e| e.to_string())?,
+            }
+        }
+        "snips" => {
+            let snips = find_snips(&conn, opts)?;
+            if opts.json() {
+                return write_json(out, &snips);
+            }
+            for s in snips {
+                w(out, format!("{}\t{}\tp. {}\t{}\t{}", s.status, s.slug, s.page, s.label, s.tags.join(",")))?;
+            }
+        }
+        "clip" => {
+            let source = resolve_document(&conn, opts.arg(0, "book")?)?;
+            let (start_page, end_page) = parse_range(opts.arg(1, "start-end")?)?;
+            let output = absolute(opts.arg(2, "out.pdf")?)?;
+            let pdf = start_pdf_engine()?;
+            request(&pdf, |tx| PdfRequest::ClipPdf {
+                source_path: source.clone(),
+                start_page,
+                end_page,
+                output_path: output.clone(),
+                tx,
+            })?;
+            record_clip_provenance_inner(&conn, &source, start_page, end_page, &output)?;
+            w(out, format!("Wrote pages {}-{} to {}", start_page, end_page, output))?;
+        }
+        "search" => {
+            let path = resolve_document(&conn, opts.arg(0, "book")?)?;
+            opts.arg(1, "query")?;
+            let query = opts.positional[1..].join(" ");
+            let pdf = start_pdf_engine()?;
+            let results = request(&pdf, |tx| PdfRequest::SearchDocument { path, query, tx })?;
+            if opts.json() {
+                return write_json(out, &results);
+            }
+            let mut pages: Vec<(u32, usize)> = Vec::new();
+            for r in &results {
+                match pages.last_mut() {
+                    Some((page, n)) if *page == r.page => *n += 1,
+                    _ => pages.push((r.page, 1)),
+                }
+            }
+            for (page, n) in pages {
+                w(out, format!("p. {}\t{} match(es)", page, n))?;
+            }
+        }
+        other => return Err(format!("unknown command '{}'\n\n{}", other, USAGE)),
+    }
+    Ok(())
+}
+
+/// Every indexed document, scanning directories that were never indexed.
+fn textbooks(conn: &Connection) -> Result<Vec<Textbook>, String> {
+    let dirs = list_directories_inner(conn)?;
+    let unindexed: Vec<Directory> = cached_textbooks_inner(conn, &dirs)?
+        .into_iter()
+        .filter(|(_, books)| books.is_none())
+        .map(|(dir, _)| dir)
+        .collect();
+    if !unindexed.is_empty() {
+        refresh_inner(conn, &unindexed)?;
+    }
+    Ok(cached_textbooks_inner(conn, &dirs)?
+        .into_iter()
+        .flat_map(|(_, books)| books.unwrap_or_default())
+        .collect())
+}
+
+/// A document path as given, or the path of the book with that slug.
+fn resolve_document(conn: &Connection, book: &str) -> Result<String, String> {
+    if Path::new(book).is_file() {
+        return absolute(book);
+    }
+    textbooks(conn)?
+        .into_iter()
+        .find(|b| b.slug == book)
+        .map(|b| b.full_path)
+        .ok_or_else(|| format!("no such document or slug: {}", book))
+}
+
+fn absolute(path: &str) -> Result<String, String> {
+    let path = Path::new(path);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().map_err(|e| e.to_string())?.join(path)
+    };
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// `10-40` or a single page `12`, 1-based and inclusive.
+fn parse_range(range: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid page range '{}', expected e.g. 10-40", range);
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let start: u32 = start.trim().parse().map_err(|_| invalid())?;
+    let end: u32 = end.trim().parse().map_err(|_| invalid())?;
+    if start == 0 || end < start {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+/// Copy documents (or the documents under directories) into `target`,
+/// registering it as a library directory if needed, then index it.
+/// Returns how many files were copied and how many already existed.
+fn import(conn: &Connection, target: &Path, sources: &[&str]) -> Result<(usize, usize), String> {
+    // Stored library paths are absolute; `./books` must match them too
+    let target = &std::fs::canonicalize(target).map_err(|e| format!("{}: {}", target.display(), e))?;
+    if !target.is_dir() {
+        return Err(format!("not a directory: {}", target.display()));
+    }
+    let target_str = target.to_string_lossy().to_string();
+    let dirs = list_directories_inner(conn)?;
+    let dir = match dirs
+        .into_iter()
+        .find(|d| std::fs::canonicalize(&d.path).is_ok_and(|p| &p == target))
+    {
+        Some(dir) => dir,
+        None => {
+            let label = target
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| target_str.clone());
+            add_directory_inner(conn, &target_str, &label)?
+        }
+    };
+
+    let mut files = Vec::new();
+    for source in sources {
+        let path = Path::new(source);
+        if path.is_dir() {
+            files.extend(
+                WalkDir::new(path)
+                    .into_iter()
+                    .filter_entry(|e| e.file_name() != ".axiomatic")
+                    .flatten()
+                    .map(|e| e.into_path())
+                    .filter(|p| p.is_file() && is_supported_document(p)),
+            );
+        } else if path.is_file() && is_supported_document(path) {
+            files.push(path.to_path_buf());
+        } else {
+            return Err(format!("not a supported document: {}", source));
+        }
+    }
+
+    let (mut copied, mut skipped) = (0, 0);
+    for file in files {
+        let dest = target.join(file.file_name().unwrap_or_default());
+        if dest.exists() {
+            skipped += 1;
+            continue;
+        }
+        std::fs::copy(&file, &dest).map_err(|e| format!("cannot copy {}: {}", file.display(), e))?;
+        copied += 1;
+    }
+    refresh_inner(conn, &[dir])?;
+    Ok((copied, skipped))
+}
+
+fn find_snips(conn: &Connection, opts: &Options) -> Result<Vec<Snip>, String> {
+    let dirs: Vec<String> = match opts.value("dir") {
+        Some(dir) => vec![dir.to_string()],
+        None => list_directories_inner(conn)?.into_iter().map(|d| d.path).collect(),
+    };
+    let mut snips = Vec::new();
+    for dir in dirs {
+        let all: Vec<Snip> = read_json(&dir, SNIPS_FILE);
+        snips.extend(all.into_iter().filter(|s| {
+            opts.value("slug").map_or(true, |slug| s.slug == slug)
+                && opts.value("tag").map_or(true, |tag| s.tags.iter().any(|t| t == tag))
+                && opts.value("status").map_or(true, |status| s.status == status)
+        }));
+    }
+    Ok(snips)
+}
+
+/// Bind PDFium and start a single render worker.
+fn start_pdf_engine() -> Result<Sender<PdfRequest>, String> {
+    let lib_path = match std::env::var_os("AXIOMATIC_PDFIUM") {
+        Some(path) => PathBuf::from(path),
+        None => crate::find_pdfium(resource_dir()),
+    };
+    pdfium_render::prelude::Pdfium::bind_to_library(&lib_path)
+        .map_err(|e| format!("cannot load PDFium from {}: {:?}", lib_path.display(), e))?;
+    let (tx, rx) = crossbeam_channel::unbounded::<PdfRequest>();
+    let ocr = crate::ocr::OcrQueue::start(tx.clone());
+    crate::pdf_engine::run_pool(
+        rx,
+        lib_path,
+        Arc::new(AtomicU64::new(0)),
+        crate::pdf_models::new_shared_render_cache(),
+        ocr,
+        1,
+    );
+    Ok(tx)
+}
+
+/// Where an installed app keeps PDFium: next to the executable (Windows),
+/// in `Contents/Resources` (macOS) or `/usr/lib/<product>` (Linux).
+fn resource_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let dir = exe.parent()?;
+    let lib_name = crate::pdfium_lib_name();
+    [dir.to_path_buf(), dir.join("../Resources"), dir.join("../lib/Axiomatic")]
+        .into_iter()
+        .find(|d| d.join(lib_name).exists() || d.join("resources").join(lib_name).exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::set_note_inner;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    fn run_with(db: &Path, command: &str) -> Result<String, String> {
+        let mut opts = parse(&args(command))?;
+        opts.values.insert("db".into(), db.to_string_lossy().to_string());
+        let mut out = Vec::new();
+        execute(&opts, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn parses_commands_and_options() {
+        assert!(is_cli_invocation(&args("axiomatic snips --json")));
+        assert!(!is_cli_invocation(&args("axiomatic /home/me/book.pdf")));
+        assert!(!is_cli_invocation(&args("axiomatic")));
+
+        let opts = parse(&args("snips --tag proof --json --status attention")).unwrap();
+        assert_eq!(opts.command, "snips");
+        assert_eq!((opts.value("tag"), opts.value("status")), (Some("proof"), Some("attention")));
+        assert!(opts.json());
+        assert!(parse(&args("snips --tag")).is_err());
+
+        assert_eq!(parse_range("10-40").unwrap(), (10, 40));
+        assert_eq!(parse_range("7").unwrap(), (7, 7));
+        assert!(parse_range("40-10").is_err() && parse_range("0-3").is_err() && parse_range("a-b").is_err());
+    }
+
+    #[test]
+    fn import_books_notes_and_snips() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("axiomatic.db");
+        let library = tmp.path().join("library");
+        let inbox = tmp.path().join("inbox");
+        std::fs::create_dir_all(&library).unwrap();
+        std::fs::create_dir_all(inbox.join("sub")).unwrap();
+        std::fs::write(inbox.join("algebra.pdf"), b"%PDF-1.7\n%%EOF\n").unwrap();
+        std::fs::write(inbox.join("sub/topology.pdf"), b"%PDF-1.7\n%%EOF\n").unwrap();
+        std::fs::write(inbox.join("readme.txt"), b"not a book").unwrap();
+
+        let cmd = format!("import {} {}", library.display(), inbox.display());
+        assert!(run_with(&db, &cmd).unwrap().contains("Imported 2"));
+        assert!(run_with(&db, &cmd).unwrap().contains("skipped 2"));
+
+        let books: Vec<Textbook> = serde_json::from_str(&run_with(&db, "books --json").unwrap()).unwrap();
+        assert_eq!(books.len(), 2);
+        let algebra = books.iter().find(|b| b.file == "algebra.pdf").unwrap();
+
+        let conn = crate::db::init_db(&db).unwrap();
+        set_note_inner(&conn, &algebra.slug, 3, "Group axioms", "markdown").unwrap();
+        let notes = run_with(&db, &format!("export-notes {}", algebra.slug)).unwrap();
+        assert!(notes.contains("## Page 3") && notes.contains("Group axioms"));
+
+        let dir = library.to_string_lossy().to_string();
+        let snip = |label: &str, tags: &[&str], status: &str| Snip {
+            id: label.into(),
+            slug: algebra.slug.clone(),
+            full_path: algebra.full_path.clone(),
+            page: 1,
+            label: label.into(),
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+            created_at: String::new(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            status: status.into(),
+            solid_at: None,
+        };
+        crate::json_storage::write_json(
+            &dir,
+            SNIPS_FILE,
+            &vec![snip("Lemma 1", &["proof"], "attention"), snip("Lemma 2", &["proof"], "solid"), snip("Def 1", &[], "attention")],
+        )
+        .unwrap();
+        let listed = run_with(&db, "snips --tag proof --status attention").unwrap();
+        assert_eq!(listed.lines().count(), 1);
+        assert!(listed.contains("Lemma 1"));
+
+        assert!(run_with(&db, "export-notes").unwrap_err().contains("missing <slug>"));
+        assert!(run_with(&db, "frobnicate").unwrap_err().contains("unknown command"));
+    }
+
+    #[test]
+    fn import_registers_the_canonical_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("axiomatic.db");
+        let library = tmp.path().join("library");
+        let inbox = tmp.path().join("inbox");
+        std::fs::create_dir_all(&library).unwrap();
+        std::fs::create_dir_all(&inbox).unwrap();
+
+        let indirect = tmp.path().join("inbox/../library");
+        run_with(&db, &format!("import {} {}", indirect.display(), inbox.display())).unwrap();
+        run_with(&db, &format!("import {} {}", library.display(), inbox.display())).unwrap();
+        let conn = crate::db::init_db(&db).unwrap();
+        let dirs = list_directories_inner(&conn).unwrap();
+        assert_eq!(dirs.len(), 1);
+        assert_eq!(Path::new(&dirs[0].path), std::fs::canonicalize(&library).unwrap());
+
+        let missing = tmp.path().join("nowhere");
+        assert!(run_with(&db, &format!("import {} {}", missing.display(), inbox.display())).is_err());
+    }
+}
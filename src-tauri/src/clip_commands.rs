@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+use tauri::State;
+
+use crate::commands::{get_db, list_notes_for_book_inner, resolve_book_for_path, DbState};
+use crate::highlight_commands::list_highlights_inner;
+use crate::json_storage::{read_json, update_json};
+use crate::models::{ClipAnnotations, ClipProvenance, Snip};
+use crate::note_store;
+use crate::snip_commands::now_iso8601;
+
+const CLIPS_FILE: &str = "clips.json";
+
+/// Record where a freshly written clip came from. Only clips written into a
+/// tracked directory from a tracked source book get provenance; anything else
+/// stays a loose PDF and `None` is returned.
+pub fn record_clip_provenance_inner(
+    conn: &Connection,
+    source_path: &str,
+    start_page: u32,
+    end_page: u32,
+    output_path: &str,
+) -> Result<Option<ClipProvenance>, String> {
+    let Some((source_dir, source_slug)) = resolve_book_for_path(conn, source_path)? else {
+        return Ok(None);
+    };
+    let Some((clip_dir, clip_slug)) = resolve_book_for_path(conn, output_path)? else {
+        return Ok(None);
+    };
+
+    let provenance = ClipProvenance {
+        source_slug,
+        source_dir_path: source_dir.path,
+        source_path: source_path.to_string(),
+        page_offset: start_page as i64 - 1,
+        page_count: (end_page - start_page + 1) as i64,
+        created_at: now_iso8601(),
+    };
+    update_json::<HashMap<String, ClipProvenance>, _>(&clip_dir.path, CLIPS_FILE, |map| {
+        map.insert(clip_slug, provenance.clone());
+        Ok(())
+    })?;
+    Ok(Some(provenance))
+}
+
+#[tauri::command]
+pub fn get_clip_provenance(dir_path: String, slug: String) -> Result<Option<ClipProvenance>, String> {
+    let map: HashMap<String, ClipProvenance> = read_json(&dir_path, CLIPS_FILE);
+    Ok(map.get(&slug).cloned())
+}
+
+/// Map a source page into clip numbering, or `None` if it lies outside the clip.
+fn remap_page(provenance: &ClipProvenance, source_page: i64) -> Option<i64> {
+    let clip_page = source_page - provenance.page_offset;
+    (1..=provenance.page_count).contains(&clip_page).then_some(clip_page)
+}
+
+/// Collect the source book's notes, highlights and snips that fall inside the
+/// clipped page range. Records keep their ids and source slug so edits still
+/// land on the source; only `page` is rewritten into clip numbering.
+pub fn get_clip_annotations_inner(
+    conn: &Connection,
+    dir_path: &str,
+    slug: &str,
+) -> Result<Option<ClipAnnotations>, String> {
+    let map: HashMap<String, ClipProvenance> = read_json(dir_path, CLIPS_FILE);
+    let Some(provenance) = map.get(slug).cloned() else {
+        return Ok(None);
+    };
+
+    let source = note_store::open(conn, &provenance.source_slug)?;
+    let notes = list_notes_for_book_inner(&source, &provenance.source_slug)?
+        .into_iter()
+        .filter_map(|mut n| {
+            n.page = remap_page(&provenance, n.page)?;
+            Some(n)
+        })
+        .collect();
+    let highlights = list_highlights_inner(&source, &provenance.source_slug)?
+        .into_iter()
+        .filter_map(|mut h| {
+            h.page = remap_page(&provenance, h.page)?;
+            Some(h)
+        })
+        .collect();
+    let snips = read_json::<Vec<Snip>>(&provenance.source_dir_path, "snips.json")
+        .into_iter()
+        .filter(|s| s.slug == provenance.source_slug)
+        .filter_map(|mut s| {
+            s.page = remap_page(&provenance, s.page)?;
+            Some(s)
+        })
+        .collect();
+
+    Ok(Some(ClipAnnotations {
+        provenance,
+        notes,
+        highlights,
+        snips,
+    }))
+}
+
+#[tauri::command]
+pub fn get_clip_annotations(
+    dir_path: String,
+    slug: String,
+    state: State<'_, DbState>,
+) -> Result<Option<ClipAnnotations>, String> {
+    let conn = get_db(&state)?;
+    get_clip_annotations_inner(&conn, &dir_path, &slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{add_directory_inner, set_note_inner};
+    use crate::db;
+    use crate::highlight_commands::create_highlight_inner;
+    use crate::snip_commands::create_snip;
+
+    fn test_db() -> (tempfile::TempDir, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let conn = db::init_db(&db_path).unwrap();
+        (dir, conn)
+    }
+
+    #[test]
+    fn clip_into_tracked_dir_records_provenance() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let lib_path = lib.path().to_string_lossy().to_string();
+        let dir = add_directory_inner(&conn, &lib_path, "lib").unwrap();
+
+        let source = lib.path().join("Real Analysis.pdf");
+        let output = lib.path().join("clip_p10-20.pdf");
+        let prov = record_clip_provenance_inner(
+            &conn,
+            &source.to_string_lossy(),
+            10,
+            20,
+            &output.to_string_lossy(),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(prov.source_slug, format!("{}_real-analysis", dir.id));
+        assert_eq!(prov.page_offset, 9);
+        assert_eq!(prov.page_count, 11);
+
+        let stored = get_clip_provenance(lib_path, format!("{}_clip_p10-20", dir.id)).unwrap();
+        assert_eq!(stored.unwrap().source_slug, prov.source_slug);
+    }
+
+    #[test]
+    fn clip_outside_tracked_dirs_is_loose() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        add_directory_inner(&conn, &lib.path().to_string_lossy(), "lib").unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+
+        let prov = record_clip_provenance_inner(
+            &conn,
+            &lib.path().join("book.pdf").to_string_lossy(),
+            1,
+            2,
+            &elsewhere.path().join("clip.pdf").to_string_lossy(),
+        )
+        .unwrap();
+        assert!(prov.is_none());
+    }
+
+    #[test]
+    fn clip_annotations_are_remapped() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let lib_path = lib.path().to_string_lossy().to_string();
+        let dir = add_directory_inner(&conn, &lib_path, "lib").unwrap();
+        let source_slug = format!("{}_book", dir.id);
+        let clip_slug = format!("{}_clip", dir.id);
+
+        record_clip_provenance_inner(
+            &conn,
+            &lib.path().join("book.pdf").to_string_lossy(),
+            5,
+            7,
+            &lib.path().join("clip.pdf").to_string_lossy(),
+        )
+        .unwrap();
+
+        set_note_inner(&conn, &source_slug, 4, "before", "markdown").unwrap();
+        set_note_inner(&conn, &source_slug, 5, "first", "markdown").unwrap();
+        set_note_inner(&conn, &source_slug, 7, "last", "markdown").unwrap();
+        create_highlight_inner(&conn, &source_slug, 6, 0.0, 0.0, 1.0, 1.0, "yellow", "", "", "g1").unwrap();
+        create_highlight_inner(&conn, &source_slug, 8, 0.0, 0.0, 1.0, 1.0, "yellow", "", "", "g2").unwrap();
+        create_snip(
+            lib_path.clone(), source_slug.clone(), "/book.pdf".into(),
+            6, "Lemma".into(), 0.0, 0.0, 1.0, 1.0,
+        ).unwrap();
+
+        let ann = get_clip_annotations_inner(&conn, &lib_path, &clip_slug).unwrap().unwrap();
+        let note_pages: Vec<i64> = ann.notes.iter().map(|n| n.page).collect();
+        assert_eq!(note_pages, vec![1, 3]);
+        assert_eq!(ann.highlights.len(), 1);
+        assert_eq!(ann.highlights[0].page, 2);
+        assert_eq!(ann.snips.len(), 1);
+        assert_eq!(ann.snips[0].page, 2);
+        assert_eq!(ann.snips[0].slug, source_slug);
+    }
+
+    #[test]
+    fn clip_annotations_missing_provenance() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let ann = get_clip_annotations_inner(&conn, &lib.path().to_string_lossy(), "1_nothing").unwrap();
+        assert!(ann.is_none());
+    }
+}
@@ -7,7 +7,13 @@ use rusqlite::Connection;
 use tauri::State;
 use walkdir::WalkDir;
 
-use crate::models::{BookProgress, BookTagMapping, Directory, NoteRecord, OrphanCandidate, Tag, Textbook};
+use crate::doc_backend::is_supported_document;
+use crate::library_watcher::LibraryWatcher;
+use crate::models::{BookProgress, BookTagMapping, Directory, NoteAnchor, NoteRecord, OrphanCandidate, Tag, Textbook, XpSource};
+use crate::note_images;
+use crate::note_links;
+use crate::note_revisions;
+use crate::note_store;
 
 pub struct DbState(pub Mutex<Connection>);
 pub struct PendingFile(pub Mutex<Option<String>>);
@@ -24,7 +30,12 @@ fn sanitize_slug(name: &str) -> String {
         .to_string()
 }
 
-fn title_from_stem(stem: &str) -> String {
+/// Build the library slug for a file stem inside the directory with `dir_id`.
+pub fn book_slug(dir_id: i64, stem: &str) -> String {
+    format!("{}_{}", dir_id, sanitize_slug(stem))
+}
+
+pub(crate) fn title_from_stem(stem: &str) -> String {
     stem.replace(|c: char| c == '-' || c == '_', " ")
         .split_whitespace()
         .map(|w| {
@@ -47,14 +58,17 @@ fn row_to_directory(row: &rusqlite::Row) -> rusqlite::Result<Directory> {
     })
 }
 
-fn row_to_note(row: &rusqlite::Row) -> rusqlite::Result<NoteRecord> {
+pub(crate) const NOTE_COLUMNS: &str = "id, slug, page, anchor, content, format, updated_at";
+
+pub(crate) fn row_to_note(row: &rusqlite::Row) -> rusqlite::Result<NoteRecord> {
     Ok(NoteRecord {
         id: row.get(0)?,
         slug: row.get(1)?,
         page: row.get(2)?,
-        content: row.get(3)?,
-        format: row.get(4)?,
-        updated_at: row.get(5)?,
+        anchor: NoteAnchor::from_key(&row.get::<_, String>(3)?).unwrap_or_default(),
+        content: row.get(4)?,
+        format: row.get(5)?,
+        updated_at: row.get(6)?,
     })
 }
 
@@ -97,7 +111,11 @@ pub fn add_directory_inner(conn: &Connection, path: &str, label: &str) -> Result
 }
 
 #[tauri::command]
-pub fn add_directory(path: String, state: State<'_, DbState>) -> Result<Directory, String> {
+pub fn add_directory(
+    path: String,
+    state: State<'_, DbState>,
+    watcher: State<'_, LibraryWatcher>,
+) -> Result<Directory, String> {
     let p = Path::new(&path);
     if !p.is_dir() {
         return Err(format!("Not a directory: {}", path));
@@ -112,6 +130,7 @@ pub fn add_directory(path: String, state: State<'_, DbState>) -> Result<Director
 
     // Auto-create .axiomatic/ project state directory
     ensure_axiomatic_dir(&path)?;
+    watcher.watch(&dir);
 
     Ok(dir)
 }
@@ -123,13 +142,20 @@ pub fn remove_directory_inner(conn: &Connection, id: i64) -> Result<(), String>
 }
 
 #[tauri::command]
-pub fn remove_directory(id: i64, state: State<'_, DbState>) -> Result<(), String> {
+pub fn remove_directory(
+    id: i64,
+    state: State<'_, DbState>,
+    watcher: State<'_, LibraryWatcher>,
+) -> Result<(), String> {
     let conn = get_db(&state)?;
+    if let Some(dir) = list_directories_inner(&conn)?.into_iter().find(|d| d.id == id) {
+        watcher.unwatch(&dir);
+    }
     remove_directory_inner(&conn, id)
 }
 
-/// Scan directories for PDF files and return textbook metadata.
-fn scan_textbooks(dirs: &[Directory]) -> Vec<Textbook> {
+/// Scan directories for supported documents and return textbook metadata.
+pub(crate) fn scan_textbooks(dirs: &[Directory]) -> Vec<Textbook> {
     let mut textbooks = Vec::new();
     for dir in dirs {
         let dir_path = Path::new(&dir.path);
@@ -142,18 +168,18 @@ fn scan_textbooks(dirs: &[Directory]) -> Vec<Textbook> {
             .flatten()
         {
             let path = entry.path();
-            if path.is_file()
-                && path.extension().map(|e| e.to_ascii_lowercase() == "pdf").unwrap_or(false)
-            {
+            if path.is_file() && is_supported_document(path) {
                 let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
                 let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
                 textbooks.push(Textbook {
-                    slug: format!("{}_{}", dir.id, sanitize_slug(&stem)),
+                    slug: book_slug(dir.id, &stem),
                     title: title_from_stem(&stem),
                     file: file_name,
                     dir_id: dir.id,
                     dir_path: dir.path.clone(),
                     full_path: path.to_string_lossy().to_string(),
+                    doc_id: None,
+                    page_count: None,
                 });
             }
         }
@@ -161,15 +187,56 @@ fn scan_textbooks(dirs: &[Directory]) -> Vec<Textbook> {
     textbooks
 }
 
+/// List textbooks from the scan index. Cached results are returned at once
+/// and a background refresh emits `library-changed` when anything moved.
+/// Directories that were never indexed are scanned before returning.
 #[tauri::command]
-pub async fn list_textbooks(state: State<'_, DbState>) -> Result<Vec<Textbook>, String> {
-    let dirs = {
+pub async fn list_textbooks(app: tauri::AppHandle, state: State<'_, DbState>) -> Result<Vec<Textbook>, String> {
+    let cached = {
         let conn = get_db(&state)?;
-        list_directories_inner(&conn)?
+        let dirs = list_directories_inner(&conn)?;
+        crate::scan_index::cached_textbooks_inner(&conn, &dirs)?
     };
-    tauri::async_runtime::spawn_blocking(move || Ok(scan_textbooks(&dirs)))
+
+    let mut textbooks = Vec::new();
+    let mut unindexed = Vec::new();
+    for (dir, books) in cached {
+        match books {
+            Some(books) => textbooks.extend(books),
+            None => unindexed.push(dir),
+        }
+    }
+
+    if !unindexed.is_empty() {
+        let known = crate::doc_identity::known_files(&*get_db(&state)?)?;
+        // Walk and hash off the async runtime and without the database lock
+        let (scanned, mut fresh, identities) = tauri::async_runtime::spawn_blocking(move || {
+            let scanned: Vec<_> = unindexed
+                .iter()
+                .map(|dir| (dir.clone(), crate::scan_index::rescan(dir, &Default::default(), &[])))
+                .collect();
+            let fresh: Vec<_> = scanned
+                .iter()
+                .flat_map(|(dir, index)| crate::scan_index::textbooks_from_index(dir, index))
+                .collect();
+            let identities = crate::doc_identity::identify_files(&fresh, &known);
+            (scanned, fresh, identities)
+        })
         .await
-        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+        let conn = get_db(&state)?;
+        for (dir, index) in &scanned {
+            crate::scan_index::commit_index(&conn, dir, index)?;
+        }
+        // Follow renames/moves of known documents before handing slugs out
+        crate::doc_identity::record_identities_inner(&conn, &mut fresh, identities)?;
+        crate::scan_index::store_doc_ids(&conn, &fresh)?;
+        textbooks.extend(fresh);
+    }
+
+    crate::scan_index::spawn_refresh(app);
+    Ok(textbooks)
 }
 
 #[tauri::command]
@@ -179,10 +246,15 @@ pub fn rename_textbook(full_path: String, new_name: String) -> Result<(), String
         return Err(format!("File not found: {}", full_path));
     }
     let parent = path.parent().ok_or("No parent directory")?;
-    let new_file = if new_name.to_lowercase().ends_with(".pdf") {
+    // Keep the document's own extension (.pdf, .djvu, .epub)
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_else(|| "pdf".to_string());
+    let new_file = if new_name.to_lowercase().ends_with(&format!(".{}", ext.to_lowercase())) {
         new_name
     } else {
-        format!("{}.pdf", new_name)
+        format!("{}.{}", new_name, ext)
     };
     let new_path = parent.join(&new_file);
     std::fs::rename(path, &new_path).map_err(|e| e.to_string())?;
@@ -256,11 +328,21 @@ pub fn read_file_bytes(path: String) -> Result<tauri::ipc::Response, String> {
     Ok(tauri::ipc::Response::new(bytes))
 }
 
+/// The page-level note of a page.
 pub fn get_note_inner(conn: &Connection, slug: &str, page: i64) -> Result<Option<NoteRecord>, String> {
+    get_anchored_note_inner(conn, slug, page, &NoteAnchor::Page)
+}
+
+pub fn get_anchored_note_inner(
+    conn: &Connection,
+    slug: &str,
+    page: i64,
+    anchor: &NoteAnchor,
+) -> Result<Option<NoteRecord>, String> {
     let mut stmt = conn
-        .prepare("SELECT id, slug, page, content, format, updated_at FROM notes WHERE slug = ?1 AND page = ?2")
+        .prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes WHERE slug = ?1 AND page = ?2 AND anchor = ?3"))
         .map_err(|e| e.to_string())?;
-    let result = stmt.query_row(rusqlite::params![slug, page], row_to_note);
+    let result = stmt.query_row(rusqlite::params![slug, page, anchor.key()], row_to_note);
     match result {
         Ok(note) => Ok(Some(note)),
         Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
@@ -268,38 +350,92 @@ pub fn get_note_inner(conn: &Connection, slug: &str, page: i64) -> Result<Option
     }
 }
 
+/// Without `anchor`, the page-level note.
 #[tauri::command]
-pub fn get_note(slug: String, page: i64, state: State<'_, DbState>) -> Result<Option<NoteRecord>, String> {
+pub fn get_note(
+    slug: String,
+    page: i64,
+    anchor: Option<NoteAnchor>,
+    state: State<'_, DbState>,
+) -> Result<Option<NoteRecord>, String> {
     let conn = get_db(&state)?;
-    get_note_inner(&conn, &slug, page)
+    get_anchored_note_inner(&*note_store::open(&conn, &slug)?, &slug, page, &anchor.unwrap_or_default())
 }
 
+/// Set the page-level note of a page.
 pub fn set_note_inner(conn: &Connection, slug: &str, page: i64, content: &str, format: &str) -> Result<(), String> {
+    set_anchored_note_inner(conn, slug, page, &NoteAnchor::Page, content, format)
+}
+
+/// Set the note at `anchor`. Empty content deletes it.
+pub fn set_anchored_note_inner(
+    conn: &Connection,
+    slug: &str,
+    page: i64,
+    anchor: &NoteAnchor,
+    content: &str,
+    format: &str,
+) -> Result<(), String> {
+    note_revisions::record_revision_inner(conn, slug, page, anchor, content, false)?;
+    let anchor = anchor.key();
     if content.is_empty() {
+        note_links::unindex_note(conn, slug, page, &anchor)?;
         conn.execute(
-            "DELETE FROM notes WHERE slug = ?1 AND page = ?2",
-            rusqlite::params![slug, page],
+            "DELETE FROM notes WHERE slug = ?1 AND page = ?2 AND anchor = ?3",
+            rusqlite::params![slug, page, anchor],
         ).map_err(|e| e.to_string())?;
     } else {
         conn.execute(
-            "INSERT INTO notes (slug, page, content, format, updated_at)
-             VALUES (?1, ?2, ?3, ?4, datetime('now'))
-             ON CONFLICT(slug, page) DO UPDATE SET content = ?3, format = ?4, updated_at = datetime('now')",
-            rusqlite::params![slug, page, content, format],
+            "INSERT INTO notes (slug, page, anchor, content, format, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'))
+             ON CONFLICT(slug, page, anchor) DO UPDATE SET content = ?4, format = ?5, updated_at = datetime('now')",
+            rusqlite::params![slug, page, anchor, content, format],
         ).map_err(|e| e.to_string())?;
+        note_links::index_note(conn, slug, page, &anchor)?;
     }
     Ok(())
 }
 
+/// Without `anchor`, sets the page-level note.
 #[tauri::command]
-pub fn set_note(slug: String, page: i64, content: String, format: String, state: State<'_, DbState>) -> Result<(), String> {
+pub fn set_note(
+    slug: String,
+    page: i64,
+    content: String,
+    format: String,
+    anchor: Option<NoteAnchor>,
+    state: State<'_, DbState>,
+) -> Result<(), String> {
     let conn = get_db(&state)?;
-    set_note_inner(&conn, &slug, page, &content, &format)
+    set_anchored_note_inner(&*note_store::open(&conn, &slug)?, &slug, page, &anchor.unwrap_or_default(), &content, &format)
+}
+
+/// Every note on a page, the page-level note first.
+pub fn list_notes_for_page_inner(conn: &Connection, slug: &str, page: i64) -> Result<Vec<NoteRecord>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE slug = ?1 AND page = ?2 ORDER BY anchor != '', id"
+        ))
+        .map_err(|e| e.to_string())?;
+    let notes = stmt
+        .query_map(rusqlite::params![slug, page], row_to_note)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(notes)
+}
+
+#[tauri::command]
+pub fn list_notes_for_page(slug: String, page: i64, state: State<'_, DbState>) -> Result<Vec<NoteRecord>, String> {
+    let conn = get_db(&state)?;
+    list_notes_for_page_inner(&*note_store::open(&conn, &slug)?, &slug, page)
 }
 
 pub fn list_notes_for_book_inner(conn: &Connection, slug: &str) -> Result<Vec<NoteRecord>, String> {
     let mut stmt = conn
-        .prepare("SELECT id, slug, page, content, format, updated_at FROM notes WHERE slug = ?1 ORDER BY page")
+        .prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE slug = ?1 ORDER BY page, anchor != '', id"
+        ))
         .map_err(|e| e.to_string())?;
     let notes = stmt
         .query_map(rusqlite::params![slug], row_to_note)
@@ -312,43 +448,51 @@ pub fn list_notes_for_book_inner(conn: &Connection, slug: &str) -> Result<Vec<No
 #[tauri::command]
 pub fn list_notes_for_book(slug: String, state: State<'_, DbState>) -> Result<Vec<NoteRecord>, String> {
     let conn = get_db(&state)?;
-    list_notes_for_book_inner(&conn, &slug)
+    list_notes_for_book_inner(&*note_store::open(&conn, &slug)?, &slug)
 }
 
-pub fn delete_note_inner(conn: &Connection, slug: &str, page: i64) -> Result<(), String> {
+pub fn delete_note_inner(conn: &Connection, slug: &str, page: i64, anchor: &NoteAnchor) -> Result<(), String> {
+    note_revisions::record_revision_inner(conn, slug, page, anchor, "", true)?;
+    let anchor = anchor.key();
+    note_links::unindex_note(conn, slug, page, &anchor)?;
     conn.execute(
-        "DELETE FROM notes WHERE slug = ?1 AND page = ?2",
-        rusqlite::params![slug, page],
+        "DELETE FROM notes WHERE slug = ?1 AND page = ?2 AND anchor = ?3",
+        rusqlite::params![slug, page, anchor],
     ).map_err(|e| e.to_string())?;
     Ok(())
 }
 
+/// Without `anchor`, deletes the page-level note.
 #[tauri::command]
-pub fn delete_note(slug: String, page: i64, state: State<'_, DbState>) -> Result<(), String> {
+pub fn delete_note(slug: String, page: i64, anchor: Option<NoteAnchor>, state: State<'_, DbState>) -> Result<(), String> {
     let conn = get_db(&state)?;
-    delete_note_inner(&conn, &slug, page)
+    delete_note_inner(&*note_store::open(&conn, &slug)?, &slug, page, &anchor.unwrap_or_default())
 }
 
+/// Identical image bytes are stored once; each note reference points at the
+/// blob by content hash.
 pub fn save_note_image_inner(conn: &Connection, slug: &str, page: i64, filename: &str, data: &[u8]) -> Result<i64, String> {
-    conn.execute(
-        "INSERT INTO note_images (note_slug, note_page, filename, data)
+    let hash = note_images::put_blob(conn, data).map_err(|e| e.to_string())?;
+    conn.query_row(
+        "INSERT INTO note_images (note_slug, note_page, filename, hash)
          VALUES (?1, ?2, ?3, ?4)
-         ON CONFLICT(note_slug, note_page, filename) DO UPDATE SET data = ?4",
-        rusqlite::params![slug, page, filename, data],
-    ).map_err(|e| e.to_string())?;
-    Ok(conn.last_insert_rowid())
+         ON CONFLICT(note_slug, note_page, filename) DO UPDATE SET hash = ?4
+         RETURNING id",
+        rusqlite::params![slug, page, filename, hash],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn save_note_image(slug: String, page: i64, filename: String, data: Vec<u8>, state: State<'_, DbState>) -> Result<i64, String> {
     let conn = get_db(&state)?;
-    save_note_image_inner(&conn, &slug, page, &filename, &data)
+    save_note_image_inner(&*note_store::open(&conn, &slug)?, &slug, page, &filename, &data)
 }
 
 pub fn get_note_image_inner(conn: &Connection, id: i64) -> Result<Vec<u8>, String> {
     let data: Vec<u8> = conn
         .query_row(
-            "SELECT data FROM note_images WHERE id = ?1",
+            "SELECT b.data FROM note_images i JOIN note_image_blobs b ON b.hash = i.hash WHERE i.id = ?1",
             [id],
             |row| row.get(0),
         )
@@ -356,34 +500,41 @@ pub fn get_note_image_inner(conn: &Connection, id: i64) -> Result<Vec<u8>, Strin
     Ok(data)
 }
 
+/// `slug` is the note's book, which decides the database holding the image.
 #[tauri::command]
-pub fn get_note_image(id: i64, state: State<'_, DbState>) -> Result<tauri::ipc::Response, String> {
+pub fn get_note_image(id: i64, slug: String, state: State<'_, DbState>) -> Result<tauri::ipc::Response, String> {
     let conn = get_db(&state)?;
-    let data = get_note_image_inner(&conn, id)?;
+    let data = get_note_image_inner(&*note_store::open(&conn, &slug)?, id)?;
     Ok(tauri::ipc::Response::new(data))
 }
 
-#[tauri::command]
-pub fn export_notes_for_book(slug: String, state: State<'_, DbState>) -> Result<String, String> {
-    let conn = get_db(&state)?;
-    let mut stmt = conn
-        .prepare("SELECT page, content FROM notes WHERE slug = ?1 ORDER BY page")
-        .map_err(|e| e.to_string())?;
-    let rows: Vec<(i64, String)> = stmt
-        .query_map(rusqlite::params![slug], |row| {
-            Ok((row.get(0)?, row.get(1)?))
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
-
+/// A book's notes as Markdown, one section per page. Anchored notes follow
+/// the page's own note as subsections.
+pub fn export_notes_for_book_inner(conn: &Connection, slug: &str) -> Result<String, String> {
     let mut output = String::new();
-    for (page, content) in rows {
-        output.push_str(&format!("## Page {}\n\n{}\n\n", page, content));
+    let mut current_page = None;
+    for note in list_notes_for_book_inner(conn, slug)? {
+        if current_page != Some(note.page) {
+            output.push_str(&format!("## Page {}\n\n", note.page));
+            current_page = Some(note.page);
+        }
+        match &note.anchor {
+            NoteAnchor::Page => {}
+            NoteAnchor::Rect { .. } => output.push_str("### Region\n\n"),
+            NoteAnchor::Highlight { .. } => output.push_str("### Highlight\n\n"),
+            NoteAnchor::Snip { .. } => output.push_str("### Snip\n\n"),
+        }
+        output.push_str(&format!("{}\n\n", note.content));
     }
     Ok(output)
 }
 
+#[tauri::command]
+pub fn export_notes_for_book(slug: String, state: State<'_, DbState>) -> Result<String, String> {
+    let conn = get_db(&state)?;
+    export_notes_for_book_inner(&*note_store::open(&conn, &slug)?, &slug)
+}
+
 #[tauri::command]
 pub fn migrate_notes_from_json(json_data: String, state: State<'_, DbState>) -> Result<i64, String> {
     let map: std::collections::HashMap<String, String> =
@@ -404,12 +555,14 @@ pub fn migrate_notes_from_json(json_data: String, state: State<'_, DbState>) ->
         if is_empty {
             continue;
         }
-        conn.execute(
+        let notes = note_store::open(&conn, slug)?;
+        notes.execute(
             "INSERT INTO notes (slug, page, content, format, updated_at)
              VALUES (?1, ?2, ?3, 'html', datetime('now'))
-             ON CONFLICT(slug, page) DO UPDATE SET content = ?3, updated_at = datetime('now')",
+             ON CONFLICT(slug, page, anchor) DO UPDATE SET content = ?3, updated_at = datetime('now')",
             rusqlite::params![slug, page, content],
         ).map_err(|e| e.to_string())?;
+        note_links::index_note(&notes, slug, page, "")?;
         count += 1;
     }
     Ok(count)
@@ -559,12 +712,8 @@ pub fn open_file(file_path: String, state: State<'_, DbState>) -> Result<String,
     if !path.is_file() {
         return Err(format!("File not found: {}", file_path));
     }
-    if path
-        .extension()
-        .map(|e| e.to_ascii_lowercase() != "pdf")
-        .unwrap_or(true)
-    {
-        return Err(format!("Not a PDF file: {}", file_path));
+    if !is_supported_document(path) {
+        return Err(format!("Unsupported document type: {}", file_path));
     }
 
     let parent = path
@@ -601,8 +750,26 @@ pub fn open_file(file_path: String, state: State<'_, DbState>) -> Result<String,
         .unwrap_or_default()
         .to_string_lossy()
         .to_string();
-    let slug = format!("{}_{}", dir_id, sanitize_slug(&stem));
-    Ok(slug)
+    Ok(book_slug(dir_id, &stem))
+}
+
+/// Find the tracked directory containing `file_path` and return it together
+/// with the file's slug. Nested directories resolve to the deepest match.
+/// Returns `None` if the file does not live inside any tracked directory.
+pub fn resolve_book_for_path(conn: &Connection, file_path: &str) -> Result<Option<(Directory, String)>, String> {
+    let path = Path::new(file_path);
+    let stem = match path.file_stem() {
+        Some(s) => s.to_string_lossy().to_string(),
+        None => return Ok(None),
+    };
+    let dir = list_directories_inner(conn)?
+        .into_iter()
+        .filter(|d| path.starts_with(&d.path))
+        .max_by_key(|d| d.path.len());
+    Ok(dir.map(|d| {
+        let slug = book_slug(d.id, &stem);
+        (d, slug)
+    }))
 }
 
 #[tauri::command]
@@ -673,8 +840,12 @@ pub fn get_all_progress(dir_path: String) -> Result<HashMap<String, BookProgress
 
 #[tauri::command]
 pub fn save_progress(dir_path: String, slug: String, progress: BookProgress) -> Result<(), String> {
+    // Credit XP first: page marks are seeded from the progress saved so far
+    if let Err(e) = crate::xp_ledger::record_pages_read_inner(&dir_path, &slug, progress.current_page) {
+        log::warn!("save_progress: failed to record page XP for {}: {}", slug, e);
+    }
     crate::json_storage::update_json::<HashMap<String, BookProgress>, _>(&dir_path, "progress.json", |map| {
-        map.insert(slug, progress);
+        map.insert(slug.clone(), progress);
         Ok(())
     })
 }
@@ -714,30 +885,31 @@ pub fn set_book_status(dir_path: String, slug: String, status: String) -> Result
     if !VALID.contains(&status.as_str()) {
         return Err(format!("Invalid book status '{}'. Must be one of: {}", status, VALID.join(", ")));
     }
+    let done = status == "done";
     crate::json_storage::update_json::<HashMap<String, String>, _>(&dir_path, "book-status.json", |map| {
-        map.insert(slug, status);
+        map.insert(slug.clone(), status);
         Ok(())
-    })
+    })?;
+    if done {
+        if let Err(e) = crate::xp_ledger::record_book_finished_inner(&dir_path, &slug) {
+            log::warn!("set_book_status: failed to record XP for {}: {}", slug, e);
+        }
+    }
+    Ok(())
 }
 
 // ---------- task-006: xp commands ----------
 
+/// Snip-review XP of a book, from the XP ledger.
 #[tauri::command]
 pub fn get_xp(dir_path: String, slug: String) -> Result<i64, String> {
-    let map: HashMap<String, i64> = crate::json_storage::read_json(&dir_path, "xp.json");
-    Ok(map.get(&slug).copied().unwrap_or(0))
+    crate::xp_ledger::book_xp_inner(&dir_path, &slug, XpSource::SnipReview)
 }
 
 #[tauri::command]
 pub fn increment_xp(dir_path: String, slug: String) -> Result<i64, String> {
-    let mut new_value = 0i64;
-    crate::json_storage::update_json::<HashMap<String, i64>, _>(&dir_path, "xp.json", |map| {
-        let entry = map.entry(slug).or_insert(0);
-        *entry += 1;
-        new_value = *entry;
-        Ok(())
-    })?;
-    Ok(new_value)
+    crate::xp_ledger::record_xp_inner(&dir_path, &slug, XpSource::SnipReview, None)?;
+    get_xp(dir_path, slug)
 }
 
 // ---------- task-007: slug migration commands ----------
@@ -751,13 +923,17 @@ fn collect_db_slugs(conn: &Connection) -> Result<HashMap<String, Vec<String>>, S
         ("SELECT DISTINCT note_slug FROM note_images", "note_images"),
         ("SELECT DISTINCT book_slug FROM book_tags", "book_tags"),
     ];
-    for (sql, table) in tables {
-        let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
-        for slug in stmt.query_map([], |row| row.get::<_, String>(0))
-            .map_err(|e| e.to_string())?
-            .flatten()
-        {
-            evidence.entry(slug).or_default().push(table.into());
+    let stores = note_store::directory_stores(conn)?;
+    let dbs = std::iter::once((conn, &tables[..])).chain(stores.iter().map(|store| (store, &tables[..3])));
+    for (db, tables) in dbs {
+        for (sql, table) in tables {
+            let mut stmt = db.prepare(sql).map_err(|e| e.to_string())?;
+            for slug in stmt.query_map([], |row| row.get::<_, String>(0))
+                .map_err(|e| e.to_string())?
+                .flatten()
+            {
+                evidence.entry(slug).or_default().push(table.to_string());
+            }
         }
     }
     Ok(evidence)
@@ -864,6 +1040,19 @@ pub async fn detect_orphaned_slugs(
     Ok(candidates)
 }
 
+/// `.axiomatic/` files holding a JSON object keyed by book slug.
+pub(crate) const SLUG_KEYED_FILES: &[&str] = &[
+    "progress.json",
+    "starred.json",
+    "xp.json",
+    "book-status.json",
+    "pomodoro-xp.json",
+    "clips.json",
+    "outlines.json",
+    "history.json",
+    "page-marks.json",
+];
+
 /// Migrate slug references in SQLite tables and .axiomatic/ JSON files.
 /// The SQLite updates are wrapped in a transaction for atomicity.
 pub fn migrate_slug_inner(
@@ -872,7 +1061,23 @@ pub fn migrate_slug_inner(
     new_slug: &str,
     dir_path: &str,
 ) -> Result<(), String> {
-    // 1. SQLite transaction: update all tables atomically
+    migrate_slug_between_dirs_inner(conn, old_slug, new_slug, dir_path, dir_path)
+}
+
+/// Like `migrate_slug_inner`, but for a book that moved from `old_dir_path` to
+/// `new_dir_path`: per-book entries in `.axiomatic/` are moved into the new
+/// directory's files. Session history stays in the directory it was logged in.
+pub fn migrate_slug_between_dirs_inner(
+    conn: &Connection,
+    old_slug: &str,
+    new_slug: &str,
+    old_dir_path: &str,
+    new_dir_path: &str,
+) -> Result<(), String> {
+    // 1. Notes kept in a directory's own store follow the book
+    note_store::move_book(conn, old_slug, new_slug)?;
+
+    // 2. SQLite transaction: update all tables atomically
     conn.execute_batch("BEGIN TRANSACTION")
         .map_err(|e| e.to_string())?;
 
@@ -880,8 +1085,13 @@ pub fn migrate_slug_inner(
         for sql in [
             "UPDATE highlights SET slug = ?1 WHERE slug = ?2",
             "UPDATE notes SET slug = ?1 WHERE slug = ?2",
+            "UPDATE note_revisions SET slug = ?1 WHERE slug = ?2",
             "UPDATE note_images SET note_slug = ?1 WHERE note_slug = ?2",
             "UPDATE book_tags SET book_slug = ?1 WHERE book_slug = ?2",
+            "UPDATE structures SET slug = ?1 WHERE slug = ?2",
+            "UPDATE structure_scans SET slug = ?1 WHERE slug = ?2",
+            "UPDATE reading_log SET slug = ?1 WHERE slug = ?2",
+            "UPDATE reading_stack SET slug = ?1 WHERE slug = ?2",
         ] {
             conn.execute(sql, rusqlite::params![new_slug, old_slug])
                 .map_err(|e| e.to_string())?;
@@ -900,74 +1110,140 @@ pub fn migrate_slug_inner(
         }
     }
 
-    // 2. Update .axiomatic/ JSON files in the directory
-    let axiomatic_dir = Path::new(dir_path).join(".axiomatic");
-    if axiomatic_dir.is_dir() {
-        // Helper: rename a key in a JSON map file
-        let rename_key = |filename: &str| {
-            let path = axiomatic_dir.join(filename);
-            if let Ok(data) = std::fs::read_to_string(&path) {
-                if let Ok(mut map) = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&data) {
-                    if let Some(val) = map.remove(old_slug) {
-                        map.insert(new_slug.to_string(), val);
-                        if let Ok(json) = serde_json::to_string_pretty(&map) {
-                            if let Err(e) = std::fs::write(&path, json) {
-                                log::warn!("migrate_slug: failed to write {}: {}", path.display(), e);
-                            }
-                        }
-                    }
-                }
+    // 3. Links to the book from any note follow it
+    note_links::rename_book_links(conn, old_slug, new_slug)?;
+
+    // 4. Update .axiomatic/ JSON files
+    rename_slugs_in_json(old_dir_path, new_dir_path, |slug| (slug == old_slug).then(|| new_slug.to_string()))
+}
+
+/// Rename book slugs in a directory's `.axiomatic/` JSON files: every slug
+/// `rename` maps to a new one. Entries of renamed books move from
+/// `old_dir_path` into `new_dir_path`'s files when the two differ.
+pub(crate) fn rename_slugs_in_json(
+    old_dir_path: &str,
+    new_dir_path: &str,
+    rename: impl Fn(&str) -> Option<String>,
+) -> Result<(), String> {
+    let old_dir = Path::new(old_dir_path).join(".axiomatic");
+    if !old_dir.is_dir() {
+        return Ok(());
+    }
+    let new_dir = if old_dir_path == new_dir_path {
+        old_dir.clone()
+    } else {
+        ensure_axiomatic_dir(new_dir_path)?
+    };
+
+    type JsonMap = serde_json::Map<String, serde_json::Value>;
+
+    // Move the keys of renamed books from the old directory's JSON map files into the new one's
+    for &filename in SLUG_KEYED_FILES {
+        let Some(mut src) = read_json_file::<JsonMap>(&old_dir.join(filename)) else {
+            continue;
+        };
+        let renamed: Vec<(String, String)> =
+            src.keys().filter_map(|old| rename(old).map(|new| (old.clone(), new))).collect();
+        if renamed.is_empty() {
+            continue;
+        }
+        let mut moved = JsonMap::new();
+        for (old, new) in renamed {
+            if let Some(val) = src.remove(&old) {
+                moved.insert(new, val);
             }
+        }
+        if old_dir == new_dir {
+            src.extend(moved);
+        } else {
+            let dst_path = new_dir.join(filename);
+            let mut dst = read_json_file::<JsonMap>(&dst_path).unwrap_or_default();
+            dst.extend(moved);
+            write_json_file(&dst_path, &dst);
+        }
+        write_json_file(&old_dir.join(filename), &src);
+    }
+
+    // snips.json and the XP ledger — update slug field in array items, moving them if needed
+    for filename in ["snips.json", crate::xp_ledger::XP_EVENTS_FILE] {
+        let path = old_dir.join(filename);
+        let Some(arr) = read_json_file::<Vec<serde_json::Value>>(&path) else {
+            continue;
         };
+        let renamed = |v: &serde_json::Value| v.get("slug").and_then(|s| s.as_str()).and_then(&rename);
+        let (mut moved, mut kept): (Vec<_>, Vec<_>) = arr.into_iter().partition(|v| renamed(v).is_some());
+        for item in &mut moved {
+            if let Some(new_slug) = renamed(item) {
+                item["slug"] = serde_json::Value::String(new_slug);
+            }
+        }
+        if old_dir == new_dir {
+            kept.extend(moved);
+        } else if !moved.is_empty() {
+            let dst_path = new_dir.join(filename);
+            let mut dst = read_json_file::<Vec<serde_json::Value>>(&dst_path).unwrap_or_default();
+            dst.extend(moved);
+            write_json_file(&dst_path, &dst);
+        }
+        write_json_file(&path, &kept);
+    }
 
-        rename_key("progress.json");
-        rename_key("starred.json");
-        rename_key("xp.json");
-        rename_key("book-status.json");
-        rename_key("pomodoro-xp.json");
-
-        // snips.json — update slug field in array items
-        let snips_path = axiomatic_dir.join("snips.json");
-        if let Ok(data) = std::fs::read_to_string(&snips_path) {
-            if let Ok(mut arr) = serde_json::from_str::<Vec<serde_json::Value>>(&data) {
-                for item in arr.iter_mut().filter_map(|v| v.as_object_mut()) {
-                    if item.get("slug").and_then(|v| v.as_str()) == Some(old_slug) {
-                        item.insert("slug".into(), serde_json::Value::String(new_slug.to_string()));
-                    }
-                }
-                if let Ok(json) = serde_json::to_string_pretty(&arr) {
-                    if let Err(e) = std::fs::write(&snips_path, json) {
-                        log::warn!("migrate_slug: failed to write {}: {}", snips_path.display(), e);
+    // sessions.json — update slug in nested book entries
+    let sessions_path = old_dir.join("sessions.json");
+    if let Some(mut arr) = read_json_file::<Vec<serde_json::Value>>(&sessions_path) {
+        for session in &mut arr {
+            if let Some(books) = session.get_mut("books").and_then(|b| b.as_array_mut()) {
+                for book in books.iter_mut().filter_map(|v| v.as_object_mut()) {
+                    if let Some(new_slug) = book.get("slug").and_then(|v| v.as_str()).and_then(&rename) {
+                        book.insert("slug".into(), serde_json::Value::String(new_slug));
                     }
                 }
             }
         }
+        write_json_file(&sessions_path, &arr);
+    }
 
-        // sessions.json — update slug in nested book entries
-        let sessions_path = axiomatic_dir.join("sessions.json");
-        if let Ok(data) = std::fs::read_to_string(&sessions_path) {
-            if let Ok(mut arr) = serde_json::from_str::<Vec<serde_json::Value>>(&data) {
-                for session in &mut arr {
-                    if let Some(books) = session.get_mut("books").and_then(|b| b.as_array_mut()) {
-                        for book in books.iter_mut().filter_map(|v| v.as_object_mut()) {
-                            if book.get("slug").and_then(|v| v.as_str()) == Some(old_slug) {
-                                book.insert("slug".into(), serde_json::Value::String(new_slug.to_string()));
-                            }
-                        }
-                    }
-                }
-                if let Ok(json) = serde_json::to_string_pretty(&arr) {
-                    if let Err(e) = std::fs::write(&sessions_path, json) {
-                        log::warn!("migrate_slug: failed to write {}: {}", sessions_path.display(), e);
-                    }
-                }
+    // clips.json — update the source slug of clips cut from the renamed book
+    let mut clip_dirs = vec![old_dir.clone()];
+    if new_dir != old_dir {
+        clip_dirs.push(new_dir.clone());
+    }
+    for dir in clip_dirs {
+        let clips_path = dir.join("clips.json");
+        let Some(mut map) = read_json_file::<JsonMap>(&clips_path) else {
+            continue;
+        };
+        let mut changed = false;
+        for clip in map.values_mut().filter_map(|v| v.as_object_mut()) {
+            if let Some(new_slug) = clip.get("sourceSlug").and_then(|v| v.as_str()).and_then(&rename) {
+                clip.insert("sourceSlug".into(), serde_json::Value::String(new_slug));
+                clip.insert("sourceDirPath".into(), serde_json::Value::String(new_dir_path.to_string()));
+                changed = true;
             }
         }
+        if changed {
+            write_json_file(&clips_path, &map);
+        }
     }
 
     Ok(())
 }
 
+/// Read and parse a JSON file, returning `None` if it is missing or malformed.
+fn read_json_file<T: serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let data = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+/// Best-effort pretty JSON write used by slug migration; failures are logged.
+fn write_json_file<T: serde::Serialize>(path: &Path, value: &T) {
+    if let Ok(json) = serde_json::to_string_pretty(value) {
+        if let Err(e) = std::fs::write(path, json) {
+            log::warn!("migrate_slug: failed to write {}: {}", path.display(), e);
+        }
+    }
+}
+
 #[tauri::command]
 pub fn migrate_slug(
     old_slug: String,
@@ -1131,10 +1407,7 @@ mod tests {
             "INSERT INTO highlights (slug, page, x, y, width, height, color, text, group_id) VALUES ('old-slug', 2, 0.0, 0.0, 1.0, 1.0, 'yellow', '', 'g1')",
             [],
         ).unwrap();
-        conn.execute(
-            "INSERT INTO note_images (note_slug, note_page, filename, data) VALUES ('old-slug', 1, 'img.png', X'89504E47')",
-            [],
-        ).unwrap();
+        save_note_image_inner(&conn, "old-slug", 1, "img.png", b"\x89PNG").unwrap();
 
         // Create a tag and associate with old slug
         conn.execute("INSERT INTO tags (name, color) VALUES ('math', 'blue')", []).unwrap();
@@ -1317,7 +1590,7 @@ mod tests {
         assert_eq!(note.format, "markdown");
 
         // Delete it
-        delete_note_inner(&conn, "book-a", 5).unwrap();
+        delete_note_inner(&conn, "book-a", 5, &NoteAnchor::Page).unwrap();
 
         // Verify gone
         let note = get_note_inner(&conn, "book-a", 5).unwrap();
@@ -1350,6 +1623,49 @@ mod tests {
         assert!(get_note_inner(&conn, "book-a", 1).unwrap().is_none());
     }
 
+    #[test]
+    fn anchored_notes_share_a_page() {
+        let (_dir, conn) = test_db();
+        let rect = NoteAnchor::Rect { x: 0.1, y: 0.25, width: 0.5, height: 0.125 };
+        let snip = NoteAnchor::Snip { id: "s1".into() };
+
+        set_anchored_note_inner(&conn, "book-a", 2, &snip, "About the snip", "md").unwrap();
+        set_note_inner(&conn, "book-a", 2, "Page note", "md").unwrap();
+        set_anchored_note_inner(&conn, "book-a", 2, &rect, "About the figure", "md").unwrap();
+        set_anchored_note_inner(&conn, "book-a", 2, &rect, "About the figure, again", "md").unwrap();
+
+        let notes = list_notes_for_page_inner(&conn, "book-a", 2).unwrap();
+        let anchors: Vec<&NoteAnchor> = notes.iter().map(|n| &n.anchor).collect();
+        assert_eq!(anchors, [&NoteAnchor::Page, &snip, &rect]);
+        assert_eq!(notes[2].content, "About the figure, again");
+        assert_eq!(get_note_inner(&conn, "book-a", 2).unwrap().unwrap().content, "Page note");
+
+        assert_eq!(
+            export_notes_for_book_inner(&conn, "book-a").unwrap(),
+            "## Page 2\n\nPage note\n\n### Snip\n\nAbout the snip\n\n### Region\n\nAbout the figure, again\n\n"
+        );
+
+        // Deleting the page note leaves the anchored ones
+        delete_note_inner(&conn, "book-a", 2, &NoteAnchor::Page).unwrap();
+        assert_eq!(list_notes_for_page_inner(&conn, "book-a", 2).unwrap().len(), 2);
+        delete_note_inner(&conn, "book-a", 2, &snip).unwrap();
+        assert!(get_anchored_note_inner(&conn, "book-a", 2, &snip).unwrap().is_none());
+    }
+
+    #[test]
+    fn note_anchor_keys_round_trip() {
+        for anchor in [
+            NoteAnchor::Page,
+            NoteAnchor::Rect { x: 0.1, y: 1.0 / 3.0, width: 12.5, height: 0.0 },
+            NoteAnchor::Highlight { group_id: "a:b/c".into() },
+            NoteAnchor::Snip { id: "snip-1".into() },
+        ] {
+            assert_eq!(NoteAnchor::from_key(&anchor.key()), Some(anchor));
+        }
+        assert_eq!(NoteAnchor::from_key("rect:1,2,3"), None);
+        assert_eq!(NoteAnchor::from_key("margin:1"), None);
+    }
+
     #[test]
     fn save_and_get_note_image() {
         let (_dir, conn) = test_db();
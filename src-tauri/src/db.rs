@@ -2,6 +2,103 @@ use log::info;
 use rusqlite::{Connection, Result};
 use std::path::Path;
 
+/// Notes, note images and highlights as migration 1 created them. These
+/// tables also make up a directory's note store, which `note_store` brings up
+/// to date with the same steps as the app database: the constants below.
+pub(crate) const NOTE_TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS notes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        slug TEXT NOT NULL,
+        page INTEGER NOT NULL,
+        content TEXT NOT NULL DEFAULT '',
+        format TEXT NOT NULL DEFAULT 'html',
+        updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+        UNIQUE(slug, page)
+    );
+
+    CREATE TABLE IF NOT EXISTS note_images (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        note_slug TEXT NOT NULL,
+        note_page INTEGER NOT NULL,
+        filename TEXT NOT NULL,
+        data BLOB NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        UNIQUE(note_slug, note_page, filename)
+    );
+
+    CREATE TABLE IF NOT EXISTS highlights (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        slug TEXT NOT NULL,
+        page INTEGER NOT NULL,
+        x REAL NOT NULL,
+        y REAL NOT NULL,
+        width REAL NOT NULL,
+        height REAL NOT NULL,
+        color TEXT NOT NULL,
+        note TEXT NOT NULL DEFAULT '',
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    CREATE INDEX IF NOT EXISTS idx_highlights_slug_page ON highlights(slug, page);
+";
+
+pub(crate) const HIGHLIGHT_TEXT_AND_GROUP_ID: &str = "
+    ALTER TABLE highlights ADD COLUMN text TEXT NOT NULL DEFAULT '';
+    ALTER TABLE highlights ADD COLUMN group_id TEXT NOT NULL DEFAULT '';
+    CREATE INDEX IF NOT EXISTS idx_highlights_group_id ON highlights(group_id);
+";
+
+pub(crate) const NOTE_REVISIONS: &str = "
+    CREATE TABLE IF NOT EXISTS note_revisions (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        slug       TEXT NOT NULL,
+        page       INTEGER NOT NULL,
+        format     TEXT NOT NULL,
+        content    TEXT,
+        diff       TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    CREATE INDEX IF NOT EXISTS idx_note_revisions_note ON note_revisions(slug, page, id);
+";
+
+pub(crate) const NOTE_ANCHORS: &str = "
+    BEGIN;
+    DROP TABLE IF EXISTS notes_anchored;
+    CREATE TABLE notes_anchored (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        slug TEXT NOT NULL,
+        page INTEGER NOT NULL,
+        anchor TEXT NOT NULL DEFAULT '',
+        content TEXT NOT NULL DEFAULT '',
+        format TEXT NOT NULL DEFAULT 'html',
+        updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+        UNIQUE(slug, page, anchor)
+    );
+    INSERT INTO notes_anchored (id, slug, page, content, format, updated_at)
+        SELECT id, slug, page, content, format, updated_at FROM notes;
+    DROP TABLE notes;
+    ALTER TABLE notes_anchored RENAME TO notes;
+    ALTER TABLE note_revisions ADD COLUMN anchor TEXT NOT NULL DEFAULT '';
+    COMMIT;
+";
+
+pub(crate) const NOTE_LINKS: &str = "
+    CREATE TABLE IF NOT EXISTS note_links (
+        note_id INTEGER NOT NULL,
+        kind    TEXT NOT NULL,
+        target  TEXT NOT NULL,
+        page    INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS idx_note_links_target ON note_links(kind, target, page);
+    CREATE INDEX IF NOT EXISTS idx_note_links_note ON note_links(note_id);
+";
+
+pub(crate) const NOTE_IMAGE_BLOBS: &str = "
+    CREATE TABLE IF NOT EXISTS note_image_blobs (
+        hash TEXT PRIMARY KEY,
+        data BLOB NOT NULL
+    );
+";
+
 /// A versioned schema migration.
 struct Migration {
     version: i64,
@@ -24,26 +121,6 @@ fn migrations() -> Vec<Migration> {
                     added_at TEXT NOT NULL DEFAULT (datetime('now'))
                 );
 
-                CREATE TABLE IF NOT EXISTS notes (
-                    id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    slug TEXT NOT NULL,
-                    page INTEGER NOT NULL,
-                    content TEXT NOT NULL DEFAULT '',
-                    format TEXT NOT NULL DEFAULT 'html',
-                    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
-                    UNIQUE(slug, page)
-                );
-
-                CREATE TABLE IF NOT EXISTS note_images (
-                    id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    note_slug TEXT NOT NULL,
-                    note_page INTEGER NOT NULL,
-                    filename TEXT NOT NULL,
-                    data BLOB NOT NULL,
-                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                    UNIQUE(note_slug, note_page, filename)
-                );
-
                 CREATE TABLE IF NOT EXISTS tags (
                     id    INTEGER PRIMARY KEY AUTOINCREMENT,
                     name  TEXT NOT NULL UNIQUE,
@@ -55,30 +132,12 @@ fn migrations() -> Vec<Migration> {
                     tag_id    INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
                     UNIQUE(book_slug, tag_id)
                 );
-
-                CREATE TABLE IF NOT EXISTS highlights (
-                    id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    slug TEXT NOT NULL,
-                    page INTEGER NOT NULL,
-                    x REAL NOT NULL,
-                    y REAL NOT NULL,
-                    width REAL NOT NULL,
-                    height REAL NOT NULL,
-                    color TEXT NOT NULL,
-                    note TEXT NOT NULL DEFAULT '',
-                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
-                );
-                CREATE INDEX IF NOT EXISTS idx_highlights_slug_page ON highlights(slug, page);
             ",
         },
         Migration {
             version: 2,
             name: "highlights_text_and_group_id",
-            sql: "
-                ALTER TABLE highlights ADD COLUMN text TEXT NOT NULL DEFAULT '';
-                ALTER TABLE highlights ADD COLUMN group_id TEXT NOT NULL DEFAULT '';
-                CREATE INDEX IF NOT EXISTS idx_highlights_group_id ON highlights(group_id);
-            ",
+            sql: HIGHLIGHT_TEXT_AND_GROUP_ID,
         },
         Migration {
             version: 3,
@@ -88,6 +147,137 @@ fn migrations() -> Vec<Migration> {
                 DROP TABLE IF EXISTS snips;
             ",
         },
+        Migration {
+            version: 4,
+            name: "document_identity",
+            sql: "
+                CREATE TABLE IF NOT EXISTS documents (
+                    doc_id     TEXT PRIMARY KEY,
+                    slug       TEXT NOT NULL UNIQUE,
+                    full_path  TEXT NOT NULL,
+                    file_size  INTEGER NOT NULL,
+                    mtime      INTEGER NOT NULL,
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+                CREATE TABLE IF NOT EXISTS slug_aliases (
+                    alias      TEXT PRIMARY KEY,
+                    doc_id     TEXT NOT NULL REFERENCES documents(doc_id) ON DELETE CASCADE,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+            ",
+        },
+        Migration {
+            version: 5,
+            name: "scan_index",
+            sql: "
+                CREATE TABLE IF NOT EXISTS scan_dirs (
+                    dir_id INTEGER NOT NULL REFERENCES directories(id) ON DELETE CASCADE,
+                    path   TEXT NOT NULL,
+                    mtime  INTEGER NOT NULL,
+                    PRIMARY KEY (dir_id, path)
+                );
+
+                CREATE TABLE IF NOT EXISTS scan_index (
+                    dir_id     INTEGER NOT NULL REFERENCES directories(id) ON DELETE CASCADE,
+                    full_path  TEXT NOT NULL,
+                    parent     TEXT NOT NULL,
+                    slug       TEXT NOT NULL,
+                    title      TEXT NOT NULL,
+                    file_size  INTEGER NOT NULL,
+                    mtime      INTEGER NOT NULL,
+                    page_count INTEGER,
+                    doc_id     TEXT,
+                    PRIMARY KEY (dir_id, full_path)
+                );
+                CREATE INDEX IF NOT EXISTS idx_scan_index_full_path ON scan_index(full_path);
+            ",
+        },
+        Migration {
+            version: 6,
+            name: "structures",
+            sql: "
+                CREATE TABLE IF NOT EXISTS structures (
+                    id     INTEGER PRIMARY KEY AUTOINCREMENT,
+                    slug   TEXT NOT NULL,
+                    kind   TEXT NOT NULL,
+                    number TEXT NOT NULL,
+                    title  TEXT,
+                    page   INTEGER NOT NULL,
+                    x      REAL NOT NULL,
+                    y      REAL NOT NULL,
+                    width  REAL NOT NULL,
+                    height REAL NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_structures_slug ON structures(slug, kind, number);
+
+                CREATE TABLE IF NOT EXISTS structure_scans (
+                    slug       TEXT PRIMARY KEY,
+                    full_path  TEXT NOT NULL,
+                    indexed_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+            ",
+        },
+        Migration {
+            version: 7,
+            name: "note_revisions",
+            sql: NOTE_REVISIONS,
+        },
+        Migration {
+            version: 8,
+            name: "note_anchors",
+            sql: NOTE_ANCHORS,
+        },
+        Migration {
+            version: 9,
+            name: "note_links",
+            sql: NOTE_LINKS,
+        },
+        Migration {
+            version: 10,
+            name: "note_image_blobs",
+            sql: NOTE_IMAGE_BLOBS,
+        },
+        Migration {
+            version: 11,
+            name: "document_copies",
+            sql: "
+                CREATE TABLE IF NOT EXISTS document_copies (
+                    full_path  TEXT PRIMARY KEY,
+                    slug       TEXT NOT NULL,
+                    file_size  INTEGER NOT NULL,
+                    mtime      INTEGER NOT NULL,
+                    doc_id     TEXT NOT NULL
+                );
+            ",
+        },
+        Migration {
+            version: 12,
+            name: "reading_history",
+            sql: "
+                CREATE TABLE IF NOT EXISTS reading_log (
+                    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+                    slug          TEXT NOT NULL,
+                    page          INTEGER NOT NULL,
+                    scroll_offset REAL NOT NULL DEFAULT 0,
+                    zoom          REAL NOT NULL DEFAULT 1,
+                    timestamp     TEXT NOT NULL,
+                    jump          INTEGER NOT NULL DEFAULT 0
+                );
+                CREATE INDEX IF NOT EXISTS idx_reading_log_slug ON reading_log(slug, id);
+
+                CREATE TABLE IF NOT EXISTS reading_stack (
+                    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+                    slug          TEXT NOT NULL,
+                    forward       INTEGER NOT NULL,
+                    page          INTEGER NOT NULL,
+                    scroll_offset REAL NOT NULL DEFAULT 0,
+                    zoom          REAL NOT NULL DEFAULT 1,
+                    timestamp     TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_reading_stack_slug ON reading_stack(slug, forward, id);
+            ",
+        },
     ]
 }
 
@@ -103,7 +293,7 @@ fn ensure_migrations_table(conn: &Connection) -> Result<()> {
 }
 
 /// Get the highest migration version that has been applied, or 0 if none.
-fn get_current_version(conn: &Connection) -> Result<i64> {
+pub(crate) fn get_current_version(conn: &Connection) -> Result<i64> {
     conn.query_row(
         "SELECT COALESCE(MAX(version), 0) FROM migrations",
         [],
@@ -120,31 +310,48 @@ fn record_migration(conn: &Connection, version: i64, name: &str) -> Result<()> {
     Ok(())
 }
 
-/// Apply a single migration's SQL. For Migration 2 (ALTER TABLE), individual
-/// statements that fail due to duplicate columns are tolerated (the column
-/// already exists from a prior ad-hoc run).
-fn apply_migration(conn: &Connection, migration: &Migration) -> Result<()> {
-    if migration.version == 2 {
-        // Migration 2 must be idempotent: ALTER TABLE ADD COLUMN fails if the
-        // column already exists (from the old ad-hoc schema). Execute each
-        // statement individually, tolerating "duplicate column" errors.
-        for stmt in migration.sql.split(';') {
-            let trimmed = stmt.trim();
-            if trimmed.is_empty() {
+/// Run `sql`, skipping `ADD COLUMN` statements whose column already exists
+/// (from the old ad-hoc schema, or a store that was created with it).
+pub(crate) fn add_columns(conn: &Connection, sql: &str) -> Result<()> {
+    for stmt in sql.split(';') {
+        let trimmed = stmt.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let result = conn.execute_batch(trimmed);
+        if let Err(ref e) = result {
+            let msg = e.to_string();
+            // Tolerate "duplicate column name" errors for idempotency
+            if msg.contains("duplicate column name") {
                 continue;
             }
-            let result = conn.execute_batch(trimmed);
-            if let Err(ref e) = result {
-                let msg = e.to_string();
-                // Tolerate "duplicate column name" errors for idempotency
-                if msg.contains("duplicate column name") {
-                    continue;
-                }
-                // Any other error is a real failure
-                result?;
-            }
+            // Any other error is a real failure
+            result?;
         }
-        Ok(())
+    }
+    Ok(())
+}
+
+/// Apply a single migration's SQL. Migration 1 also creates the note tables,
+/// which it shares with directory note stores. For Migration 2 (ALTER TABLE),
+/// individual statements that fail due to duplicate columns are tolerated
+/// (the column already exists from a prior ad-hoc run). Migration 9 also
+/// indexes the links in existing notes, and migration 10 moves image bytes
+/// into blobs.
+fn apply_migration(conn: &Connection, migration: &Migration) -> Result<()> {
+    if migration.version == 1 {
+        conn.execute_batch(migration.sql)?;
+        conn.execute_batch(NOTE_TABLES)
+    } else if migration.version == 2 {
+        add_columns(conn, migration.sql)
+    } else if migration.version == 9 {
+        // The links of existing notes are parsed out of their content
+        conn.execute_batch(migration.sql)?;
+        crate::note_links::reindex(conn)
+    } else if migration.version == 10 {
+        // Image bytes move out of note_images, deduplicated by content hash
+        conn.execute_batch(migration.sql)?;
+        crate::note_images::split_blobs(conn)
     } else {
         conn.execute_batch(migration.sql)
     }
@@ -232,6 +439,14 @@ mod tests {
         assert!(tables.contains("book_tags"), "missing book_tags");
         assert!(tables.contains("highlights"), "missing highlights");
         assert!(tables.contains("migrations"), "missing migrations");
+        assert!(tables.contains("documents"), "missing documents");
+        assert!(tables.contains("slug_aliases"), "missing slug_aliases");
+        assert!(tables.contains("scan_index"), "missing scan_index");
+        assert!(tables.contains("scan_dirs"), "missing scan_dirs");
+        assert!(tables.contains("structures"), "missing structures");
+        assert!(tables.contains("structure_scans"), "missing structure_scans");
+        assert!(tables.contains("reading_log"), "missing reading_log");
+        assert!(tables.contains("reading_stack"), "missing reading_stack");
 
         // Vestigial tables must NOT exist
         assert!(!tables.contains("bookmarks"), "bookmarks should not exist");
@@ -245,13 +460,13 @@ mod tests {
         let db_path = dir.path().join("test.db");
         let conn = init_db(&db_path).unwrap();
 
-        // All 3 migrations should be recorded
+        // All 12 migrations should be recorded
         let count: i64 = conn
             .query_row("SELECT COUNT(*) FROM migrations", [], |r| r.get(0))
             .unwrap();
-        assert_eq!(count, 3);
+        assert_eq!(count, 12);
 
-        // Versions are 1, 2, 3
+        // Versions are 1..=12
         let mut stmt = conn
             .prepare("SELECT version, name FROM migrations ORDER BY version")
             .unwrap();
@@ -260,10 +475,19 @@ mod tests {
             .unwrap()
             .filter_map(|r| r.ok())
             .collect();
-        assert_eq!(rows.len(), 3);
+        assert_eq!(rows.len(), 12);
         assert_eq!(rows[0], (1, "initial_schema".to_string()));
         assert_eq!(rows[1], (2, "highlights_text_and_group_id".to_string()));
         assert_eq!(rows[2], (3, "drop_bookmarks_and_snips".to_string()));
+        assert_eq!(rows[3], (4, "document_identity".to_string()));
+        assert_eq!(rows[4], (5, "scan_index".to_string()));
+        assert_eq!(rows[5], (6, "structures".to_string()));
+        assert_eq!(rows[6], (7, "note_revisions".to_string()));
+        assert_eq!(rows[7], (8, "note_anchors".to_string()));
+        assert_eq!(rows[8], (9, "note_links".to_string()));
+        assert_eq!(rows[9], (10, "note_image_blobs".to_string()));
+        assert_eq!(rows[10], (11, "document_copies".to_string()));
+        assert_eq!(rows[11], (12, "reading_history".to_string()));
 
         // Each has a non-empty applied_at
         let empty_count: i64 = conn
@@ -309,11 +533,11 @@ mod tests {
 
         // Timestamps must be identical (no re-run)
         assert_eq!(ts1, ts2);
-        // Still exactly 3 migrations
+        // Still exactly 12 migrations
         let count: i64 = conn2
             .query_row("SELECT COUNT(*) FROM migrations", [], |r| r.get(0))
             .unwrap();
-        assert_eq!(count, 3);
+        assert_eq!(count, 12);
     }
 
     /// AC-101: Bookmarks table is dropped by migration. Highlight bookmarks
@@ -397,23 +621,23 @@ mod tests {
         // Run init_db to get a fully migrated DB
         let conn = init_db(&db_path).unwrap();
 
-        // Verify all 3 are applied
+        // Verify all 12 are applied
         let count: i64 = conn
             .query_row("SELECT COUNT(*) FROM migrations", [], |r| r.get(0))
             .unwrap();
-        assert_eq!(count, 3);
+        assert_eq!(count, 12);
 
         // Simulate adding a bad migration by manually calling run logic:
-        // Insert a fake version 4 that would fail
+        // Insert a fake version 13 that would fail
         // First, verify that applying invalid SQL to the connection fails
         let result = conn.execute_batch("THIS IS INVALID SQL");
         assert!(result.is_err());
 
-        // The 3 existing migrations remain
+        // The 12 existing migrations remain
         let count_after: i64 = conn
             .query_row("SELECT COUNT(*) FROM migrations", [], |r| r.get(0))
             .unwrap();
-        assert_eq!(count_after, 3);
+        assert_eq!(count_after, 12);
     }
 
     /// AC-080 + AC-103: Highlights table has text and group_id columns after migration 2.
@@ -537,6 +761,11 @@ mod tests {
             [],
         )
         .unwrap();
+        conn.execute(
+            "INSERT INTO notes (slug, page, content) VALUES ('book', 2, 'page note on [[other#p4]]')",
+            [],
+        )
+        .unwrap();
         drop(conn);
 
         // Now run init_db — should upgrade cleanly
@@ -558,10 +787,27 @@ mod tests {
             .unwrap();
         assert_eq!(text, "hi");
 
-        // All 3 migrations recorded
+        // Notes moved to the page anchor; a page can now hold several
+        let anchor: String = conn
+            .query_row("SELECT anchor FROM notes WHERE slug = 'book' AND page = 2", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(anchor, "");
+        conn.execute(
+            "INSERT INTO notes (slug, page, anchor, content) VALUES ('book', 2, 'snip:s1', 'snip note')",
+            [],
+        )
+        .unwrap();
+
+        // Links in existing notes were indexed
+        let link: (String, String, i64) = conn
+            .query_row("SELECT kind, target, page FROM note_links", [], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap();
+        assert_eq!(link, ("page".to_string(), "other".to_string(), 4));
+
+        // All 12 migrations recorded
         let count: i64 = conn
             .query_row("SELECT COUNT(*) FROM migrations", [], |r| r.get(0))
             .unwrap();
-        assert_eq!(count, 3);
+        assert_eq!(count, 12);
     }
 }
@@ -0,0 +1,437 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::process::Command;
+
+use image::{DynamicImage, ImageFormat};
+use lru::LruCache;
+
+use crate::doc_backend::{check_page, page_dimension, word_span, DocumentBackend};
+use crate::pdf_models::{NormalizedRect, OutlineEntry, PageDimension, PageTextLayer};
+
+/// DjVu stores pixel sizes only; djvused does not report the resolution, so
+/// page sizes in points assume the usual 300 dpi of scanned books. Only the
+/// relative size of pages matters to the viewer.
+const ASSUMED_DPI: f32 = 300.0;
+/// Rendered pages kept per document, so re-rendering a page at the same
+/// width (another pixel ratio, recognition) doesn't start `ddjvu` again.
+const RENDERED_PAGES: usize = 8;
+
+/// DjVu documents, served by the djvulibre command-line tools
+/// (`djvused`, `ddjvu`, `djvutxt`).
+pub struct DjvuBackend {
+    path: String,
+    /// Pixel width and height of each page, rotation applied.
+    pages: Vec<(u32, u32)>,
+    /// Pages rendered recently, by page and pixel width.
+    rendered: RefCell<LruCache<(u32, u32), DynamicImage>>,
+    /// Text layers read so far; layout analysis reads each page's
+    /// neighbours as well, so most are asked for several times.
+    text_layers: RefCell<HashMap<u32, PageTextLayer>>,
+}
+
+impl DjvuBackend {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let count: u32 = djvused(path, "n")?
+            .trim()
+            .parse()
+            .map_err(|_| format!("Failed to read page count of '{}'", path))?;
+        let script: String = (1..=count).map(|p| format!("select {}; size; ", p)).collect();
+        let pages = parse_sizes(&djvused(path, &script)?);
+        if pages.len() != count as usize {
+            return Err(format!(
+                "Failed to read page sizes of '{}' ({} of {} pages)",
+                path,
+                pages.len(),
+                count
+            ));
+        }
+        Ok(Self {
+            path: path.to_string(),
+            pages,
+            rendered: RefCell::new(LruCache::new(NonZeroUsize::new(RENDERED_PAGES).unwrap())),
+            text_layers: RefCell::new(HashMap::new()),
+        })
+    }
+
+    fn page_size(&self, page: u32) -> Result<(u32, u32), String> {
+        check_page(page, self.page_count())?;
+        Ok(self.pages[page as usize - 1])
+    }
+
+    /// Map of component file names to page numbers, for outline entries that
+    /// point at a page by name rather than number.
+    fn page_names(&self) -> Result<HashMap<String, u32>, String> {
+        Ok(parse_page_names(&djvused(&self.path, "ls")?))
+    }
+}
+
+impl DocumentBackend for DjvuBackend {
+    fn page_count(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    fn page_sizes(&self) -> Result<Vec<PageDimension>, String> {
+        let scale = 72.0 / ASSUMED_DPI;
+        Ok(self
+            .pages
+            .iter()
+            .map(|&(w, h)| page_dimension(w as f32 * scale, h as f32 * scale))
+            .collect())
+    }
+
+    fn render_page(&self, page: u32, target_width: i32) -> Result<DynamicImage, String> {
+        let (w, h) = self.page_size(page)?;
+        let width = target_width.max(1) as u32;
+        if let Some(image) = self.rendered.borrow_mut().get(&(page, width)) {
+            return Ok(image.clone());
+        }
+        let height = ((width as f32 * h as f32 / w.max(1) as f32).round() as u32).max(1);
+        let out = Command::new("ddjvu")
+            .arg("-format=pnm")
+            .arg(format!("-page={}", page))
+            .arg(format!("-size={}x{}", width, height))
+            .arg(&self.path)
+            .arg("-")
+            .output()
+            .map_err(|e| format!("DjVu rendering requires ddjvu (djvulibre): {}", e))?;
+        if !out.status.success() {
+            return Err(format!(
+                "Failed to render page {}: {}",
+                page,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ));
+        }
+        let image = image::load_from_memory_with_format(&out.stdout, ImageFormat::Pnm)
+            .map_err(|e| format!("Failed to decode page {}: {:?}", page, e))?;
+        self.rendered.borrow_mut().put((page, width), image.clone());
+        Ok(image)
+    }
+
+    fn outline(&self) -> Result<Vec<OutlineEntry>, String> {
+        let out = djvused(&self.path, "print-outline")?;
+        let Some(Sexp::List(items)) = parse_sexp(&out).into_iter().next() else {
+            return Ok(Vec::new());
+        };
+        let names = if out.contains("\"#") && items.iter().any(has_named_dest) {
+            self.page_names()?
+        } else {
+            HashMap::new()
+        };
+        Ok(items.iter().skip(1).filter_map(|i| outline_entry(i, &names)).collect())
+    }
+
+    fn page_text(&self, page: u32) -> Result<String, String> {
+        check_page(page, self.page_count())?;
+        djvused(&self.path, &format!("select {}; print-pure-txt", page))
+    }
+
+    fn all_page_text(&self) -> Result<Vec<String>, String> {
+        // djvutxt separates pages with form feeds; fall back to per-page
+        // extraction if that does not line up with the page count.
+        if let Ok(out) = Command::new("djvutxt").arg(&self.path).output() {
+            if out.status.success() {
+                let text = String::from_utf8_lossy(&out.stdout);
+                let mut pages: Vec<String> = text.split('\u{c}').map(str::to_string).collect();
+                if pages.last().map(|p| p.trim().is_empty()).unwrap_or(false)
+                    && pages.len() == self.pages.len() + 1
+                {
+                    pages.pop();
+                }
+                if pages.len() == self.pages.len() {
+                    return Ok(pages);
+                }
+            }
+        }
+        (1..=self.page_count()).map(|p| self.page_text(p)).collect()
+    }
+
+    fn text_layer(&self, page: u32) -> Result<PageTextLayer, String> {
+        if let Some(layer) = self.text_layers.borrow().get(&page) {
+            return Ok(layer.clone());
+        }
+        let (w, h) = self.page_size(page)?;
+        let out = djvused(&self.path, &format!("select {}; print-txt", page))?;
+        let mut spans = Vec::new();
+        if let Some(zone) = parse_sexp(&out).first() {
+            collect_words(zone, w as f32, h as f32, &mut spans);
+        }
+        let layer = PageTextLayer { page, spans };
+        self.text_layers.borrow_mut().insert(page, layer.clone());
+        Ok(layer)
+    }
+}
+
+fn djvused(path: &str, script: &str) -> Result<String, String> {
+    let out = Command::new("djvused")
+        .arg("-u")
+        .arg("-e")
+        .arg(script)
+        .arg(path)
+        .output()
+        .map_err(|e| format!("DjVu support requires djvused (djvulibre): {}", e))?;
+    if !out.status.success() {
+        return Err(format!(
+            "Failed to read DjVu '{}': {}",
+            path,
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Parse `size` output lines (`width=W height=H [rotation=R]`).
+fn parse_sizes(out: &str) -> Vec<(u32, u32)> {
+    out.lines()
+        .filter_map(|line| {
+            let mut w = None;
+            let mut h = None;
+            let mut rotation = 0;
+            for token in line.split_whitespace() {
+                match token.split_once('=') {
+                    Some(("width", v)) => w = v.parse().ok(),
+                    Some(("height", v)) => h = v.parse().ok(),
+                    Some(("rotation", v)) => rotation = v.parse().unwrap_or(0),
+                    _ => {}
+                }
+            }
+            let (w, h) = (w?, h?);
+            Some(if rotation % 180 == 90 { (h, w) } else { (w, h) })
+        })
+        .collect()
+}
+
+/// Parse `ls` output, where page components look like `  3 P  1234 p0003.djvu`.
+fn parse_page_names(out: &str) -> HashMap<String, u32> {
+    out.lines()
+        .filter_map(|line| {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.as_slice() {
+                [num, "P", _size, name, ..] => Some((name.to_string(), num.parse().ok()?)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// A parsed djvused S-expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Sexp {
+    Atom(String),
+    Str(String),
+    List(Vec<Sexp>),
+}
+
+fn parse_sexp(input: &str) -> Vec<Sexp> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut pos = 0;
+    let mut out = Vec::new();
+    while let Some(e) = parse_one(&chars, &mut pos) {
+        out.push(e);
+    }
+    out
+}
+
+fn parse_one(chars: &[char], pos: &mut usize) -> Option<Sexp> {
+    while *pos < chars.len() && chars[*pos].is_whitespace() {
+        *pos += 1;
+    }
+    match chars.get(*pos)? {
+        '(' => {
+            *pos += 1;
+            let mut items = Vec::new();
+            loop {
+                while *pos < chars.len() && chars[*pos].is_whitespace() {
+                    *pos += 1;
+                }
+                match chars.get(*pos) {
+                    None => break,
+                    Some(')') => {
+                        *pos += 1;
+                        break;
+                    }
+                    _ => items.push(parse_one(chars, pos)?),
+                }
+            }
+            Some(Sexp::List(items))
+        }
+        ')' => {
+            *pos += 1;
+            parse_one(chars, pos)
+        }
+        '"' => {
+            *pos += 1;
+            // Octal escapes are raw UTF-8 bytes, so collect bytes and decode once
+            let mut bytes = Vec::new();
+            while let Some(&c) = chars.get(*pos) {
+                *pos += 1;
+                match c {
+                    '"' => break,
+                    '\\' => {
+                        let Some(&e) = chars.get(*pos) else { break };
+                        *pos += 1;
+                        match e {
+                            'n' => bytes.push(b'\n'),
+                            't' => bytes.push(b'\t'),
+                            '0'..='7' => {
+                                let mut v = e.to_digit(8).unwrap_or(0);
+                                for _ in 0..2 {
+                                    match chars.get(*pos).and_then(|c| c.to_digit(8)) {
+                                        Some(d) => {
+                                            v = v * 8 + d;
+                                            *pos += 1;
+                                        }
+                                        None => break,
+                                    }
+                                }
+                                bytes.push(v as u8);
+                            }
+                            other => bytes.extend(other.to_string().as_bytes()),
+                        }
+                    }
+                    c => bytes.extend(c.to_string().as_bytes()),
+                }
+            }
+            Some(Sexp::Str(String::from_utf8_lossy(&bytes).into_owned()))
+        }
+        _ => {
+            let start = *pos;
+            while *pos < chars.len() && !chars[*pos].is_whitespace() && !matches!(chars[*pos], '(' | ')' | '"') {
+                *pos += 1;
+            }
+            Some(Sexp::Atom(chars[start..*pos].iter().collect()))
+        }
+    }
+}
+
+fn has_named_dest(item: &Sexp) -> bool {
+    let Sexp::List(parts) = item else { return false };
+    let named = matches!(parts.get(1), Some(Sexp::Str(d)) if d.starts_with('#') && d[1..].parse::<u32>().is_err());
+    named || parts.iter().skip(2).any(has_named_dest)
+}
+
+/// Outline entries are `("title" "#dest" children...)`; `#dest` is a page
+/// number or a page component name.
+fn outline_entry(item: &Sexp, names: &HashMap<String, u32>) -> Option<OutlineEntry> {
+    let Sexp::List(parts) = item else { return None };
+    let Some(Sexp::Str(title)) = parts.first() else { return None };
+    let page = match parts.get(1) {
+        Some(Sexp::Str(dest)) => dest.strip_prefix('#').and_then(|d| {
+            d.parse::<u32>().ok().or_else(|| names.get(d).copied())
+        }),
+        _ => None,
+    };
+    Some(OutlineEntry {
+        title: title.clone(),
+        page,
+        view: None,
+        children: parts.iter().skip(2).filter_map(|c| outline_entry(c, names)).collect(),
+    })
+}
+
+/// Walk a hidden-text zone tree `(type x0 y0 x1 y1 children... | "text")` and
+/// emit one span per innermost zone carrying text. DjVu coordinates are in
+/// pixels with the origin at the bottom left.
+fn collect_words(zone: &Sexp, page_w: f32, page_h: f32, spans: &mut Vec<crate::pdf_models::TextSpan>) {
+    let Sexp::List(parts) = zone else { return };
+    let coords: Vec<f32> = parts
+        .iter()
+        .skip(1)
+        .take(4)
+        .filter_map(|p| match p {
+            Sexp::Atom(a) => a.parse().ok(),
+            _ => None,
+        })
+        .collect();
+    if coords.len() != 4 || page_w <= 0.0 || page_h <= 0.0 {
+        return;
+    }
+    let rest = &parts[5..];
+    if let [Sexp::Str(text)] = rest {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        let (x0, y0, x1, y1) = (coords[0], coords[1], coords[2], coords[3]);
+        let rect = NormalizedRect {
+            x: x0 / page_w,
+            y: 1.0 - y1 / page_h,
+            width: (x1 - x0) / page_w,
+            height: (y1 - y0) / page_h,
+        };
+        spans.push(word_span(text, rect));
+        return;
+    }
+    for child in rest {
+        collect_words(child, page_w, page_h, spans);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_with_rotation() {
+        let out = "width=2550 height=3300\nwidth=2550 height=3300 rotation=90\n";
+        assert_eq!(parse_sizes(out), vec![(2550, 3300), (3300, 2550)]);
+    }
+
+    #[test]
+    fn page_names_from_ls() {
+        let out = "    1 P     15034 p0001.djvu\n    2 P     14873 p0002.djvu\n      I      1200 shared.djbz\n";
+        let names = parse_page_names(out);
+        assert_eq!(names.get("p0002.djvu"), Some(&2));
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn sexp_strings_and_escapes() {
+        let parsed = parse_sexp(r#"(word 1 2 3 4 "a\"b\303\251")"#);
+        let Sexp::List(parts) = &parsed[0] else { panic!() };
+        assert_eq!(parts[0], Sexp::Atom("word".into()));
+        assert_eq!(parts[5], Sexp::Str("a\"bé".into()));
+    }
+
+    #[test]
+    fn outline_numeric_and_named_dests() {
+        let out = r##"(bookmarks
+ ("Chapter 1" "#5"
+  ("Section 1.1" "#p0007.djvu") )
+ ("Index" "" ) )"##;
+        let Sexp::List(items) = &parse_sexp(out)[0] else { panic!() };
+        assert!(items.iter().any(has_named_dest));
+        let names = HashMap::from([("p0007.djvu".to_string(), 7)]);
+        let entries: Vec<OutlineEntry> = items.iter().skip(1).filter_map(|i| outline_entry(i, &names)).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "Chapter 1");
+        assert_eq!(entries[0].page, Some(5));
+        assert_eq!(entries[0].children[0].page, Some(7));
+        assert_eq!(entries[1].page, None);
+    }
+
+    #[test]
+    fn text_zones_become_spans() {
+        let out = r#"(page 0 0 1000 2000
+ (line 100 1800 400 1900
+  (word 100 1800 200 1900 "Lemma")
+  (word 250 1800 400 1900 "3.1")))"#;
+        let mut spans = Vec::new();
+        collect_words(&parse_sexp(out)[0], 1000.0, 2000.0, &mut spans);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].text, "Lemma");
+        assert!((spans[0].rect.x - 0.1).abs() < 1e-6);
+        assert!((spans[0].rect.y - 0.05).abs() < 1e-6);
+        assert!((spans[0].rect.height - 0.05).abs() < 1e-6);
+        assert_eq!(spans[1].char_rects.len(), 3);
+    }
+
+    #[test]
+    fn line_level_text_without_words() {
+        let out = r#"(page 0 0 100 100 (line 0 0 100 10 "whole line"))"#;
+        let mut spans = Vec::new();
+        collect_words(&parse_sexp(out)[0], 100.0, 100.0, &mut spans);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].text, "whole line");
+    }
+}
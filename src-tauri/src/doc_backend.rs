@@ -0,0 +1,205 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use image::DynamicImage;
+use pdfium_render::prelude::PdfDocument;
+
+use crate::math_text::PageGlyphs;
+use crate::pdf_models::{
+    Destination, LinkAnnotation, NormalizedRect, OutlineEntry, PageDimension, PageTextLayer, TextSpan,
+};
+
+/// File extensions the engine has a backend for. DjVu and EPUB also need
+/// their command-line tools; see `DocumentKind::is_available`.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["pdf", "djvu", "djv", "epub"];
+
+/// Document formats, each served by its own `DocumentBackend`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    Pdf,
+    Djvu,
+    Epub,
+}
+
+impl DocumentKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "pdf" => Some(Self::Pdf),
+            "djvu" | "djv" => Some(Self::Djvu),
+            "epub" => Some(Self::Epub),
+            _ => None,
+        }
+    }
+
+    /// Command-line tools the format is served through; PDF needs none.
+    pub fn required_tools(self) -> &'static [&'static str] {
+        match self {
+            Self::Pdf => &[],
+            Self::Djvu => &["djvused", "ddjvu"],
+            Self::Epub => &["mutool"],
+        }
+    }
+
+    /// Whether this machine has the tools to open the format. Probed once
+    /// per process; a format without them is treated as unsupported.
+    pub fn is_available(self) -> bool {
+        static AVAILABLE: OnceLock<[bool; 3]> = OnceLock::new();
+        let available = AVAILABLE.get_or_init(|| {
+            [Self::Pdf, Self::Djvu, Self::Epub].map(|kind| kind.required_tools().iter().all(|t| on_path(t)))
+        });
+        available[self as usize]
+    }
+}
+
+/// Whether an executable named `tool` is in one of the `PATH` directories.
+fn on_path(tool: &str) -> bool {
+    let Some(paths) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&paths).any(|dir| {
+        let candidate = dir.join(tool);
+        candidate.is_file() || candidate.with_extension("exe").is_file()
+    })
+}
+
+/// True if `path` is a document this machine can open: a known extension
+/// whose tools are installed.
+pub fn is_supported_document(path: &Path) -> bool {
+    DocumentKind::from_path(path).is_some_and(DocumentKind::is_available)
+}
+
+/// One open document. `PdfEngine` keeps a backend per path and routes every
+/// `PdfRequest` through this trait, so rendering, caching and search behave
+/// the same for all formats. Pages are 1-indexed throughout.
+pub trait DocumentBackend {
+    fn page_count(&self) -> u32;
+
+    fn page_sizes(&self) -> Result<Vec<PageDimension>, String>;
+
+    fn title(&self) -> Option<String> {
+        None
+    }
+
+    /// Render a page scaled to `target_width` pixels.
+    fn render_page(&self, page: u32, target_width: i32) -> Result<DynamicImage, String>;
+
+    fn outline(&self) -> Result<Vec<OutlineEntry>, String>;
+
+    fn page_links(&self, _page: u32) -> Result<Vec<LinkAnnotation>, String> {
+        Ok(Vec::new())
+    }
+
+    /// Resolve a named destination (`#name` fragments, `/Dests`). `None` if
+    /// the document has no such name or the format has no named destinations.
+    fn named_destination(&self, _name: &str) -> Result<Option<Destination>, String> {
+        Ok(None)
+    }
+
+    /// Resolve the destination of the `index`-th remote link on a page by
+    /// opening the file it points to. `None` if the file or destination
+    /// can't be found.
+    fn remote_destination(&self, _page: u32, _index: u32) -> Result<Option<Destination>, String> {
+        Ok(None)
+    }
+
+    fn page_text(&self, page: u32) -> Result<String, String>;
+
+    /// Text of every page in order. Backends where per-page extraction is
+    /// expensive (external tools) override this with a single pass.
+    fn all_page_text(&self) -> Result<Vec<String>, String> {
+        (1..=self.page_count()).map(|p| self.page_text(p)).collect()
+    }
+
+    fn text_layer(&self, page: u32) -> Result<PageTextLayer, String>;
+
+    /// Positioned glyphs with font sizes, for math-aware extraction. `None`
+    /// when the format has no glyph-level geometry (plain text is used).
+    fn glyphs(&self, _page: u32) -> Result<Option<PageGlyphs>, String> {
+        Ok(None)
+    }
+
+    /// The underlying PDF, for operations that only exist for PDF
+    /// (clipping). EPUB is served from a converted PDF and exposes it too.
+    fn as_pdf(&self) -> Option<&PdfDocument<'static>> {
+        None
+    }
+}
+
+/// Check a 1-indexed page number against the page count.
+pub fn check_page(page: u32, page_count: u32) -> Result<(), String> {
+    if page == 0 {
+        return Err("Page number must be >= 1".to_string());
+    }
+    if page > page_count {
+        return Err(format!("Page {} out of range (document has {} pages)", page, page_count));
+    }
+    Ok(())
+}
+
+pub fn page_dimension(width_pts: f32, height_pts: f32) -> PageDimension {
+    PageDimension {
+        width_pts,
+        height_pts,
+        aspect_ratio: if height_pts > 0.0 { width_pts / height_pts } else { 1.0 },
+    }
+}
+
+/// Build a text span for a word whose per-character boxes are unknown,
+/// splitting the word box evenly between its characters.
+pub fn word_span(text: &str, rect: NormalizedRect) -> TextSpan {
+    let n = text.chars().count().max(1) as f32;
+    let char_width = rect.width / n;
+    let char_rects = (0..text.chars().count())
+        .map(|i| NormalizedRect {
+            x: rect.x + char_width * i as f32,
+            y: rect.y,
+            width: char_width,
+            height: rect.height,
+        })
+        .collect();
+    TextSpan {
+        text: text.to_string(),
+        rect,
+        char_rects,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_from_extension() {
+        assert_eq!(DocumentKind::from_path(Path::new("/a/b.PDF")), Some(DocumentKind::Pdf));
+        assert_eq!(DocumentKind::from_path(Path::new("scan.djvu")), Some(DocumentKind::Djvu));
+        assert_eq!(DocumentKind::from_path(Path::new("scan.djv")), Some(DocumentKind::Djvu));
+        assert_eq!(DocumentKind::from_path(Path::new("book.epub")), Some(DocumentKind::Epub));
+        assert_eq!(DocumentKind::from_path(Path::new("notes.txt")), None);
+        assert_eq!(DocumentKind::from_path(Path::new("README")), None);
+        assert!(is_supported_document(Path::new("x.PDF")));
+        assert_eq!(is_supported_document(Path::new("x.Epub")), DocumentKind::Epub.is_available());
+        assert!(!is_supported_document(Path::new("notes.txt")));
+        assert!(!on_path("axiomatic-no-such-tool"));
+        for ext in SUPPORTED_EXTENSIONS {
+            let path = format!("doc.{}", ext);
+            assert!(DocumentKind::from_path(Path::new(&path)).is_some(), "{} has no backend", ext);
+        }
+    }
+
+    #[test]
+    fn check_page_bounds() {
+        assert!(check_page(0, 3).is_err());
+        assert!(check_page(1, 3).is_ok());
+        assert!(check_page(3, 3).is_ok());
+        assert!(check_page(4, 3).is_err());
+    }
+
+    #[test]
+    fn word_span_splits_chars_evenly() {
+        let span = word_span("abcd", NormalizedRect { x: 0.1, y: 0.2, width: 0.4, height: 0.05 });
+        assert_eq!(span.char_rects.len(), 4);
+        assert!((span.char_rects[2].x - 0.3).abs() < 1e-6);
+        assert!((span.char_rects[3].width - 0.1).abs() < 1e-6);
+    }
+}
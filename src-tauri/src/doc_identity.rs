@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use rusqlite::{Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use tauri::State;
+
+use crate::commands::{get_db, migrate_slug_between_dirs_inner, DbState};
+use crate::models::{SlugMigration, Textbook};
+
+/// Bytes read from each end of the file when looking for the trailer `/ID`
+/// and when falling back to a content hash.
+const PROBE_BYTES: u64 = 64 * 1024;
+
+/// Compute a stable identity for a document file.
+///
+/// Prefers the first element of the PDF trailer `/ID` array, which the spec
+/// defines as permanent across revisions, so incremental saves keep the
+/// identity. Files without an `/ID` fall back to a SHA-256 of the first
+/// 64 KiB plus the file size.
+pub fn compute_doc_id(path: &Path) -> Result<String, String> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+
+    // The trailer lives at the end; linearized files repeat it near the start.
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(len.saturating_sub(PROBE_BYTES)))
+        .map_err(|e| e.to_string())?;
+    file.read_to_end(&mut tail).map_err(|e| e.to_string())?;
+
+    let mut head = Vec::new();
+    file.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+    (&mut file).take(PROBE_BYTES).read_to_end(&mut head).map_err(|e| e.to_string())?;
+
+    if let Some(id) = find_trailer_id(&tail).or_else(|| find_trailer_id(&head)) {
+        return Ok(format!("pdfid:{}", id));
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(&head);
+    hasher.update(len.to_le_bytes());
+    Ok(format!("sha256:{}", to_hex(&hasher.finalize())))
+}
+
+/// Extract the first element of the last usable `/ID [<..> <..>]` entry in
+/// `buf`, lowercase hex encoded. Literal-string IDs are hex encoded
+/// byte-for-byte. Names that merely start with `/ID`, like `/IDTree`, don't
+/// count, and an entry that doesn't parse gives way to the one before it.
+fn find_trailer_id(buf: &[u8]) -> Option<String> {
+    buf.windows(3)
+        .enumerate()
+        .rev()
+        .filter(|(_, w)| *w == b"/ID")
+        .map(|(pos, _)| pos + 3)
+        .filter(|&end| buf.get(end).map_or(true, |&b| is_delimiter(b)))
+        .find_map(|end| parse_id(&buf[end..]))
+}
+
+/// Whitespace or a PDF delimiter, which ends a name.
+fn is_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || b"()<>[]{}/%".contains(&b)
+}
+
+/// The first element of the `[<..> <..>]` array at the start of `buf`.
+fn parse_id(buf: &[u8]) -> Option<String> {
+    let mut rest = buf.iter().copied().skip_while(|b| b.is_ascii_whitespace());
+    if rest.next()? != b'[' {
+        return None;
+    }
+    let mut rest = rest.skip_while(|b| b.is_ascii_whitespace());
+    let id = match rest.next()? {
+        b'<' => {
+            let hex: String = rest
+                .take_while(|&b| b != b'>')
+                .filter(|b| b.is_ascii_hexdigit())
+                .map(|b| (b as char).to_ascii_lowercase())
+                .collect();
+            hex
+        }
+        b'(' => {
+            let bytes: Vec<u8> = rest.take_while(|&b| b != b')').collect();
+            to_hex(&bytes)
+        }
+        _ => return None,
+    };
+    // Some producers write an all-zero placeholder ID; that identifies nothing.
+    (!id.is_empty() && id.chars().any(|c| c != '0')).then_some(id)
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn mtime_secs(meta: &std::fs::Metadata) -> i64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Resolve the library directory path encoded in a slug's `{dir_id}_` prefix.
+fn dir_path_for_slug(conn: &Connection, slug: &str) -> Result<Option<String>, String> {
+    let Some(dir_id) = slug.split('_').next().and_then(|p| p.parse::<i64>().ok()) else {
+        return Ok(None);
+    };
+    conn.query_row("SELECT path FROM directories WHERE id = ?1", [dir_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())
+}
+
+/// A file as the identity tables last saw it.
+struct SeenFile {
+    slug: String,
+    size: i64,
+    mtime: i64,
+    doc_id: String,
+    /// Holds the identity, rather than being a copy of the file that does.
+    owner: bool,
+}
+
+/// The files whose identity is on record, by path: documents and known
+/// copies of them. Loaded under the database lock so `identify_files` can
+/// run without it.
+pub(crate) struct KnownFiles(HashMap<String, SeenFile>);
+
+pub(crate) fn known_files(conn: &Connection) -> Result<KnownFiles, String> {
+    let mut files = HashMap::new();
+    // Copies first, so a path that is on record as a document wins
+    for (table, owner) in [("document_copies", false), ("documents", true)] {
+        let mut stmt = conn
+            .prepare(&format!("SELECT full_path, slug, file_size, mtime, doc_id FROM {}", table))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    SeenFile { slug: row.get(1)?, size: row.get(2)?, mtime: row.get(3)?, doc_id: row.get(4)?, owner },
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (path, seen) = row.map_err(|e| e.to_string())?;
+            files.insert(path, seen);
+        }
+    }
+    Ok(KnownFiles(files))
+}
+
+/// Size, mtime and identity of a scanned file.
+pub(crate) struct FileIdentity {
+    size: i64,
+    mtime: i64,
+    doc_id: String,
+    /// Already on record as this document under this slug.
+    unchanged: bool,
+}
+
+/// Identify each textbook's file, hashing only files that are new or changed
+/// since `known`. Touches no database, so callers run it without the lock;
+/// `None` for files that can't be read.
+pub(crate) fn identify_files(textbooks: &[Textbook], known: &KnownFiles) -> Vec<Option<FileIdentity>> {
+    textbooks
+        .iter()
+        .map(|tb| {
+            let meta = std::fs::metadata(&tb.full_path).ok()?;
+            let (size, mtime) = (meta.len() as i64, mtime_secs(&meta));
+            if let Some(seen) = known.0.get(&tb.full_path) {
+                if seen.slug == tb.slug && seen.size == size && seen.mtime == mtime {
+                    let doc_id = seen.doc_id.clone();
+                    return Some(FileIdentity { size, mtime, doc_id, unchanged: seen.owner });
+                }
+            }
+            match compute_doc_id(Path::new(&tb.full_path)) {
+                Ok(doc_id) => Some(FileIdentity { size, mtime, doc_id, unchanged: false }),
+                Err(e) => {
+                    log::warn!("doc identity: {}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Match scanned textbooks against the stored document identities, filling
+/// in `doc_id` and following renames and moves. Identifies the files first,
+/// so it suits callers that own the connection; the app identifies them
+/// without the lock and calls `record_identities_inner`.
+pub fn reconcile_identities_inner(
+    conn: &Connection,
+    textbooks: &mut [Textbook],
+) -> Result<Vec<SlugMigration>, String> {
+    let identities = identify_files(textbooks, &known_files(conn)?);
+    record_identities_inner(conn, textbooks, identities)
+}
+
+/// Record the identities `identify_files` found for `textbooks`.
+///
+/// When a known document shows up under a new slug and its previous file is
+/// gone, all data is migrated to the new slug and the old slug is kept as an
+/// alias. If the previous file still exists the new file is a copy: it is
+/// left alone, and remembered so it isn't hashed again while unchanged.
+pub(crate) fn record_identities_inner(
+    conn: &Connection,
+    textbooks: &mut [Textbook],
+    identities: Vec<Option<FileIdentity>>,
+) -> Result<Vec<SlugMigration>, String> {
+    let mut migrations = Vec::new();
+
+    for (tb, identity) in textbooks.iter_mut().zip(identities) {
+        let Some(FileIdentity { size, mtime, doc_id, unchanged }) = identity else {
+            continue;
+        };
+        if unchanged {
+            tb.doc_id = Some(doc_id);
+            continue;
+        }
+
+        let existing: Option<(String, String)> = conn
+            .query_row(
+                "SELECT slug, full_path FROM documents WHERE doc_id = ?1",
+                [&doc_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+
+        let is_copy = existing
+            .as_ref()
+            .is_some_and(|(old_slug, old_path)| *old_slug != tb.slug && Path::new(old_path).exists());
+        if is_copy {
+            // A copy of a known book: the original keeps the identity.
+            conn.execute(
+                "INSERT OR REPLACE INTO document_copies (full_path, slug, file_size, mtime, doc_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![tb.full_path, tb.slug, size, mtime, doc_id],
+            )
+            .map_err(|e| e.to_string())?;
+            continue;
+        }
+        conn.execute("DELETE FROM document_copies WHERE full_path = ?1", [&tb.full_path])
+            .map_err(|e| e.to_string())?;
+
+        match existing {
+            Some((old_slug, _)) if old_slug == tb.slug => {
+                conn.execute(
+                    "UPDATE documents SET full_path = ?1, file_size = ?2, mtime = ?3,
+                     updated_at = datetime('now') WHERE doc_id = ?4",
+                    rusqlite::params![tb.full_path, size, mtime, doc_id],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            Some((old_slug, _)) => {
+                let old_dir = dir_path_for_slug(conn, &old_slug)?
+                    .unwrap_or_else(|| tb.dir_path.clone());
+                if !Path::new(&old_dir).is_dir() {
+                    // Old library directory is unavailable (e.g. unmounted);
+                    // we cannot tell a move from a missing mount.
+                    continue;
+                }
+
+                conn.execute(
+                    "DELETE FROM documents WHERE slug = ?1 AND doc_id != ?2",
+                    rusqlite::params![tb.slug, doc_id],
+                )
+                .map_err(|e| e.to_string())?;
+                migrate_slug_between_dirs_inner(conn, &old_slug, &tb.slug, &old_dir, &tb.dir_path)?;
+                conn.execute(
+                    "UPDATE documents SET slug = ?1, full_path = ?2, file_size = ?3, mtime = ?4,
+                     updated_at = datetime('now') WHERE doc_id = ?5",
+                    rusqlite::params![tb.slug, tb.full_path, size, mtime, doc_id],
+                )
+                .map_err(|e| e.to_string())?;
+                conn.execute(
+                    "INSERT OR REPLACE INTO slug_aliases (alias, doc_id) VALUES (?1, ?2)",
+                    rusqlite::params![old_slug, doc_id],
+                )
+                .map_err(|e| e.to_string())?;
+                // Moving back to a previous name makes that alias the live slug again
+                conn.execute("DELETE FROM slug_aliases WHERE alias = ?1", [&tb.slug])
+                    .map_err(|e| e.to_string())?;
+
+                log::info!("doc identity: {} moved, migrated {} -> {}", doc_id, old_slug, tb.slug);
+                migrations.push(SlugMigration {
+                    old_slug,
+                    new_slug: tb.slug.clone(),
+                    doc_id: doc_id.clone(),
+                });
+            }
+            None => {
+                // Different content under this slug (replaced or edited without
+                // a stable /ID): the slug's data stays, the identity is renewed.
+                conn.execute("DELETE FROM documents WHERE slug = ?1", [&tb.slug])
+                    .map_err(|e| e.to_string())?;
+                conn.execute(
+                    "INSERT INTO documents (doc_id, slug, full_path, file_size, mtime)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![doc_id, tb.slug, tb.full_path, size, mtime],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+        tb.doc_id = Some(doc_id);
+    }
+
+    Ok(migrations)
+}
+
+/// Map a possibly stale slug to the document's current slug. Unknown slugs
+/// are returned unchanged.
+pub fn resolve_slug_inner(conn: &Connection, slug: &str) -> Result<String, String> {
+    let current: Option<String> = conn
+        .query_row(
+            "SELECT d.slug FROM slug_aliases a JOIN documents d ON d.doc_id = a.doc_id
+             WHERE a.alias = ?1",
+            [slug],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(current.unwrap_or_else(|| slug.to_string()))
+}
+
+#[tauri::command]
+pub fn resolve_slug(slug: String, state: State<'_, DbState>) -> Result<String, String> {
+    let conn = get_db(&state)?;
+    resolve_slug_inner(&conn, &slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{add_directory_inner, get_note_inner, scan_textbooks, set_note_inner, list_directories_inner};
+    use crate::db;
+
+    fn test_db() -> (tempfile::TempDir, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let conn = db::init_db(&db_path).unwrap();
+        (dir, conn)
+    }
+
+    fn fake_pdf(id: &str) -> Vec<u8> {
+        format!(
+            "%PDF-1.7\n1 0 obj << /Type /Catalog >> endobj\ntrailer\n<< /Root 1 0 R /ID [<{id}> <FFFF>] >>\n%%EOF\n"
+        )
+        .into_bytes()
+    }
+
+    fn scan(conn: &Connection) -> Vec<Textbook> {
+        scan_textbooks(&list_directories_inner(conn).unwrap())
+    }
+
+    #[test]
+    fn trailer_id_is_parsed() {
+        let id = find_trailer_id(&fake_pdf("A1B2C3")).unwrap();
+        assert_eq!(id, "a1b2c3");
+        assert_eq!(find_trailer_id(b"trailer << /ID [(ab)(cd)] >>").unwrap(), "6162");
+        assert!(find_trailer_id(b"trailer << /Root 1 0 R >>").is_none());
+        assert!(find_trailer_id(b"/ID [<0000> <0000>]").is_none());
+    }
+
+    #[test]
+    fn trailer_id_skips_longer_names_and_bad_entries() {
+        assert_eq!(find_trailer_id(b"/ID [<abcd> <ef01>] /IDTree 5 0 R").unwrap(), "abcd");
+        assert_eq!(find_trailer_id(b"/ID[<abcd>] /ID [<0000> <0000>]").unwrap(), "abcd");
+        assert_eq!(find_trailer_id(b"/ID [<abcd>] /ID 7 0 R").unwrap(), "abcd");
+    }
+
+    #[test]
+    fn content_hash_fallback_is_stable() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.pdf");
+        let b = dir.path().join("b.pdf");
+        std::fs::write(&a, b"%PDF-1.4 no id here").unwrap();
+        std::fs::write(&b, b"%PDF-1.4 no id here").unwrap();
+        let id_a = compute_doc_id(&a).unwrap();
+        assert!(id_a.starts_with("sha256:"));
+        assert_eq!(id_a, compute_doc_id(&b).unwrap());
+
+        std::fs::write(&b, b"%PDF-1.4 different").unwrap();
+        assert_ne!(id_a, compute_doc_id(&b).unwrap());
+    }
+
+    #[test]
+    fn rename_migrates_data_and_keeps_alias() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let dir = add_directory_inner(&conn, &lib.path().to_string_lossy(), "lib").unwrap();
+        std::fs::write(lib.path().join("old-name.pdf"), fake_pdf("abcd")).unwrap();
+
+        let mut books = scan(&conn);
+        assert!(reconcile_identities_inner(&conn, &mut books).unwrap().is_empty());
+        assert_eq!(books[0].doc_id.as_deref(), Some("pdfid:abcd"));
+        let old_slug = format!("{}_old-name", dir.id);
+        set_note_inner(&conn, &old_slug, 3, "kept", "markdown").unwrap();
+
+        std::fs::rename(lib.path().join("old-name.pdf"), lib.path().join("new-name.pdf")).unwrap();
+        let mut books = scan(&conn);
+        let moves = reconcile_identities_inner(&conn, &mut books).unwrap();
+
+        let new_slug = format!("{}_new-name", dir.id);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].old_slug, old_slug);
+        assert_eq!(moves[0].new_slug, new_slug);
+        assert_eq!(get_note_inner(&conn, &new_slug, 3).unwrap().unwrap().content, "kept");
+        assert!(get_note_inner(&conn, &old_slug, 3).unwrap().is_none());
+        assert_eq!(resolve_slug_inner(&conn, &old_slug).unwrap(), new_slug);
+        assert_eq!(resolve_slug_inner(&conn, "unknown").unwrap(), "unknown");
+    }
+
+    #[test]
+    fn move_between_dirs_moves_json_state() {
+        let (_db_dir, conn) = test_db();
+        let lib_a = tempfile::tempdir().unwrap();
+        let lib_b = tempfile::tempdir().unwrap();
+        let a_path = lib_a.path().to_string_lossy().to_string();
+        let b_path = lib_b.path().to_string_lossy().to_string();
+        let dir_a = add_directory_inner(&conn, &a_path, "a").unwrap();
+        let dir_b = add_directory_inner(&conn, &b_path, "b").unwrap();
+        std::fs::write(lib_a.path().join("book.pdf"), fake_pdf("beef")).unwrap();
+
+        let mut books = scan(&conn);
+        reconcile_identities_inner(&conn, &mut books).unwrap();
+        let old_slug = format!("{}_book", dir_a.id);
+        crate::commands::set_book_status(a_path.clone(), old_slug.clone(), "done".into()).unwrap();
+
+        std::fs::rename(lib_a.path().join("book.pdf"), lib_b.path().join("book.pdf")).unwrap();
+        let mut books = scan(&conn);
+        let moves = reconcile_identities_inner(&conn, &mut books).unwrap();
+        let new_slug = format!("{}_book", dir_b.id);
+        assert_eq!(moves[0].new_slug, new_slug);
+
+        let status_a = crate::commands::get_all_book_status(a_path).unwrap();
+        let status_b = crate::commands::get_all_book_status(b_path).unwrap();
+        assert!(!status_a.contains_key(&old_slug));
+        assert_eq!(status_b[&new_slug], "done");
+    }
+
+    #[test]
+    fn copy_does_not_steal_identity() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let dir = add_directory_inner(&conn, &lib.path().to_string_lossy(), "lib").unwrap();
+        std::fs::write(lib.path().join("original.pdf"), fake_pdf("cafe")).unwrap();
+        let mut books = scan(&conn);
+        reconcile_identities_inner(&conn, &mut books).unwrap();
+        let original = format!("{}_original", dir.id);
+        set_note_inner(&conn, &original, 1, "mine", "markdown").unwrap();
+
+        std::fs::copy(lib.path().join("original.pdf"), lib.path().join("copy.pdf")).unwrap();
+        let mut books = scan(&conn);
+        let moves = reconcile_identities_inner(&conn, &mut books).unwrap();
+        assert!(moves.is_empty());
+        assert!(get_note_inner(&conn, &original, 1).unwrap().is_some());
+
+        // The copy is remembered and not hashed again while unchanged
+        conn.execute("UPDATE document_copies SET doc_id = 'pdfid:remembered'", []).unwrap();
+        let identities = identify_files(&books, &known_files(&conn).unwrap());
+        let copy = books.iter().position(|b| b.file == "copy.pdf").unwrap();
+        assert_eq!(identities[copy].as_ref().unwrap().doc_id, "pdfid:remembered");
+        conn.execute("UPDATE document_copies SET doc_id = 'pdfid:cafe'", []).unwrap();
+
+        // Once the original is gone, the copy takes over its identity
+        std::fs::remove_file(lib.path().join("original.pdf")).unwrap();
+        let mut books = scan(&conn);
+        let moves = reconcile_identities_inner(&conn, &mut books).unwrap();
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].new_slug, format!("{}_copy", dir.id));
+        let copies: i64 = conn.query_row("SELECT COUNT(*) FROM document_copies", [], |r| r.get(0)).unwrap();
+        assert_eq!(copies, 0);
+    }
+}
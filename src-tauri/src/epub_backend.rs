@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+/// Converted books kept; the least recently opened beyond this are removed.
+const MAX_CACHED_BOOKS: usize = 16;
+
+/// Where conversions are cached: the app cache dir, set at startup. The temp
+/// dir stands in until then (and in tests).
+static CACHE_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_cache_dir(dir: PathBuf) {
+    let _ = CACHE_ROOT.set(dir);
+}
+
+/// EPUB is reflowable, so it is laid out once into a PDF with MuPDF's
+/// `mutool` and then served by the PDFium backend. Conversions are cached,
+/// keyed by path, size and mtime, so a book is only converted again after it
+/// changes.
+pub fn converted_pdf(path: &Path) -> Result<PathBuf, String> {
+    let meta = std::fs::metadata(path)
+        .map_err(|e| format!("Failed to open '{}': {}", path.display(), e))?;
+    let root = CACHE_ROOT.get().cloned().unwrap_or_else(std::env::temp_dir);
+    let target = cache_path(&root, path, &meta);
+    if target.is_file() {
+        // Opening counts as use, for eviction
+        let _ = std::fs::File::options()
+            .write(true)
+            .open(&target)
+            .and_then(|f| f.set_modified(SystemTime::now()));
+        return Ok(target);
+    }
+
+    let cache_dir = target.parent().ok_or("Invalid EPUB cache path")?;
+    std::fs::create_dir_all(cache_dir).map_err(|e| e.to_string())?;
+    // Convert to a unique name first: another render worker may be converting
+    // the same book, and a half-written PDF must never be picked up.
+    let partial = cache_dir.join(format!("{}.partial.pdf", uuid::Uuid::new_v4()));
+    let out = Command::new("mutool")
+        .arg("convert")
+        .arg("-o")
+        .arg(&partial)
+        .arg(path)
+        .output()
+        .map_err(|e| format!("EPUB support requires mutool (MuPDF): {}", e))?;
+    if !out.status.success() {
+        let _ = std::fs::remove_file(&partial);
+        return Err(format!(
+            "Failed to convert EPUB '{}': {}",
+            path.display(),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    std::fs::rename(&partial, &target).map_err(|e| e.to_string())?;
+    evict(cache_dir);
+    Ok(target)
+}
+
+/// Remove the least recently opened conversions beyond `MAX_CACHED_BOOKS`.
+/// Conversions still being written are left alone.
+fn evict(cache_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(cache_dir) else {
+        return;
+    };
+    let mut books: Vec<(SystemTime, PathBuf)> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "pdf") && !p.to_string_lossy().ends_with(".partial.pdf"))
+        .filter_map(|p| Some((std::fs::metadata(&p).and_then(|m| m.modified()).ok()?, p)))
+        .collect();
+    books.sort_by(|a, b| b.0.cmp(&a.0));
+    for (_, path) in books.into_iter().skip(MAX_CACHED_BOOKS) {
+        if let Err(e) = std::fs::remove_file(&path) {
+            log::warn!("epub: failed to evict {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn cache_path(root: &Path, path: &Path, meta: &std::fs::Metadata) -> PathBuf {
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let mut hasher = Sha256::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    hasher.update(meta.len().to_le_bytes());
+    hasher.update(mtime.to_le_bytes());
+    let hex: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    root.join("axiomatic-epub").join(format!("{}.pdf", hex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_path_changes_with_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = dir.path().join("book.epub");
+        std::fs::write(&book, b"one").unwrap();
+        let first = cache_path(dir.path(), &book, &std::fs::metadata(&book).unwrap());
+        assert_eq!(first, cache_path(dir.path(), &book, &std::fs::metadata(&book).unwrap()));
+        assert!(first.starts_with(dir.path().join("axiomatic-epub")));
+
+        std::fs::write(&book, b"longer").unwrap();
+        let second = cache_path(dir.path(), &book, &std::fs::metadata(&book).unwrap());
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn least_recently_opened_conversions_are_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for i in 0..MAX_CACHED_BOOKS + 2 {
+            let path = dir.path().join(format!("{}.pdf", i));
+            let file = std::fs::File::create(&path).unwrap();
+            file.set_modified(now - std::time::Duration::from_secs(i as u64 * 60)).unwrap();
+        }
+        std::fs::write(dir.path().join("x.partial.pdf"), b"").unwrap();
+
+        evict(dir.path());
+        assert!(dir.path().join("0.pdf").exists());
+        assert!(!dir.path().join(format!("{}.pdf", MAX_CACHED_BOOKS)).exists());
+        assert!(dir.path().join("x.partial.pdf").exists());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), MAX_CACHED_BOOKS + 1);
+    }
+
+    #[test]
+    fn missing_file_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(converted_pdf(&dir.path().join("nope.epub")).is_err());
+    }
+}
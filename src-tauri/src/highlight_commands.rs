@@ -3,6 +3,7 @@ use tauri::State;
 
 use crate::commands::{get_db, DbState};
 use crate::models::Highlight;
+use crate::note_store;
 
 fn row_to_highlight(row: &rusqlite::Row) -> rusqlite::Result<Highlight> {
     Ok(Highlight {
@@ -41,7 +42,7 @@ pub fn list_highlights_inner(conn: &Connection, slug: &str) -> Result<Vec<Highli
 #[tauri::command]
 pub fn list_highlights(slug: String, state: State<'_, DbState>) -> Result<Vec<Highlight>, String> {
     let conn = get_db(&state)?;
-    list_highlights_inner(&conn, &slug)
+    list_highlights_inner(&*note_store::open(&conn, &slug)?, &slug)
 }
 
 pub fn create_highlight_inner(
@@ -86,7 +87,7 @@ pub fn create_highlight(
     state: State<'_, DbState>,
 ) -> Result<Highlight, String> {
     let conn = get_db(&state)?;
-    create_highlight_inner(&conn, &slug, page, x, y, width, height, &color, &note, &text, &group_id)
+    create_highlight_inner(&*note_store::open(&conn, &slug)?, &slug, page, x, y, width, height, &color, &note, &text, &group_id)
 }
 
 pub fn delete_highlight_inner(conn: &Connection, id: i64) -> Result<(), String> {
@@ -95,10 +96,11 @@ pub fn delete_highlight_inner(conn: &Connection, id: i64) -> Result<(), String>
     Ok(())
 }
 
+/// `slug` is the highlight's book, which decides the database holding it.
 #[tauri::command]
-pub fn delete_highlight(id: i64, state: State<'_, DbState>) -> Result<(), String> {
+pub fn delete_highlight(id: i64, slug: String, state: State<'_, DbState>) -> Result<(), String> {
     let conn = get_db(&state)?;
-    delete_highlight_inner(&conn, id)
+    delete_highlight_inner(&*note_store::open(&conn, &slug)?, id)
 }
 
 pub fn delete_highlight_group_inner(conn: &Connection, group_id: &str) -> Result<(), String> {
@@ -108,9 +110,9 @@ pub fn delete_highlight_group_inner(conn: &Connection, group_id: &str) -> Result
 }
 
 #[tauri::command]
-pub fn delete_highlight_group(group_id: String, state: State<'_, DbState>) -> Result<(), String> {
+pub fn delete_highlight_group(group_id: String, slug: String, state: State<'_, DbState>) -> Result<(), String> {
     let conn = get_db(&state)?;
-    delete_highlight_group_inner(&conn, &group_id)
+    delete_highlight_group_inner(&*note_store::open(&conn, &slug)?, &group_id)
 }
 
 #[cfg(test)]
@@ -0,0 +1,562 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use tauri::State;
+
+use crate::commands::{get_db, DbState};
+use crate::json_storage::read_json;
+use crate::models::{BookProgress, HistoryEntry, ReadingHistory, ReadingPace, ReadingPosition};
+use crate::snip_commands::now_iso8601;
+
+/// Where earlier versions kept every book's history, per directory. It is
+/// moved into the database the first time the directory's history is used.
+pub(crate) const HISTORY_FILE: &str = "history.json";
+/// Oldest log entries are dropped beyond this many per book.
+const MAX_LOG_ENTRIES: i64 = 10_000;
+/// Back/forward stacks keep this many positions.
+const MAX_STACK: i64 = 100;
+/// While scrolling, a position reported within this many seconds of the
+/// entry before the last one moves the last entry instead of adding one.
+const COALESCE_SECONDS: i64 = 30;
+/// A longer pause between positions ends a reading stretch.
+const IDLE_MINUTES: f64 = 10.0;
+/// Moving further than this without a jump is skimming, not reading.
+pub(crate) const MAX_PAGE_STEP: f64 = 5.0;
+
+pub(crate) type HistoryMap = HashMap<String, ReadingHistory>;
+
+fn stamped(mut position: ReadingPosition) -> ReadingPosition {
+    if position.timestamp.is_empty() {
+        position.timestamp = now_iso8601();
+    }
+    position
+}
+
+fn position_of(row: &Row, first: usize) -> rusqlite::Result<ReadingPosition> {
+    Ok(ReadingPosition {
+        page: row.get(first)?,
+        scroll_offset: row.get(first + 1)?,
+        zoom: row.get(first + 2)?,
+        timestamp: row.get(first + 3)?,
+    })
+}
+
+fn seconds_between(from: &str, to: &str) -> Option<i64> {
+    let from = DateTime::parse_from_rfc3339(from).ok()?;
+    let to = DateTime::parse_from_rfc3339(to).ok()?;
+    Some((to - from).num_seconds())
+}
+
+fn insert_log(conn: &Connection, slug: &str, position: &ReadingPosition, jump: bool) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO reading_log (slug, page, scroll_offset, zoom, timestamp, jump) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![slug, position.page, position.scroll_offset, position.zoom, position.timestamp, jump],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM reading_log WHERE slug = ?1 AND id <= (
+             SELECT id FROM reading_log WHERE slug = ?1 ORDER BY id DESC LIMIT 1 OFFSET ?2)",
+        params![slug, MAX_LOG_ENTRIES],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn push_log(conn: &Connection, slug: &str, position: ReadingPosition, jump: bool) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, jump, page, scroll_offset, zoom, timestamp FROM reading_log
+             WHERE slug = ?1 ORDER BY id DESC LIMIT 2",
+        )
+        .map_err(|e| e.to_string())?;
+    let recent = stmt
+        .query_map([slug], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, bool>(1)?, position_of(row, 2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    if let Some((id, last_jump, last)) = recent.first() {
+        if !jump {
+            // Positions reported again without moving add nothing
+            if last.page == position.page
+                && (last.scroll_offset - position.scroll_offset).abs() < 0.01
+                && last.zoom == position.zoom
+            {
+                return Ok(());
+            }
+            // Scrolling moves the last entry until it is far enough from the one before
+            let coalesce = !last_jump
+                && recent
+                    .get(1)
+                    .and_then(|(_, _, before)| seconds_between(&before.timestamp, &position.timestamp))
+                    .is_some_and(|s| s < COALESCE_SECONDS);
+            if coalesce {
+                conn.execute(
+                    "UPDATE reading_log SET page = ?1, scroll_offset = ?2, zoom = ?3, timestamp = ?4 WHERE id = ?5",
+                    params![position.page, position.scroll_offset, position.zoom, position.timestamp, id],
+                )
+                .map_err(|e| e.to_string())?;
+                return Ok(());
+            }
+        }
+    }
+    insert_log(conn, slug, &position, jump)
+}
+
+fn push_stack(conn: &Connection, slug: &str, forward: bool, position: &ReadingPosition) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO reading_stack (slug, forward, page, scroll_offset, zoom, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![slug, forward, position.page, position.scroll_offset, position.zoom, position.timestamp],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM reading_stack WHERE slug = ?1 AND forward = ?2 AND id <= (
+             SELECT id FROM reading_stack WHERE slug = ?1 AND forward = ?2 ORDER BY id DESC LIMIT 1 OFFSET ?3)",
+        params![slug, forward, MAX_STACK],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn pop_stack(conn: &Connection, slug: &str, forward: bool) -> Result<Option<ReadingPosition>, String> {
+    let top = conn
+        .query_row(
+            "SELECT id, page, scroll_offset, zoom, timestamp FROM reading_stack
+             WHERE slug = ?1 AND forward = ?2 ORDER BY id DESC LIMIT 1",
+            params![slug, forward],
+            |row| Ok((row.get::<_, i64>(0)?, position_of(row, 1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some((id, position)) = top else {
+        return Ok(None);
+    };
+    conn.execute("DELETE FROM reading_stack WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    Ok(Some(position))
+}
+
+fn stack(conn: &Connection, slug: &str, forward: bool) -> Result<Vec<ReadingPosition>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT page, scroll_offset, zoom, timestamp FROM reading_stack
+             WHERE slug = ?1 AND forward = ?2 ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let positions = stmt
+        .query_map(params![slug, forward], |row| position_of(row, 0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string());
+    positions
+}
+
+fn entry_of(row: &Row, first: usize) -> rusqlite::Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        position: position_of(row, first)?,
+        jump: row.get(first + 4)?,
+    })
+}
+
+fn reading_log(conn: &Connection, slug: &str) -> Result<Vec<HistoryEntry>, String> {
+    let mut stmt = conn
+        .prepare("SELECT page, scroll_offset, zoom, timestamp, jump FROM reading_log WHERE slug = ?1 ORDER BY id")
+        .map_err(|e| e.to_string())?;
+    let log = stmt
+        .query_map([slug], |row| entry_of(row, 0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string());
+    log
+}
+
+/// Every book's reading log, by slug.
+pub(crate) fn reading_logs(conn: &Connection) -> Result<HashMap<String, Vec<HistoryEntry>>, String> {
+    let mut stmt = conn
+        .prepare("SELECT slug, page, scroll_offset, zoom, timestamp, jump FROM reading_log ORDER BY id")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, entry_of(row, 1)?)))
+        .map_err(|e| e.to_string())?;
+    let mut logs: HashMap<String, Vec<HistoryEntry>> = HashMap::new();
+    for row in rows {
+        let (slug, entry) = row.map_err(|e| e.to_string())?;
+        logs.entry(slug).or_default().push(entry);
+    }
+    Ok(logs)
+}
+
+/// Move a directory's `history.json` into the database, once.
+pub(crate) fn import_legacy_history(conn: &Connection, dir_path: &str) -> Result<(), String> {
+    let path = Path::new(dir_path).join(".axiomatic").join(HISTORY_FILE);
+    if !path.is_file() {
+        return Ok(());
+    }
+    let map: HistoryMap = read_json(dir_path, HISTORY_FILE);
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    for (slug, history) in map {
+        for entry in &history.log {
+            insert_log(&tx, &slug, &entry.position, entry.jump)?;
+        }
+        for (forward, positions) in [(false, &history.back), (true, &history.forward)] {
+            for position in positions {
+                push_stack(&tx, &slug, forward, position)?;
+            }
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    std::fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+}
+
+/// Move one step back or forward: pop the target from one stack, leaving
+/// the current position on the other.
+fn step(conn: &Connection, slug: &str, current: ReadingPosition, back: bool) -> Result<Option<ReadingPosition>, String> {
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let Some(target) = pop_stack(&tx, slug, !back)? else {
+        return Ok(None);
+    };
+    push_stack(&tx, slug, back, &current)?;
+    let target = ReadingPosition {
+        timestamp: now_iso8601(),
+        ..target
+    };
+    push_log(&tx, slug, target.clone(), true)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(Some(target))
+}
+
+/// Time spent reading between two logged positions, attributed to the page
+/// the stretch started on.
+#[derive(Debug, Clone)]
+pub struct Stretch {
+    pub at: DateTime<FixedOffset>,
+    pub page: i64,
+    pub minutes: f64,
+    pub pages: f64,
+}
+
+fn timed(log: &[HistoryEntry]) -> Vec<(DateTime<FixedOffset>, &HistoryEntry)> {
+    let mut entries: Vec<_> = log
+        .iter()
+        .filter_map(|e| Some((DateTime::parse_from_rfc3339(&e.position.timestamp).ok()?, e)))
+        .collect();
+    entries.sort_by_key(|(t, _)| *t);
+    entries
+}
+
+/// Reading stretches of a log. Consecutive positions less than
+/// `IDLE_MINUTES` apart count as reading; jumps and large moves do not.
+pub fn reading_stretches(log: &[HistoryEntry]) -> Vec<Stretch> {
+    timed(log)
+        .windows(2)
+        .filter_map(|pair| {
+            let ((t0, prev), (t1, cur)) = (pair[0], pair[1]);
+            let minutes = (t1 - t0).num_seconds() as f64 / 60.0;
+            let moved = (cur.position.page as f64 + cur.position.scroll_offset)
+                - (prev.position.page as f64 + prev.position.scroll_offset);
+            (!cur.jump && minutes <= IDLE_MINUTES && moved <= MAX_PAGE_STEP).then(|| Stretch {
+                at: t0,
+                page: prev.position.page,
+                minutes,
+                pages: moved.max(0.0),
+            })
+        })
+        .collect()
+}
+
+/// Pages per hour over the whole log, and the time left at that pace.
+pub fn reading_pace(log: &[HistoryEntry], total_pages: Option<i64>) -> ReadingPace {
+    let stretches = reading_stretches(log);
+    let pages: f64 = stretches.iter().map(|s| s.pages).sum();
+    let minutes: f64 = stretches.iter().map(|s| s.minutes).sum();
+
+    let pages_per_hour = (minutes >= 1.0).then(|| pages / (minutes / 60.0));
+    let last_page = timed(log).last().map(|(_, e)| e.position.page);
+    let remaining_pages = total_pages.zip(last_page).map(|(total, page)| (total - page).max(0));
+    let eta_minutes = remaining_pages
+        .zip(pages_per_hour.filter(|p| *p > 0.0))
+        .map(|(remaining, pph)| remaining as f64 / pph * 60.0);
+    ReadingPace {
+        pages_read: pages,
+        minutes_read: minutes,
+        pages_per_hour,
+        remaining_pages,
+        eta_minutes,
+    }
+}
+
+pub(crate) fn record_reading_position_inner(
+    conn: &Connection,
+    dir_path: &str,
+    slug: &str,
+    position: ReadingPosition,
+) -> Result<(), String> {
+    import_legacy_history(conn, dir_path)?;
+    push_log(conn, slug, stamped(position), false)
+}
+
+pub(crate) fn record_jump_inner(
+    conn: &Connection,
+    dir_path: &str,
+    slug: &str,
+    from: ReadingPosition,
+    to: ReadingPosition,
+) -> Result<(), String> {
+    import_legacy_history(conn, dir_path)?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    push_stack(&tx, slug, false, &stamped(from))?;
+    tx.execute("DELETE FROM reading_stack WHERE slug = ?1 AND forward = 1", [slug])
+        .map_err(|e| e.to_string())?;
+    push_log(&tx, slug, stamped(to), true)?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+pub(crate) fn navigate_inner(
+    conn: &Connection,
+    dir_path: &str,
+    slug: &str,
+    current: ReadingPosition,
+    back: bool,
+) -> Result<Option<ReadingPosition>, String> {
+    import_legacy_history(conn, dir_path)?;
+    step(conn, slug, stamped(current), back)
+}
+
+pub(crate) fn get_reading_history_inner(conn: &Connection, dir_path: &str, slug: &str) -> Result<ReadingHistory, String> {
+    import_legacy_history(conn, dir_path)?;
+    Ok(ReadingHistory {
+        log: reading_log(conn, slug)?,
+        back: stack(conn, slug, false)?,
+        forward: stack(conn, slug, true)?,
+    })
+}
+
+pub(crate) fn get_last_position_inner(
+    conn: &Connection,
+    dir_path: &str,
+    slug: &str,
+) -> Result<Option<ReadingPosition>, String> {
+    import_legacy_history(conn, dir_path)?;
+    conn.query_row(
+        "SELECT page, scroll_offset, zoom, timestamp FROM reading_log WHERE slug = ?1 ORDER BY id DESC LIMIT 1",
+        [slug],
+        |row| position_of(row, 0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// Log the reader's position; called as the reader scrolls. Positions
+/// close together in time are coalesced into one log entry.
+#[tauri::command]
+pub fn record_reading_position(
+    dir_path: String,
+    slug: String,
+    position: ReadingPosition,
+    state: State<'_, DbState>,
+) -> Result<(), String> {
+    let conn = get_db(&state)?;
+    record_reading_position_inner(&conn, &dir_path, &slug, position)
+}
+
+/// Log a link or outline jump and make `from` reachable with back.
+#[tauri::command]
+pub fn record_jump(
+    dir_path: String,
+    slug: String,
+    from: ReadingPosition,
+    to: ReadingPosition,
+    state: State<'_, DbState>,
+) -> Result<(), String> {
+    let conn = get_db(&state)?;
+    record_jump_inner(&conn, &dir_path, &slug, from, to)
+}
+
+/// The position before the last jump, or `None` at the start of history.
+#[tauri::command]
+pub fn navigate_back(
+    dir_path: String,
+    slug: String,
+    current: ReadingPosition,
+    state: State<'_, DbState>,
+) -> Result<Option<ReadingPosition>, String> {
+    let conn = get_db(&state)?;
+    navigate_inner(&conn, &dir_path, &slug, current, true)
+}
+
+#[tauri::command]
+pub fn navigate_forward(
+    dir_path: String,
+    slug: String,
+    current: ReadingPosition,
+    state: State<'_, DbState>,
+) -> Result<Option<ReadingPosition>, String> {
+    let conn = get_db(&state)?;
+    navigate_inner(&conn, &dir_path, &slug, current, false)
+}
+
+#[tauri::command]
+pub fn get_reading_history(dir_path: String, slug: String, state: State<'_, DbState>) -> Result<ReadingHistory, String> {
+    let conn = get_db(&state)?;
+    get_reading_history_inner(&conn, &dir_path, &slug)
+}
+
+/// Where to reopen a book, including the offset into the page and zoom.
+#[tauri::command]
+pub fn get_last_position(
+    dir_path: String,
+    slug: String,
+    state: State<'_, DbState>,
+) -> Result<Option<ReadingPosition>, String> {
+    let conn = get_db(&state)?;
+    get_last_position_inner(&conn, &dir_path, &slug)
+}
+
+/// Reading pace and estimated time to finish, using the page count saved
+/// with the book's progress.
+#[tauri::command]
+pub fn get_reading_pace(dir_path: String, slug: String, state: State<'_, DbState>) -> Result<ReadingPace, String> {
+    let progress: HashMap<String, BookProgress> = read_json(&dir_path, "progress.json");
+    let total_pages = progress.get(&slug).map(|p| p.total_pages).filter(|&t| t > 0);
+    let log = {
+        let conn = get_db(&state)?;
+        import_legacy_history(&conn, &dir_path)?;
+        reading_log(&conn, &slug)?
+    };
+    Ok(reading_pace(&log, total_pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(page: i64, offset: f64, timestamp: &str) -> ReadingPosition {
+        ReadingPosition {
+            page,
+            scroll_offset: offset,
+            zoom: 1.0,
+            timestamp: timestamp.into(),
+        }
+    }
+
+    fn setup() -> (tempfile::TempDir, Connection, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = crate::db::init_db(&dir.path().join("axiomatic.db")).unwrap();
+        let dp = dir.path().to_string_lossy().to_string();
+        (dir, conn, dp)
+    }
+
+    #[test]
+    fn back_and_forward_across_jumps() {
+        let (_dir, conn, dp) = setup();
+        let slug = "algebra";
+
+        record_reading_position_inner(&conn, &dp, slug, pos(12, 0.4, "")).unwrap();
+        record_jump_inner(&conn, &dp, slug, pos(12, 0.4, ""), pos(80, 0.1, "")).unwrap();
+        record_jump_inner(&conn, &dp, slug, pos(80, 0.5, ""), pos(3, 0.0, "")).unwrap();
+
+        let back = navigate_inner(&conn, &dp, slug, pos(3, 0.2, ""), true).unwrap().unwrap();
+        assert_eq!((back.page, back.scroll_offset), (80, 0.5));
+        let back = navigate_inner(&conn, &dp, slug, back, true).unwrap().unwrap();
+        assert_eq!((back.page, back.scroll_offset), (12, 0.4));
+        assert!(navigate_inner(&conn, &dp, slug, back.clone(), true).unwrap().is_none());
+
+        let fwd = navigate_inner(&conn, &dp, slug, back, false).unwrap().unwrap();
+        assert_eq!(fwd.page, 80);
+
+        // A new jump discards the forward history
+        record_jump_inner(&conn, &dp, slug, fwd, pos(40, 0.0, "")).unwrap();
+        assert!(navigate_inner(&conn, &dp, slug, pos(40, 0.0, ""), false).unwrap().is_none());
+
+        let last = get_last_position_inner(&conn, &dp, slug).unwrap().unwrap();
+        assert_eq!(last.page, 40);
+        assert!(!last.timestamp.is_empty());
+        assert!(get_last_position_inner(&conn, &dp, "topology").unwrap().is_none());
+    }
+
+    #[test]
+    fn scrolling_is_coalesced() {
+        let (_dir, conn, dp) = setup();
+        let log = |conn: &Connection| -> Vec<(i64, String)> {
+            reading_log(conn, "algebra")
+                .unwrap()
+                .into_iter()
+                .map(|e| (e.position.page, e.position.timestamp))
+                .collect()
+        };
+        let record = |page, offset, ts: &str| {
+            record_reading_position_inner(&conn, &dp, "algebra", pos(page, offset, ts)).unwrap();
+        };
+        record(5, 0.3, "2025-01-01T10:00:00Z");
+        // Reported again without moving
+        record(5, 0.302, "2025-01-01T10:00:05Z");
+        record(5, 0.6, "2025-01-01T10:00:10Z");
+        record(6, 0.1, "2025-01-01T10:00:20Z");
+        assert_eq!(
+            log(&conn),
+            [(5, "2025-01-01T10:00:00Z".to_string()), (6, "2025-01-01T10:00:20Z".to_string())]
+        );
+        record(6, 0.5, "2025-01-01T10:00:40Z");
+        record(7, 0.0, "2025-01-01T10:00:45Z");
+        assert_eq!(log(&conn).len(), 3);
+
+        // Jumps are never folded into scrolling
+        record_jump_inner(&conn, &dp, "algebra", pos(7, 0.0, ""), pos(90, 0.0, "2025-01-01T10:00:55Z")).unwrap();
+        record(90, 0.4, "2025-01-01T10:00:58Z");
+        assert_eq!(log(&conn).len(), 5);
+    }
+
+    #[test]
+    fn legacy_history_is_imported_once() {
+        let (_dir, conn, dp) = setup();
+        let mut map = HistoryMap::new();
+        map.insert(
+            "algebra".into(),
+            ReadingHistory {
+                log: vec![HistoryEntry { position: pos(9, 0.5, "2025-01-01T10:00:00Z"), jump: false }],
+                back: vec![pos(2, 0.0, "2025-01-01T09:00:00Z")],
+                forward: vec![],
+            },
+        );
+        crate::json_storage::write_json(&dp, HISTORY_FILE, &map).unwrap();
+
+        let history = get_reading_history_inner(&conn, &dp, "algebra").unwrap();
+        assert_eq!(history.log.len(), 1);
+        assert_eq!(history.back, vec![pos(2, 0.0, "2025-01-01T09:00:00Z")]);
+        assert!(!Path::new(&dp).join(".axiomatic").join(HISTORY_FILE).exists());
+
+        let back = navigate_inner(&conn, &dp, "algebra", pos(9, 0.5, ""), true).unwrap().unwrap();
+        assert_eq!(back.page, 2);
+        assert_eq!(get_reading_history_inner(&conn, &dp, "algebra").unwrap().forward.len(), 1);
+    }
+
+    #[test]
+    fn pace_skips_idle_gaps_and_jumps() {
+        let entry = |page, offset, ts: &str, jump| HistoryEntry { position: pos(page, offset, ts), jump };
+        let log = vec![
+            entry(10, 0.0, "2025-01-01T10:00:00Z", false),
+            entry(11, 0.0, "2025-01-01T10:03:00Z", false),
+            entry(12, 0.5, "2025-01-01T10:09:00Z", false),
+            // Lunch break
+            entry(13, 0.0, "2025-01-01T13:00:00Z", false),
+            entry(14, 0.0, "2025-01-01T13:03:00Z", false),
+            // Followed a link to the index
+            entry(390, 0.0, "2025-01-01T13:04:00Z", true),
+            entry(14, 0.0, "2025-01-01T13:05:00Z", true),
+            entry(15, 0.0, "2025-01-01T13:06:00Z", false),
+        ];
+        let pace = reading_pace(&log, Some(115));
+        assert!((pace.pages_read - 4.5).abs() < 1e-9);
+        assert!((pace.minutes_read - 13.0).abs() < 1e-9);
+        let pph = pace.pages_per_hour.unwrap();
+        assert!((pph - 4.5 / 13.0 * 60.0).abs() < 1e-9);
+        assert_eq!(pace.remaining_pages, Some(100));
+        assert!((pace.eta_minutes.unwrap() - 100.0 / pph * 60.0).abs() < 1e-9);
+
+        let empty = reading_pace(&[], Some(100));
+        assert!(empty.pages_per_hour.is_none() && empty.eta_minutes.is_none());
+    }
+}
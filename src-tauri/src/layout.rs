@@ -0,0 +1,512 @@
+//! Page layout analysis on top of the text layer: lines, columns, reading
+//! order, block classification, and running headers/footers/page numbers.
+//!
+//! Works on `PageTextLayer` words, so it behaves the same for PDF, DjVu and
+//! OCR'd pages. All coordinates are normalized top-down like the rest of the
+//! text layer.
+
+use std::collections::HashSet;
+
+use crate::pdf_models::{BlockKind, LayoutBlock, NormalizedRect, PageLayout, PageTextLayer};
+
+/// Lines whose rect lies within this distance of the top or bottom edge are
+/// candidates for running headers, footers and page numbers.
+const MARGIN_BAND: f32 = 0.1;
+/// Largest horizontal gap between words of one line (fraction of page width).
+/// Column gutters are wider than this.
+const WORD_GAP: f32 = 0.02;
+/// Width of the bins used to find column gutters.
+const GUTTER_BIN: f32 = 0.005;
+
+#[derive(Debug, Clone)]
+struct Line {
+    text: String,
+    rect: NormalizedRect,
+}
+
+impl Line {
+    fn bottom(&self) -> f32 {
+        self.rect.y + self.rect.height
+    }
+
+    fn right(&self) -> f32 {
+        self.rect.x + self.rect.width
+    }
+
+    fn center_y(&self) -> f32 {
+        self.rect.y + self.rect.height / 2.0
+    }
+}
+
+fn union(a: &NormalizedRect, b: &NormalizedRect) -> NormalizedRect {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    NormalizedRect {
+        x,
+        y,
+        width: (a.x + a.width).max(b.x + b.width) - x,
+        height: (a.y + a.height).max(b.y + b.height) - y,
+    }
+}
+
+/// Analyze one page. `neighbours` are the text layers of nearby pages, used
+/// to recognise running headers and footers that repeat between pages.
+pub fn analyze(layer: &PageTextLayer, neighbours: &[PageTextLayer]) -> PageLayout {
+    let all = lines(layer);
+
+    let repeated: HashSet<String> = neighbours
+        .iter()
+        .flat_map(|n| {
+            lines(n)
+                .into_iter()
+                .filter(in_margin)
+                .map(|l| furniture_key(&l.text))
+        })
+        .collect();
+
+    let mut furniture = Vec::new();
+    let mut body = Vec::new();
+    for line in all {
+        let kind = if !in_margin(&line) {
+            None
+        } else if is_page_number(&line.text) {
+            Some(BlockKind::PageNumber)
+        } else if repeated.contains(&furniture_key(&line.text)) {
+            Some(if line.rect.y < 0.5 {
+                BlockKind::Header
+            } else {
+                BlockKind::Footer
+            })
+        } else {
+            None
+        };
+        match kind {
+            Some(kind) => furniture.push(LayoutBlock {
+                kind,
+                text: line.text,
+                rect: line.rect,
+                column: None,
+            }),
+            None => body.push(line),
+        }
+    }
+
+    let gutters = gutters(&body);
+    let ordered = reading_order(body, &gutters);
+    let blocks = blocks(ordered);
+
+    PageLayout {
+        page: layer.page,
+        columns: gutters.len() as u32 + 1,
+        blocks,
+        furniture,
+    }
+}
+
+/// Text of the page in reading order, without headers, footers and page
+/// numbers; blocks are separated by blank lines.
+pub fn reading_text(layout: &PageLayout) -> String {
+    layout
+        .blocks
+        .iter()
+        .map(|b| b.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Group words into lines: same vertical band, small horizontal gaps.
+fn lines(layer: &PageTextLayer) -> Vec<Line> {
+    let mut words: Vec<_> = layer
+        .spans
+        .iter()
+        .filter(|s| !s.text.trim().is_empty())
+        .collect();
+    words.sort_by(|a, b| a.rect.x.total_cmp(&b.rect.x));
+
+    let mut lines: Vec<Line> = Vec::new();
+    for word in words {
+        let r = &word.rect;
+        let cy = r.y + r.height / 2.0;
+        let home = lines
+            .iter_mut()
+            .filter(|l| {
+                let gap = r.x - l.right();
+                (cy - l.center_y()).abs() < r.height.min(l.rect.height) * 0.5
+                    && gap > -0.005
+                    && gap < WORD_GAP
+            })
+            .min_by(|a, b| (r.x - a.right()).total_cmp(&(r.x - b.right())));
+        match home {
+            Some(line) => {
+                line.text.push(' ');
+                line.text.push_str(word.text.trim());
+                line.rect = union(&line.rect, r);
+            }
+            None => lines.push(Line {
+                text: word.text.trim().to_string(),
+                rect: r.clone(),
+            }),
+        }
+    }
+    lines.sort_by(|a, b| {
+        a.rect
+            .y
+            .total_cmp(&b.rect.y)
+            .then(a.rect.x.total_cmp(&b.rect.x))
+    });
+    lines
+}
+
+fn in_margin(line: &Line) -> bool {
+    line.bottom() < MARGIN_BAND || line.rect.y > 1.0 - MARGIN_BAND
+}
+
+/// Running headers differ between pages only in their numbers.
+fn furniture_key(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii_digit() {
+                '#'
+            } else {
+                c.to_ascii_lowercase()
+            }
+        })
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+fn is_page_number(text: &str) -> bool {
+    let t = text.trim_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '–' | '—'));
+    let t = t.strip_prefix("Page ").unwrap_or(t);
+    !t.is_empty()
+        && t.len() <= 8
+        && (t.chars().all(|c| c.is_ascii_digit())
+            || t.chars()
+                .all(|c| matches!(c.to_ascii_lowercase(), 'i' | 'v' | 'x' | 'l' | 'c')))
+}
+
+/// Column boundaries: vertical strips in the middle of the page that almost
+/// no line crosses, with a substantial amount of text on both sides.
+fn gutters(lines: &[Line]) -> Vec<f32> {
+    if lines.len() < 6 {
+        return Vec::new();
+    }
+    let bins = (1.0 / GUTTER_BIN) as usize;
+    let mut coverage = vec![0usize; bins];
+    for line in lines {
+        let start = ((line.rect.x / GUTTER_BIN) as usize).min(bins - 1);
+        let end = ((line.right() / GUTTER_BIN) as usize).min(bins - 1);
+        for c in &mut coverage[start..=end] {
+            *c += 1;
+        }
+    }
+    // Titles and figure captions may span the gutter
+    let allowed = lines.len() / 10;
+
+    let mut gutters = Vec::new();
+    let mut run_start: Option<usize> = None;
+    let lo = (0.15 / GUTTER_BIN) as usize;
+    let hi = (0.85 / GUTTER_BIN) as usize;
+    for (i, &c) in coverage.iter().enumerate().take(hi + 1).skip(lo) {
+        let open = c <= allowed && i < hi;
+        match (open, run_start) {
+            (true, None) => run_start = Some(i),
+            (false, Some(start)) => {
+                run_start = None;
+                if i - start < 2 {
+                    continue;
+                }
+                let x = (start + i) as f32 * GUTTER_BIN / 2.0;
+                let left = lines.iter().filter(|l| l.right() <= x).count();
+                let right = lines.iter().filter(|l| l.rect.x >= x).count();
+                if left >= 3 && right >= 3 {
+                    gutters.push(x);
+                }
+            }
+            _ => {}
+        }
+    }
+    gutters
+}
+
+/// Column index of a line, or `None` if it spans a gutter.
+fn column_of(line: &Line, gutters: &[f32]) -> Option<u32> {
+    let mut col = 0;
+    for &g in gutters {
+        if line.right() <= g + GUTTER_BIN {
+            break;
+        }
+        if line.rect.x < g - GUTTER_BIN {
+            return None;
+        }
+        col += 1;
+    }
+    Some(col)
+}
+
+/// Order lines column by column. Lines spanning the gutters (titles, wide
+/// figures) cut the page into bands; each band is read column by column.
+fn reading_order(lines: Vec<Line>, gutters: &[f32]) -> Vec<(Option<u32>, Line)> {
+    let mut out = Vec::new();
+    let mut band: Vec<Vec<Line>> = vec![Vec::new(); gutters.len() + 1];
+    for line in lines {
+        match column_of(&line, gutters) {
+            Some(col) => band[col as usize].push(line),
+            None => {
+                for (col, lines) in band.iter_mut().enumerate() {
+                    out.extend(lines.drain(..).map(|l| (Some(col as u32), l)));
+                }
+                out.push((None, line));
+            }
+        }
+    }
+    for (col, lines) in band.into_iter().enumerate() {
+        out.extend(lines.into_iter().map(|l| (Some(col as u32), l)));
+    }
+    out
+}
+
+fn median(mut values: Vec<f32>) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    values[values.len() / 2]
+}
+
+/// Merge consecutive lines of a column into blocks and classify them.
+fn blocks(lines: Vec<(Option<u32>, Line)>) -> Vec<LayoutBlock> {
+    let body_height = median(lines.iter().map(|(_, l)| l.rect.height).collect());
+
+    // (column, lines)
+    let mut groups: Vec<(Option<u32>, Vec<Line>)> = Vec::new();
+    for (col, line) in lines {
+        if let Some((gcol, group)) = groups.last_mut() {
+            let prev = group.last().unwrap();
+            let gap = line.rect.y - prev.bottom();
+            let similar = (line.rect.height - prev.rect.height).abs() < prev.rect.height * 0.25;
+            if *gcol == col
+                && similar
+                && gap > -prev.rect.height * 0.5
+                && gap < prev.rect.height * 0.6
+            {
+                group.push(line);
+                continue;
+            }
+        }
+        groups.push((col, vec![line]));
+    }
+
+    groups
+        .into_iter()
+        .map(|(column, lines)| {
+            let rect = lines
+                .iter()
+                .skip(1)
+                .fold(lines[0].rect.clone(), |r, l| union(&r, &l.rect));
+            let height = median(lines.iter().map(|l| l.rect.height).collect());
+            let text = join_lines(&lines);
+            LayoutBlock {
+                kind: classify(&text, &rect, height, body_height, lines.len()),
+                text,
+                rect,
+                column,
+            }
+        })
+        .collect()
+}
+
+/// Join lines, undoing end-of-line hyphenation.
+fn join_lines(lines: &[Line]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        let next_lower = line
+            .text
+            .chars()
+            .next()
+            .map(char::is_lowercase)
+            .unwrap_or(false);
+        if out.ends_with('-') && next_lower {
+            out.pop();
+        } else if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(&line.text);
+    }
+    out
+}
+
+fn classify(
+    text: &str,
+    rect: &NormalizedRect,
+    height: f32,
+    body_height: f32,
+    line_count: usize,
+) -> BlockKind {
+    let first_word = text.split_whitespace().next().unwrap_or("");
+    let second_starts_digit = text
+        .split_whitespace()
+        .nth(1)
+        .and_then(|w| w.chars().next())
+        .map(|c| c.is_ascii_digit())
+        .unwrap_or(false);
+    if matches!(
+        first_word,
+        "Figure" | "Fig." | "Table" | "Algorithm" | "Listing"
+    ) && second_starts_digit
+    {
+        return BlockKind::Caption;
+    }
+
+    if height >= body_height * 1.15 && line_count <= 3 {
+        return BlockKind::Heading;
+    }
+    if line_count == 1 && text.len() < 80 && !text.ends_with('.') && is_section_number(first_word) {
+        return BlockKind::Heading;
+    }
+
+    let marker = text
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_digit() || matches!(c, '*' | '∗' | '†' | '‡' | '§'))
+        .unwrap_or(false);
+    if height < body_height * 0.9 && rect.y > 0.6 && marker {
+        return BlockKind::Footnote;
+    }
+    BlockKind::Paragraph
+}
+
+/// "3", "2.1", "4.3.2" or "A.1" style section numbers.
+pub(crate) fn is_section_number(word: &str) -> bool {
+    let word = word.trim_end_matches('.');
+    !word.is_empty()
+        && word.split('.').all(|part| {
+            !part.is_empty()
+                && (part.chars().all(|c| c.is_ascii_digit())
+                    || (part.len() == 1 && part.chars().all(|c| c.is_ascii_uppercase())))
+        })
+        && word.chars().any(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doc_backend::word_span;
+
+    /// A line of words starting at (x, y), each word 0.01 wide per char.
+    fn line(spans: &mut Vec<crate::pdf_models::TextSpan>, text: &str, x: f32, y: f32, h: f32) {
+        let mut x = x;
+        for word in text.split(' ') {
+            let w = word.chars().count() as f32 * 0.01;
+            spans.push(word_span(
+                word,
+                NormalizedRect {
+                    x,
+                    y,
+                    width: w,
+                    height: h,
+                },
+            ));
+            x += w + 0.008;
+        }
+    }
+
+    fn two_column_page(page: u32) -> PageTextLayer {
+        let mut spans = Vec::new();
+        line(&mut spans, "Journal of Things", 0.1, 0.04, 0.012);
+        line(&mut spans, "A Title Across", 0.3, 0.12, 0.025);
+        for i in 0..6 {
+            let y = 0.2 + i as f32 * 0.018;
+            line(&mut spans, &format!("left {}", i), 0.1, y, 0.012);
+            line(&mut spans, &format!("right {}", i), 0.55, y, 0.012);
+        }
+        line(&mut spans, &page.to_string(), 0.5, 0.95, 0.012);
+        PageTextLayer { page, spans }
+    }
+
+    #[test]
+    fn columns_reading_order_and_furniture() {
+        let layout = analyze(
+            &two_column_page(5),
+            &[two_column_page(4), two_column_page(6)],
+        );
+        assert_eq!(layout.columns, 2);
+
+        let texts: Vec<&str> = layout.blocks.iter().map(|b| b.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "A Title Across",
+                "left 0 left 1 left 2 left 3 left 4 left 5",
+                "right 0 right 1 right 2 right 3 right 4 right 5",
+            ]
+        );
+        assert_eq!(layout.blocks[0].kind, BlockKind::Heading);
+        assert_eq!(layout.blocks[0].column, None);
+        assert_eq!(layout.blocks[2].column, Some(1));
+
+        let furniture: Vec<(BlockKind, &str)> = layout
+            .furniture
+            .iter()
+            .map(|b| (b.kind, b.text.as_str()))
+            .collect();
+        assert_eq!(
+            furniture,
+            vec![
+                (BlockKind::Header, "Journal of Things"),
+                (BlockKind::PageNumber, "5")
+            ]
+        );
+        assert!(!reading_text(&layout).contains("Journal"));
+    }
+
+    #[test]
+    fn single_column_without_neighbours_keeps_header() {
+        let mut spans = Vec::new();
+        line(&mut spans, "Running head", 0.1, 0.04, 0.012);
+        line(&mut spans, "1.2 Groups", 0.1, 0.2, 0.012);
+        line(
+            &mut spans,
+            "A group is a set with an associative opera-",
+            0.1,
+            0.24,
+            0.012,
+        );
+        line(&mut spans, "tion and an identity.", 0.1, 0.256, 0.012);
+        line(&mut spans, "Figure 3: The Cayley table.", 0.1, 0.4, 0.012);
+        line(&mut spans, "1 Named after Cayley.", 0.1, 0.85, 0.009);
+        let layout = analyze(&PageTextLayer { page: 1, spans }, &[]);
+
+        assert_eq!(layout.columns, 1);
+        let kinds: Vec<BlockKind> = layout.blocks.iter().map(|b| b.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                BlockKind::Paragraph,
+                BlockKind::Heading,
+                BlockKind::Paragraph,
+                BlockKind::Caption,
+                BlockKind::Footnote,
+            ]
+        );
+        assert_eq!(
+            layout.blocks[2].text,
+            "A group is a set with an associative operation and an identity."
+        );
+        assert!(layout.furniture.is_empty());
+    }
+
+    #[test]
+    fn page_numbers_and_section_numbers() {
+        assert!(is_page_number("12"));
+        assert!(is_page_number("– 7 –"));
+        assert!(is_page_number("xiv"));
+        assert!(is_page_number("Page 3"));
+        assert!(!is_page_number("Chapter"));
+        assert!(is_section_number("2.1"));
+        assert!(is_section_number("A.1"));
+        assert!(!is_section_number("A"));
+        assert!(!is_section_number("Theorem"));
+        assert_eq!(furniture_key("Chapter 3. Groups  41"), "chapter#.groups##");
+    }
+}
@@ -1,15 +1,41 @@
+mod agent_server;
+mod analytics;
+mod backup;
+#[cfg(not(mobile))]
+pub mod cli;
+mod clip_commands;
 mod commands;
 mod db;
+mod djvu_backend;
+mod doc_backend;
+mod doc_identity;
+mod epub_backend;
 mod folder_picker;
 mod highlight_commands;
+mod history_commands;
 mod json_storage;
+mod layout;
+mod library_watcher;
+mod math_text;
 mod models;
+mod note_images;
+mod note_links;
+mod note_revisions;
+mod note_store;
+mod ocr;
+mod outline_writer;
+mod outlines;
 mod pdf_commands;
 mod pdf_engine;
 mod pdf_models;
 mod pdf_protocol;
+mod pomodoro;
+mod scan_index;
 mod session_commands;
 mod snip_commands;
+mod structures;
+mod sync;
+mod xp_ledger;
 
 use commands::{DbState, PendingFile};
 use pdf_commands::PdfState;
@@ -22,7 +48,7 @@ use tauri::Manager;
 use tauri::Emitter;
 
 #[cfg(not(mobile))]
-fn find_pdf_in_args(args: &[String]) -> Option<String> {
+fn find_document_in_args(args: &[String]) -> Option<String> {
     for arg in args.iter().skip(1) {
         // Skip flags
         if arg.starts_with('-') {
@@ -33,11 +59,11 @@ fn find_pdf_in_args(args: &[String]) -> Option<String> {
             let decoded = url::form_urlencoded::parse(path.as_bytes())
                 .map(|(k, v)| if v.is_empty() { k.to_string() } else { format!("{}={}", k, v) })
                 .collect::<String>();
-            if decoded.to_lowercase().ends_with(".pdf") {
+            if doc_backend::is_supported_document(std::path::Path::new(&decoded)) {
                 return Some(decoded);
             }
         }
-        if arg.to_lowercase().ends_with(".pdf") {
+        if doc_backend::is_supported_document(std::path::Path::new(arg)) {
             return Some(arg.clone());
         }
     }
@@ -46,7 +72,7 @@ fn find_pdf_in_args(args: &[String]) -> Option<String> {
 
 #[cfg(not(mobile))]
 fn handle_file_open(app: &tauri::AppHandle, args: &[String]) {
-    if let Some(path) = find_pdf_in_args(args) {
+    if let Some(path) = find_document_in_args(args) {
         let _ = app.emit("open-file", path);
     }
 }
@@ -62,6 +88,41 @@ fn pdfium_lib_name() -> &'static str {
     }
 }
 
+/// Locate the PDFium shared library in the bundle's resource directory, or
+/// under `resources/` when running from the source tree.
+#[cfg(not(mobile))]
+pub(crate) fn find_pdfium(resource_dir: Option<std::path::PathBuf>) -> std::path::PathBuf {
+    let lib_name = pdfium_lib_name();
+    resource_dir
+        .and_then(|d| {
+            let nested = d.join("resources").join(lib_name);
+            let flat = d.join(lib_name);
+            if nested.exists() {
+                Some(nested)
+            } else if flat.exists() {
+                Some(flat)
+            } else {
+                None
+            }
+        })
+        .or_else(|| {
+            [
+                std::path::PathBuf::from("resources").join(lib_name),
+                std::path::PathBuf::from("src-tauri/resources").join(lib_name),
+            ]
+            .into_iter()
+            .find(|p| p.exists())
+        })
+        .unwrap_or_else(|| {
+            log::warn!(
+                "PDFium library ({}) not found — PDF rendering will fail. \
+                 Download from https://github.com/bblanchon/pdfium-binaries",
+                lib_name
+            );
+            std::path::PathBuf::from(lib_name)
+        })
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let (tx, rx) = crossbeam_channel::unbounded::<pdf_engine::PdfRequest>();
@@ -110,11 +171,30 @@ pub fn run() {
             let conn = db::init_db(&db_path).expect("failed to init database");
             app.manage(DbState(Mutex::new(conn)));
 
-            // Check CLI args for a PDF file path (desktop only)
+            let watcher = library_watcher::LibraryWatcher::start(app.handle());
+            app.manage(watcher);
+
+            let pomodoro = pomodoro::PomodoroService::start(app.handle(), &app_data);
+            app.manage(pomodoro);
+
+            let agent_server = agent_server::AgentServer::start(app.handle(), &app_data);
+            app.manage(agent_server);
+
+            let sync = sync::SyncService::start(app.handle(), &app_data);
+            app.manage(sync);
+
+            let revisions = {
+                let db = app.state::<DbState>();
+                let conn = db.0.lock().expect("database lock poisoned");
+                note_revisions::NoteRevisionSettings::start(&app_data, &conn)
+            };
+            app.manage(revisions);
+
+            // Check CLI args for a document path (desktop only)
             #[cfg(not(mobile))]
             let pending = {
                 let args: Vec<String> = std::env::args().collect();
-                find_pdf_in_args(&args)
+                find_document_in_args(&args)
             };
             #[cfg(mobile)]
             let pending: Option<String> = None;
@@ -126,39 +206,7 @@ pub fn run() {
             // dlopen("libpdfium.so") resolves it from the app's native lib dir.
             #[cfg(not(mobile))]
             let lib_path = {
-                let lib_name = pdfium_lib_name();
-                let path = app
-                    .path()
-                    .resource_dir()
-                    .ok()
-                    .and_then(|d| {
-                        let nested = d.join("resources").join(lib_name);
-                        let flat = d.join(lib_name);
-                        if nested.exists() {
-                            Some(nested)
-                        } else if flat.exists() {
-                            Some(flat)
-                        } else {
-                            None
-                        }
-                    })
-                    .or_else(|| {
-                        [
-                            std::path::PathBuf::from("resources").join(lib_name),
-                            std::path::PathBuf::from("src-tauri/resources").join(lib_name),
-                        ]
-                        .into_iter()
-                        .find(|p| p.exists())
-                    })
-                    .unwrap_or_else(|| {
-                        log::warn!(
-                            "PDFium library ({}) not found — PDF rendering will fail. \
-                             Download from https://github.com/bblanchon/pdfium-binaries",
-                            lib_name
-                        );
-                        std::path::PathBuf::from(lib_name)
-                    });
-
+                let path = find_pdfium(app.path().resource_dir().ok());
                 log::info!("Loading PDFium from {:?}", path);
                 Pdfium::bind_to_library(&path)
                     .map_err(|e| {
@@ -184,8 +232,15 @@ pub fn run() {
                 path
             };
 
+            // EPUB books are converted to PDF once and kept in the app cache
+            if let Ok(dir) = app.path().app_cache_dir() {
+                epub_backend::set_cache_dir(dir);
+            }
+
+            // Scanned pages are recognised in the background (`ocr` feature)
+            let ocr_queue = ocr::OcrQueue::start(tx.clone());
             let _render_workers = pdf_engine::run_pool(
-                rx, lib_path, gen_render, cache_render, pdf_engine::worker_count(),
+                rx, lib_path, gen_render, cache_render, ocr_queue, pdf_engine::worker_count(),
             );
 
             app.manage(PdfState {
@@ -207,6 +262,7 @@ pub fn run() {
             commands::get_note,
             commands::set_note,
             commands::list_notes_for_book,
+            commands::list_notes_for_page,
             commands::delete_note,
             commands::save_note_image,
             commands::get_note_image,
@@ -235,6 +291,7 @@ pub fn run() {
             commands::increment_xp,
             commands::detect_orphaned_slugs,
             commands::migrate_slug,
+            doc_identity::resolve_slug,
             highlight_commands::list_highlights,
             highlight_commands::create_highlight,
             highlight_commands::delete_highlight,
@@ -243,9 +300,14 @@ pub fn run() {
             pdf_commands::close_document,
             pdf_commands::get_outline,
             pdf_commands::get_page_links,
+            pdf_commands::resolve_named_destination,
+            pdf_commands::resolve_remote_link,
             pdf_commands::extract_page_text,
             pdf_commands::search_document,
             pdf_commands::clip_pdf,
+            clip_commands::get_clip_provenance,
+            clip_commands::get_clip_annotations,
+            pdf_commands::get_page_layout,
             pdf_commands::get_page_text_layer,
             pdf_commands::prerender_pages,
             snip_commands::list_snips,
@@ -266,10 +328,57 @@ pub fn run() {
             snip_commands::delete_snip_tag_def,
             snip_commands::rename_snip_tag_def,
             snip_commands::recolor_snip_tag_def,
+            structures::list_structures,
+            structures::find_structure,
+            structures::index_structures,
+            structures::create_snip_from_structure,
+            outlines::get_custom_outline,
+            outlines::save_custom_outline,
+            outlines::delete_custom_outline,
+            outlines::get_book_outline,
+            outlines::generate_outline,
+            outlines::export_outline_pdf,
             session_commands::log_study_session,
             session_commands::increment_pomodoro_xp,
             session_commands::get_pomodoro_xp,
             session_commands::list_study_sessions,
+            pomodoro::get_pomodoro_state,
+            pomodoro::toggle_pomodoro,
+            pomodoro::reset_pomodoro,
+            pomodoro::skip_pomodoro_phase,
+            pomodoro::dismiss_pomodoro_overlay,
+            pomodoro::set_pomodoro_config,
+            pomodoro::set_pomodoro_book,
+            agent_server::get_agent_server,
+            agent_server::set_agent_server_config,
+            backup::export_backup,
+            backup::import_backup,
+            sync::get_sync_status,
+            sync::set_sync_config,
+            sync::sync_now,
+            sync::dismiss_sync_conflict,
+            note_store::get_note_storage,
+            note_store::set_note_storage,
+            note_links::get_backlinks,
+            note_images::collect_note_images,
+            note_images::get_storage_report,
+            note_images::vacuum,
+            note_revisions::list_note_revisions,
+            note_revisions::restore_note_revision,
+            note_revisions::get_note_revision_config,
+            note_revisions::set_note_revision_config,
+            history_commands::record_reading_position,
+            history_commands::record_jump,
+            history_commands::navigate_back,
+            history_commands::navigate_forward,
+            history_commands::get_reading_history,
+            history_commands::get_last_position,
+            history_commands::get_reading_pace,
+            analytics::get_study_stats,
+            xp_ledger::record_xp,
+            xp_ledger::revert_xp_event,
+            xp_ledger::list_xp_events,
+            xp_ledger::get_xp_summary,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::commands::{list_directories_inner, DbState};
+use crate::models::Directory;
+use crate::scan_index;
+
+/// Quiet period after the last filesystem event before a batch is processed.
+/// Copying a large PDF produces a burst of write events; we only want one rescan.
+const DEBOUNCE: Duration = Duration::from_millis(750);
+
+/// Background watcher over all library directories. Filesystem events are
+/// debounced, the affected directories rescanned through the scan index, and
+/// the difference emitted to the frontend as a `library-changed` event.
+pub struct LibraryWatcher {
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl LibraryWatcher {
+    /// Start watching every tracked directory. Failure to create the OS watcher
+    /// is logged and leaves a no-op watcher; the library still works on demand.
+    pub fn start(app: &AppHandle) -> Self {
+        let (tx, rx) = crossbeam_channel::unbounded::<PathBuf>();
+
+        let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+            Ok(event) => {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+            Err(e) => log::warn!("library watcher: {}", e),
+        });
+        let watcher = match watcher {
+            Ok(w) => Some(w),
+            Err(e) => {
+                log::warn!("library watcher unavailable: {}", e);
+                None
+            }
+        };
+
+        let this = Self {
+            watcher: Mutex::new(watcher),
+        };
+
+        let dirs = {
+            let state = app.state::<DbState>();
+            let conn = state.0.lock().ok();
+            conn.and_then(|c| list_directories_inner(&c).ok()).unwrap_or_default()
+        };
+        for dir in &dirs {
+            this.watch(dir);
+        }
+
+        let app = app.clone();
+        std::thread::Builder::new()
+            .name("library-watcher".into())
+            .spawn(move || run(app, rx))
+            .expect("failed to spawn library watcher thread");
+
+        this
+    }
+
+    /// Begin watching a directory.
+    pub fn watch(&self, dir: &Directory) {
+        if let Ok(mut guard) = self.watcher.lock() {
+            if let Some(w) = guard.as_mut() {
+                if let Err(e) = w.watch(Path::new(&dir.path), RecursiveMode::Recursive) {
+                    log::warn!("library watcher: cannot watch {}: {}", dir.path, e);
+                }
+            }
+        }
+    }
+
+    /// Stop watching a directory that was removed from the library.
+    pub fn unwatch(&self, dir: &Directory) {
+        if let Ok(mut guard) = self.watcher.lock() {
+            if let Some(w) = guard.as_mut() {
+                let _ = w.unwatch(Path::new(&dir.path));
+            }
+        }
+    }
+}
+
+/// Debounce loop: collect changed paths until the filesystem goes quiet, then
+/// rescan the directories they belong to.
+fn run(app: AppHandle, rx: Receiver<PathBuf>) {
+    while let Ok(first) = rx.recv() {
+        let mut changed = vec![first];
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(path) => changed.push(path),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let state = app.state::<DbState>();
+        let dirs = match state.0.lock().map_err(|e| e.to_string()).and_then(|c| list_directories_inner(&c)) {
+            Ok(dirs) => dirs,
+            Err(e) => {
+                log::warn!("library watcher: {}", e);
+                continue;
+            }
+        };
+        let affected = affected_dirs(&dirs, &changed);
+        if affected.is_empty() {
+            continue;
+        }
+
+        match scan_index::refresh_dirs(&app, &affected, &changed) {
+            Ok(change) if !change.is_empty() => {
+                let _ = app.emit("library-changed", change);
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("library watcher: rescan failed: {}", e),
+        }
+    }
+}
+
+/// Library directories containing any of `changed`. Paths inside
+/// `.axiomatic/` are our own state writes and never trigger a rescan.
+fn affected_dirs(dirs: &[Directory], changed: &[PathBuf]) -> Vec<Directory> {
+    let relevant: Vec<&PathBuf> = changed
+        .iter()
+        .filter(|p| !p.components().any(|c| c.as_os_str() == ".axiomatic"))
+        .collect();
+    dirs.iter()
+        .filter(|d| relevant.iter().any(|p| p.starts_with(&d.path)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(id: i64, path: &str) -> Directory {
+        Directory {
+            id,
+            path: path.into(),
+            label: String::new(),
+            added_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn affected_dirs_match_by_prefix() {
+        let dirs = vec![dir(1, "/lib/math"), dir(2, "/lib/physics")];
+        let changed = vec![PathBuf::from("/lib/math/analysis/rudin.pdf")];
+        let affected = affected_dirs(&dirs, &changed);
+        assert_eq!(affected.len(), 1);
+        assert_eq!(affected[0].id, 1);
+    }
+
+    #[test]
+    fn axiomatic_writes_are_ignored() {
+        let dirs = vec![dir(1, "/lib/math")];
+        let changed = vec![
+            PathBuf::from("/lib/math/.axiomatic/progress.json"),
+            PathBuf::from("/lib/math/.axiomatic"),
+        ];
+        assert!(affected_dirs(&dirs, &changed).is_empty());
+    }
+
+    #[test]
+    fn component_prefix_only() {
+        // "/lib/mathematics" is not inside "/lib/math"
+        let dirs = vec![dir(1, "/lib/math")];
+        let changed = vec![PathBuf::from("/lib/mathematics/a.pdf")];
+        assert!(affected_dirs(&dirs, &changed).is_empty());
+    }
+}
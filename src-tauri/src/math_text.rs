@@ -0,0 +1,552 @@
+//! Math-aware text extraction: rebuilds LaTeX from glyph geometry.
+//!
+//! PDF text extraction loses the 2D layout of formulas, so `∑_{i=1}^n x_i^2`
+//! comes out as "∑ n i=1 x 2 i". Here glyphs are grouped into lines, then
+//! classified against the line's baseline and font size as base, superscript,
+//! subscript, or fraction numerator/denominator, and reassembled as LaTeX.
+//! Runs that look like math are wrapped in `$...$`.
+
+use crate::pdf_models::NormalizedRect;
+
+/// One glyph in page space (points, y axis pointing up).
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub ch: char,
+    pub left: f32,
+    pub right: f32,
+    /// Ink bounds.
+    pub bottom: f32,
+    pub top: f32,
+    /// Baseline (text origin y).
+    pub baseline: f32,
+    pub size: f32,
+    pub italic: bool,
+}
+
+/// All glyphs of a page with the page size in points.
+#[derive(Debug, Clone, Default)]
+pub struct PageGlyphs {
+    pub width: f32,
+    pub height: f32,
+    pub glyphs: Vec<Glyph>,
+}
+
+/// Glyphs smaller than this fraction of the line size are scripts.
+const SCRIPT_RATIO: f32 = 0.85;
+/// Horizontal gap, relative to font size, that separates words.
+const WORD_GAP: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    Base,
+    Sup,
+    Sub,
+    Above,
+    Below,
+}
+
+/// A base symbol with its scripts, or a fraction.
+#[derive(Debug, Clone)]
+struct Atom {
+    latex: String,
+    left: f32,
+    right: f32,
+    size: f32,
+    sub: Vec<(f32, String)>,
+    sup: Vec<(f32, String)>,
+    math: bool,
+}
+
+impl Atom {
+    fn render(&self) -> String {
+        let mut out = self.latex.clone();
+        for (marker, parts) in [("_", &self.sub), ("^", &self.sup)] {
+            if parts.is_empty() {
+                continue;
+            }
+            let mut parts = parts.clone();
+            parts.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let body: String = parts.into_iter().map(|(_, s)| s).collect();
+            if body.chars().count() == 1 {
+                out.push_str(&format!("{}{}", marker, body));
+            } else {
+                out.push_str(&format!("{}{{{}}}", marker, body));
+            }
+        }
+        out
+    }
+
+    fn is_math(&self) -> bool {
+        self.math || !self.sub.is_empty() || !self.sup.is_empty()
+    }
+}
+
+/// Reconstruct the text of a page, optionally limited to a region in
+/// normalized top-down coordinates (as used by snips).
+pub fn reconstruct(page: &PageGlyphs, region: Option<&NormalizedRect>) -> String {
+    let glyphs: Vec<&Glyph> = page
+        .glyphs
+        .iter()
+        .filter(|g| !g.ch.is_whitespace() && g.size > 0.0)
+        .filter(|g| match region {
+            None => true,
+            Some(r) => {
+                let x = (g.left + g.right) / 2.0 / page.width;
+                let y = 1.0 - (g.baseline + g.size * 0.3) / page.height;
+                x >= r.x && x <= r.x + r.width && y >= r.y && y <= r.y + r.height
+            }
+        })
+        .collect();
+
+    group_lines(&glyphs)
+        .iter()
+        .map(|line| render_line(line))
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Cluster glyphs into text lines. Glyphs sharing a baseline form rows;
+/// rows are then merged into lines, heaviest (most full-size text) first, so
+/// scripts, limits and fraction parts within about one font size of a line's
+/// baseline join it rather than starting lines of their own.
+fn group_lines<'a>(glyphs: &[&'a Glyph]) -> Vec<Vec<&'a Glyph>> {
+    let mut rows = rows(glyphs);
+    rows.sort_by(|a, b| {
+        let weight = |r: &Vec<&Glyph>| r.iter().map(|g| g.size * g.size).sum::<f32>();
+        weight(b).total_cmp(&weight(a))
+    });
+
+    // (baseline, size, members)
+    let mut lines: Vec<(f32, f32, Vec<&Glyph>)> = Vec::new();
+    for row in rows {
+        let baseline = row[0].baseline;
+        let home = lines
+            .iter_mut()
+            .filter(|(b, size, _)| {
+                let d = baseline - b;
+                d < size * 1.15 && d > -size * 0.9
+            })
+            .min_by(|a, b| (baseline - a.0).abs().total_cmp(&(baseline - b.0).abs()));
+        match home {
+            Some((_, _, members)) => members.extend(row),
+            None => {
+                let size = row.iter().map(|g| effective_size(g)).fold(0.0, f32::max);
+                lines.push((baseline, size, row));
+            }
+        }
+    }
+    // Top of the page first
+    lines.sort_by(|a, b| b.0.total_cmp(&a.0));
+    lines
+        .into_iter()
+        .map(|(_, _, mut members)| {
+            members.sort_by(|a, b| a.left.total_cmp(&b.left));
+            members
+        })
+        .collect()
+}
+
+/// Group glyphs with (nearly) the same baseline.
+fn rows<'a>(glyphs: &[&'a Glyph]) -> Vec<Vec<&'a Glyph>> {
+    let mut sorted = glyphs.to_vec();
+    sorted.sort_by(|a, b| b.baseline.total_cmp(&a.baseline));
+    let mut rows: Vec<Vec<&Glyph>> = Vec::new();
+    for g in sorted {
+        match rows.last_mut() {
+            Some(row) if row[0].baseline - g.baseline < row[0].size.min(g.size) * 0.1 => row.push(g),
+            _ => rows.push(vec![g]),
+        }
+    }
+    rows
+}
+
+/// Big operators are set in a nominal font size but drawn much larger.
+fn effective_size(g: &Glyph) -> f32 {
+    if is_big_operator(g.ch) {
+        g.size.max(g.top - g.bottom)
+    } else {
+        g.size
+    }
+}
+
+fn is_big_operator(ch: char) -> bool {
+    matches!(ch, '∑' | '∏' | '∐' | '∫' | '∬' | '∭' | '∮' | '⋃' | '⋂' | '⨁' | '⨂')
+}
+
+fn classify(g: &Glyph, baseline: f32, size: f32) -> Role {
+    if is_big_operator(g.ch) {
+        return Role::Base;
+    }
+    let rel = (g.baseline - baseline) / size;
+    let small = g.size < size * SCRIPT_RATIO;
+    if small && rel > 0.2 {
+        Role::Sup
+    } else if small && rel < -0.05 {
+        Role::Sub
+    } else if !small && rel > 0.4 {
+        Role::Above
+    } else if !small && rel < -0.4 {
+        Role::Below
+    } else {
+        Role::Base
+    }
+}
+
+/// Split x-sorted glyphs into words by horizontal gaps.
+fn runs<'a>(glyphs: &[&'a Glyph]) -> Vec<Vec<&'a Glyph>> {
+    let mut out: Vec<Vec<&Glyph>> = Vec::new();
+    for g in glyphs {
+        match out.last_mut() {
+            Some(run) if g.left - run.last().unwrap().right < g.size * WORD_GAP => run.push(g),
+            _ => out.push(vec![g]),
+        }
+    }
+    out
+}
+
+fn plain(glyphs: &[&Glyph]) -> String {
+    glyphs.iter().map(|g| latex_symbol(g.ch)).collect()
+}
+
+fn overlap(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.1.min(b.1) - a.0.max(b.0)).max(0.0)
+}
+
+fn render_line(line: &[&Glyph]) -> String {
+    let body: Vec<&Glyph> = line.iter().copied().filter(|g| !is_big_operator(g.ch)).collect();
+    let size = body.iter().map(|g| g.size).fold(0.0, f32::max);
+    if size <= 0.0 {
+        return plain(line);
+    }
+    // The baseline is the row holding most of the full-size text
+    let full: Vec<&Glyph> = body.into_iter().filter(|g| g.size >= size * SCRIPT_RATIO).collect();
+    let baseline = rows(&full)
+        .into_iter()
+        .max_by_key(|r| r.len())
+        .map(|r| r[0].baseline)
+        .unwrap_or(line[0].baseline);
+
+    let mut atoms: Vec<Atom> = Vec::new();
+    let mut scripts: Vec<(Role, &Glyph)> = Vec::new();
+    let mut above: Vec<&Glyph> = Vec::new();
+    let mut below: Vec<&Glyph> = Vec::new();
+
+    for g in line {
+        match classify(g, baseline, size) {
+            Role::Base => atoms.push(Atom {
+                latex: latex_symbol(g.ch),
+                left: g.left,
+                right: g.right,
+                size: g.size,
+                sub: Vec::new(),
+                sup: Vec::new(),
+                math: is_math_char(g.ch) || (g.italic && g.ch.is_alphabetic()),
+            }),
+            Role::Above => above.push(g),
+            Role::Below => below.push(g),
+            role => scripts.push((role, g)),
+        }
+    }
+
+    // Stacked numerator/denominator runs become fractions; leftovers are
+    // treated like limits (scripts) of whatever they sit over.
+    let above_runs = runs(&above);
+    let mut below_runs: Vec<Option<Vec<&Glyph>>> = runs(&below).into_iter().map(Some).collect();
+    for num in above_runs {
+        let span = (num.first().unwrap().left, num.last().unwrap().right);
+        let den = below_runs.iter_mut().find(|d| {
+            d.as_ref()
+                .map(|d| overlap(span, (d.first().unwrap().left, d.last().unwrap().right)) > 0.0)
+                .unwrap_or(false)
+        });
+        match den.and_then(Option::take) {
+            Some(den) => {
+                let left = span.0.min(den.first().unwrap().left);
+                let right = span.1.max(den.last().unwrap().right);
+                atoms.push(Atom {
+                    latex: format!("\\frac{{{}}}{{{}}}", plain(&num), plain(&den)),
+                    left,
+                    right,
+                    size,
+                    sub: Vec::new(),
+                    sup: Vec::new(),
+                    math: true,
+                });
+            }
+            None => scripts.extend(num.into_iter().map(|g| (Role::Sup, g))),
+        }
+    }
+    for den in below_runs.into_iter().flatten() {
+        scripts.extend(den.into_iter().map(|g| (Role::Sub, g)));
+    }
+    atoms.sort_by(|a, b| a.left.total_cmp(&b.left));
+
+    // Attach each script to the atom it overlaps most, else the one before it
+    for (role, g) in scripts {
+        let target = atoms
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (i, overlap((a.left, a.right), (g.left, g.right))))
+            .filter(|(_, o)| *o > 0.0)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+            .or_else(|| atoms.iter().rposition(|a| a.left <= g.left));
+        let entry = (g.left, latex_symbol(g.ch));
+        match (target, role) {
+            (Some(i), Role::Sup) => atoms[i].sup.push(entry),
+            (Some(i), _) => atoms[i].sub.push(entry),
+            (None, _) => atoms.insert(
+                0,
+                Atom {
+                    latex: entry.1,
+                    left: g.left,
+                    right: g.right,
+                    size: g.size,
+                    sub: Vec::new(),
+                    sup: Vec::new(),
+                    math: true,
+                },
+            ),
+        }
+    }
+
+    // Words, split where the gap after an atom (scripts included) is wide
+    let mut words: Vec<Vec<Atom>> = Vec::new();
+    let mut prev_right = f32::MIN;
+    for atom in atoms {
+        let gap = atom.left - prev_right;
+        prev_right = prev_right.max(atom.right);
+        match words.last_mut() {
+            Some(word) if gap < atom.size * WORD_GAP => word.push(atom),
+            _ => words.push(vec![atom]),
+        }
+    }
+
+    wrap_math(&words)
+}
+
+/// Join words, wrapping maximal runs of math words in `$...$`. Short words
+/// next to a relation or operator (`x`, `2` in `x = 2`) count as math too.
+fn wrap_math(words: &[Vec<Atom>]) -> String {
+    let texts: Vec<String> = words
+        .iter()
+        .map(|w| w.iter().map(Atom::render).collect())
+        .collect();
+    let mut math: Vec<bool> = words.iter().map(|w| w.iter().any(Atom::is_math)).collect();
+    let is_operator = |t: &str| matches!(t, "=" | "+" | "-" | "<" | ">" | "/" | "\\leq" | "\\geq" | "\\neq" | "\\in" | "\\to" | "\\cdot" | "\\times" | "\\pm");
+    for i in 0..texts.len() {
+        if is_operator(&texts[i]) {
+            math[i] = true;
+            for j in [i.wrapping_sub(1), i + 1] {
+                if let Some(t) = texts.get(j) {
+                    let number = t.chars().all(|c| c.is_ascii_digit() || c == '.');
+                    let short = t.chars().count() <= 3 && t.chars().all(|c| c.is_alphanumeric() || "\\{}_^".contains(c));
+                    if number || short {
+                        math[j] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let mut i = 0;
+    while i < texts.len() {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        if math[i] {
+            let start = i;
+            while i < texts.len() && math[i] {
+                i += 1;
+            }
+            out.push('$');
+            out.push_str(&texts[start..i].join(" "));
+            out.push('$');
+        } else {
+            out.push_str(&texts[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn is_math_char(ch: char) -> bool {
+    let s = latex_symbol(ch);
+    s.starts_with('\\') || matches!(ch, '=' | '<' | '>' | '+' | '^')
+}
+
+/// LaTeX for a Unicode math symbol; other characters are returned as is.
+fn latex_symbol(ch: char) -> String {
+    let s = match ch {
+        // Greek
+        'α' => "\\alpha", 'β' => "\\beta", 'γ' => "\\gamma", 'δ' => "\\delta",
+        'ε' | 'ϵ' => "\\epsilon", 'ζ' => "\\zeta", 'η' => "\\eta", 'θ' => "\\theta",
+        'ι' => "\\iota", 'κ' => "\\kappa", 'λ' => "\\lambda", 'μ' => "\\mu",
+        'ν' => "\\nu", 'ξ' => "\\xi", 'π' => "\\pi", 'ρ' => "\\rho",
+        'σ' => "\\sigma", 'τ' => "\\tau", 'υ' => "\\upsilon", 'φ' | 'ϕ' => "\\phi",
+        'χ' => "\\chi", 'ψ' => "\\psi", 'ω' => "\\omega",
+        'Γ' => "\\Gamma", 'Δ' => "\\Delta", 'Θ' => "\\Theta", 'Λ' => "\\Lambda",
+        'Ξ' => "\\Xi", 'Π' => "\\Pi", 'Σ' => "\\Sigma", 'Φ' => "\\Phi",
+        'Ψ' => "\\Psi", 'Ω' => "\\Omega",
+        // Big operators
+        '∑' => "\\sum", '∏' => "\\prod", '∐' => "\\coprod", '∫' => "\\int",
+        '∬' => "\\iint", '∭' => "\\iiint", '∮' => "\\oint", '⋃' => "\\bigcup",
+        '⋂' => "\\bigcap", '⨁' => "\\bigoplus", '⨂' => "\\bigotimes",
+        // Relations and operators
+        '≤' => "\\leq", '≥' => "\\geq", '≠' => "\\neq", '≈' => "\\approx",
+        '≡' => "\\equiv", '∼' => "\\sim", '≅' => "\\cong", '∝' => "\\propto",
+        '∈' => "\\in", '∉' => "\\notin", '∋' => "\\ni", '⊂' => "\\subset",
+        '⊆' => "\\subseteq", '⊃' => "\\supset", '⊇' => "\\supseteq",
+        '∪' => "\\cup", '∩' => "\\cap", '∖' => "\\setminus", '×' => "\\times",
+        '·' | '⋅' => "\\cdot", '÷' => "\\div", '±' => "\\pm", '∓' => "\\mp",
+        '∘' => "\\circ", '⊕' => "\\oplus", '⊗' => "\\otimes", '−' => "-",
+        '∗' => "*", '∣' => "\\mid", '∥' => "\\parallel", '⊥' => "\\perp",
+        // Arrows and logic
+        '→' => "\\to", '←' => "\\leftarrow", '↔' => "\\leftrightarrow",
+        '⇒' => "\\Rightarrow", '⇐' => "\\Leftarrow", '⇔' => "\\Leftrightarrow",
+        '↦' => "\\mapsto", '∀' => "\\forall", '∃' => "\\exists", '¬' => "\\neg",
+        '∧' => "\\wedge", '∨' => "\\vee",
+        // Misc
+        '∞' => "\\infty", '∂' => "\\partial", '∇' => "\\nabla", '√' => "\\sqrt",
+        '∅' => "\\emptyset", 'ℵ' => "\\aleph", '′' => "'", '″' => "''",
+        '…' => "\\ldots", '⋯' => "\\cdots", '⟨' => "\\langle", '⟩' => "\\rangle",
+        '⌊' => "\\lfloor", '⌋' => "\\rfloor", '⌈' => "\\lceil", '⌉' => "\\rceil",
+        'ℝ' => "\\mathbb{R}", 'ℕ' => "\\mathbb{N}", 'ℤ' => "\\mathbb{Z}",
+        'ℚ' => "\\mathbb{Q}", 'ℂ' => "\\mathbb{C}",
+        _ => return ch.to_string(),
+    };
+    s.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lay out glyphs left to right: (text, size, baseline offset).
+    fn layout(parts: &[(&str, f32, f32)]) -> PageGlyphs {
+        let mut x = 10.0;
+        let mut glyphs = Vec::new();
+        for (text, size, dy) in parts {
+            if text.is_empty() {
+                x += size; // explicit gap
+                continue;
+            }
+            for ch in text.chars() {
+                glyphs.push(Glyph { italic: false, ..at(ch, x, 700.0 + dy, *size) });
+                x += size * 0.5;
+            }
+        }
+        PageGlyphs { width: 612.0, height: 792.0, glyphs }
+    }
+
+    /// A glyph as set in a math formula: letters are italic.
+    fn at(ch: char, left: f32, baseline: f32, size: f32) -> Glyph {
+        Glyph {
+            ch,
+            left,
+            right: left + size * 0.5,
+            bottom: baseline,
+            top: baseline + size * 0.7,
+            baseline,
+            size,
+            italic: ch.is_alphabetic(),
+        }
+    }
+
+    #[test]
+    fn plain_text_is_untouched() {
+        let page = layout(&[("Let", 10.0, 0.0), ("", 4.0, 0.0), ("us", 10.0, 0.0)]);
+        assert_eq!(reconstruct(&page, None), "Let us");
+    }
+
+    #[test]
+    fn superscript_and_subscript() {
+        // x_i^2 : '2' raised and small, 'i' lowered and small, stacked
+        let page = PageGlyphs {
+            width: 612.0,
+            height: 792.0,
+            glyphs: vec![
+                at('x', 10.0, 700.0, 10.0),
+                at('2', 15.0, 704.0, 7.0),
+                at('i', 15.0, 697.5, 7.0),
+            ],
+        };
+        assert_eq!(reconstruct(&page, None), "$x_i^2$");
+    }
+
+    #[test]
+    fn sum_with_limits() {
+        // ∑ with 'n' above and 'i=1' below, then x
+        let page = PageGlyphs {
+            width: 612.0,
+            height: 792.0,
+            glyphs: vec![
+                at('∑', 10.0, 697.0, 14.0),
+                at('n', 12.0, 711.0, 7.0),
+                at('i', 10.0, 692.0, 7.0),
+                at('=', 13.5, 692.0, 7.0),
+                at('1', 17.0, 692.0, 7.0),
+                at('x', 22.0, 700.0, 10.0),
+            ],
+        };
+        assert_eq!(reconstruct(&page, None), "$\\sum_{i=1}^n x$");
+    }
+
+    #[test]
+    fn stacked_fraction() {
+        // a+b over c, next to "= 1"
+        let page = PageGlyphs {
+            width: 612.0,
+            height: 792.0,
+            glyphs: vec![
+                at('a', 10.0, 706.0, 10.0),
+                at('+', 15.0, 706.0, 10.0),
+                at('b', 20.0, 706.0, 10.0),
+                at('c', 15.0, 693.0, 10.0),
+                at('=', 30.0, 700.0, 10.0),
+                at('1', 40.0, 700.0, 10.0),
+                at('1', 44.0, 700.0, 10.0),
+                at('2', 48.0, 700.0, 10.0),
+                at('3', 52.0, 700.0, 10.0),
+            ],
+        };
+        assert_eq!(reconstruct(&page, None), "$\\frac{a+b}{c} = 1123$");
+    }
+
+    #[test]
+    fn symbols_and_inline_relations() {
+        let page = layout(&[
+            ("for", 10.0, 0.0), ("", 4.0, 0.0),
+            ("all", 10.0, 0.0), ("", 4.0, 0.0),
+            ("ε", 10.0, 0.0), ("", 4.0, 0.0),
+            (">", 10.0, 0.0), ("", 4.0, 0.0),
+            ("0", 10.0, 0.0),
+        ]);
+        assert_eq!(reconstruct(&page, None), "for all $\\epsilon > 0$");
+    }
+
+    #[test]
+    fn separate_lines_and_region() {
+        let mut page = layout(&[("top", 10.0, 0.0)]);
+        page.glyphs.extend(layout(&[("bottom", 10.0, -300.0)]).glyphs);
+        assert_eq!(reconstruct(&page, None), "top\nbottom");
+
+        // Region covering only the lower half of the page
+        let region = NormalizedRect { x: 0.0, y: 0.4, width: 1.0, height: 0.6 };
+        assert_eq!(reconstruct(&page, Some(&region)), "bottom");
+
+        // Adjacent lines at 12pt leading; the script stays with its own line
+        let page = PageGlyphs {
+            width: 612.0,
+            height: 792.0,
+            glyphs: vec![
+                at('a', 10.0, 700.0, 10.0),
+                at('b', 10.0, 688.0, 10.0),
+                at('2', 15.0, 692.0, 7.0),
+            ],
+        };
+        assert_eq!(reconstruct(&page, None), "$a$\n$b^2$");
+    }
+}
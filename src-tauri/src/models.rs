@@ -17,6 +17,12 @@ pub struct Textbook {
     pub dir_id: i64,
     pub dir_path: String,
     pub full_path: String,
+    /// Stable content identity (see `doc_identity`), filled in after scanning.
+    #[serde(default)]
+    pub doc_id: Option<String>,
+    /// Known once the document has been opened (see `scan_index`).
+    #[serde(default)]
+    pub page_count: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +30,8 @@ pub struct NoteRecord {
     pub id: i64,
     pub slug: String,
     pub page: i64,
+    #[serde(default)]
+    pub anchor: NoteAnchor,
     pub content: String,
     pub format: String,
     pub updated_at: String,
@@ -74,12 +82,39 @@ pub struct Snip {
     pub tags: Vec<String>,
     #[serde(default = "default_snip_status")]
     pub status: String,
+    /// When the snip was last marked solid; cleared when it leaves "solid".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solid_at: Option<String>,
 }
 
 fn default_snip_status() -> String {
     "open".into()
 }
 
+/// A labelled environment found in a book's text layer: "Theorem 3.2",
+/// "Definition 1.4", "Exercise 5.7". The rect (normalized, top-down) covers
+/// the statement as laid out on the page.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Structure {
+    pub id: i64,
+    pub slug: String,
+    pub kind: String,
+    pub number: String,
+    pub title: Option<String>,
+    pub page: i64,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Payload of the `structures-indexed` event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructuresIndexed {
+    pub slug: String,
+    pub count: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SnipTagDef {
     pub name: String,
@@ -94,6 +129,182 @@ pub struct BookProgress {
     pub last_read_at: String,
 }
 
+/// Where the reader is in a book: page, offset into the page (0..1 of its
+/// height), zoom factor and when the position was recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingPosition {
+    pub page: i64,
+    #[serde(default)]
+    pub scroll_offset: f64,
+    #[serde(default = "default_zoom")]
+    pub zoom: f64,
+    #[serde(default)]
+    pub timestamp: String,
+}
+
+fn default_zoom() -> f64 {
+    1.0
+}
+
+/// One entry of a book's reading log. `jump` marks positions reached by
+/// following a link or outline entry rather than by reading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    #[serde(flatten)]
+    pub position: ReadingPosition,
+    #[serde(default)]
+    pub jump: bool,
+}
+
+/// A book's reading log and back/forward stacks, kept in the
+/// `reading_log` and `reading_stack` tables.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingHistory {
+    #[serde(default)]
+    pub log: Vec<HistoryEntry>,
+    #[serde(default)]
+    pub back: Vec<ReadingPosition>,
+    #[serde(default)]
+    pub forward: Vec<ReadingPosition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingPace {
+    pub pages_read: f64,
+    pub minutes_read: f64,
+    pub pages_per_hour: Option<f64>,
+    pub remaining_pages: Option<i64>,
+    pub eta_minutes: Option<f64>,
+}
+
+/// Inclusive date range (`YYYY-MM-DD`, local time) for study statistics.
+/// Open ends are unbounded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatsRange {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsGroupBy {
+    Day,
+    Week,
+    Book,
+}
+
+/// Aggregates for one day, ISO week (`2025-W03`) or book slug.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsGroup {
+    pub key: String,
+    /// Time in logged study sessions.
+    pub minutes: f64,
+    pub sessions: i64,
+    /// Time and pages from the reading history.
+    pub reading_minutes: f64,
+    pub pages_read: f64,
+    pub pages_per_hour: Option<f64>,
+    pub snips_created: i64,
+    pub snips_solidified: i64,
+}
+
+/// Share of all snips created so far that are solid, at the end of a period.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SolidificationPoint {
+    pub period: String,
+    pub created: i64,
+    pub solidified: i64,
+    pub rate: Option<f64>,
+}
+
+/// Reading time within one top-level outline entry of a book.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterTime {
+    pub slug: String,
+    pub title: String,
+    pub page: Option<u32>,
+    pub minutes: f64,
+    pub pages_read: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StudyStats {
+    pub groups: Vec<StatsGroup>,
+    pub total_minutes: f64,
+    pub total_sessions: i64,
+    pub pages_per_hour: Option<f64>,
+    /// Consecutive days with study up to today (or yesterday).
+    pub current_streak: i64,
+    pub longest_streak: i64,
+    pub solidification: Vec<SolidificationPoint>,
+    pub chapters: Vec<ChapterTime>,
+}
+
+/// What earned an XP event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum XpSource {
+    Pomodoro,
+    SnipReview,
+    PageRead,
+    BookFinished,
+}
+
+/// One entry of a directory's XP ledger (`.axiomatic/xp-events.json`).
+/// Events are never edited; undoing one appends a negative event that
+/// names it in `reverts`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XpEvent {
+    pub id: String,
+    pub timestamp: String,
+    pub source: XpSource,
+    pub slug: String,
+    pub amount: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverts: Option<String>,
+}
+
+/// Where page-read XP of a book stands (`.axiomatic/page-marks.json`): the
+/// page last reported and the furthest page credited.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageMark {
+    pub last: i64,
+    pub furthest: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirXp {
+    pub dir_path: String,
+    pub total: i64,
+}
+
+/// XP totals derived from the ledgers of one or more directories.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XpSummary {
+    pub total: i64,
+    pub level: i64,
+    /// XP earned since reaching `level`, and needed to reach the next one.
+    pub level_xp: i64,
+    pub next_level_xp: i64,
+    pub by_source: HashMap<XpSource, i64>,
+    pub by_book: HashMap<String, i64>,
+    pub by_dir: Vec<DirXp>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrphanCandidate {
     pub old_slug: String,
@@ -102,6 +313,41 @@ pub struct OrphanCandidate {
     pub evidence: Vec<String>,
 }
 
+/// A slug change applied automatically because a known document was renamed
+/// or moved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlugMigration {
+    pub old_slug: String,
+    pub new_slug: String,
+    pub doc_id: String,
+}
+
+/// A watched document that moved to a new path; annotations were migrated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenamedDocument {
+    pub from_path: String,
+    pub to_path: String,
+    pub old_slug: String,
+    pub new_slug: String,
+}
+
+/// Payload of the `library-changed` event emitted by the library watcher.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryChange {
+    pub added: Vec<Textbook>,
+    pub removed: Vec<String>,
+    pub renamed: Vec<RenamedDocument>,
+}
+
+impl LibraryChange {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StudySession {
@@ -128,6 +374,370 @@ pub struct SessionEntry {
     pub session: StudySession,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PomodoroConfig {
+    pub preset: String,
+    pub work_minutes: i64,
+    pub break_minutes: i64,
+    pub audio_enabled: bool,
+    pub long_break_multiplier: i64,
+    /// Every this many pomodoros the break is a long one; 0 disables them.
+    pub long_break_interval: i64,
+}
+
+impl Default for PomodoroConfig {
+    fn default() -> Self {
+        Self {
+            preset: "45/10".into(),
+            work_minutes: 45,
+            break_minutes: 10,
+            audio_enabled: true,
+            long_break_multiplier: 3,
+            long_break_interval: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PomodoroPhase {
+    #[default]
+    Work,
+    Break,
+    LongBreak,
+}
+
+/// The pomodoro timer, as persisted and sent with `pomodoro-tick` events.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PomodoroState {
+    pub config: PomodoroConfig,
+    pub phase: PomodoroPhase,
+    pub running: bool,
+    pub seconds_left: i64,
+    /// When the running phase ends; `None` while paused.
+    pub ends_at: Option<String>,
+    pub completed_pomodoros: i64,
+    pub show_overlay: bool,
+    /// Start of the work session in progress.
+    pub session_start: Option<String>,
+    /// The book a finished work session is logged against.
+    pub book: Option<StudySessionBook>,
+}
+
+impl Default for PomodoroState {
+    fn default() -> Self {
+        let config = PomodoroConfig::default();
+        Self {
+            seconds_left: config.work_minutes * 60,
+            config,
+            phase: PomodoroPhase::Work,
+            running: false,
+            ends_at: None,
+            completed_pomodoros: 0,
+            show_overlay: false,
+            session_start: None,
+            book: None,
+        }
+    }
+}
+
+/// Payload of the `pomodoro-transition` event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PomodoroTransition {
+    pub from: PomodoroPhase,
+    pub to: PomodoroPhase,
+    /// Whether the phase ran out, as opposed to being skipped.
+    pub completed: bool,
+    pub chime: bool,
+    pub state: PomodoroState,
+}
+
+/// Settings of the local agent (MCP) server, kept in the app data directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AgentServerConfig {
+    pub enabled: bool,
+    pub port: u16,
+    /// Tools agents may call; everything else is hidden and refused.
+    pub allowed_tools: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentToolInfo {
+    pub name: String,
+    pub description: String,
+    /// Whether the tool changes the library (notes, snips).
+    pub writes: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentServerStatus {
+    pub config: AgentServerConfig,
+    /// Endpoint while the server is listening.
+    pub url: Option<String>,
+    pub tools: Vec<AgentToolInfo>,
+}
+
+/// A library directory as recorded in a backup; its `.axiomatic/` files are
+/// stored under `dirs/{id}/` in the archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupDirectory {
+    pub id: i64,
+    pub path: String,
+    pub label: String,
+}
+
+/// `manifest.json` at the root of a backup archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub format: String,
+    pub version: u32,
+    pub app_version: String,
+    pub created_at: String,
+    /// Database migration version the backup was taken at.
+    pub schema_version: i64,
+    pub directories: Vec<BackupDirectory>,
+    pub notes: i64,
+    pub note_images: i64,
+    /// Absent from backups made before revisions were restored.
+    #[serde(default)]
+    pub note_revisions: i64,
+    pub files: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupMode {
+    /// The backup's notes, highlights, tags and `.axiomatic/` files replace
+    /// the current ones.
+    Replace,
+    /// Only what is missing is added; newer notes win.
+    Merge,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupReport {
+    pub directories_added: Vec<String>,
+    /// Backed-up directories that don't exist on this machine.
+    pub directories_skipped: Vec<String>,
+    /// Slugs that changed because the directory has another id here.
+    pub slugs_renamed: usize,
+    pub notes: usize,
+    pub note_images: usize,
+    pub note_revisions: usize,
+    pub highlights: usize,
+    pub files: usize,
+}
+
+/// What collecting unlinked note images removed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageGcReport {
+    /// Images no note or note revision links to any more.
+    pub images_removed: usize,
+    /// Stored image contents no image uses any more.
+    pub blobs_removed: usize,
+    pub bytes_freed: i64,
+}
+
+/// Space a book's notes take up. Image bytes count each distinct image once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookStorage {
+    pub slug: String,
+    pub notes: i64,
+    pub note_bytes: i64,
+    pub images: i64,
+    pub image_bytes: i64,
+}
+
+/// Database sizes around a `vacuum`, summed over the app database and the
+/// directory stores.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VacuumReport {
+    pub gc: ImageGcReport,
+    pub bytes_before: i64,
+    pub bytes_after: i64,
+}
+
+/// One device's version of a synced record. `value` is `None` when the
+/// record was deleted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncVersion {
+    pub device: String,
+    pub device_name: String,
+    pub at: String,
+    pub value: Option<serde_json::Value>,
+}
+
+/// Concurrent edits of the same record on different devices. The newest
+/// version was kept; the others are listed so nothing is lost silently.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConflict {
+    /// Record key, e.g. `note/{book}/{page}`.
+    pub key: String,
+    /// Slug of the book on this device, when it is in the library.
+    pub slug: Option<String>,
+    pub kept: SyncVersion,
+    pub discarded: Vec<SyncVersion>,
+    pub detected_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    /// Shared folder holding the change logs; sync is off while unset.
+    pub folder: Option<String>,
+    pub device_id: String,
+    pub device_name: String,
+    pub last_sync: Option<String>,
+    pub conflicts: Vec<SyncConflict>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    /// Local changes written to this device's log.
+    pub sent: usize,
+    /// Changes from other devices applied here.
+    pub received: usize,
+    /// Conflicts detected in this run.
+    pub conflicts: usize,
+}
+
+/// Where a library directory keeps its notes, note images and highlights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteStorage {
+    /// The app database.
+    App,
+    /// `.axiomatic/notes.db`, travelling with the folder.
+    Directory,
+}
+
+/// What a note is attached to on its page. A page has at most one note per
+/// anchor; the page itself is the default anchor.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum NoteAnchor {
+    #[default]
+    Page,
+    /// A region of the page, in the same coordinates as highlights.
+    Rect { x: f64, y: f64, width: f64, height: f64 },
+    /// A highlight group (`Highlight::group_id`).
+    Highlight { group_id: String },
+    /// A snip (`Snip::id`).
+    Snip { id: String },
+}
+
+impl NoteAnchor {
+    /// The `notes.anchor` column value: empty for the page, otherwise
+    /// `rect:x,y,width,height`, `highlight:{group_id}` or `snip:{id}`.
+    pub fn key(&self) -> String {
+        match self {
+            NoteAnchor::Page => String::new(),
+            NoteAnchor::Rect { x, y, width, height } => format!("rect:{},{},{},{}", x, y, width, height),
+            NoteAnchor::Highlight { group_id } => format!("highlight:{}", group_id),
+            NoteAnchor::Snip { id } => format!("snip:{}", id),
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        if key.is_empty() {
+            return Some(NoteAnchor::Page);
+        }
+        let (kind, value) = key.split_once(':')?;
+        match kind {
+            "rect" => {
+                let rect = value.split(',').map(str::parse::<f64>).collect::<Result<Vec<_>, _>>().ok()?;
+                let [x, y, width, height] = rect[..] else {
+                    return None;
+                };
+                Some(NoteAnchor::Rect { x, y, width, height })
+            }
+            "highlight" => Some(NoteAnchor::Highlight { group_id: value.to_string() }),
+            "snip" => Some(NoteAnchor::Snip { id: value.to_string() }),
+            _ => None,
+        }
+    }
+}
+
+/// What a `[[...]]` link in a note points at: `[[slug#p42]]`, `[[snip:id]]`
+/// or `[[tag]]`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum LinkTarget {
+    Page { slug: String, page: i64 },
+    Snip { id: String },
+    Tag { name: String },
+}
+
+/// An earlier version of a note, as kept by `note_revisions`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteRevision {
+    pub id: i64,
+    pub slug: String,
+    pub page: i64,
+    #[serde(default)]
+    pub anchor: NoteAnchor,
+    pub format: String,
+    pub content: String,
+    pub created_at: String,
+}
+
+/// How long note revisions are kept. `None` means no limit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NoteRevisionConfig {
+    pub max_age_days: Option<u32>,
+    pub max_per_note: Option<u32>,
+}
+
+impl Default for NoteRevisionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: Some(90),
+            max_per_note: Some(100),
+        }
+    }
+}
+
+/// Provenance of a PDF produced by `clip_pdf`, stored in the clip directory's
+/// `.axiomatic/clips.json` keyed by the clip's slug. Clip page `n` maps to
+/// source page `n + page_offset`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipProvenance {
+    pub source_slug: String,
+    pub source_dir_path: String,
+    pub source_path: String,
+    pub page_offset: i64,
+    pub page_count: i64,
+    pub created_at: String,
+}
+
+/// Notes, highlights and snips of a clip's source pages, with page numbers
+/// remapped into the clip's own numbering.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipAnnotations {
+    pub provenance: ClipProvenance,
+    pub notes: Vec<NoteRecord>,
+    pub highlights: Vec<Highlight>,
+    pub snips: Vec<Snip>,
+}
+
 impl BookTagMapping {
     pub fn group_from_rows(rows: Vec<(String, i64, String, String)>) -> Vec<BookTagMapping> {
         let mut map: HashMap<String, Vec<Tag>> = HashMap::new();
@@ -0,0 +1,361 @@
+//! Storage of images pasted into notes.
+//!
+//! A `note_images` row ties an image id, which notes link to as
+//! `axiomatic-image://{id}`, to the note page it was pasted into. The bytes
+//! are stored once per distinct content in `note_image_blobs`, keyed by their
+//! SHA-256, so the same image pasted twice takes space once. Images no note
+//! or note revision links to any more are collected by [`collect_garbage_inner`].
+
+use std::collections::{BTreeMap, HashSet};
+
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use tauri::State;
+
+use crate::commands::{get_db, DbState};
+use crate::doc_identity::to_hex;
+use crate::models::{BookStorage, ImageGcReport, VacuumReport};
+use crate::note_revisions;
+use crate::note_store::{self, IMAGE_SCHEME};
+
+/// How long an unlinked image is kept: the editor saves a pasted image
+/// before the note that links to it.
+const GC_GRACE: &str = "-1 day";
+
+/// Store `data` unless an identical image is stored already. Returns the
+/// content hash to reference it by.
+pub(crate) fn put_blob(conn: &Connection, data: &[u8]) -> rusqlite::Result<String> {
+    let hash = to_hex(&Sha256::digest(data));
+    conn.execute(
+        "INSERT OR IGNORE INTO note_image_blobs (hash, data) VALUES (?1, ?2)",
+        rusqlite::params![hash, data],
+    )?;
+    Ok(hash)
+}
+
+/// Move image bytes from `note_images.data` into `note_image_blobs`, for
+/// databases from before images were deduplicated. Image ids are kept, and
+/// so is the id sequence, so no link can come to point at another image.
+pub(crate) fn split_blobs(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("SAVEPOINT split_blobs")?;
+    let result = (|| {
+        conn.execute_batch(
+            "CREATE TABLE note_images_hashed (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                note_slug TEXT NOT NULL,
+                note_page INTEGER NOT NULL,
+                filename TEXT NOT NULL,
+                hash TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                UNIQUE(note_slug, note_page, filename)
+            );",
+        )?;
+        let ids = {
+            let mut stmt = conn.prepare("SELECT id FROM note_images")?;
+            let ids = stmt.query_map([], |row| row.get::<_, i64>(0))?.collect::<Result<Vec<_>, _>>()?;
+            ids
+        };
+        for id in ids {
+            let data: Vec<u8> = conn.query_row("SELECT data FROM note_images WHERE id = ?1", [id], |row| row.get(0))?;
+            let hash = put_blob(conn, &data)?;
+            conn.execute(
+                "INSERT INTO note_images_hashed (id, note_slug, note_page, filename, hash, created_at)
+                 SELECT id, note_slug, note_page, filename, ?2, created_at FROM note_images WHERE id = ?1",
+                rusqlite::params![id, hash],
+            )?;
+        }
+        let seq: Option<i64> = conn
+            .query_row("SELECT seq FROM sqlite_sequence WHERE name = 'note_images'", [], |row| row.get(0))
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                e => Err(e),
+            })?;
+        conn.execute_batch("DROP TABLE note_images; ALTER TABLE note_images_hashed RENAME TO note_images;")?;
+        if let Some(seq) = seq {
+            conn.execute(
+                "UPDATE sqlite_sequence SET seq = MAX(seq, ?1) WHERE name = 'note_images'",
+                [seq],
+            )?;
+        }
+        Ok(())
+    })();
+    match result {
+        Ok(()) => conn.execute_batch("RELEASE split_blobs"),
+        Err(e) => {
+            conn.execute_batch("ROLLBACK TO split_blobs; RELEASE split_blobs").ok();
+            Err(e)
+        }
+    }
+}
+
+/// Ids of the images `content` links to.
+fn linked_images(content: &str) -> impl Iterator<Item = i64> + '_ {
+    content.match_indices(IMAGE_SCHEME).filter_map(move |(pos, _)| {
+        let rest = &content[pos + IMAGE_SCHEME.len()..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        rest[..digits].parse().ok()
+    })
+}
+
+/// Delete the images in one database that no note or revision links to,
+/// then the stored contents no image uses.
+pub(crate) fn collect_garbage_inner(conn: &Connection) -> Result<ImageGcReport, String> {
+    let mut linked = HashSet::new();
+    let mut stmt = conn
+        .prepare("SELECT content FROM notes WHERE instr(content, ?1) > 0")
+        .map_err(|e| e.to_string())?;
+    let contents = stmt
+        .query_map([IMAGE_SCHEME], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for content in contents.iter().chain(&note_revisions::all_revision_texts(conn)?) {
+        linked.extend(linked_images(content));
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT id FROM note_images WHERE created_at <= datetime('now', ?1)")
+        .map_err(|e| e.to_string())?;
+    let candidates = stmt
+        .query_map([GC_GRACE], |row| row.get::<_, i64>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut report = ImageGcReport::default();
+    for id in candidates.into_iter().filter(|id| !linked.contains(id)) {
+        report.images_removed += tx
+            .execute("DELETE FROM note_images WHERE id = ?1", [id])
+            .map_err(|e| e.to_string())?;
+    }
+    let (blobs, bytes): (i64, i64) = tx
+        .query_row(
+            "SELECT COUNT(*), COALESCE(SUM(length(data)), 0) FROM note_image_blobs
+             WHERE hash NOT IN (SELECT hash FROM note_images)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM note_image_blobs WHERE hash NOT IN (SELECT hash FROM note_images)", [])
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    report.blobs_removed = blobs as usize;
+    report.bytes_freed = bytes;
+    Ok(report)
+}
+
+/// Space taken by each book's notes and images in one database.
+pub(crate) fn storage_report_inner(conn: &Connection) -> Result<Vec<BookStorage>, String> {
+    fn entry(books: &mut BTreeMap<String, BookStorage>, slug: String) -> &mut BookStorage {
+        books.entry(slug.clone()).or_insert(BookStorage { slug, notes: 0, note_bytes: 0, images: 0, image_bytes: 0 })
+    }
+    let mut books = BTreeMap::new();
+
+    let mut stmt = conn
+        .prepare("SELECT slug, COUNT(*), SUM(length(CAST(content AS BLOB))) FROM notes GROUP BY slug")
+        .map_err(|e| e.to_string())?;
+    let notes = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (slug, count, bytes) in notes {
+        let book = entry(&mut books, slug);
+        book.notes = count;
+        book.note_bytes = bytes;
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT i.note_slug, COUNT(*),
+                 (SELECT COALESCE(SUM(length(b.data)), 0) FROM note_image_blobs b
+                  WHERE b.hash IN (SELECT hash FROM note_images WHERE note_slug = i.note_slug))
+             FROM note_images i GROUP BY i.note_slug",
+        )
+        .map_err(|e| e.to_string())?;
+    let images = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (slug, count, bytes) in images {
+        let book = entry(&mut books, slug);
+        book.images = count;
+        book.image_bytes = bytes;
+    }
+    Ok(books.into_values().collect())
+}
+
+fn database_size(conn: &Connection) -> Result<i64, String> {
+    conn.query_row("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()", [], |row| {
+        row.get(0)
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Collect unlinked images, then compact the database file.
+pub(crate) fn vacuum_inner(conn: &Connection) -> Result<VacuumReport, String> {
+    let gc = collect_garbage_inner(conn)?;
+    let bytes_before = database_size(conn)?;
+    conn.execute_batch("VACUUM").map_err(|e| e.to_string())?;
+    Ok(VacuumReport { gc, bytes_before, bytes_after: database_size(conn)? })
+}
+
+fn add_gc(total: &mut ImageGcReport, report: ImageGcReport) {
+    total.images_removed += report.images_removed;
+    total.blobs_removed += report.blobs_removed;
+    total.bytes_freed += report.bytes_freed;
+}
+
+/// Collect unlinked images in the app database and every directory store.
+#[tauri::command]
+pub fn collect_note_images(state: State<'_, DbState>) -> Result<ImageGcReport, String> {
+    let conn = get_db(&state)?;
+    let mut total = collect_garbage_inner(&conn)?;
+    for store in note_store::directory_stores(&conn)? {
+        add_gc(&mut total, collect_garbage_inner(&store)?);
+    }
+    Ok(total)
+}
+
+/// Space taken by each book's notes, largest first.
+#[tauri::command]
+pub fn get_storage_report(state: State<'_, DbState>) -> Result<Vec<BookStorage>, String> {
+    let conn = get_db(&state)?;
+    let mut books = storage_report_inner(&conn)?;
+    for store in note_store::directory_stores(&conn)? {
+        books.extend(storage_report_inner(&store)?);
+    }
+    books.sort_by_key(|b| std::cmp::Reverse(b.note_bytes + b.image_bytes));
+    Ok(books)
+}
+
+/// Collect unlinked images and compact the app database and every
+/// directory store.
+#[tauri::command]
+pub fn vacuum(state: State<'_, DbState>) -> Result<VacuumReport, String> {
+    let conn = get_db(&state)?;
+    let mut total = vacuum_inner(&conn)?;
+    for store in note_store::directory_stores(&conn)? {
+        let report = vacuum_inner(&store)?;
+        add_gc(&mut total.gc, report.gc);
+        total.bytes_before += report.bytes_before;
+        total.bytes_after += report.bytes_after;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{get_note_image_inner, save_note_image_inner, set_note_inner};
+
+    fn setup() -> (tempfile::TempDir, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = crate::db::init_db(&dir.path().join("test.db")).unwrap();
+        (dir, conn)
+    }
+
+    fn link(id: i64) -> String {
+        format!("![]({}{})", IMAGE_SCHEME, id)
+    }
+
+    fn blob_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM note_image_blobs", [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn identical_images_are_stored_once() {
+        let (_dir, conn) = setup();
+        let a = save_note_image_inner(&conn, "b", 1, "a.png", b"same bytes").unwrap();
+        let b = save_note_image_inner(&conn, "c", 4, "b.png", b"same bytes").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(blob_count(&conn), 1);
+        assert_eq!(get_note_image_inner(&conn, b).unwrap(), b"same bytes");
+
+        // Saving under the same name again keeps the id
+        assert_eq!(save_note_image_inner(&conn, "b", 1, "a.png", b"new bytes").unwrap(), a);
+        assert_eq!(get_note_image_inner(&conn, a).unwrap(), b"new bytes");
+    }
+
+    #[test]
+    fn unlinked_images_are_collected_after_the_grace_period() {
+        let (_dir, conn) = setup();
+        let kept = save_note_image_inner(&conn, "b", 1, "kept.png", b"kept").unwrap();
+        let dropped = save_note_image_inner(&conn, "b", 1, "dropped.png", b"dropped").unwrap();
+        let in_revision = save_note_image_inner(&conn, "b", 1, "old.png", b"old").unwrap();
+        let fresh = save_note_image_inner(&conn, "b", 1, "fresh.png", b"fresh").unwrap();
+
+        set_note_inner(&conn, "b", 1, &format!("{} {}", link(kept), link(in_revision)), "markdown").unwrap();
+        // The earlier content is kept as a revision, which still links to `in_revision`
+        set_note_inner(&conn, "b", 1, &link(kept), "markdown").unwrap();
+        conn.execute("UPDATE note_images SET created_at = datetime('now', '-2 days') WHERE id != ?1", [fresh])
+            .unwrap();
+
+        let report = collect_garbage_inner(&conn).unwrap();
+        assert_eq!(report, ImageGcReport { images_removed: 1, blobs_removed: 1, bytes_freed: 7 });
+        assert!(get_note_image_inner(&conn, dropped).is_err());
+        for id in [kept, in_revision, fresh] {
+            assert!(get_note_image_inner(&conn, id).is_ok());
+        }
+        assert_eq!(blob_count(&conn), 3);
+    }
+
+    #[test]
+    fn reports_space_per_book() {
+        let (_dir, conn) = setup();
+        set_note_inner(&conn, "b", 1, "ünï", "markdown").unwrap();
+        set_note_inner(&conn, "b", 2, "text", "markdown").unwrap();
+        save_note_image_inner(&conn, "b", 1, "a.png", b"12345").unwrap();
+        save_note_image_inner(&conn, "b", 2, "b.png", b"12345").unwrap();
+        save_note_image_inner(&conn, "c", 1, "c.png", b"123").unwrap();
+
+        let report = storage_report_inner(&conn).unwrap();
+        assert_eq!(
+            report,
+            [
+                BookStorage { slug: "b".into(), notes: 2, note_bytes: 9, images: 2, image_bytes: 5 },
+                BookStorage { slug: "c".into(), notes: 0, note_bytes: 0, images: 1, image_bytes: 3 },
+            ]
+        );
+        let vacuumed = vacuum_inner(&conn).unwrap();
+        assert!(vacuumed.bytes_after <= vacuumed.bytes_before);
+    }
+
+    #[test]
+    fn splits_images_of_older_databases_into_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = Connection::open(dir.path().join("old.db")).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE note_images (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                note_slug TEXT NOT NULL,
+                note_page INTEGER NOT NULL,
+                filename TEXT NOT NULL,
+                data BLOB NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                UNIQUE(note_slug, note_page, filename)
+            );
+            CREATE TABLE note_image_blobs (hash TEXT PRIMARY KEY, data BLOB NOT NULL);
+            INSERT INTO note_images (note_slug, note_page, filename, data) VALUES
+                ('b', 1, 'a.png', x'01'), ('b', 2, 'b.png', x'01'), ('b', 3, 'c.png', x'02'), ('b', 4, 'd.png', x'03');
+            DELETE FROM note_images WHERE id = 4;",
+        )
+        .unwrap();
+        split_blobs(&conn).unwrap();
+
+        assert_eq!(blob_count(&conn), 2);
+        assert_eq!(get_note_image_inner(&conn, 2).unwrap(), [1]);
+        assert_eq!(get_note_image_inner(&conn, 3).unwrap(), [2]);
+        // The deleted image's id is not handed out again
+        let id: i64 = conn
+            .query_row(
+                "INSERT INTO note_images (note_slug, note_page, filename, hash) VALUES ('b', 5, 'e.png', 'h') RETURNING id",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(id, 5);
+    }
+}
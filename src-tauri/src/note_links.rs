@@ -0,0 +1,314 @@
+//! Links between notes, pages, snips and tags.
+//!
+//! A note links with `[[slug#p42]]` to a page of a book, with `[[snip:id]]`
+//! to a snip and with `[[tag]]` to a tag; `[[target|label]]` gives the link
+//! its own text. Links inside code are ignored. The links of every note are
+//! kept in `note_links`, next to the note, whenever it is saved, so backlinks
+//! are a lookup across the app database and the directory stores.
+
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use rusqlite::Connection;
+use tauri::State;
+
+use crate::commands::{get_db, row_to_note, DbState, NOTE_COLUMNS};
+use crate::models::{LinkTarget, NoteRecord};
+use crate::note_store;
+
+/// Byte ranges of the targets of the `[[...]]` links in `content`, leaving
+/// out fenced code blocks and inline code.
+fn target_spans(content: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut in_fence = false;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let bytes = line.as_bytes();
+        let mut in_code = false;
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'`' {
+                in_code = !in_code;
+            } else if !in_code && bytes[i..].starts_with(b"[[") {
+                if let Some(len) = line[i + 2..].find("]]") {
+                    let inner = &line[i + 2..i + 2 + len];
+                    if !inner.contains(['[', '`', '\n']) {
+                        let target = inner.split('|').next().unwrap_or(inner);
+                        spans.push(start + i + 2..start + i + 2 + target.len());
+                        i += len + 4;
+                        continue;
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+    spans
+}
+
+fn parse_target(text: &str) -> Option<LinkTarget> {
+    let text = text.trim();
+    if let Some(id) = text.strip_prefix("snip:") {
+        return (!id.trim().is_empty()).then(|| LinkTarget::Snip { id: id.trim().to_string() });
+    }
+    if let Some((slug, page)) = text.rsplit_once("#p") {
+        let page = page.parse::<i64>().ok()?;
+        return (!slug.is_empty()).then(|| LinkTarget::Page { slug: slug.to_string(), page });
+    }
+    if text.is_empty() || text.contains('#') {
+        return None;
+    }
+    Some(LinkTarget::Tag { name: text.to_string() })
+}
+
+/// The distinct links in a note's content.
+pub(crate) fn parse_links(content: &str) -> BTreeSet<LinkTarget> {
+    target_spans(content).into_iter().filter_map(|span| parse_target(&content[span])).collect()
+}
+
+/// Rewrite the book of `[[slug#p..]]` links with `rename`, which returns
+/// `None` for slugs to keep. `None` when nothing changed.
+pub(crate) fn rewrite_page_links(content: &str, rename: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let mut out = String::with_capacity(content.len());
+    let mut last = 0;
+    for span in target_spans(content) {
+        let Some(LinkTarget::Page { slug, .. }) = parse_target(&content[span.clone()]) else {
+            continue;
+        };
+        let Some(new_slug) = rename(&slug) else {
+            continue;
+        };
+        // The slug is the target, trimmed, up to its last `#p`
+        let text = &content[span.clone()];
+        let slug_start = span.start + (text.len() - text.trim_start().len());
+        out.push_str(&content[last..slug_start]);
+        out.push_str(&new_slug);
+        last = slug_start + slug.len();
+    }
+    (last > 0).then(|| {
+        out.push_str(&content[last..]);
+        out
+    })
+}
+
+fn columns(target: &LinkTarget) -> (&'static str, &str, Option<i64>) {
+    match target {
+        LinkTarget::Page { slug, page } => ("page", slug, Some(*page)),
+        LinkTarget::Snip { id } => ("snip", id, None),
+        LinkTarget::Tag { name } => ("tag", name, None),
+    }
+}
+
+fn index_by_id(conn: &Connection, note_id: i64, content: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM note_links WHERE note_id = ?1", [note_id])?;
+    for target in parse_links(content) {
+        let (kind, target, page) = columns(&target);
+        conn.execute(
+            "INSERT INTO note_links (note_id, kind, target, page) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![note_id, kind, target, page],
+        )?;
+    }
+    Ok(())
+}
+
+/// Re-read the links of the note at `anchor` (a `NoteAnchor::key`) after it
+/// was written.
+pub(crate) fn index_note(conn: &Connection, slug: &str, page: i64, anchor: &str) -> Result<(), String> {
+    let note: Option<(i64, String)> = conn
+        .query_row(
+            "SELECT id, content FROM notes WHERE slug = ?1 AND page = ?2 AND anchor = ?3",
+            rusqlite::params![slug, page, anchor],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map(Some)
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e),
+        })
+        .map_err(|e| e.to_string())?;
+    match note {
+        Some((id, content)) => index_by_id(conn, id, &content).map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Drop the links of the note at `anchor` before it is deleted.
+pub(crate) fn unindex_note(conn: &Connection, slug: &str, page: i64, anchor: &str) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM note_links WHERE note_id IN
+         (SELECT id FROM notes WHERE slug = ?1 AND page = ?2 AND anchor = ?3)",
+        rusqlite::params![slug, page, anchor],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Rebuild `note_links` from every note, after notes were written in bulk.
+/// Runs in a savepoint, so it also works inside the caller's transaction.
+pub(crate) fn reindex(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("SAVEPOINT reindex_links")?;
+    let result = (|| {
+        conn.execute("DELETE FROM note_links", [])?;
+        let mut stmt = conn.prepare("SELECT id, content FROM notes WHERE content LIKE '%[[%'")?;
+        let notes = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (id, content) in notes {
+            index_by_id(conn, id, &content)?;
+        }
+        Ok(())
+    })();
+    match result {
+        Ok(()) => conn.execute_batch("RELEASE reindex_links"),
+        Err(e) => {
+            conn.execute_batch("ROLLBACK TO reindex_links; RELEASE reindex_links").ok();
+            Err(e)
+        }
+    }
+}
+
+/// Notes in one database linking to `target`.
+pub fn get_backlinks_inner(conn: &Connection, target: &LinkTarget) -> Result<Vec<NoteRecord>, String> {
+    let (kind, target, page) = columns(target);
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE id IN
+             (SELECT note_id FROM note_links WHERE kind = ?1 AND target = ?2 AND page IS ?3)
+             ORDER BY slug, page, anchor != '', id"
+        ))
+        .map_err(|e| e.to_string())?;
+    let notes = stmt
+        .query_map(rusqlite::params![kind, target, page], row_to_note)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string());
+    notes
+}
+
+/// Point `[[old_slug#p..]]` links in one database at `new_slug`.
+fn rename_links_in(conn: &Connection, old_slug: &str, new_slug: &str) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, content FROM notes WHERE id IN
+             (SELECT note_id FROM note_links WHERE kind = 'page' AND target = ?1)",
+        )
+        .map_err(|e| e.to_string())?;
+    let notes = stmt
+        .query_map([old_slug], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (id, content) in notes {
+        let Some(content) = rewrite_page_links(&content, |slug| (slug == old_slug).then(|| new_slug.to_string()))
+        else {
+            continue;
+        };
+        conn.execute("UPDATE notes SET content = ?1 WHERE id = ?2", rusqlite::params![content, id])
+            .map_err(|e| e.to_string())?;
+        index_by_id(conn, id, &content).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Point links to a renamed book at its new slug, in every note database.
+pub(crate) fn rename_book_links(conn: &Connection, old_slug: &str, new_slug: &str) -> Result<(), String> {
+    rename_links_in(conn, old_slug, new_slug)?;
+    for store in note_store::directory_stores(conn)? {
+        rename_links_in(&store, old_slug, new_slug)?;
+    }
+    Ok(())
+}
+
+/// Notes linking to `target`, from the app database and every directory store.
+#[tauri::command]
+pub fn get_backlinks(target: LinkTarget, state: State<'_, DbState>) -> Result<Vec<NoteRecord>, String> {
+    let conn = get_db(&state)?;
+    let mut notes = get_backlinks_inner(&conn, &target)?;
+    for store in note_store::directory_stores(&conn)? {
+        notes.extend(get_backlinks_inner(&store, &target)?);
+    }
+    notes.sort_by(|a, b| (&a.slug, a.page).cmp(&(&b.slug, b.page)));
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{add_directory_inner, get_note_inner, migrate_slug_inner, set_note_inner};
+
+    fn page(slug: &str, page: i64) -> LinkTarget {
+        LinkTarget::Page { slug: slug.into(), page }
+    }
+
+    #[test]
+    fn parses_links_outside_code() {
+        let content = "See [[1_algebra#p42|Theorem 3]] and [[snip: abc-1 ]], tagged [[groups]].\n\
+                       `[[1_algebra#p1]]` is code, and so is\n```\n[[1_algebra#p2]]\n```\n\
+                       Not links: [[]] [[x#y]] [[1_algebra#p]] [[unclosed";
+        let links: Vec<LinkTarget> = parse_links(content).into_iter().collect();
+        assert_eq!(
+            links,
+            [page("1_algebra", 42), LinkTarget::Snip { id: "abc-1".into() }, LinkTarget::Tag { name: "groups".into() }]
+        );
+    }
+
+    #[test]
+    fn rewrites_only_matching_page_links() {
+        let content = "[[ 1_a#p2 |label]], [[1_ab#p3]], `[[1_a#p4]]`, [[1_a#p5]] ünï";
+        let rename = |slug: &str| (slug == "1_a").then(|| "2_a".to_string());
+        assert_eq!(
+            rewrite_page_links(content, rename).as_deref(),
+            Some("[[ 2_a#p2 |label]], [[1_ab#p3]], `[[1_a#p4]]`, [[2_a#p5]] ünï")
+        );
+        assert_eq!(rewrite_page_links("[[1_ab#p3]]", rename), None);
+    }
+
+    #[test]
+    fn backlinks_follow_saves_and_renamed_books() {
+        let tmp = tempfile::tempdir().unwrap();
+        let conn = crate::db::init_db(&tmp.path().join("axiomatic.db")).unwrap();
+        let library = tmp.path().join("library");
+        std::fs::create_dir_all(&library).unwrap();
+        let library = library.to_string_lossy().to_string();
+        let dir = add_directory_inner(&conn, &library, "library").unwrap();
+        let (algebra, topology) = (format!("{}_algebra", dir.id), format!("{}_topology", dir.id));
+
+        set_note_inner(&conn, &topology, 7, &format!("Uses [[{}#p42]], see [[compactness]]", algebra), "markdown")
+            .unwrap();
+        set_note_inner(&conn, &topology, 9, &format!("Again [[{}#p42]]", algebra), "markdown").unwrap();
+        let backlinks = get_backlinks_inner(&conn, &page(&algebra, 42)).unwrap();
+        assert_eq!(backlinks.iter().map(|n| n.page).collect::<Vec<_>>(), [7, 9]);
+        assert!(get_backlinks_inner(&conn, &page(&algebra, 41)).unwrap().is_empty());
+
+        // Edits and deletions update the links
+        set_note_inner(&conn, &topology, 9, "No longer linked", "markdown").unwrap();
+        assert_eq!(get_backlinks_inner(&conn, &page(&algebra, 42)).unwrap().len(), 1);
+        set_note_inner(&conn, &topology, 7, "", "markdown").unwrap();
+        assert!(get_backlinks_inner(&conn, &LinkTarget::Tag { name: "compactness".into() }).unwrap().is_empty());
+
+        // Renaming the linked book rewrites the links to it
+        set_note_inner(&conn, &topology, 7, &format!("Uses [[{}#p42]]", algebra), "markdown").unwrap();
+        let renamed = format!("{}_algebra-2e", dir.id);
+        migrate_slug_inner(&conn, &algebra, &renamed, &library).unwrap();
+        assert_eq!(
+            get_note_inner(&conn, &topology, 7).unwrap().unwrap().content,
+            format!("Uses [[{}#p42]]", renamed)
+        );
+        assert!(get_backlinks_inner(&conn, &page(&algebra, 42)).unwrap().is_empty());
+        assert_eq!(get_backlinks_inner(&conn, &page(&renamed, 42)).unwrap().len(), 1);
+
+        // Renaming the linking book keeps its links
+        let moved = format!("{}_topology-2e", dir.id);
+        migrate_slug_inner(&conn, &topology, &moved, &library).unwrap();
+        assert_eq!(get_backlinks_inner(&conn, &page(&renamed, 42)).unwrap()[0].slug, moved);
+    }
+}
@@ -0,0 +1,510 @@
+//! Version history of notes.
+//!
+//! Before a note is overwritten, its current content is kept as a revision
+//! when the last revision is older than [`REVISION_INTERVAL`], when the edit
+//! removes most of the note, or when a restore replaces it. Every
+//! [`SNAPSHOT_EVERY`]th revision of a page holds the full text; the others
+//! hold a diff against the revision before. Old revisions are pruned by the
+//! retention settings at startup and whenever they change.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::commands::{get_anchored_note_inner, get_db, set_anchored_note_inner, DbState};
+use crate::models::{NoteAnchor, NoteRecord, NoteRevision, NoteRevisionConfig};
+use crate::note_store;
+
+const CONFIG_FILE: &str = "note-revisions.json";
+/// Minimum time between two periodic revisions of a page.
+const REVISION_INTERVAL: &str = "-10 minutes";
+const SNAPSHOT_EVERY: usize = 10;
+
+/// The edit turning one revision's text into the next: bytes kept from the
+/// start and end, and what replaces the rest.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Diff {
+    prefix: usize,
+    suffix: usize,
+    insert: String,
+}
+
+impl Diff {
+    fn between(old: &str, new: &str) -> Self {
+        let prefix: usize = old.chars().zip(new.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a.len_utf8()).sum();
+        let suffix: usize = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        Self { prefix, suffix, insert: new[prefix..new.len() - suffix].to_string() }
+    }
+
+    fn apply(&self, base: &str) -> Option<String> {
+        let end = base.len().checked_sub(self.suffix)?;
+        Some(format!("{}{}{}", base.get(..self.prefix)?, self.insert, base.get(end..)?))
+    }
+}
+
+struct StoredRevision {
+    id: i64,
+    format: String,
+    content: Option<String>,
+    diff: Option<String>,
+    created_at: String,
+}
+
+/// A note's revisions, oldest first.
+fn load_chain(conn: &Connection, slug: &str, page: i64, anchor: &str) -> Result<Vec<StoredRevision>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, format, content, diff, created_at FROM note_revisions
+             WHERE slug = ?1 AND page = ?2 AND anchor = ?3 ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(rusqlite::params![slug, page, anchor], |row| {
+            Ok(StoredRevision {
+                id: row.get(0)?,
+                format: row.get(1)?,
+                content: row.get(2)?,
+                diff: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string());
+    rows
+}
+
+/// The full text of each revision in a chain. A diff that doesn't apply
+/// (its base was lost) leaves the revision out rather than showing garbage.
+fn materialize(chain: &[StoredRevision]) -> Vec<Option<String>> {
+    let mut text: Option<String> = None;
+    chain
+        .iter()
+        .map(|rev| {
+            text = match (&rev.content, &rev.diff) {
+                (Some(content), _) => Some(content.clone()),
+                (None, Some(diff)) => text
+                    .as_deref()
+                    .zip(serde_json::from_str::<Diff>(diff).ok())
+                    .and_then(|(base, diff)| diff.apply(base)),
+                (None, None) => None,
+            };
+            text.clone()
+        })
+        .collect()
+}
+
+fn insert_revision(
+    conn: &Connection,
+    slug: &str,
+    page: i64,
+    anchor: &str,
+    format: &str,
+    content: &str,
+) -> Result<(), String> {
+    let chain = load_chain(conn, slug, page, anchor)?;
+    let last = materialize(&chain).pop().flatten();
+    if last.as_deref() == Some(content) {
+        return Ok(());
+    }
+    let since_snapshot = chain.iter().rev().take_while(|r| r.content.is_none()).count();
+    let diff = match last {
+        Some(base) if since_snapshot + 1 < SNAPSHOT_EVERY => {
+            Some(serde_json::to_string(&Diff::between(&base, content)).map_err(|e| e.to_string())?)
+        }
+        _ => None,
+    };
+    conn.execute(
+        "INSERT INTO note_revisions (slug, page, anchor, format, content, diff) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![slug, page, anchor, format, if diff.is_none() { Some(content) } else { None }, diff],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Keep the current content of the note at `anchor` as a revision before it
+/// is replaced with `new_content`, if it is due. `force` keeps it regardless.
+pub(crate) fn record_revision_inner(
+    conn: &Connection,
+    slug: &str,
+    page: i64,
+    anchor: &NoteAnchor,
+    new_content: &str,
+    force: bool,
+) -> Result<(), String> {
+    let Some(current) = get_anchored_note_inner(conn, slug, page, anchor)? else {
+        return Ok(());
+    };
+    let anchor = anchor.key();
+    if current.content == new_content {
+        return Ok(());
+    }
+    let destructive = new_content.len() * 2 < current.content.len();
+    let due: bool = conn
+        .query_row(
+            "SELECT COALESCE(MAX(created_at) <= datetime('now', ?3), 1) FROM note_revisions
+             WHERE slug = ?1 AND page = ?2 AND anchor = ?4",
+            rusqlite::params![slug, page, REVISION_INTERVAL, &anchor],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if force || destructive || due {
+        insert_revision(conn, slug, page, &anchor, &current.format, &current.content)?;
+    }
+    Ok(())
+}
+
+/// A note's revisions, newest first.
+pub fn list_note_revisions_inner(
+    conn: &Connection,
+    slug: &str,
+    page: i64,
+    anchor: &NoteAnchor,
+) -> Result<Vec<NoteRevision>, String> {
+    let chain = load_chain(conn, slug, page, &anchor.key())?;
+    let texts = materialize(&chain);
+    Ok(chain
+        .into_iter()
+        .zip(texts)
+        .rev()
+        .filter_map(|(rev, content)| {
+            Some(NoteRevision {
+                id: rev.id,
+                slug: slug.to_string(),
+                page,
+                anchor: anchor.clone(),
+                format: rev.format,
+                content: content?,
+                created_at: rev.created_at,
+            })
+        })
+        .collect())
+}
+
+/// Put a revision back as the note's content. The content it replaces is
+/// kept as a revision, so a restore can be undone.
+pub fn restore_note_revision_inner(
+    conn: &Connection,
+    slug: &str,
+    page: i64,
+    anchor: &NoteAnchor,
+    id: i64,
+) -> Result<NoteRecord, String> {
+    let revision = list_note_revisions_inner(conn, slug, page, anchor)?
+        .into_iter()
+        .find(|r| r.id == id)
+        .ok_or_else(|| format!("No revision {} for page {} of {}", id, page, slug))?;
+    record_revision_inner(conn, slug, page, anchor, &revision.content, true)?;
+    set_anchored_note_inner(conn, slug, page, anchor, &revision.content, &revision.format)?;
+    get_anchored_note_inner(conn, slug, page, anchor)?.ok_or_else(|| "Restored note is empty".to_string())
+}
+
+/// Slug, page and anchor key of every note with revisions.
+fn revised_notes(conn: &Connection) -> Result<Vec<(String, i64, String)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT slug, page, anchor FROM note_revisions")
+        .map_err(|e| e.to_string())?;
+    let notes = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string());
+    notes
+}
+
+/// One revision with its full text.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FullRevision {
+    pub format: String,
+    pub content: String,
+    pub created_at: String,
+}
+
+/// The revisions of one note, oldest first, independent of the database
+/// they came from.
+pub(crate) struct NoteHistory {
+    pub slug: String,
+    pub page: i64,
+    /// [`NoteAnchor::key`] of the note.
+    pub anchor: String,
+    pub revisions: Vec<FullRevision>,
+}
+
+fn full_chain(conn: &Connection, slug: &str, page: i64, anchor: &str) -> Result<Vec<FullRevision>, String> {
+    let chain = load_chain(conn, slug, page, anchor)?;
+    let texts = materialize(&chain);
+    Ok(chain
+        .into_iter()
+        .zip(texts)
+        .filter_map(|(rev, content)| {
+            Some(FullRevision { format: rev.format, content: content?, created_at: rev.created_at })
+        })
+        .collect())
+}
+
+/// The history of every note in the database.
+pub(crate) fn note_histories(conn: &Connection) -> Result<Vec<NoteHistory>, String> {
+    revised_notes(conn)?
+        .into_iter()
+        .map(|(slug, page, anchor)| {
+            let revisions = full_chain(conn, &slug, page, &anchor)?;
+            Ok(NoteHistory { slug, page, anchor, revisions })
+        })
+        .collect()
+}
+
+/// Add the revisions of `history` that the note doesn't have yet. Both
+/// histories are interleaved by time and the note's chain is rewritten, so
+/// its diffs stay consistent. Returns how many revisions were added.
+pub(crate) fn merge_history(conn: &Connection, history: &NoteHistory) -> Result<usize, String> {
+    let (slug, page, anchor) = (&history.slug, history.page, &history.anchor);
+    let mut merged = full_chain(conn, slug, page, anchor)?;
+    let local = merged.len();
+    for rev in &history.revisions {
+        if !merged.contains(rev) {
+            merged.push(rev.clone());
+        }
+    }
+    let added = merged.len() - local;
+    if added == 0 {
+        return Ok(0);
+    }
+    merged.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+    conn.execute(
+        "DELETE FROM note_revisions WHERE slug = ?1 AND page = ?2 AND anchor = ?3",
+        rusqlite::params![slug, page, anchor],
+    )
+    .map_err(|e| e.to_string())?;
+    let mut last: Option<&str> = None;
+    for (i, rev) in merged.iter().enumerate() {
+        let diff = match last {
+            Some(base) if i % SNAPSHOT_EVERY != 0 => {
+                Some(serde_json::to_string(&Diff::between(base, &rev.content)).map_err(|e| e.to_string())?)
+            }
+            _ => None,
+        };
+        conn.execute(
+            "INSERT INTO note_revisions (slug, page, anchor, format, content, diff, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                slug,
+                page,
+                anchor,
+                rev.format,
+                if diff.is_none() { Some(&rev.content) } else { None },
+                diff,
+                rev.created_at
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        last = Some(&rev.content);
+    }
+    Ok(added)
+}
+
+/// The text of every revision in the database.
+pub(crate) fn all_revision_texts(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut texts = Vec::new();
+    for (slug, page, anchor) in revised_notes(conn)? {
+        texts.extend(materialize(&load_chain(conn, &slug, page, &anchor)?).into_iter().flatten());
+    }
+    Ok(texts)
+}
+
+/// Drop revisions beyond the retention limits, oldest first. The oldest
+/// revision left becomes a full snapshot. Returns how many were removed.
+pub(crate) fn prune_note_revisions_inner(conn: &Connection, config: &NoteRevisionConfig) -> Result<usize, String> {
+    let cutoff: Option<String> = match config.max_age_days {
+        Some(days) => Some(
+            conn.query_row("SELECT datetime('now', ?1)", [format!("-{} days", days)], |row| row.get(0))
+                .map_err(|e| e.to_string())?,
+        ),
+        None => None,
+    };
+    let mut removed = 0;
+    for (slug, page, anchor) in revised_notes(conn)? {
+        let chain = load_chain(conn, &slug, page, &anchor)?;
+        let expired = cutoff.as_ref().map_or(0, |c| chain.iter().take_while(|r| &r.created_at < c).count());
+        let excess = config.max_per_note.map_or(0, |max| chain.len().saturating_sub(max as usize));
+        let drop = expired.max(excess);
+        if drop == 0 {
+            continue;
+        }
+        if let (Some(first), Some(Some(text))) = (chain.get(drop), materialize(&chain).get(drop)) {
+            conn.execute(
+                "UPDATE note_revisions SET content = ?1, diff = NULL WHERE id = ?2",
+                rusqlite::params![text, first.id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        for rev in &chain[..drop] {
+            conn.execute("DELETE FROM note_revisions WHERE id = ?1", [rev.id])
+                .map_err(|e| e.to_string())?;
+        }
+        removed += drop;
+    }
+    Ok(removed)
+}
+
+/// Retention settings, persisted in the app data dir.
+pub struct NoteRevisionSettings {
+    path: PathBuf,
+    config: Mutex<NoteRevisionConfig>,
+}
+
+impl NoteRevisionSettings {
+    /// Load the settings and prune every note database by them.
+    pub fn start(app_data: &Path, conn: &Connection) -> Self {
+        let path = app_data.join(CONFIG_FILE);
+        let config: NoteRevisionConfig = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        if let Err(e) = prune_all(conn, &config) {
+            log::warn!("note revisions: {}", e);
+        }
+        Self { path, config: Mutex::new(config) }
+    }
+}
+
+fn prune_all(conn: &Connection, config: &NoteRevisionConfig) -> Result<usize, String> {
+    let mut removed = prune_note_revisions_inner(conn, config)?;
+    for store in note_store::directory_stores(conn)? {
+        removed += prune_note_revisions_inner(&store, config)?;
+    }
+    Ok(removed)
+}
+
+/// Without `anchor`, the revisions of the page-level note.
+#[tauri::command]
+pub fn list_note_revisions(
+    slug: String,
+    page: i64,
+    anchor: Option<NoteAnchor>,
+    state: State<'_, DbState>,
+) -> Result<Vec<NoteRevision>, String> {
+    let conn = get_db(&state)?;
+    list_note_revisions_inner(&*note_store::open(&conn, &slug)?, &slug, page, &anchor.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn restore_note_revision(
+    slug: String,
+    page: i64,
+    anchor: Option<NoteAnchor>,
+    id: i64,
+    state: State<'_, DbState>,
+) -> Result<NoteRecord, String> {
+    let conn = get_db(&state)?;
+    restore_note_revision_inner(&*note_store::open(&conn, &slug)?, &slug, page, &anchor.unwrap_or_default(), id)
+}
+
+#[tauri::command]
+pub fn get_note_revision_config(state: State<'_, NoteRevisionSettings>) -> Result<NoteRevisionConfig, String> {
+    Ok(state.config.lock().map_err(|e| e.to_string())?.clone())
+}
+
+/// Save the retention settings and prune to them right away.
+#[tauri::command]
+pub fn set_note_revision_config(
+    config: NoteRevisionConfig,
+    state: State<'_, NoteRevisionSettings>,
+    db: State<'_, DbState>,
+) -> Result<NoteRevisionConfig, String> {
+    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    std::fs::write(&state.path, json).map_err(|e| format!("Failed to save revision settings: {}", e))?;
+    *state.config.lock().map_err(|e| e.to_string())? = config.clone();
+    prune_all(&*get_db(&db)?, &config)?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{get_note_inner, set_note_inner};
+
+    const PAGE: &NoteAnchor = &NoteAnchor::Page;
+
+    fn setup() -> (tempfile::TempDir, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = crate::db::init_db(&dir.path().join("test.db")).unwrap();
+        (dir, conn)
+    }
+
+    fn age_revisions(conn: &Connection, minutes: i64) {
+        conn.execute(
+            "UPDATE note_revisions SET created_at = datetime(created_at, ?1)",
+            [format!("-{} minutes", minutes)],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn wiping_a_note_keeps_it_restorable() {
+        let (_dir, conn) = setup();
+        set_note_inner(&conn, "b", 1, "Lemma 1 and its proof", "markdown").unwrap();
+        // Small edits within the interval are coalesced
+        set_note_inner(&conn, "b", 1, "Lemma 1 and its proof.", "markdown").unwrap();
+        set_note_inner(&conn, "b", 1, "Lemma 1 and its proof..", "markdown").unwrap();
+        assert_eq!(list_note_revisions_inner(&conn, "b", 1, PAGE).unwrap().len(), 1);
+
+        // ggdG
+        set_note_inner(&conn, "b", 1, "", "markdown").unwrap();
+        assert!(get_note_inner(&conn, "b", 1).unwrap().is_none());
+        let revisions = list_note_revisions_inner(&conn, "b", 1, PAGE).unwrap();
+        assert_eq!(revisions[0].content, "Lemma 1 and its proof..");
+
+        let restored = restore_note_revision_inner(&conn, "b", 1, PAGE, revisions[0].id).unwrap();
+        assert_eq!(restored.content, "Lemma 1 and its proof..");
+
+        // The restore itself can be undone
+        set_note_inner(&conn, "b", 1, "Lemma 1 and its proof.. Rewritten", "markdown").unwrap();
+        let newest = list_note_revisions_inner(&conn, "b", 1, PAGE).unwrap().remove(0);
+        restore_note_revision_inner(&conn, "b", 1, PAGE, newest.id).unwrap();
+        assert_eq!(list_note_revisions_inner(&conn, "b", 1, PAGE).unwrap()[0].content, "Lemma 1 and its proof.. Rewritten");
+    }
+
+    #[test]
+    fn diffs_rebuild_every_revision_and_pruning_keeps_the_rest() {
+        let (_dir, conn) = setup();
+        let versions: Vec<String> = (0..25).map(|i| format!("# Notes\n\nEdit {} ünïcode\n\nEnd", i)).collect();
+        for v in &versions {
+            age_revisions(&conn, 11);
+            set_note_inner(&conn, "b", 2, v, "markdown").unwrap();
+        }
+        let revisions = list_note_revisions_inner(&conn, "b", 2, PAGE).unwrap();
+        let contents: Vec<&str> = revisions.iter().rev().map(|r| r.content.as_str()).collect();
+        assert_eq!(contents, versions[..24].iter().map(String::as_str).collect::<Vec<_>>());
+        let snapshots: i64 = conn
+            .query_row("SELECT COUNT(*) FROM note_revisions WHERE content IS NOT NULL", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(snapshots, 3);
+
+        let config = NoteRevisionConfig { max_age_days: Some(30), max_per_note: Some(5) };
+        assert_eq!(prune_note_revisions_inner(&conn, &config).unwrap(), 19);
+        let kept: Vec<String> = list_note_revisions_inner(&conn, "b", 2, PAGE).unwrap().into_iter().map(|r| r.content).collect();
+        assert_eq!(kept, versions[19..24].iter().rev().cloned().collect::<Vec<_>>());
+
+        conn.execute("UPDATE note_revisions SET created_at = datetime('now', '-40 days')", []).unwrap();
+        assert_eq!(prune_note_revisions_inner(&conn, &config).unwrap(), 5);
+    }
+
+    #[test]
+    fn diff_round_trips() {
+        for (old, new) in [("abc", "abc"), ("", "new"), ("aXc", "aYYc"), ("ää", "äöä"), ("same end", "different end")] {
+            let diff = Diff::between(old, new);
+            assert_eq!(diff.apply(old).as_deref(), Some(new));
+        }
+        assert_eq!(Diff::between("abcabc", "abc").apply("abcabc").as_deref(), Some("abc"));
+    }
+}
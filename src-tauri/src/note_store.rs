@@ -0,0 +1,620 @@
+//! Where a book's notes, note images and highlights are stored.
+//!
+//! By default they live in the app database. A library directory can keep
+//! them in `.axiomatic/notes.db` instead, next to its snips and progress, so
+//! copying the folder to another machine carries the notes along. The file's
+//! presence is the mode: a copied library is picked up as is.
+//!
+//! Slugs embed the directory id, which differs between machines; the store
+//! records the id its slugs were written under and rewrites them, along with
+//! the slug-keyed JSON files of `.axiomatic/`, when opened under another.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use rusqlite::{Connection, OptionalExtension};
+use tauri::State;
+
+use crate::commands::{ensure_axiomatic_dir, get_db, list_directories_inner, rename_slugs_in_json, DbState};
+use crate::db;
+use crate::models::{Directory, NoteStorage};
+use crate::note_images;
+use crate::note_links;
+
+pub(crate) const STORE_FILE: &str = "notes.db";
+/// Link target of an image embedded in a note: `axiomatic-image://{id}`.
+pub(crate) const IMAGE_SCHEME: &str = "axiomatic-image://";
+
+/// What a store holds besides the note tables: the directory id its slugs
+/// were written under.
+const STORE_META: &str = "
+    CREATE TABLE IF NOT EXISTS store_meta (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+/// Stores this process has brought up to date, so each is upgraded once rather than on every open.
+static UPGRADED: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+
+/// The database holding a book's notes: the app database or a directory's
+/// own store.
+pub(crate) enum NoteDb<'a> {
+    App(&'a Connection),
+    Dir(Connection),
+}
+
+impl Deref for NoteDb<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            NoteDb::App(conn) => conn,
+            NoteDb::Dir(conn) => conn,
+        }
+    }
+}
+
+fn store_path(dir_path: &str) -> PathBuf {
+    Path::new(dir_path).join(".axiomatic").join(STORE_FILE)
+}
+
+pub(crate) fn storage_of(dir_path: &str) -> NoteStorage {
+    if store_path(dir_path).is_file() {
+        NoteStorage::Directory
+    } else {
+        NoteStorage::App
+    }
+}
+
+/// Open (or create) a directory's store, rewriting slugs written under
+/// another directory id.
+fn open_store(dir_id: i64, dir_path: &str) -> Result<Connection, String> {
+    let path = store_path(dir_path);
+    // A store created by this open, or recreated since, needs its schema
+    let existed = path.is_file();
+    let store = Connection::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    store.busy_timeout(std::time::Duration::from_secs(5)).map_err(|e| e.to_string())?;
+    let upgraded = UPGRADED.get_or_init(Default::default);
+    if !existed || !upgraded.lock().map_err(|e| e.to_string())?.contains(&path) {
+        upgrade_store(&store)?;
+        upgraded.lock().map_err(|e| e.to_string())?.insert(path.clone());
+    }
+
+    let recorded: Option<String> = store
+        .query_row("SELECT value FROM store_meta WHERE key = 'dir_id'", [], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if recorded.as_deref() != Some(dir_id.to_string().as_str()) {
+        let prefixes = recorded.map(|old_id| (format!("{}_", old_id), format!("{}_", dir_id)));
+        let rename = |slug: &str| {
+            let (old, new) = prefixes.as_ref()?;
+            slug.strip_prefix(old.as_str()).map(|rest| format!("{}{}", new, rest))
+        };
+        let tx = store.unchecked_transaction().map_err(|e| e.to_string())?;
+        if let Some((old, new)) = &prefixes {
+            for (table, col) in [("notes", "slug"), ("note_images", "note_slug"), ("highlights", "slug"), ("note_revisions", "slug")] {
+                tx.execute(
+                    &format!(
+                        "UPDATE {table} SET {col} = ?2 || substr({col}, length(?1) + 1)
+                         WHERE substr({col}, 1, length(?1)) = ?1"
+                    ),
+                    [old, new],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+
+            // Links between books of the directory follow their slugs
+            let mut stmt = tx
+                .prepare("SELECT id, content FROM notes WHERE content LIKE '%[[%'")
+                .map_err(|e| e.to_string())?;
+            let notes = stmt
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+                .map_err(|e| e.to_string())?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            drop(stmt);
+            for (id, content) in notes {
+                if let Some(content) = note_links::rewrite_page_links(&content, rename) {
+                    tx.execute("UPDATE notes SET content = ?1 WHERE id = ?2", rusqlite::params![content, id])
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO store_meta (key, value) VALUES ('dir_id', ?1)",
+            [dir_id.to_string()],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        note_links::reindex(&store).map_err(|e| e.to_string())?;
+
+        // So do the directory's snips, progress and other per-book files
+        if prefixes.is_some() {
+            rename_slugs_in_json(dir_path, dir_path, rename)?;
+        }
+    }
+    Ok(store)
+}
+
+/// Bring a store, new or written by an older version, up to date. The note
+/// tables go through the app database's migrations, each applied when the
+/// store doesn't have its change yet.
+pub(crate) fn upgrade_store(store: &Connection) -> Result<(), String> {
+    let has_column = |table: &str, column: &str| -> Result<bool, String> {
+        store
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+                [table, column],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())
+    };
+    let has_links: bool = store
+        .query_row("SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'note_links')", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    store.execute_batch(STORE_META).map_err(|e| e.to_string())?;
+    store.execute_batch(db::NOTE_TABLES).map_err(|e| e.to_string())?;
+    db::add_columns(store, db::HIGHLIGHT_TEXT_AND_GROUP_ID).map_err(|e| e.to_string())?;
+    store.execute_batch(db::NOTE_REVISIONS).map_err(|e| e.to_string())?;
+    if !has_column("notes", "anchor")? {
+        store.execute_batch(db::NOTE_ANCHORS).map_err(|e| e.to_string())?;
+    }
+    store.execute_batch(db::NOTE_LINKS).map_err(|e| e.to_string())?;
+    if !has_links {
+        note_links::reindex(store).map_err(|e| e.to_string())?;
+    }
+    store.execute_batch(db::NOTE_IMAGE_BLOBS).map_err(|e| e.to_string())?;
+    if has_column("note_images", "data")? {
+        note_images::split_blobs(store).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The library directory a slug belongs to, from its `{dir_id}_` prefix.
+fn dir_of_slug(conn: &Connection, slug: &str) -> Result<Option<(i64, String)>, String> {
+    let Some(dir_id) = slug.split('_').next().and_then(|p| p.parse::<i64>().ok()) else {
+        return Ok(None);
+    };
+    conn.query_row("SELECT id, path FROM directories WHERE id = ?1", [dir_id], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// The database holding the notes of the book with `slug`.
+pub(crate) fn open<'a>(conn: &'a Connection, slug: &str) -> Result<NoteDb<'a>, String> {
+    match dir_of_slug(conn, slug)? {
+        Some((id, path)) if storage_of(&path) == NoteStorage::Directory => Ok(NoteDb::Dir(open_store(id, &path)?)),
+        _ => Ok(NoteDb::App(conn)),
+    }
+}
+
+/// The store of a directory in directory mode.
+pub(crate) fn dir_store(dir: &Directory) -> Result<Option<Connection>, String> {
+    match storage_of(&dir.path) {
+        NoteStorage::Directory => open_store(dir.id, &dir.path).map(Some),
+        NoteStorage::App => Ok(None),
+    }
+}
+
+/// The stores of every available directory in directory mode.
+pub(crate) fn directory_stores(conn: &Connection) -> Result<Vec<Connection>, String> {
+    let mut stores = Vec::new();
+    for dir in list_directories_inner(conn)? {
+        stores.extend(dir_store(&dir)?);
+    }
+    Ok(stores)
+}
+
+/// Rewrite `axiomatic-image://{id}` links whose image got a new id.
+pub(crate) fn relink_images(content: &str, ids: &HashMap<i64, i64>) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(pos) = rest.find(IMAGE_SCHEME) {
+        let start = pos + IMAGE_SCHEME.len();
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        match rest[..digits].parse().ok().and_then(|id: i64| ids.get(&id)) {
+            Some(new_id) => out.push_str(&new_id.to_string()),
+            None => out.push_str(&rest[..digits]),
+        }
+        rest = &rest[digits..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Which rows to move: one book, or every book of a directory.
+#[derive(Clone, Copy)]
+enum Scope<'a> {
+    Book(&'a str),
+    Dir(i64),
+}
+
+impl Scope<'_> {
+    fn filter(&self, col: &str) -> (String, String) {
+        match self {
+            Scope::Book(slug) => (format!("{} = ?1", col), slug.to_string()),
+            Scope::Dir(id) => (format!("substr({col}, 1, length(?1)) = ?1"), format!("{}_", id)),
+        }
+    }
+}
+
+/// Move rows in `scope` from `src` to `dst`, renaming slugs with `rename`.
+/// Images keep their id where it is free in `dst`; otherwise the notes
+/// linking to them are rewritten. `dst` is committed before `src` is cleared,
+/// so a failure leaves the rows in both stores rather than in neither.
+fn move_rows(
+    src: &Connection,
+    dst: &Connection,
+    scope: Scope,
+    rename: impl Fn(String) -> String,
+) -> Result<(), String> {
+    let tx = dst.unchecked_transaction().map_err(|e| e.to_string())?;
+
+    let (cond, param) = scope.filter("note_slug");
+    let mut stmt = src
+        .prepare(&format!(
+            "SELECT i.id, i.note_slug, i.note_page, i.filename, b.data, i.created_at
+             FROM note_images i JOIN note_image_blobs b ON b.hash = i.hash WHERE {cond}"
+        ))
+        .map_err(|e| e.to_string())?;
+    let images = stmt
+        .query_map([&param], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Vec<u8>>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let mut ids = HashMap::new();
+    for (id, slug, page, filename, data, created_at) in images {
+        let slug = rename(slug);
+        let taken: bool = tx
+            .query_row("SELECT EXISTS(SELECT 1 FROM note_images WHERE id = ?1)", [id], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        let hash = note_images::put_blob(&tx, &data).map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT INTO note_images (id, note_slug, note_page, filename, hash, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(note_slug, note_page, filename) DO UPDATE SET hash = excluded.hash",
+            rusqlite::params![if taken { None } else { Some(id) }, slug, page, filename, hash, created_at],
+        )
+        .map_err(|e| e.to_string())?;
+        let new_id: i64 = tx
+            .query_row(
+                "SELECT id FROM note_images WHERE note_slug = ?1 AND note_page = ?2 AND filename = ?3",
+                rusqlite::params![slug, page, filename],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if new_id != id {
+            ids.insert(id, new_id);
+        }
+    }
+
+    let (cond, param) = scope.filter("slug");
+    let mut stmt = src
+        .prepare(&format!("SELECT slug, page, anchor, content, format, updated_at FROM notes WHERE {cond}"))
+        .map_err(|e| e.to_string())?;
+    let notes = stmt
+        .query_map([&param], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (slug, page, anchor, content, format, updated_at) in notes {
+        tx.execute(
+            "INSERT INTO notes (slug, page, anchor, content, format, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(slug, page, anchor) DO UPDATE SET content = excluded.content, format = excluded.format,
+                 updated_at = excluded.updated_at",
+            rusqlite::params![rename(slug), page, anchor, relink_images(&content, &ids), format, updated_at],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    let mut stmt = src
+        .prepare(&format!(
+            "SELECT slug, page, x, y, width, height, color, note, text, group_id, created_at FROM highlights WHERE {cond}"
+        ))
+        .map_err(|e| e.to_string())?;
+    let columns = stmt.column_count();
+    let highlights = stmt
+        .query_map([&param], |row| (0..columns).map(|i| row.get::<_, rusqlite::types::Value>(i)).collect())
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<Vec<_>>, _>>()
+        .map_err(|e| e.to_string())?;
+    for mut row in highlights {
+        if let rusqlite::types::Value::Text(slug) = &mut row[0] {
+            *slug = rename(std::mem::take(slug));
+        }
+        tx.execute(
+            "INSERT INTO highlights (slug, page, x, y, width, height, color, note, text, group_id, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            rusqlite::params_from_iter(row),
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    // Revisions keep their order, so diffs still apply to the one before
+    let mut stmt = src
+        .prepare(&format!(
+            "SELECT slug, page, anchor, format, content, diff, created_at FROM note_revisions WHERE {cond} ORDER BY id"
+        ))
+        .map_err(|e| e.to_string())?;
+    let revisions = stmt
+        .query_map([&param], |row| (0..7).map(|i| row.get::<_, rusqlite::types::Value>(i)).collect())
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<Vec<_>>, _>>()
+        .map_err(|e| e.to_string())?;
+    for mut row in revisions {
+        if let rusqlite::types::Value::Text(slug) = &mut row[0] {
+            *slug = rename(std::mem::take(slug));
+        }
+        tx.execute(
+            "INSERT INTO note_revisions (slug, page, anchor, format, content, diff, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params_from_iter(row),
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    note_links::reindex(dst).map_err(|e| e.to_string())?;
+
+    let src_tx = src.unchecked_transaction().map_err(|e| e.to_string())?;
+    for (table, col) in [("note_images", "note_slug"), ("notes", "slug"), ("highlights", "slug"), ("note_revisions", "slug")] {
+        let (cond, param) = scope.filter(col);
+        src_tx
+            .execute(&format!("DELETE FROM {table} WHERE {cond}"), [&param])
+            .map_err(|e| e.to_string())?;
+    }
+    src_tx
+        .execute("DELETE FROM note_image_blobs WHERE hash NOT IN (SELECT hash FROM note_images)", [])
+        .map_err(|e| e.to_string())?;
+    src_tx.commit().map_err(|e| e.to_string())?;
+    note_links::reindex(src).map_err(|e| e.to_string())
+}
+
+/// Move a book's notes to its new slug when either slug's directory keeps
+/// its own store. Runs before the caller renames rows in the app database.
+pub(crate) fn move_book(conn: &Connection, old_slug: &str, new_slug: &str) -> Result<(), String> {
+    let same_dir = dir_of_slug(conn, old_slug)? == dir_of_slug(conn, new_slug)?;
+    match (open(conn, old_slug)?, open(conn, new_slug)?) {
+        (NoteDb::App(_), NoteDb::App(_)) => {}
+        (NoteDb::Dir(store), NoteDb::Dir(_)) if same_dir => {
+            for (table, col) in [("notes", "slug"), ("note_images", "note_slug"), ("highlights", "slug"), ("note_revisions", "slug")] {
+                store
+                    .execute(&format!("UPDATE {table} SET {col} = ?1 WHERE {col} = ?2"), [new_slug, old_slug])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        (src, dst) => {
+            move_rows(&src, &dst, Scope::Book(old_slug), |_| new_slug.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Switch a directory between the app database and its own store, moving
+/// its notes, images and highlights.
+pub(crate) fn set_note_storage_inner(conn: &Connection, dir_path: &str, storage: NoteStorage) -> Result<(), String> {
+    let dir = list_directories_inner(conn)?
+        .into_iter()
+        .find(|d| d.path == dir_path)
+        .ok_or_else(|| format!("Not a library directory: {}", dir_path))?;
+    if storage_of(&dir.path) == storage {
+        return Ok(());
+    }
+    match storage {
+        NoteStorage::Directory => {
+            ensure_axiomatic_dir(&dir.path)?;
+            let store = open_store(dir.id, &dir.path)?;
+            if let Err(e) = move_rows(conn, &store, Scope::Dir(dir.id), |slug| slug) {
+                drop(store);
+                let _ = std::fs::remove_file(store_path(&dir.path));
+                return Err(e);
+            }
+        }
+        NoteStorage::App => {
+            let store = open_store(dir.id, &dir.path)?;
+            move_rows(&store, conn, Scope::Dir(dir.id), |slug| slug)?;
+            drop(store);
+            std::fs::remove_file(store_path(&dir.path))
+                .map_err(|e| format!("Failed to remove {}: {}", store_path(&dir.path).display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_note_storage(dir_path: String) -> NoteStorage {
+    storage_of(&dir_path)
+}
+
+/// Keep a directory's notes, images and highlights in the app database or
+/// in its `.axiomatic/` folder, moving what is there.
+#[tauri::command]
+pub fn set_note_storage(dir_path: String, storage: NoteStorage, state: State<'_, DbState>) -> Result<(), String> {
+    let conn = get_db(&state)?;
+    set_note_storage_inner(&conn, &dir_path, storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{add_directory_inner, get_note_inner, save_note_image_inner, set_note_inner};
+    use crate::highlight_commands::{create_highlight_inner, list_highlights_inner};
+
+    fn setup() -> (tempfile::TempDir, Connection, String, String) {
+        let tmp = tempfile::tempdir().unwrap();
+        let conn = crate::db::init_db(&tmp.path().join("axiomatic.db")).unwrap();
+        let library = tmp.path().join("library");
+        std::fs::create_dir_all(&library).unwrap();
+        let library = library.to_string_lossy().to_string();
+        let dir = add_directory_inner(&conn, &library, "library").unwrap();
+        (tmp, conn, library, format!("{}_algebra", dir.id))
+    }
+
+    #[test]
+    fn switching_storage_moves_notes_both_ways() {
+        let (_tmp, conn, library, slug) = setup();
+        let image = save_note_image_inner(&conn, &slug, 2, "fig.png", b"png").unwrap();
+        let content = format!("![fig]({}{})", IMAGE_SCHEME, image);
+        set_note_inner(&conn, &slug, 2, &content, "markdown").unwrap();
+        create_highlight_inner(&conn, &slug, 2, 0.1, 0.2, 0.3, 0.4, "yellow", "", "text", "g1").unwrap();
+
+        set_note_storage_inner(&conn, &library, NoteStorage::Directory).unwrap();
+        assert_eq!(storage_of(&library), NoteStorage::Directory);
+        assert!(get_note_inner(&conn, &slug, 2).unwrap().is_none());
+        let db = open(&conn, &slug).unwrap();
+        assert_eq!(get_note_inner(&db, &slug, 2).unwrap().unwrap().content, content);
+        assert_eq!(list_highlights_inner(&db, &slug).unwrap().len(), 1);
+        drop(db);
+
+        // An image saved meanwhile in the app database takes the id: the
+        // note is relinked on the way back
+        conn.execute(
+            "INSERT INTO note_images (id, note_slug, note_page, filename, hash) VALUES (?1, '99_other', 1, 'x.png', 'x')",
+            [image],
+        )
+        .unwrap();
+        set_note_storage_inner(&conn, &library, NoteStorage::App).unwrap();
+        assert_eq!(storage_of(&library), NoteStorage::App);
+        let note = get_note_inner(&conn, &slug, 2).unwrap().unwrap();
+        assert_ne!(note.content, content);
+        let new_id: i64 = note.content.trim_end_matches(')').rsplit('/').next().unwrap().parse().unwrap();
+        assert_eq!(crate::commands::get_note_image_inner(&conn, new_id).unwrap(), b"png");
+        assert_eq!(list_highlights_inner(&conn, &slug).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn store_follows_the_directory_to_another_machine() {
+        let (tmp, conn, library, slug) = setup();
+        set_note_storage_inner(&conn, &library, NoteStorage::Directory).unwrap();
+        set_note_inner(&open(&conn, &slug).unwrap(), &slug, 1, "Portable", "markdown").unwrap();
+        let progress = serde_json::json!({ slug.clone(): { "page": 12 } });
+        crate::json_storage::write_json(&library, "progress.json", &progress).unwrap();
+        let snips = serde_json::json!([{ "id": "s1", "slug": slug.clone() }]);
+        crate::json_storage::write_json(&library, "snips.json", &snips).unwrap();
+
+        // The same folder registered as another directory id elsewhere
+        let other = crate::db::init_db(&tmp.path().join("other.db")).unwrap();
+        add_directory_inner(&other, &tmp.path().to_string_lossy(), "first").unwrap();
+        let dir = add_directory_inner(&other, &library, "library").unwrap();
+        let moved = format!("{}_algebra", dir.id);
+        assert_ne!(moved, slug);
+        let db = open(&other, &moved).unwrap();
+        assert_eq!(get_note_inner(&db, &moved, 1).unwrap().unwrap().content, "Portable");
+
+        // Per-book JSON state is remapped along with the store
+        let progress: serde_json::Value = crate::json_storage::read_json(&library, "progress.json");
+        assert_eq!(progress[moved.as_str()]["page"], 12);
+        assert!(progress.get(&slug).is_none());
+        let snips: serde_json::Value = crate::json_storage::read_json(&library, "snips.json");
+        assert_eq!(snips[0]["slug"], moved.as_str());
+    }
+
+    #[test]
+    fn renamed_books_keep_their_notes_in_the_store() {
+        let (tmp, conn, library, slug) = setup();
+        set_note_storage_inner(&conn, &library, NoteStorage::Directory).unwrap();
+        set_note_inner(&open(&conn, &slug).unwrap(), &slug, 1, "Stored", "markdown").unwrap();
+
+        // Renamed within the directory
+        let renamed = slug.replace("algebra", "algebra-2e");
+        crate::commands::migrate_slug_inner(&conn, &slug, &renamed, &library).unwrap();
+        assert_eq!(get_note_inner(&open(&conn, &renamed).unwrap(), &renamed, 1).unwrap().unwrap().content, "Stored");
+
+        // Moved to a directory using the app database
+        let other = tmp.path().join("other");
+        std::fs::create_dir_all(&other).unwrap();
+        let other = add_directory_inner(&conn, &other.to_string_lossy(), "other").unwrap();
+        let moved = format!("{}_algebra-2e", other.id);
+        crate::commands::migrate_slug_between_dirs_inner(&conn, &renamed, &moved, &library, &other.path).unwrap();
+        assert_eq!(get_note_inner(&conn, &moved, 1).unwrap().unwrap().content, "Stored");
+        assert!(get_note_inner(&open(&conn, &renamed).unwrap(), &renamed, 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn stores_from_before_anchors_are_upgraded() {
+        let (_tmp, conn, library, slug) = setup();
+        std::fs::create_dir_all(Path::new(&library).join(".axiomatic")).unwrap();
+        let old = Connection::open(store_path(&library)).unwrap();
+        old.execute_batch(
+            "CREATE TABLE notes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                slug TEXT NOT NULL,
+                page INTEGER NOT NULL,
+                content TEXT NOT NULL DEFAULT '',
+                format TEXT NOT NULL DEFAULT 'html',
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                UNIQUE(slug, page)
+            );",
+        )
+        .unwrap();
+        old.execute("INSERT INTO notes (slug, page, content) VALUES (?1, 3, 'kept')", [&slug]).unwrap();
+        drop(old);
+
+        let db = open(&conn, &slug).unwrap();
+        assert_eq!(get_note_inner(&db, &slug, 3).unwrap().unwrap().content, "kept");
+        let anchor = crate::models::NoteAnchor::Snip { id: "s1".into() };
+        crate::commands::set_anchored_note_inner(&db, &slug, 3, &anchor, "on the snip", "markdown").unwrap();
+        assert_eq!(crate::commands::list_notes_for_page_inner(&db, &slug, 3).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn stores_are_upgraded_once() {
+        let (_tmp, conn, library, slug) = setup();
+        set_note_storage_inner(&conn, &library, NoteStorage::Directory).unwrap();
+        drop(open(&conn, &slug).unwrap());
+        let path = store_path(&library);
+        let has_index = || -> bool {
+            Connection::open(&path)
+                .unwrap()
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'idx_note_links_note')",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        Connection::open(&path).unwrap().execute_batch("DROP INDEX idx_note_links_note").unwrap();
+        drop(open(&conn, &slug).unwrap());
+        assert!(!has_index());
+
+        // A store created again under the same path gets its schema
+        std::fs::remove_file(&path).unwrap();
+        set_note_storage_inner(&conn, &library, NoteStorage::Directory).unwrap();
+        assert!(has_index());
+        set_note_inner(&open(&conn, &slug).unwrap(), &slug, 1, "Fresh", "markdown").unwrap();
+    }
+
+    #[test]
+    fn relinks_only_remapped_images() {
+        let ids = HashMap::from([(1, 7), (7, 9)]);
+        assert_eq!(
+            relink_images("![a](axiomatic-image://1) ![b](axiomatic-image://7) ![c](axiomatic-image://12)", &ids),
+            "![a](axiomatic-image://7) ![b](axiomatic-image://9) ![c](axiomatic-image://12)"
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::Sender;
+
+use crate::doc_identity::compute_doc_id;
+use crate::json_storage::read_json_opt;
+use crate::pdf_models::OcrPage;
+
+/// Where a document's OCR sidecar lives: the library's `.axiomatic/` dir and
+/// the folder inside it holding one file per recognised page, so reading or
+/// adding a page never touches the others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SidecarLocation {
+    pub dir_path: String,
+    pub folder: String,
+}
+
+impl SidecarLocation {
+    /// Locate the sidecar for `doc_path`. The nearest ancestor directory with
+    /// an `.axiomatic/` folder is the library root; loose files outside any
+    /// library keep their OCR in the temp directory.
+    pub fn for_document(doc_path: &Path) -> Result<Self, String> {
+        let doc_id = compute_doc_id(doc_path)?;
+        let root = doc_path
+            .ancestors()
+            .skip(1)
+            .find(|d| d.join(".axiomatic").is_dir())
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| std::env::temp_dir().join("axiomatic-ocr"));
+        let name: String = doc_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        Ok(Self {
+            dir_path: root.to_string_lossy().to_string(),
+            folder: format!("ocr/{}", name),
+        })
+    }
+
+    fn filename(&self, page: u32) -> String {
+        format!("{}/{}.json", self.folder, page)
+    }
+
+    pub fn page(&self, page: u32) -> Option<OcrPage> {
+        read_json_opt(&self.dir_path, &self.filename(page))
+    }
+
+    #[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+    fn store(&self, page: u32, result: &OcrPage) -> Result<(), String> {
+        let folder: PathBuf = Path::new(&self.dir_path).join(".axiomatic").join(&self.folder);
+        std::fs::create_dir_all(&folder).map_err(|e| e.to_string())?;
+        crate::json_storage::write_json(&self.dir_path, &self.filename(page), result)
+    }
+}
+
+/// A page waiting to be recognised.
+#[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+struct OcrJob {
+    path: String,
+    page: u32,
+    sidecar: SidecarLocation,
+}
+
+/// Handle to the background OCR thread, shared by all render workers. Pages
+/// are queued at most once per session; without the `ocr` feature the queue
+/// accepts nothing and only existing sidecars are used.
+#[derive(Clone, Default)]
+pub struct OcrQueue {
+    tx: Option<Sender<OcrJob>>,
+    /// Queued or already attempted pages, so failures are not retried in a loop.
+    seen: Arc<Mutex<HashSet<(String, u32)>>>,
+}
+
+impl OcrQueue {
+    /// Queue a page with no text objects for recognition.
+    pub fn enqueue(&self, path: &str, page: u32, sidecar: &SidecarLocation) {
+        let Some(tx) = &self.tx else { return };
+        let Ok(mut seen) = self.seen.lock() else { return };
+        if seen.insert((path.to_string(), page)) {
+            let _ = tx.send(OcrJob {
+                path: path.to_string(),
+                page,
+                sidecar: sidecar.clone(),
+            });
+        }
+    }
+
+    #[cfg(not(feature = "ocr"))]
+    pub fn start(_pdf_tx: Sender<crate::pdf_engine::PdfRequest>) -> Self {
+        Self::default()
+    }
+
+    /// Spawn the OCR thread. Pages are rendered through the regular worker
+    /// pool, bypassing the render cache, and recognised with the `tesseract`
+    /// command-line tool.
+    #[cfg(feature = "ocr")]
+    pub fn start(pdf_tx: Sender<crate::pdf_engine::PdfRequest>) -> Self {
+        let (tx, rx) = crossbeam_channel::unbounded::<OcrJob>();
+        std::thread::Builder::new()
+            .name("ocr".into())
+            .spawn(move || {
+                while let Ok(job) = rx.recv() {
+                    match tesseract::recognize_page(&pdf_tx, &job.path, job.page) {
+                        Ok(result) => {
+                            if let Err(e) = job.sidecar.store(job.page, &result) {
+                                log::warn!("ocr: failed to store page {} of {}: {}", job.page, job.path, e);
+                            }
+                        }
+                        Err(e) => log::warn!("ocr: page {} of {}: {}", job.page, job.path, e),
+                    }
+                }
+            })
+            .expect("failed to spawn ocr thread");
+        Self {
+            tx: Some(tx),
+            seen: Arc::default(),
+        }
+    }
+}
+
+#[cfg(feature = "ocr")]
+mod tesseract {
+    use std::collections::BTreeMap;
+    use std::process::Command;
+    use std::sync::mpsc;
+
+    use crossbeam_channel::Sender;
+
+    use crate::doc_backend::word_span;
+    use crate::pdf_engine::PdfRequest;
+    use crate::pdf_models::{NormalizedRect, OcrPage};
+
+    /// Render width for recognition; roughly 300 dpi for a letter-size page.
+    const OCR_RENDER_WIDTH: i32 = 2500;
+    /// Words below this Tesseract confidence are dropped as noise.
+    const MIN_CONFIDENCE: f32 = 30.0;
+
+    pub fn recognize_page(pdf_tx: &Sender<PdfRequest>, path: &str, page: u32) -> Result<OcrPage, String> {
+        let (tx, rx) = mpsc::sync_channel(1);
+        pdf_tx
+            .send(PdfRequest::RenderForOcr {
+                path: path.to_string(),
+                page,
+                width: OCR_RENDER_WIDTH,
+                tx,
+            })
+            .map_err(|_| "PDF engine disconnected".to_string())?;
+        let pgm = rx.recv().map_err(|_| "PDF engine disconnected".to_string())??;
+
+        let image = std::env::temp_dir().join(format!("axiomatic-ocr-{}.pgm", uuid::Uuid::new_v4()));
+        std::fs::write(&image, &pgm).map_err(|e| e.to_string())?;
+        let out = Command::new("tesseract").arg(&image).arg("stdout").arg("tsv").output();
+        let _ = std::fs::remove_file(&image);
+        let out = out.map_err(|e| format!("OCR requires the tesseract command: {}", e))?;
+        if !out.status.success() {
+            return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+        }
+        Ok(parse_tsv(&String::from_utf8_lossy(&out.stdout)))
+    }
+
+    /// Parse Tesseract TSV output into page text and word spans normalized to
+    /// the page size reported by the level-1 (page) row.
+    pub fn parse_tsv(tsv: &str) -> OcrPage {
+        let mut page_w = 0.0f32;
+        let mut page_h = 0.0f32;
+        let mut spans = Vec::new();
+        let mut lines: BTreeMap<(u32, u32, u32), Vec<String>> = BTreeMap::new();
+
+        for row in tsv.lines().skip(1) {
+            let cols: Vec<&str> = row.split('\t').collect();
+            if cols.len() < 12 {
+                continue;
+            }
+            let num = |i: usize| cols[i].trim().parse::<f32>().unwrap_or(0.0);
+            match cols[0] {
+                "1" => {
+                    page_w = num(8);
+                    page_h = num(9);
+                }
+                "5" => {
+                    let text = cols[11].trim();
+                    if text.is_empty() || num(10) < MIN_CONFIDENCE || page_w <= 0.0 || page_h <= 0.0 {
+                        continue;
+                    }
+                    let rect = NormalizedRect {
+                        x: num(6) / page_w,
+                        y: num(7) / page_h,
+                        width: num(8) / page_w,
+                        height: num(9) / page_h,
+                    };
+                    spans.push(word_span(text, rect));
+                    let key = (num(2) as u32, num(3) as u32, num(4) as u32);
+                    lines.entry(key).or_default().push(text.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        let text = lines.values().map(|words| words.join(" ")).collect::<Vec<_>>().join("\n");
+        OcrPage { text, spans }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn tsv_words_lines_and_confidence() {
+            let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t1000\t2000\t-1\t
+5\t1\t1\t1\t1\t1\t100\t200\t300\t40\t96.5\tTheorem
+5\t1\t1\t1\t1\t2\t420\t200\t50\t40\t91.0\t2.
+5\t1\t1\t1\t2\t1\t100\t260\t50\t40\t12.0\t~
+5\t1\t1\t1\t2\t2\t160\t260\t200\t40\t88.0\tLet
+";
+            let page = parse_tsv(tsv);
+            assert_eq!(page.text, "Theorem 2.\nLet");
+            assert_eq!(page.spans.len(), 3);
+            assert!((page.spans[0].rect.x - 0.1).abs() < 1e-6);
+            assert!((page.spans[0].rect.y - 0.1).abs() < 1e-6);
+            assert_eq!(page.spans[0].char_rects.len(), 7);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf_models::TextSpan;
+
+    #[test]
+    fn sidecar_lives_in_library_and_follows_identity() {
+        let lib = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(lib.path().join(".axiomatic")).unwrap();
+        std::fs::create_dir_all(lib.path().join("scans")).unwrap();
+        let doc = lib.path().join("scans/old.pdf");
+        std::fs::write(&doc, b"%PDF-1.4\ntrailer << /ID [<abcd> <abcd>] >>").unwrap();
+
+        let loc = SidecarLocation::for_document(&doc).unwrap();
+        assert_eq!(loc.dir_path, lib.path().to_string_lossy());
+        assert_eq!(loc.folder, "ocr/pdfid-abcd");
+
+        let page = OcrPage {
+            text: "Lemma".into(),
+            spans: vec![TextSpan {
+                text: "Lemma".into(),
+                rect: crate::pdf_models::NormalizedRect { x: 0.0, y: 0.0, width: 0.1, height: 0.1 },
+                char_rects: vec![],
+            }],
+        };
+        loc.store(3, &page).unwrap();
+        assert!(lib.path().join(".axiomatic/ocr/pdfid-abcd/3.json").is_file());
+
+        // A renamed file has the same identity and finds the same sidecar
+        let renamed = lib.path().join("scans/new.pdf");
+        std::fs::rename(&doc, &renamed).unwrap();
+        let loc = SidecarLocation::for_document(&renamed).unwrap();
+        assert_eq!(loc.page(3).unwrap().text, "Lemma");
+        assert!(loc.page(4).is_none());
+    }
+
+    #[test]
+    fn disabled_queue_ignores_jobs() {
+        let queue = OcrQueue::default();
+        let loc = SidecarLocation {
+            dir_path: "/nonexistent".into(),
+            folder: "ocr/x".into(),
+        };
+        queue.enqueue("/a.pdf", 1, &loc);
+        assert!(queue.seen.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,434 @@
+//! Write an outline (bookmark tree) into a PDF as an incremental update.
+//!
+//! PDFium has no API for creating bookmarks, so the outline objects are
+//! appended by hand: new outline items, a rewritten catalog pointing at them,
+//! and an xref section chained to the original via `/Prev`. The input should
+//! be a full save from PDFium, whose objects are all uncompressed; objects
+//! are located by their `N G obj` header rather than through the xref.
+
+use std::ops::Range;
+
+use crate::pdf_models::OutlineEntry;
+
+/// Guards against cyclic or absurdly deep page trees.
+const MAX_PAGE_TREE_DEPTH: usize = 32;
+
+/// An indirect reference `num gen R`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ObjRef {
+    num: u32,
+    gen: u16,
+}
+
+/// Top-level entries of a dictionary: key and the byte range of its value.
+type DictEntries = Vec<(String, Range<usize>)>;
+
+/// Append `entries` as the document outline of `pdf`. `page_sizes` are page
+/// widths and heights in points, used to place destination views.
+pub fn append_outline(pdf: &[u8], entries: &[OutlineEntry], page_sizes: &[(f32, f32)]) -> Result<Vec<u8>, String> {
+    let startxref = find_startxref(pdf)?;
+    let trailer = read_trailer(pdf, startxref)?;
+    if trailer.iter().any(|(k, _)| k == "Encrypt") {
+        return Err("Cannot add an outline to an encrypted PDF".to_string());
+    }
+    let value = |key: &str| trailer.iter().find(|(k, _)| k == key).map(|(_, r)| r.clone());
+    let root = value("Root")
+        .and_then(|r| parse_ref(&pdf[r]))
+        .ok_or("PDF trailer has no /Root")?;
+    let size = value("Size")
+        .and_then(|r| ascii(&pdf[r]).trim().parse::<u32>().ok())
+        .ok_or("PDF trailer has no /Size")?;
+
+    let catalog_at = find_object(pdf, root).ok_or("Document catalog is compressed or missing")?;
+    let (catalog, _) = dict_entries(pdf, catalog_at)?;
+    let pages_root = catalog
+        .iter()
+        .find(|(k, _)| k == "Pages")
+        .and_then(|(_, r)| parse_ref(&pdf[r.clone()]))
+        .ok_or("Document catalog has no /Pages")?;
+    let mut pages = Vec::new();
+    collect_pages(pdf, pages_root, 0, &mut pages)?;
+
+    // Number the outline root and items in pre-order after the existing objects
+    let mut items = Vec::new();
+    flatten(entries, None, &mut items);
+    let outline_root = size;
+    let first_item = size + 1;
+    let item_num = |i: usize| first_item + i as u32;
+
+    let mut out = pdf.to_vec();
+    if !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    let mut offsets: Vec<(u32, u16, usize)> = Vec::new();
+
+    // Rewritten catalog: same object number, outline attached
+    offsets.push((root.num, root.gen, out.len()));
+    let mut body = String::new();
+    for (key, range) in &catalog {
+        if key != "Outlines" && key != "PageMode" {
+            body.push_str(&format!("/{} {} ", key, ascii(&pdf[range.clone()]).trim()));
+        }
+    }
+    body.push_str(&format!("/Outlines {} 0 R /PageMode /UseOutlines", outline_root));
+    out.extend(format!("{} {} obj\n<< {} >>\nendobj\n", root.num, root.gen, body).as_bytes());
+
+    let top: Vec<usize> = (0..items.len()).filter(|&i| items[i].parent.is_none()).collect();
+    offsets.push((outline_root, 0, out.len()));
+    let mut dict = String::from("/Type /Outlines");
+    if let (Some(&first), Some(&last)) = (top.first(), top.last()) {
+        dict.push_str(&format!(
+            " /First {} 0 R /Last {} 0 R /Count {}",
+            item_num(first),
+            item_num(last),
+            top.len()
+        ));
+    }
+    out.extend(format!("{} 0 obj\n<< {} >>\nendobj\n", outline_root, dict).as_bytes());
+
+    for (i, item) in items.iter().enumerate() {
+        let siblings: Vec<usize> = (0..items.len()).filter(|&j| items[j].parent == item.parent).collect();
+        let pos = siblings.iter().position(|&j| j == i).unwrap();
+        let children: Vec<usize> = (0..items.len()).filter(|&j| items[j].parent == Some(i)).collect();
+
+        let mut dict = format!(
+            "/Title {} /Parent {} 0 R",
+            pdf_text_string(&item.entry.title),
+            item.parent.map(item_num).unwrap_or(outline_root)
+        );
+        if pos > 0 {
+            dict.push_str(&format!(" /Prev {} 0 R", item_num(siblings[pos - 1])));
+        }
+        if let Some(&next) = siblings.get(pos + 1) {
+            dict.push_str(&format!(" /Next {} 0 R", item_num(next)));
+        }
+        if let (Some(&first), Some(&last)) = (children.first(), children.last()) {
+            // Negative count: collapsed with this many children
+            dict.push_str(&format!(
+                " /First {} 0 R /Last {} 0 R /Count -{}",
+                item_num(first),
+                item_num(last),
+                children.len()
+            ));
+        }
+        if let Some(dest) = destination(item.entry, &pages, page_sizes) {
+            dict.push_str(&format!(" /Dest {}", dest));
+        }
+        offsets.push((item_num(i), 0, out.len()));
+        out.extend(format!("{} 0 obj\n<< {} >>\nendobj\n", item_num(i), dict).as_bytes());
+    }
+
+    let xref_at = out.len();
+    out.extend(b"xref\n");
+    for (num, gen, offset) in &offsets {
+        out.extend(format!("{} 1\n{:010} {:05} n\r\n", num, offset, gen).as_bytes());
+    }
+    let mut trailer_dict = format!("/Size {} /Root {} {} R /Prev {}", item_num(items.len()), root.num, root.gen, startxref);
+    for key in ["Info", "ID"] {
+        if let Some(r) = value(key) {
+            trailer_dict.push_str(&format!(" /{} {}", key, ascii(&pdf[r]).trim()));
+        }
+    }
+    out.extend(format!("trailer\n<< {} >>\nstartxref\n{}\n%%EOF\n", trailer_dict, xref_at).as_bytes());
+    Ok(out)
+}
+
+struct Item<'a> {
+    entry: &'a OutlineEntry,
+    parent: Option<usize>,
+}
+
+fn flatten<'a>(entries: &'a [OutlineEntry], parent: Option<usize>, items: &mut Vec<Item<'a>>) {
+    for entry in entries {
+        items.push(Item { entry, parent });
+        let index = items.len() - 1;
+        flatten(&entry.children, Some(index), items);
+    }
+}
+
+/// `[page /XYZ left top zoom]` for entries with a view, `[page /Fit]` otherwise.
+fn destination(entry: &OutlineEntry, pages: &[ObjRef], sizes: &[(f32, f32)]) -> Option<String> {
+    let index = entry.page?.checked_sub(1)? as usize;
+    let page = pages.get(index)?;
+    let Some(view) = &entry.view else {
+        return Some(format!("[{} {} R /Fit]", page.num, page.gen));
+    };
+    let (width, height) = sizes.get(index).copied().unwrap_or((612.0, 792.0));
+    let num = |v: Option<f32>| v.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "null".into());
+    Some(format!(
+        "[{} {} R /XYZ {} {} {}]",
+        page.num,
+        page.gen,
+        num(view.x.map(|x| x * width)),
+        num(view.y.map(|y| (1.0 - y) * height)),
+        num(view.zoom)
+    ))
+}
+
+/// A text string as UTF-16BE hex with a byte order mark, which needs no escaping.
+fn pdf_text_string(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        hex.push_str(&format!("{:04X}", unit));
+    }
+    hex.push('>');
+    hex
+}
+
+fn ascii(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+fn find_startxref(pdf: &[u8]) -> Result<usize, String> {
+    let at = rfind(pdf, b"startxref").ok_or("PDF has no startxref")?;
+    let rest = ascii(&pdf[at + 9..pdf.len().min(at + 40)]);
+    rest.split_whitespace()
+        .next()
+        .and_then(|n| n.parse().ok())
+        .filter(|&n: &usize| n < pdf.len())
+        .ok_or_else(|| "PDF has an invalid startxref".to_string())
+}
+
+/// The trailer dictionary: after `trailer` for a classic xref table, or the
+/// xref stream's own dictionary.
+fn read_trailer(pdf: &[u8], startxref: usize) -> Result<DictEntries, String> {
+    let at = if pdf[startxref..].starts_with(b"xref") {
+        let keyword = find(&pdf[startxref..], b"trailer").ok_or("PDF has no trailer")? + startxref;
+        skip_ws(pdf, keyword + 7)
+    } else {
+        let obj = find(&pdf[startxref..], b"obj").ok_or("PDF has no trailer")? + startxref;
+        skip_ws(pdf, obj + 3)
+    };
+    Ok(dict_entries(pdf, at)?.0)
+}
+
+/// Position of the dictionary of object `r`, from its last definition.
+fn find_object(pdf: &[u8], r: ObjRef) -> Option<usize> {
+    let needle = format!("{} {} obj", r.num, r.gen);
+    let mut end = pdf.len();
+    while let Some(at) = rfind(&pdf[..end], needle.as_bytes()) {
+        if at == 0 || pdf[at - 1].is_ascii_whitespace() {
+            let dict = skip_ws(pdf, at + needle.len());
+            return pdf[dict..].starts_with(b"<<").then_some(dict);
+        }
+        end = at;
+    }
+    None
+}
+
+fn collect_pages(pdf: &[u8], node: ObjRef, depth: usize, pages: &mut Vec<ObjRef>) -> Result<(), String> {
+    if depth > MAX_PAGE_TREE_DEPTH {
+        return Err("PDF page tree is too deep".to_string());
+    }
+    let at = find_object(pdf, node).ok_or_else(|| format!("Page tree object {} not found", node.num))?;
+    let (dict, _) = dict_entries(pdf, at)?;
+    let kids = dict.iter().find(|(k, _)| k == "Kids").map(|(_, r)| r.clone());
+    let Some(kids) = kids else {
+        pages.push(node);
+        return Ok(());
+    };
+    let tokens = ascii(&pdf[kids]);
+    let tokens: Vec<&str> = tokens.trim_matches(|c| c == '[' || c == ']').split_whitespace().collect();
+    for t in tokens.chunks(3) {
+        if let Some(kid) = parse_ref(t.join(" ").as_bytes()) {
+            collect_pages(pdf, kid, depth + 1, pages)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_ref(bytes: &[u8]) -> Option<ObjRef> {
+    let text = ascii(bytes);
+    let mut parts = text.split_whitespace();
+    let num = parts.next()?.parse().ok()?;
+    let gen = parts.next()?.parse().ok()?;
+    (parts.next()? == "R").then_some(ObjRef { num, gen })
+}
+
+/// Parse the dictionary starting at `at` (which must be `<<`). Returns its
+/// top-level entries and the position after the closing `>>`.
+fn dict_entries(pdf: &[u8], at: usize) -> Result<(DictEntries, usize), String> {
+    if !pdf[at..].starts_with(b"<<") {
+        return Err(format!("Expected a dictionary at offset {}", at));
+    }
+    let mut entries = Vec::new();
+    let mut pos = skip_ws(pdf, at + 2);
+    loop {
+        if pos >= pdf.len() {
+            return Err("Unterminated dictionary".to_string());
+        }
+        if pdf[pos..].starts_with(b">>") {
+            return Ok((entries, pos + 2));
+        }
+        if pdf[pos] != b'/' {
+            return Err(format!("Expected a name at offset {}", pos));
+        }
+        let key_end = token_end(pdf, pos + 1);
+        let key = ascii(&pdf[pos + 1..key_end]);
+        let value_start = skip_ws(pdf, key_end);
+        let value_end = skip_value(pdf, value_start)?;
+        entries.push((key, value_start..value_end));
+        pos = skip_ws(pdf, value_end);
+    }
+}
+
+/// End of the value starting at `pos`: a dictionary, array, string, name,
+/// indirect reference or other simple token.
+fn skip_value(pdf: &[u8], pos: usize) -> Result<usize, String> {
+    match pdf.get(pos) {
+        None => Err("Unexpected end of PDF".to_string()),
+        Some(b'<') if pdf[pos..].starts_with(b"<<") => Ok(dict_entries(pdf, pos)?.1),
+        Some(b'<') => find(&pdf[pos..], b">").map(|e| pos + e + 1).ok_or_else(|| "Unterminated hex string".into()),
+        Some(b'(') => skip_literal_string(pdf, pos),
+        Some(b'[') => {
+            let mut p = skip_ws(pdf, pos + 1);
+            while pdf.get(p) != Some(&b']') {
+                if p >= pdf.len() {
+                    return Err("Unterminated array".to_string());
+                }
+                p = skip_ws(pdf, skip_value(pdf, p)?);
+            }
+            Ok(p + 1)
+        }
+        Some(b'/') => Ok(token_end(pdf, pos + 1)),
+        Some(_) => {
+            let end = token_end(pdf, pos);
+            // `num gen R` is one value
+            let gen_start = skip_ws(pdf, end);
+            let gen_end = token_end(pdf, gen_start);
+            let r_start = skip_ws(pdf, gen_end);
+            let r_end = token_end(pdf, r_start);
+            let is_num = |r: Range<usize>| !r.is_empty() && pdf[r].iter().all(u8::is_ascii_digit);
+            if is_num(pos..end) && is_num(gen_start..gen_end) && &pdf[r_start..r_end] == b"R" {
+                Ok(r_end)
+            } else {
+                Ok(end.max(pos + 1))
+            }
+        }
+    }
+}
+
+fn skip_literal_string(pdf: &[u8], pos: usize) -> Result<usize, String> {
+    let mut depth = 0;
+    let mut p = pos;
+    while p < pdf.len() {
+        match pdf[p] {
+            b'\\' => p += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(p + 1);
+                }
+            }
+            _ => {}
+        }
+        p += 1;
+    }
+    Err("Unterminated string".to_string())
+}
+
+fn is_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || b"()<>[]{}/%".contains(&b)
+}
+
+fn token_end(pdf: &[u8], mut pos: usize) -> usize {
+    while pos < pdf.len() && !is_delimiter(pdf[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+fn skip_ws(pdf: &[u8], mut pos: usize) -> usize {
+    while pos < pdf.len() && pdf[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf_models::DestinationView;
+
+    /// A two-page PDF with a classic xref table and correct offsets.
+    fn minimal_pdf() -> Vec<u8> {
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R /Lang (en (US)) >>",
+            "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>",
+        ];
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, obj) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, obj).as_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend(b"xref\n0 5\n0000000000 65535 f\r\n");
+        for o in offsets {
+            pdf.extend(format!("{:010} 00000 n\r\n", o).as_bytes());
+        }
+        pdf.extend(format!("trailer\n<< /Size 5 /Root 1 0 R /ID [<ab> <ab>] >>\nstartxref\n{}\n%%EOF\n", xref).as_bytes());
+        pdf
+    }
+
+    fn entry(title: &str, page: u32, children: Vec<OutlineEntry>) -> OutlineEntry {
+        OutlineEntry { title: title.into(), page: Some(page), view: None, children }
+    }
+
+    #[test]
+    fn outline_is_appended_as_incremental_update() {
+        let pdf = minimal_pdf();
+        let mut intro = entry("Introduction", 1, vec![]);
+        intro.view = Some(DestinationView { x: None, y: Some(0.25), zoom: None });
+        let entries = vec![intro, entry("Groups", 2, vec![entry("Cosets", 2, vec![])])];
+        let out = append_outline(&pdf, &entries, &[(612.0, 792.0), (612.0, 792.0)]).unwrap();
+
+        assert!(out.starts_with(&pdf));
+        let text = String::from_utf8_lossy(&out);
+        assert!(text.contains("/Dest [3 0 R /XYZ null 594.00 null]"));
+        assert!(text.contains("/Dest [4 0 R /Fit]"));
+        assert!(text.contains("/Count -1"));
+        assert!(text.contains(&format!("/Prev {}", find_startxref(&pdf).unwrap())));
+
+        // The new catalog keeps its entries and points at the outline
+        let startxref = find_startxref(&out).unwrap();
+        let trailer = read_trailer(&out, startxref).unwrap();
+        let root = parse_ref(&out[trailer.iter().find(|(k, _)| k == "Root").unwrap().1.clone()]).unwrap();
+        let (catalog, _) = dict_entries(&out, find_object(&out, root).unwrap()).unwrap();
+        let keys: Vec<&str> = catalog.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["Type", "Pages", "Lang", "Outlines", "PageMode"]);
+        assert_eq!(ascii(&out[catalog[2].1.clone()]), "(en (US))");
+        assert!(trailer.iter().any(|(k, _)| k == "ID"));
+
+        // Every xref entry of the update points at its object
+        let section = &text[startxref..];
+        let lines: Vec<&str> = section.lines().skip(1).take_while(|l| !l.starts_with("trailer")).collect();
+        assert_eq!(lines.len(), 2 * 5);
+        for pair in lines.chunks(2) {
+            let num = pair[0].split_whitespace().next().unwrap();
+            let offset: usize = pair[1][..10].parse().unwrap();
+            assert!(text[offset..].starts_with(&format!("{} 0 obj", num)), "bad offset for {}", num);
+        }
+    }
+
+    #[test]
+    fn titles_are_utf16_and_pages_out_of_range_have_no_dest() {
+        assert_eq!(pdf_text_string("Ä("), "<FEFF00C40028>");
+        let pdf = minimal_pdf();
+        let out = append_outline(&pdf, &[entry("Index", 9, vec![])], &[]).unwrap();
+        let text = String::from_utf8_lossy(&out[pdf.len()..]).to_string();
+        assert!(!text.contains("/Dest"));
+        assert!(text.contains("/Count 1"));
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+
+use crossbeam_channel::Sender;
+use tauri::State;
+
+use crate::json_storage::{read_json, update_json};
+use crate::layout;
+use crate::pdf_commands::{request, PdfState};
+use crate::pdf_engine::PdfRequest;
+use crate::pdf_models::{
+    BlockKind, CustomOutline, DestinationView, OutlineEntry, OutlineMode, PageTextLayer,
+};
+
+const OUTLINES_FILE: &str = "outlines.json";
+/// Generated outlines nest at most this deep.
+const MAX_LEVELS: usize = 3;
+/// Heading sizes within this ratio of each other are the same level.
+const SIZE_TOLERANCE: f32 = 0.08;
+/// Longer "headings" are misclassified paragraphs.
+const MAX_HEADING_CHARS: usize = 120;
+
+/// Combine the document outline with a custom one. In merge mode custom
+/// top-level entries are inserted before the first PDF entry on a later
+/// page, replacing PDF entries with the same title and page.
+pub fn merge_outlines(pdf: Vec<OutlineEntry>, custom: &CustomOutline) -> Vec<OutlineEntry> {
+    if custom.mode == OutlineMode::Replace {
+        return custom.entries.clone();
+    }
+    let same = |a: &OutlineEntry, b: &OutlineEntry| {
+        a.page == b.page && a.title.trim().eq_ignore_ascii_case(b.title.trim())
+    };
+    let mut merged: Vec<OutlineEntry> = pdf
+        .into_iter()
+        .filter(|p| !custom.entries.iter().any(|c| same(p, c)))
+        .collect();
+    for entry in &custom.entries {
+        let at = entry
+            .page
+            .and_then(|page| merged.iter().position(|e| e.page.is_some_and(|p| p > page)))
+            .unwrap_or(merged.len());
+        merged.insert(at, entry.clone());
+    }
+    merged
+}
+
+/// A heading found on a page, with its font size in page-height units.
+struct Heading {
+    page: u32,
+    title: String,
+    y: f32,
+    size: f32,
+    depth: Option<usize>,
+}
+
+/// Build an outline from the headings of a document's pages. Numbered
+/// headings ("2.3 Cosets") nest by their number; the rest are ranked by
+/// font size, largest first.
+pub fn outline_from_headings(layers: &[PageTextLayer]) -> Vec<OutlineEntry> {
+    let mut headings = Vec::new();
+    for (i, layer) in layers.iter().enumerate() {
+        let neighbours: Vec<PageTextLayer> = [i.checked_sub(1), Some(i + 1)]
+            .into_iter()
+            .flatten()
+            .filter_map(|j| layers.get(j).cloned())
+            .collect();
+        let page_layout = layout::analyze(layer, &neighbours);
+        for block in page_layout.blocks.iter().filter(|b| b.kind == BlockKind::Heading) {
+            let title = block.text.trim().to_string();
+            if title.len() > MAX_HEADING_CHARS || !title.chars().any(char::is_alphabetic) {
+                continue;
+            }
+            let sizes: Vec<f32> = layer
+                .spans
+                .iter()
+                .filter(|s| {
+                    let (cx, cy) = (s.rect.x + s.rect.width / 2.0, s.rect.y + s.rect.height / 2.0);
+                    cx >= block.rect.x
+                        && cx <= block.rect.x + block.rect.width
+                        && cy >= block.rect.y
+                        && cy <= block.rect.y + block.rect.height
+                })
+                .map(|s| s.rect.height)
+                .collect();
+            let first = title.split_whitespace().next().unwrap_or("");
+            headings.push(Heading {
+                page: layer.page,
+                depth: layout::is_section_number(first)
+                    .then(|| first.trim_end_matches('.').split('.').count()),
+                title,
+                y: block.rect.y,
+                size: median(sizes).unwrap_or(block.rect.height),
+            });
+        }
+    }
+
+    // Distinct sizes, largest first. A size used by numbered headings takes
+    // their level; other sizes are ranked.
+    let mut sizes: Vec<f32> = headings.iter().map(|h| h.size).collect();
+    sizes.sort_by(|a, b| b.total_cmp(a));
+    let mut clusters: Vec<(f32, Option<usize>)> = Vec::new();
+    for size in sizes {
+        if clusters.last().map_or(true, |(l, _)| size < l * (1.0 - SIZE_TOLERANCE)) {
+            clusters.push((size, None));
+        }
+    }
+    for h in &headings {
+        if let Some(depth) = h.depth {
+            let c = cluster_of(&clusters, h.size);
+            clusters[c].1 = Some(clusters[c].1.map_or(depth - 1, |l| l.min(depth - 1)));
+        }
+    }
+
+    let mut roots: Vec<OutlineEntry> = Vec::new();
+    // Levels of the open path from the root to the last inserted entry
+    let mut path: Vec<usize> = Vec::new();
+    for h in headings {
+        let level = match h.depth {
+            Some(depth) => depth - 1,
+            None => {
+                let c = cluster_of(&clusters, h.size);
+                clusters[c].1.unwrap_or(c)
+            }
+        }
+        .min(MAX_LEVELS - 1);
+        while path.last().is_some_and(|&l| l >= level) {
+            path.pop();
+        }
+        let mut siblings = &mut roots;
+        for _ in 0..path.len() {
+            siblings = &mut siblings.last_mut().unwrap().children;
+        }
+        siblings.push(OutlineEntry {
+            title: h.title,
+            page: Some(h.page),
+            view: Some(DestinationView { x: None, y: Some(h.y), zoom: None }),
+            children: Vec::new(),
+        });
+        path.push(level);
+    }
+    roots
+}
+
+/// Index of the largest size cluster that `size` belongs to.
+fn cluster_of(clusters: &[(f32, Option<usize>)], size: f32) -> usize {
+    clusters
+        .iter()
+        .position(|(l, _)| size >= l * (1.0 - SIZE_TOLERANCE))
+        .unwrap_or(0)
+}
+
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    values.sort_by(|a, b| a.total_cmp(b));
+    values.get(values.len() / 2).copied()
+}
+
+fn generate_outline_inner(sender: &Sender<PdfRequest>, path: &str) -> Result<Vec<OutlineEntry>, String> {
+    let info = request(sender, |tx| PdfRequest::OpenDocument {
+        path: path.to_string(),
+        tx,
+    })?;
+    let layers = (1..=info.page_count)
+        .map(|page| {
+            request(sender, |tx| PdfRequest::GetPageTextLayer {
+                path: path.to_string(),
+                page,
+                tx,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(outline_from_headings(&layers))
+}
+
+#[tauri::command]
+pub fn get_custom_outline(dir_path: String, slug: String) -> Result<Option<CustomOutline>, String> {
+    let mut map: HashMap<String, CustomOutline> = read_json(&dir_path, OUTLINES_FILE);
+    Ok(map.remove(&slug))
+}
+
+#[tauri::command]
+pub fn save_custom_outline(dir_path: String, slug: String, outline: CustomOutline) -> Result<(), String> {
+    update_json::<HashMap<String, CustomOutline>, _>(&dir_path, OUTLINES_FILE, |map| {
+        map.insert(slug, outline);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn delete_custom_outline(dir_path: String, slug: String) -> Result<(), String> {
+    update_json::<HashMap<String, CustomOutline>, _>(&dir_path, OUTLINES_FILE, |map| {
+        map.remove(&slug);
+        Ok(())
+    })
+}
+
+/// The outline to show for a book: the PDF's own, combined with the
+/// custom outline if one is saved.
+#[tauri::command]
+pub fn get_book_outline(
+    dir_path: String,
+    slug: String,
+    path: String,
+    state: State<'_, PdfState>,
+) -> Result<Vec<OutlineEntry>, String> {
+    book_outline_inner(&state.sender, dir_path, slug, path)
+}
+
+pub(crate) fn book_outline_inner(
+    sender: &Sender<PdfRequest>,
+    dir_path: String,
+    slug: String,
+    path: String,
+) -> Result<Vec<OutlineEntry>, String> {
+    let pdf = request(sender, |tx| PdfRequest::GetOutline { path, tx })?;
+    match get_custom_outline(dir_path, slug)? {
+        Some(custom) => Ok(merge_outlines(pdf, &custom)),
+        None => Ok(pdf),
+    }
+}
+
+/// Suggest an outline from the document's headings. Nothing is saved; the
+/// caller edits the result and stores it with `save_custom_outline`.
+#[tauri::command]
+pub async fn generate_outline(path: String, state: State<'_, PdfState>) -> Result<Vec<OutlineEntry>, String> {
+    let sender = state.sender.clone();
+    tokio::task::spawn_blocking(move || generate_outline_inner(&sender, &path))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// Save a copy of the PDF at `output_path` with `entries` as its bookmarks.
+#[tauri::command]
+pub async fn export_outline_pdf(
+    path: String,
+    entries: Vec<OutlineEntry>,
+    output_path: String,
+    state: State<'_, PdfState>,
+) -> Result<(), String> {
+    let sender = state.sender.clone();
+    tokio::task::spawn_blocking(move || {
+        request(&sender, |tx| PdfRequest::WriteOutline {
+            path,
+            entries,
+            output_path,
+            tx,
+        })
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doc_backend::word_span;
+    use crate::pdf_models::NormalizedRect;
+
+    fn entry(title: &str, page: u32) -> OutlineEntry {
+        OutlineEntry { title: title.into(), page: Some(page), view: None, children: vec![] }
+    }
+
+    fn titles(entries: &[OutlineEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.title.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_inserts_by_page_and_replace_ignores_pdf() {
+        let pdf = vec![entry("Preface", 1), entry("Groups", 10), entry("Rings", 40)];
+        let mut custom = CustomOutline {
+            mode: OutlineMode::Merge,
+            entries: vec![entry("Sylow theorems", 25), entry("groups ", 10), entry("Notes", 99)],
+        };
+        let mut groups = entry("Groups", 10);
+        groups.children.push(entry("Cosets", 12));
+        custom.entries[1] = groups;
+
+        let merged = merge_outlines(pdf.clone(), &custom);
+        assert_eq!(titles(&merged), vec!["Preface", "Groups", "Sylow theorems", "Rings", "Notes"]);
+        assert_eq!(merged[1].children.len(), 1);
+
+        custom.mode = OutlineMode::Replace;
+        assert_eq!(titles(&merge_outlines(pdf, &custom)), vec!["Sylow theorems", "Groups", "Notes"]);
+    }
+
+    #[test]
+    fn stored_per_slug() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+        let outline = CustomOutline { mode: OutlineMode::Replace, entries: vec![entry("Intro", 1)] };
+        save_custom_outline(dir_path.clone(), "algebra".into(), outline).unwrap();
+
+        let stored = get_custom_outline(dir_path.clone(), "algebra".into()).unwrap().unwrap();
+        assert_eq!(stored.mode, OutlineMode::Replace);
+        assert_eq!(titles(&stored.entries), vec!["Intro"]);
+        assert!(get_custom_outline(dir_path.clone(), "topology".into()).unwrap().is_none());
+
+        delete_custom_outline(dir_path.clone(), "algebra".into()).unwrap();
+        assert!(get_custom_outline(dir_path, "algebra".into()).unwrap().is_none());
+    }
+
+    fn page(number: u32, lines: &[(&str, f32, f32)]) -> PageTextLayer {
+        let mut spans = Vec::new();
+        for &(text, y, h) in lines {
+            let mut x = 0.15;
+            for word in text.split_whitespace() {
+                let width = 0.012 * word.len() as f32 * h / 0.012;
+                spans.push(word_span(word, NormalizedRect { x, y, width, height: h }));
+                x += width + 0.01;
+            }
+        }
+        PageTextLayer { page: number, spans }
+    }
+
+    #[test]
+    fn headings_nest_by_number_and_size() {
+        let body = |y: f32| ("we prove that every group of this order is abelian", y, 0.012);
+        let layers = vec![
+            page(1, &[("Preface", 0.2, 0.024), body(0.3), body(0.315), body(0.33)]),
+            page(2, &[("1 Groups", 0.2, 0.02), body(0.3), body(0.315), ("1.1 Cosets", 0.4, 0.016), body(0.45)]),
+            page(3, &[body(0.2), body(0.215), ("Exercises", 0.4, 0.016), body(0.45), body(0.465)]),
+            page(4, &[("2 Rings", 0.2, 0.02), body(0.3), body(0.315), body(0.33)]),
+        ];
+        let outline = outline_from_headings(&layers);
+        assert_eq!(titles(&outline), vec!["Preface", "1 Groups", "2 Rings"]);
+        assert_eq!(titles(&outline[1].children), vec!["1.1 Cosets", "Exercises"]);
+        assert_eq!(outline[1].children[1].page, Some(3));
+        assert_eq!(outline[2].view.as_ref().unwrap().y, Some(0.2));
+    }
+}
@@ -5,8 +5,14 @@ use std::sync::Arc;
 use crossbeam_channel::Sender;
 use tauri::State;
 
+use crate::clip_commands::record_clip_provenance_inner;
+use crate::commands::{get_db, DbState};
 use crate::pdf_engine::PdfRequest;
-use crate::pdf_models::{DocumentInfo, LinkAnnotation, OutlineEntry, PageTextLayer, SearchResult};
+use crate::scan_index::record_page_count_inner;
+use crate::structures::{indexed_slug_inner, rects_overlap, resolve_references_inner};
+use crate::pdf_models::{
+    Destination, DocumentInfo, LinkAnnotation, NormalizedRect, OutlineEntry, PageLayout, PageTextLayer, SearchResult, TextMode,
+};
 
 pub struct PdfState {
     pub sender: Sender<PdfRequest>,
@@ -16,11 +22,19 @@ pub struct PdfState {
 fn send_request<T>(
     state: &State<'_, PdfState>,
     request_fn: impl FnOnce(mpsc::SyncSender<Result<T, String>>) -> PdfRequest,
+) -> Result<T, String> {
+    request(&state.sender, request_fn)
+}
+
+/// Send a request to the render workers and wait for the reply. Usable from
+/// background threads that hold a clone of the sender.
+pub(crate) fn request<T>(
+    sender: &Sender<PdfRequest>,
+    request_fn: impl FnOnce(mpsc::SyncSender<Result<T, String>>) -> PdfRequest,
 ) -> Result<T, String> {
     let (tx, rx) = mpsc::sync_channel(1);
     let request = request_fn(tx);
-    state
-        .sender
+    sender
         .send(request)
         .map_err(|_| "PDF engine disconnected".to_string())?;
     rx.recv()
@@ -31,22 +45,31 @@ fn send_request<T>(
 pub async fn open_document(
     path: String,
     state: State<'_, PdfState>,
+    db: State<'_, DbState>,
 ) -> Result<DocumentInfo, String> {
     // Bump generation so the render thread skips stale renders queued before this.
     state.generation.fetch_add(1, Ordering::Relaxed);
 
     let sender = state.sender.clone();
+    let open_path = path.clone();
 
-    tokio::task::spawn_blocking(move || {
+    let info = tokio::task::spawn_blocking(move || {
         let (tx, rx) = mpsc::sync_channel(1);
         sender
-            .send(PdfRequest::OpenDocument { path, tx })
+            .send(PdfRequest::OpenDocument { path: open_path, tx })
             .map_err(|_| "PDF engine disconnected".to_string())?;
         rx.recv()
             .map_err(|_| "PDF engine disconnected".to_string())?
     })
     .await
-    .map_err(|e| format!("Task join error: {}", e))?
+    .map_err(|e| format!("Task join error: {}", e))??;
+
+    // Page count is only known once opened; keep it for the library listing
+    let conn = get_db(&db)?;
+    if let Err(e) = record_page_count_inner(&conn, &path, info.page_count as i64) {
+        log::warn!("open_document: failed to record page count of {}: {}", path, e);
+    }
+    Ok(info)
 }
 
 #[tauri::command]
@@ -123,23 +146,66 @@ pub fn get_page_links(
     path: String,
     page: u32,
     state: State<'_, PdfState>,
+    db: State<'_, DbState>,
 ) -> Result<Vec<LinkAnnotation>, String> {
-    send_request(&state, |tx| PdfRequest::GetPageLinks {
-        path,
+    let mut links = send_request(&state, |tx| PdfRequest::GetPageLinks {
+        path: path.clone(),
         page,
         tx,
-    })
+    })?;
+
+    // Books with a structure index also get links for textual references
+    // ("by Lemma 2.3", "see (4.12)") that the document doesn't hyperlink.
+    let slug = indexed_slug_inner(&*get_db(&db)?, &path)?;
+    if let Some(slug) = slug {
+        let layer = send_request(&state, |tx| PdfRequest::GetPageTextLayer { path, page, tx });
+        if let Ok(layer) = layer {
+            let synthetic = resolve_references_inner(&*get_db(&db)?, &slug, page, &layer)?;
+            let synthetic: Vec<_> = synthetic
+                .into_iter()
+                .filter(|s| !links.iter().any(|l| rects_overlap(&l.rect, &s.rect)))
+                .collect();
+            links.extend(synthetic);
+        }
+    }
+    Ok(links)
+}
+
+/// Resolve a named destination, e.g. the fragment of `book.pdf#chap2`.
+#[tauri::command]
+pub fn resolve_named_destination(
+    path: String,
+    name: String,
+    state: State<'_, PdfState>,
+) -> Result<Option<Destination>, String> {
+    send_request(&state, |tx| PdfRequest::ResolveDestination { path, name, tx })
+}
+
+/// Resolve where a remote link leads. The target file is only opened when
+/// the link is followed, not each time the page's links are listed.
+#[tauri::command]
+pub fn resolve_remote_link(
+    path: String,
+    page: u32,
+    index: u32,
+    state: State<'_, PdfState>,
+) -> Result<Option<Destination>, String> {
+    send_request(&state, |tx| PdfRequest::ResolveRemoteLink { path, page, index, tx })
 }
 
 #[tauri::command]
 pub fn extract_page_text(
     path: String,
     page: u32,
+    mode: Option<TextMode>,
+    region: Option<NormalizedRect>,
     state: State<'_, PdfState>,
 ) -> Result<String, String> {
     send_request(&state, |tx| PdfRequest::ExtractPageText {
         path,
         page,
+        mode: mode.unwrap_or_default(),
+        region,
         tx,
     })
 }
@@ -164,12 +230,38 @@ pub fn clip_pdf(
     end_page: u32,
     output_path: String,
     state: State<'_, PdfState>,
+    db: State<'_, DbState>,
 ) -> Result<(), String> {
     send_request(&state, |tx| PdfRequest::ClipPdf {
-        source_path,
+        source_path: source_path.clone(),
         start_page,
         end_page,
-        output_path,
+        output_path: output_path.clone(),
+        tx,
+    })?;
+
+    // Link the clip back to its source book when both live in the library.
+    // The clip is already on disk, so a failure here must not fail the command.
+    let recorded = get_db(&db).and_then(|conn| {
+        record_clip_provenance_inner(&conn, &source_path, start_page, end_page, &output_path)
+    });
+    if let Err(e) = recorded {
+        log::warn!("clip_pdf: failed to record provenance for {}: {}", output_path, e);
+    }
+    Ok(())
+}
+
+/// Columns, reading order and block types of a page, with running headers,
+/// footers and page numbers separated out.
+#[tauri::command]
+pub fn get_page_layout(
+    path: String,
+    page: u32,
+    state: State<'_, PdfState>,
+) -> Result<PageLayout, String> {
+    send_request(&state, |tx| PdfRequest::GetPageLayout {
+        path,
+        page,
         tx,
     })
 }
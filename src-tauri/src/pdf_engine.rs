@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::SyncSender;
 use std::sync::Arc;
@@ -17,8 +18,16 @@ pub fn worker_count() -> usize {
 }
 
 use image::codecs::jpeg::JpegEncoder;
+use image::DynamicImage;
 use pdfium_render::prelude::*;
 
+use crate::djvu_backend::DjvuBackend;
+use crate::doc_backend::{page_dimension, DocumentBackend, DocumentKind};
+use crate::epub_backend;
+use crate::layout;
+use crate::math_text::{self, Glyph, PageGlyphs};
+use crate::ocr::{OcrQueue, SidecarLocation};
+use crate::outline_writer;
 use crate::pdf_models::*;
 
 /// Requests sent from IPC commands / protocol handler to the render thread.
@@ -39,6 +48,15 @@ pub enum PdfRequest {
         generation: u64,
         tx: SyncSender<Result<Vec<u8>, String>>,
     },
+    /// Full-size grayscale PGM of a page for recognition. Kept out of the
+    /// render cache and never preempted.
+    #[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+    RenderForOcr {
+        path: String,
+        page: u32,
+        width: i32,
+        tx: SyncSender<Result<Vec<u8>, String>>,
+    },
     GetOutline {
         path: String,
         tx: SyncSender<Result<Vec<OutlineEntry>, String>>,
@@ -48,9 +66,22 @@ pub enum PdfRequest {
         page: u32,
         tx: SyncSender<Result<Vec<LinkAnnotation>, String>>,
     },
+    ResolveDestination {
+        path: String,
+        name: String,
+        tx: SyncSender<Result<Option<Destination>, String>>,
+    },
+    ResolveRemoteLink {
+        path: String,
+        page: u32,
+        index: u32,
+        tx: SyncSender<Result<Option<Destination>, String>>,
+    },
     ExtractPageText {
         path: String,
         page: u32,
+        mode: TextMode,
+        region: Option<NormalizedRect>, // only text inside this area (snips)
         tx: SyncSender<Result<String, String>>,
     },
     SearchDocument {
@@ -65,6 +96,17 @@ pub enum PdfRequest {
         output_path: String,
         tx: SyncSender<Result<(), String>>,
     },
+    GetPageLayout {
+        path: String,
+        page: u32,
+        tx: SyncSender<Result<PageLayout, String>>,
+    },
+    WriteOutline {
+        path: String,
+        entries: Vec<OutlineEntry>,
+        output_path: String,
+        tx: SyncSender<Result<(), String>>,
+    },
     GetPageTextLayer {
         path: String,
         page: u32,
@@ -72,175 +114,168 @@ pub enum PdfRequest {
     },
 }
 
-struct PdfEngine {
-    pdfium: &'static Pdfium,
-    documents: HashMap<String, PdfDocument<'static>>,
-    cache: SharedRenderCache,
+/// PDF documents, served by PDFium.
+pub struct PdfiumBackend {
+    doc: PdfDocument<'static>,
+    /// Remote GoTo file paths are relative to the document's directory.
+    path: PathBuf,
 }
 
-impl PdfEngine {
-    fn new(pdfium: &'static Pdfium, cache: SharedRenderCache) -> Self {
-        Self {
-            pdfium,
-            documents: HashMap::new(),
-            cache,
-        }
-    }
-
-    /// Load document into cache if not already present.
-    fn ensure_document(&mut self, path: &str) -> Result<(), String> {
-        if !self.documents.contains_key(path) {
-            let doc = self
-                .pdfium
-                .load_pdf_from_file(path, None)
-                .map_err(|e| format!("Failed to load PDF '{}': {:?}", path, e))?;
-            self.documents.insert(path.to_string(), doc);
-        }
-        Ok(())
+impl PdfiumBackend {
+    pub fn open(pdfium: &'static Pdfium, path: &str) -> Result<Self, String> {
+        let doc = pdfium
+            .load_pdf_from_file(path, None)
+            .map_err(|e| format!("Failed to load PDF '{}': {:?}", path, e))?;
+        Ok(Self {
+            doc,
+            path: PathBuf::from(path),
+        })
     }
 
-    fn open_document(&mut self, path: &str) -> Result<DocumentInfo, String> {
-        self.ensure_document(path)?;
-        let doc = self.documents.get(path).unwrap();
-        let page_count = doc.pages().len() as u32;
-
-        // Read per-page dimensions via FPDF_GetPageSizeByIndexF (cheap metadata query,
-        // no full page loading — safe even for 500+ page textbooks).
-        let pages = doc
+    /// Page sizes in points, for normalizing destination coordinates.
+    fn point_sizes(&self) -> Vec<(f32, f32)> {
+        self.doc
             .pages()
             .page_sizes()
-            .map_err(|e| format!("Failed to read page sizes: {:?}", e))?
-            .into_iter()
-            .map(|rect| {
-                let w = rect.width().value;
-                let h = rect.height().value;
-                PageDimension {
-                    width_pts: w,
-                    height_pts: h,
-                    aspect_ratio: if h > 0.0 { w / h } else { 1.0 },
-                }
-            })
-            .collect();
-
-        Ok(DocumentInfo {
-            doc_id: path.to_string(),
-            page_count,
-            pages,
-            title: None,
-        })
+            .map(|sizes| sizes.iter().map(|r| (r.width().value, r.height().value)).collect())
+            .unwrap_or_default()
     }
 
-    fn render_page(
-        &mut self,
-        path: &str,
-        page: u32,
-        width: i32,
-        dpr: f32,
-    ) -> Result<Vec<u8>, String> {
-        let key = RenderKey {
-            path: path.to_string(),
-            page,
-            width,
-            dpr_hundredths: (dpr * 100.0) as u32,
-        };
-
-        // Check shared cache (another thread may have missed, but render thread
-        // might have since rendered it for a different caller).
-        {
-            let mut cache = self.cache.lock().unwrap();
-            if let Some(cached) = cache.get(&key) {
-                return Ok(cached.clone());
+    /// GoTo-remote links on a page, which pdfium-render does not expose:
+    /// enumerate the raw link annotations and keep those with a remote
+    /// action, along with the file the action names.
+    fn remote_actions(&self, page: &PdfPage) -> Vec<(FPDF_LINK, FPDF_ACTION, String)> {
+        let bindings = self.doc.bindings();
+        let page_handle = bindings.get_handle_from_page(page);
+        let mut result = Vec::new();
+        let mut pos: std::os::raw::c_int = 0;
+        let mut link: FPDF_LINK = std::ptr::null_mut();
+        while bindings.is_true(bindings.FPDFLink_Enumerate(page_handle, &mut pos, &mut link)) {
+            let action = bindings.FPDFLink_GetAction(link);
+            if action.is_null() || bindings.FPDFAction_GetType(action) != PDFACTION_REMOTEGOTO {
+                continue;
+            }
+            if let Some(file) = action_file_path(bindings, action) {
+                result.push((link, action, file));
             }
         }
+        result
+    }
 
-        self.ensure_document(path)?;
-
-        let buf = {
-            let doc = self.documents.get(path).unwrap();
-            let page_index = page
-                .checked_sub(1)
-                .ok_or_else(|| "Page number must be >= 1".to_string())?
-                as u16;
-            let page_obj = doc
-                .pages()
-                .get(page_index)
-                .map_err(|e| format!("Failed to get page {}: {:?}", page, e))?;
+    /// Path of a file named by a remote link.
+    fn remote_target(&self, file: &str) -> PathBuf {
+        self.path.parent().unwrap_or(Path::new("")).join(file)
+    }
 
-            let render_width = (width as f32 * dpr) as i32;
-            let config = PdfRenderConfig::new().set_target_width(render_width);
+    /// Remote links of a page, numbered in page order. Their destinations
+    /// are left for `remote_destination`, since resolving one means loading
+    /// the target file.
+    fn remote_links(&self, page: &PdfPage, width: f32, height: f32) -> Vec<LinkAnnotation> {
+        let bindings = self.doc.bindings();
+        let mut result = Vec::new();
+        for (index, (link, _, file)) in self.remote_actions(page).into_iter().enumerate() {
+            let mut rect = FS_RECTF { left: 0.0, top: 0.0, right: 0.0, bottom: 0.0 };
+            if !bindings.is_true(bindings.FPDFLink_GetAnnotRect(link, &mut rect)) {
+                continue;
+            }
+            result.push(LinkAnnotation {
+                rect: NormalizedRect {
+                    x: rect.left / width,
+                    y: 1.0 - rect.top / height,
+                    width: (rect.right - rect.left) / width,
+                    height: (rect.top - rect.bottom) / height,
+                },
+                link_type: LinkType::Remote {
+                    file: self.remote_target(&file).to_string_lossy().to_string(),
+                    index: index as u32,
+                },
+                synthetic: false,
+            });
+        }
+        result
+    }
 
-            let bitmap = page_obj
-                .render_with_config(&config)
-                .map_err(|e| format!("Failed to render page {}: {:?}", page, e))?;
+    fn page(&self, page: u32) -> Result<PdfPage<'_>, String> {
+        let page_index = page
+            .checked_sub(1)
+            .ok_or_else(|| "Page number must be >= 1".to_string())?
+            as u16;
+        self.doc
+            .pages()
+            .get(page_index)
+            .map_err(|e| format!("Failed to get page {}: {:?}", page, e))
+    }
+}
 
-            let image = bitmap.as_image();
-            let mut buf = Vec::new();
-            let encoder = JpegEncoder::new_with_quality(&mut buf, 80);
-            image
-                .write_with_encoder(encoder)
-                .map_err(|e| format!("Failed to encode JPEG: {:?}", e))?;
-            buf
-        };
+impl DocumentBackend for PdfiumBackend {
+    fn page_count(&self) -> u32 {
+        self.doc.pages().len() as u32
+    }
 
-        // Store in shared cache so the protocol handler can serve it directly
-        self.cache.lock().unwrap().put(key, buf.clone());
-        Ok(buf)
+    fn page_sizes(&self) -> Result<Vec<PageDimension>, String> {
+        // Read per-page dimensions via FPDF_GetPageSizeByIndexF (cheap metadata query,
+        // no full page loading — safe even for 500+ page textbooks).
+        Ok(self
+            .doc
+            .pages()
+            .page_sizes()
+            .map_err(|e| format!("Failed to read page sizes: {:?}", e))?
+            .into_iter()
+            .map(|rect| page_dimension(rect.width().value, rect.height().value))
+            .collect())
     }
 
-    fn close_document(&mut self, path: &str) {
-        self.documents.remove(path);
+    fn render_page(&self, page: u32, target_width: i32) -> Result<DynamicImage, String> {
+        let page_obj = self.page(page)?;
+        let config = PdfRenderConfig::new().set_target_width(target_width);
+        let bitmap = page_obj
+            .render_with_config(&config)
+            .map_err(|e| format!("Failed to render page {}: {:?}", page, e))?;
+        Ok(bitmap.as_image())
     }
 
-    fn get_outline(&mut self, path: &str) -> Result<Vec<OutlineEntry>, String> {
-        self.ensure_document(path)?;
-        let doc = self.documents.get(path).unwrap();
-        let bookmarks = doc.bookmarks();
+    fn outline(&self) -> Result<Vec<OutlineEntry>, String> {
+        let bookmarks = self.doc.bookmarks();
 
         let root = match bookmarks.root() {
             Some(r) => r,
             None => return Ok(Vec::new()),
         };
 
-        fn collect(bookmark: &PdfBookmark) -> Vec<OutlineEntry> {
+        fn collect(bookmark: &PdfBookmark, sizes: &[(f32, f32)]) -> Vec<OutlineEntry> {
             bookmark
                 .iter_direct_children()
                 .map(|child| {
                     let title = child.title().unwrap_or_default();
-                    let page = child
+                    // Bookmarks carry either a destination or a GoTo action
+                    let destination = child
                         .destination()
-                        .and_then(|d| d.page_index().ok())
-                        .map(|i| i as u32 + 1);
+                        .and_then(|d| destination_of(&d, sizes))
+                        .or_else(|| {
+                            let action = child.action()?;
+                            let dest = action.as_local_destination_action()?.destination().ok()?;
+                            destination_of(&dest, sizes)
+                        });
                     OutlineEntry {
                         title,
-                        page,
-                        children: collect(&child),
+                        page: destination.as_ref().map(|d| d.page),
+                        view: destination.and_then(|d| d.view),
+                        children: collect(&child, sizes),
                     }
                 })
                 .collect()
         }
 
-        Ok(collect(&root))
+        Ok(collect(&root, &self.point_sizes()))
     }
 
-    fn get_page_links(
-        &mut self,
-        path: &str,
-        page: u32,
-    ) -> Result<Vec<LinkAnnotation>, String> {
-        self.ensure_document(path)?;
-        let doc = self.documents.get(path).unwrap();
-        let page_index = page
-            .checked_sub(1)
-            .ok_or_else(|| "Page number must be >= 1".to_string())?
-            as u16;
-        let page_obj = doc
-            .pages()
-            .get(page_index)
-            .map_err(|e| format!("Failed to get page {}: {:?}", page, e))?;
+    fn page_links(&self, page: u32) -> Result<Vec<LinkAnnotation>, String> {
+        let page_obj = self.page(page)?;
 
         let page_width = page_obj.width().value;
         let page_height = page_obj.height().value;
         let links = page_obj.links();
+        let sizes = self.point_sizes();
         let mut result = Vec::new();
 
         for link in links.iter() {
@@ -259,11 +294,12 @@ impl PdfEngine {
 
             // Try destination first (simpler), then action
             let link_type = if let Some(dest) = link.destination() {
-                dest.page_index()
-                    .ok()
-                    .map(|idx| LinkType::Internal { page: idx as u32 + 1 })
+                destination_of(&dest, &sizes).map(|d| LinkType::Internal {
+                    page: d.page,
+                    view: d.view,
+                })
             } else if let Some(action) = link.action() {
-                extract_link_type_from_action(&action)
+                extract_link_type_from_action(&action, &sizes)
             } else {
                 None
             };
@@ -272,83 +308,72 @@ impl PdfEngine {
                 result.push(LinkAnnotation {
                     rect: normalized,
                     link_type: lt,
+                    synthetic: false,
                 });
             }
         }
 
+        result.extend(self.remote_links(&page_obj, page_width, page_height));
         Ok(result)
     }
 
-    fn extract_page_text(&mut self, path: &str, page: u32) -> Result<String, String> {
-        self.ensure_document(path)?;
-        let doc = self.documents.get(path).unwrap();
-        let page_index = page
-            .checked_sub(1)
-            .ok_or_else(|| "Page number must be >= 1".to_string())?
-            as u16;
-        let page_obj = doc
-            .pages()
-            .get(page_index)
-            .map_err(|e| format!("Failed to get page {}: {:?}", page, e))?;
+    fn named_destination(&self, name: &str) -> Result<Option<Destination>, String> {
+        let bindings = self.doc.bindings();
+        let doc = bindings.get_handle_from_document(&self.doc);
+        let dest = bindings.FPDF_GetNamedDestByName(doc, name);
+        if dest.is_null() {
+            return Ok(None);
+        }
+        Ok(raw_destination(bindings, doc, dest))
+    }
+
+    fn remote_destination(&self, page: u32, index: u32) -> Result<Option<Destination>, String> {
+        let page_obj = self.page(page)?;
+        let Some((_, action, file)) = self.remote_actions(&page_obj).into_iter().nth(index as usize) else {
+            return Ok(None);
+        };
+        Ok(remote_destination(self.doc.bindings(), &self.remote_target(&file), action))
+    }
+
+    fn page_text(&self, page: u32) -> Result<String, String> {
+        let page_obj = self.page(page)?;
         let text = page_obj
             .text()
             .map_err(|e| format!("Failed to extract text: {:?}", e))?;
         Ok(text.all())
     }
 
-    fn search_document(
-        &mut self,
-        path: &str,
-        query: &str,
-    ) -> Result<Vec<SearchResult>, String> {
-        self.ensure_document(path)?;
-        let doc = self.documents.get(path).unwrap();
-        let page_count = doc.pages().len();
-        let mut results = Vec::new();
-        let mut match_index = 0u32;
-        let lower_query = query.to_lowercase();
-
-        for i in 0..page_count {
-            let page_obj = doc
-                .pages()
-                .get(i)
-                .map_err(|e| format!("Failed to get page {}: {:?}", i + 1, e))?;
-            let text = page_obj
-                .text()
-                .map_err(|e| format!("Failed to extract text from page {}: {:?}", i + 1, e))?;
-            let page_text = text.all().to_lowercase();
+    fn glyphs(&self, page: u32) -> Result<Option<PageGlyphs>, String> {
+        let page_obj = self.page(page)?;
+        let text = page_obj
+            .text()
+            .map_err(|e| format!("Failed to extract text: {:?}", e))?;
 
-            let mut start = 0;
-            while let Some(idx) = page_text[start..].find(&lower_query) {
-                let _abs_idx = start + idx;
-                results.push(SearchResult {
-                    page: i as u32 + 1,
-                    match_index,
-                    rects: Vec::new(), // TODO: character-level bounding boxes
-                });
-                match_index += 1;
-                start += idx + 1;
-            }
+        let mut glyphs = Vec::new();
+        for ch in text.chars().iter() {
+            let (Some(c), Ok(bounds), Ok(baseline)) = (ch.unicode_char(), ch.tight_bounds(), ch.origin_y()) else {
+                continue;
+            };
+            glyphs.push(Glyph {
+                ch: c,
+                left: bounds.left().value,
+                right: bounds.right().value,
+                bottom: bounds.bottom().value,
+                top: bounds.top().value,
+                baseline: baseline.value,
+                size: ch.scaled_font_size().value,
+                italic: ch.font_is_italic(),
+            });
         }
-
-        Ok(results)
+        Ok(Some(PageGlyphs {
+            width: page_obj.width().value,
+            height: page_obj.height().value,
+            glyphs,
+        }))
     }
 
-    fn get_page_text_layer(
-        &mut self,
-        path: &str,
-        page: u32,
-    ) -> Result<PageTextLayer, String> {
-        self.ensure_document(path)?;
-        let doc = self.documents.get(path).unwrap();
-        let page_index = page
-            .checked_sub(1)
-            .ok_or_else(|| "Page number must be >= 1".to_string())?
-            as u16;
-        let page_obj = doc
-            .pages()
-            .get(page_index)
-            .map_err(|e| format!("Failed to get page {}: {:?}", page, e))?;
+    fn text_layer(&self, page: u32) -> Result<PageTextLayer, String> {
+        let page_obj = self.page(page)?;
 
         let page_width = page_obj.width().value;
         let page_height = page_obj.height().value;
@@ -470,6 +495,266 @@ impl PdfEngine {
         Ok(PageTextLayer { page, spans })
     }
 
+    fn as_pdf(&self) -> Option<&PdfDocument<'static>> {
+        Some(&self.doc)
+    }
+}
+
+struct PdfEngine {
+    pdfium: &'static Pdfium,
+    documents: HashMap<String, Box<dyn DocumentBackend>>,
+    sidecars: HashMap<String, SidecarLocation>,
+    cache: SharedRenderCache,
+    ocr: OcrQueue,
+}
+
+impl PdfEngine {
+    fn new(pdfium: &'static Pdfium, cache: SharedRenderCache, ocr: OcrQueue) -> Self {
+        Self {
+            pdfium,
+            documents: HashMap::new(),
+            sidecars: HashMap::new(),
+            cache,
+            ocr,
+        }
+    }
+
+    /// OCR sidecar of a document, located once per open document.
+    fn sidecar(&mut self, path: &str) -> Option<SidecarLocation> {
+        if !self.sidecars.contains_key(path) {
+            match SidecarLocation::for_document(Path::new(path)) {
+                Ok(loc) => {
+                    self.sidecars.insert(path.to_string(), loc);
+                }
+                Err(e) => {
+                    log::warn!("ocr: {}", e);
+                    return None;
+                }
+            }
+        }
+        self.sidecars.get(path).cloned()
+    }
+
+    /// Recognised text for a page without text objects. Queues the page for
+    /// OCR when it has not been recognised yet.
+    fn ocr_fallback(&mut self, path: &str, page: u32) -> Option<OcrPage> {
+        let loc = self.sidecar(path)?;
+        let found = loc.page(page);
+        if found.is_none() {
+            self.ocr.enqueue(path, page, &loc);
+        }
+        found
+    }
+
+    /// Load document into cache if not already present, picking the backend
+    /// from the file extension.
+    fn ensure_document(&mut self, path: &str) -> Result<&dyn DocumentBackend, String> {
+        if !self.documents.contains_key(path) {
+            let backend: Box<dyn DocumentBackend> = match DocumentKind::from_path(Path::new(path)) {
+                Some(DocumentKind::Pdf) => Box::new(PdfiumBackend::open(self.pdfium, path)?),
+                Some(DocumentKind::Djvu) => Box::new(DjvuBackend::open(path)?),
+                Some(DocumentKind::Epub) => {
+                    let converted = epub_backend::converted_pdf(Path::new(path))?;
+                    Box::new(PdfiumBackend::open(self.pdfium, &converted.to_string_lossy())?)
+                }
+                None => return Err(format!("Unsupported document type: {}", path)),
+            };
+            self.documents.insert(path.to_string(), backend);
+        }
+        Ok(self.documents[path].as_ref())
+    }
+
+    fn open_document(&mut self, path: &str) -> Result<DocumentInfo, String> {
+        let doc = self.ensure_document(path)?;
+        Ok(DocumentInfo {
+            doc_id: path.to_string(),
+            page_count: doc.page_count(),
+            pages: doc.page_sizes()?,
+            title: doc.title(),
+        })
+    }
+
+    fn render_page(
+        &mut self,
+        path: &str,
+        page: u32,
+        width: i32,
+        dpr: f32,
+    ) -> Result<Vec<u8>, String> {
+        let key = RenderKey {
+            path: path.to_string(),
+            page,
+            width,
+            dpr_hundredths: (dpr * 100.0) as u32,
+        };
+
+        // Check shared cache (another thread may have missed, but render thread
+        // might have since rendered it for a different caller).
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(cached) = cache.get(&key) {
+                return Ok(cached.clone());
+            }
+        }
+
+        let buf = {
+            let doc = self.ensure_document(path)?;
+            let render_width = (width as f32 * dpr) as i32;
+            let image = doc.render_page(page, render_width)?;
+
+            let mut buf = Vec::new();
+            let encoder = JpegEncoder::new_with_quality(&mut buf, 80);
+            image
+                .write_with_encoder(encoder)
+                .map_err(|e| format!("Failed to encode JPEG: {:?}", e))?;
+            buf
+        };
+
+        // Store in shared cache so the protocol handler can serve it directly
+        self.cache.lock().unwrap().put(key, buf.clone());
+        Ok(buf)
+    }
+
+    fn render_for_ocr(&mut self, path: &str, page: u32, width: i32) -> Result<Vec<u8>, String> {
+        let image = self.ensure_document(path)?.render_page(page, width)?;
+        let mut buf = Vec::new();
+        DynamicImage::ImageLuma8(image.to_luma8())
+            .write_to(&mut std::io::Cursor::new(&mut buf), image::ImageFormat::Pnm)
+            .map_err(|e| format!("Failed to encode PGM: {:?}", e))?;
+        Ok(buf)
+    }
+
+    fn close_document(&mut self, path: &str) {
+        self.documents.remove(path);
+        self.sidecars.remove(path);
+    }
+
+    fn get_outline(&mut self, path: &str) -> Result<Vec<OutlineEntry>, String> {
+        self.ensure_document(path)?.outline()
+    }
+
+    fn get_page_links(
+        &mut self,
+        path: &str,
+        page: u32,
+    ) -> Result<Vec<LinkAnnotation>, String> {
+        self.ensure_document(path)?.page_links(page)
+    }
+
+    fn resolve_destination(&mut self, path: &str, name: &str) -> Result<Option<Destination>, String> {
+        self.ensure_document(path)?.named_destination(name)
+    }
+
+    fn resolve_remote_link(&mut self, path: &str, page: u32, index: u32) -> Result<Option<Destination>, String> {
+        self.ensure_document(path)?.remote_destination(page, index)
+    }
+
+    fn extract_page_text(
+        &mut self,
+        path: &str,
+        page: u32,
+        mode: TextMode,
+        region: Option<&NormalizedRect>,
+    ) -> Result<String, String> {
+        if mode == TextMode::Math {
+            if let Some(glyphs) = self.ensure_document(path)?.glyphs(page)? {
+                if !glyphs.glyphs.is_empty() {
+                    return Ok(math_text::reconstruct(&glyphs, region));
+                }
+            }
+        }
+        if mode == TextMode::Reading {
+            let mut layout = self.get_page_layout(path, page)?;
+            if let Some(region) = region {
+                layout.blocks.retain(|b| center_inside(&b.rect, region));
+            }
+            return Ok(layout::reading_text(&layout));
+        }
+        if let Some(region) = region {
+            let layer = self.get_page_text_layer(path, page)?;
+            return Ok(text_in_region(&layer, region));
+        }
+
+        let text = self.ensure_document(path)?.page_text(page)?;
+        if text.trim().is_empty() {
+            if let Some(ocr) = self.ocr_fallback(path, page) {
+                return Ok(ocr.text);
+            }
+        }
+        Ok(text)
+    }
+
+    fn search_document(
+        &mut self,
+        path: &str,
+        query: &str,
+    ) -> Result<Vec<SearchResult>, String> {
+        let mut pages = self.ensure_document(path)?.all_page_text()?;
+
+        // Scanned pages: search the OCR layer, queueing pages not yet recognised
+        if pages.iter().any(|t| t.trim().is_empty()) {
+            if let Some(loc) = self.sidecar(path) {
+                for (i, text) in pages.iter_mut().enumerate() {
+                    if !text.trim().is_empty() {
+                        continue;
+                    }
+                    let page = i as u32 + 1;
+                    match loc.page(page) {
+                        Some(ocr) => *text = ocr.text,
+                        None => self.ocr.enqueue(path, page, &loc),
+                    }
+                }
+            }
+        }
+
+        let mut results = Vec::new();
+        let mut match_index = 0u32;
+        let lower_query = query.to_lowercase();
+
+        for (i, text) in pages.iter().enumerate() {
+            let page_text = text.to_lowercase();
+
+            let mut start = 0;
+            while let Some(idx) = page_text[start..].find(&lower_query) {
+                results.push(SearchResult {
+                    page: i as u32 + 1,
+                    match_index,
+                    rects: Vec::new(), // TODO: character-level bounding boxes
+                });
+                match_index += 1;
+                start += idx + 1;
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn get_page_text_layer(
+        &mut self,
+        path: &str,
+        page: u32,
+    ) -> Result<PageTextLayer, String> {
+        let layer = self.ensure_document(path)?.text_layer(page)?;
+        if layer.spans.is_empty() {
+            if let Some(ocr) = self.ocr_fallback(path, page) {
+                return Ok(PageTextLayer { page, spans: ocr.spans });
+            }
+        }
+        Ok(layer)
+    }
+
+    /// Layout of a page. The two pages on either side are read as well, to
+    /// tell running headers and footers from ordinary text.
+    fn get_page_layout(&mut self, path: &str, page: u32) -> Result<PageLayout, String> {
+        let layer = self.get_page_text_layer(path, page)?;
+        let page_count = self.ensure_document(path)?.page_count();
+        let neighbours: Vec<PageTextLayer> = (page.saturating_sub(2).max(1)..=(page + 2).min(page_count))
+            .filter(|&p| p != page)
+            .filter_map(|p| self.get_page_text_layer(path, p).ok())
+            .collect();
+        Ok(layout::analyze(&layer, &neighbours))
+    }
+
     fn clip_pdf(
         &mut self,
         source_path: &str,
@@ -477,8 +762,11 @@ impl PdfEngine {
         end_page: u32,
         output_path: &str,
     ) -> Result<(), String> {
-        self.ensure_document(source_path)?;
-        let source_doc = self.documents.get(source_path).unwrap();
+        let pdfium = self.pdfium;
+        let source_doc = self
+            .ensure_document(source_path)?
+            .as_pdf()
+            .ok_or_else(|| format!("Clipping is not supported for {}", source_path))?;
         let page_count = source_doc.pages().len() as u32;
 
         if start_page < 1 || start_page > page_count || end_page < start_page || end_page > page_count {
@@ -488,14 +776,14 @@ impl PdfEngine {
             ));
         }
 
-        let mut new_doc = self.pdfium.create_new_pdf()
+        let mut new_doc = pdfium.create_new_pdf()
             .map_err(|e| format!("Failed to create new PDF: {:?}", e))?;
 
         for (dest_idx, page_num) in (start_page..=end_page).enumerate() {
             let page_index = (page_num - 1) as u16;
 
             new_doc.pages_mut()
-                .copy_page_from_document(&source_doc, page_index, dest_idx as u16)
+                .copy_page_from_document(source_doc, page_index, dest_idx as u16)
                 .map_err(|e| format!("Failed to copy page {}: {:?}", page_num, e))?;
         }
 
@@ -504,9 +792,52 @@ impl PdfEngine {
 
         Ok(())
     }
+
+    /// Save a copy of the PDF with `entries` as its outline. PDFium writes a
+    /// full, uncompressed copy; the outline is appended to it.
+    fn write_outline(&mut self, path: &str, entries: &[OutlineEntry], output_path: &str) -> Result<(), String> {
+        let backend = self.ensure_document(path)?;
+        let sizes: Vec<(f32, f32)> = backend
+            .page_sizes()?
+            .iter()
+            .map(|d| (d.width_pts, d.height_pts))
+            .collect();
+        let doc = backend
+            .as_pdf()
+            .ok_or_else(|| format!("Writing an outline is not supported for {}", path))?;
+        let bytes = doc
+            .save_to_bytes()
+            .map_err(|e| format!("Failed to save PDF: {:?}", e))?;
+        let with_outline = outline_writer::append_outline(&bytes, entries, &sizes)?;
+        std::fs::write(output_path, with_outline).map_err(|e| format!("Failed to write {}: {}", output_path, e))
+    }
 }
 
-fn extract_link_type_from_action(action: &PdfAction) -> Option<LinkType> {
+fn center_inside(rect: &NormalizedRect, region: &NormalizedRect) -> bool {
+    let (cx, cy) = (rect.x + rect.width / 2.0, rect.y + rect.height / 2.0);
+    cx >= region.x && cx <= region.x + region.width && cy >= region.y && cy <= region.y + region.height
+}
+
+/// Text of the words whose centre lies inside `region`, one line per row.
+fn text_in_region(layer: &PageTextLayer, region: &NormalizedRect) -> String {
+    let mut out = String::new();
+    let mut prev: Option<&NormalizedRect> = None;
+    for span in &layer.spans {
+        let r = &span.rect;
+        if !center_inside(r, region) {
+            continue;
+        }
+        let cy = r.y + r.height / 2.0;
+        if let Some(p) = prev {
+            out.push(if (cy - (p.y + p.height / 2.0)).abs() > p.height / 2.0 { '\n' } else { ' ' });
+        }
+        out.push_str(&span.text);
+        prev = Some(r);
+    }
+    out
+}
+
+fn extract_link_type_from_action(action: &PdfAction, sizes: &[(f32, f32)]) -> Option<LinkType> {
     match action.action_type() {
         PdfActionType::Uri => action
             .as_uri_action()
@@ -515,12 +846,137 @@ fn extract_link_type_from_action(action: &PdfAction) -> Option<LinkType> {
         PdfActionType::GoToDestinationInSameDocument => action
             .as_local_destination_action()
             .and_then(|a| a.destination().ok())
-            .and_then(|d| d.page_index().ok())
-            .map(|idx| LinkType::Internal { page: idx as u32 + 1 }),
+            .and_then(|d| destination_of(&d, sizes))
+            .map(|d| LinkType::Internal { page: d.page, view: d.view }),
+        // Remote GoTo is read from the raw link annotations in `remote_links`
         _ => None,
     }
 }
 
+/// `PDFACTION_REMOTEGOTO` from fpdf_doc.h.
+const PDFACTION_REMOTEGOTO: std::os::raw::c_ulong = 2;
+
+/// Normalize a destination's top-left point (PDF points, y up) to the
+/// page's top-down 0..1 space. Returns `None` if nothing is specified.
+fn normalized_view(
+    x: Option<f32>,
+    y: Option<f32>,
+    zoom: Option<f32>,
+    size: Option<(f32, f32)>,
+) -> Option<DestinationView> {
+    let (width, height) = size.filter(|(w, h)| *w > 0.0 && *h > 0.0)?;
+    let view = DestinationView {
+        x: x.map(|x| (x / width).clamp(0.0, 1.0)),
+        y: y.map(|y| (1.0 - y / height).clamp(0.0, 1.0)),
+        zoom: zoom.filter(|z| *z > 0.0),
+    };
+    (view != DestinationView::default()).then_some(view)
+}
+
+/// Page and view of a destination. Fit modes that only name one edge keep
+/// that coordinate; whole-page fits have no view.
+fn destination_of(dest: &PdfDestination, sizes: &[(f32, f32)]) -> Option<Destination> {
+    let index = dest.page_index().ok()? as usize;
+    let size = sizes.get(index).copied();
+    let pts = |p: Option<PdfPoints>| p.map(|p| p.value);
+    let view = match dest.view_settings() {
+        Ok(PdfDestinationViewSettings::SpecificCoordinatesAndZoom(x, y, zoom)) => {
+            normalized_view(pts(x), pts(y), zoom, size)
+        }
+        Ok(PdfDestinationViewSettings::FitPageHorizontallyToWindow(y))
+        | Ok(PdfDestinationViewSettings::FitBoundsHorizontallyToWindow(y)) => {
+            normalized_view(None, pts(y), None, size)
+        }
+        Ok(PdfDestinationViewSettings::FitPageVerticallyToWindow(x))
+        | Ok(PdfDestinationViewSettings::FitBoundsVerticallyToWindow(x)) => {
+            normalized_view(pts(x), None, None, size)
+        }
+        Ok(PdfDestinationViewSettings::FitPageToRectangle(rect)) => {
+            normalized_view(Some(rect.left().value), Some(rect.top().value), None, size)
+        }
+        _ => None,
+    };
+    Some(Destination {
+        page: index as u32 + 1,
+        view,
+    })
+}
+
+/// Resolve a raw destination handle, for destinations pdfium-render has no
+/// wrapper for (named and remote). Only the XYZ location is read.
+fn raw_destination(
+    bindings: &dyn PdfiumLibraryBindings,
+    doc: FPDF_DOCUMENT,
+    dest: FPDF_DEST,
+) -> Option<Destination> {
+    let index = bindings.FPDFDest_GetDestPageIndex(doc, dest);
+    if index < 0 {
+        return None;
+    }
+    let mut size = FS_SIZEF { width: 0.0, height: 0.0 };
+    let size = bindings
+        .is_true(bindings.FPDF_GetPageSizeByIndexF(doc, index, &mut size))
+        .then_some((size.width, size.height));
+
+    let (mut has_x, mut has_y, mut has_zoom) = (0, 0, 0);
+    let (mut x, mut y, mut zoom) = (0.0f32, 0.0f32, 0.0f32);
+    let view = if bindings.is_true(bindings.FPDFDest_GetLocationInPage(
+        dest,
+        &mut has_x,
+        &mut has_y,
+        &mut has_zoom,
+        &mut x,
+        &mut y,
+        &mut zoom,
+    )) {
+        normalized_view(
+            bindings.is_true(has_x).then_some(x),
+            bindings.is_true(has_y).then_some(y),
+            bindings.is_true(has_zoom).then_some(zoom),
+            size,
+        )
+    } else {
+        None
+    };
+    Some(Destination {
+        page: index as u32 + 1,
+        view,
+    })
+}
+
+/// File path of a remote GoTo action (UTF-8, NUL-terminated).
+fn action_file_path(bindings: &dyn PdfiumLibraryBindings, action: FPDF_ACTION) -> Option<String> {
+    let len = bindings.FPDFAction_GetFilePath(action, std::ptr::null_mut(), 0);
+    if len == 0 {
+        return None;
+    }
+    let mut buf = vec![0u8; len as usize];
+    bindings.FPDFAction_GetFilePath(action, buf.as_mut_ptr() as *mut std::ffi::c_void, len);
+    let path = String::from_utf8_lossy(&buf).trim_end_matches('\0').to_string();
+    (!path.is_empty()).then_some(path)
+}
+
+/// Open the target of a remote GoTo just long enough to resolve its
+/// destination. `None` if the file is missing or the destination is invalid.
+fn remote_destination(
+    bindings: &dyn PdfiumLibraryBindings,
+    target: &Path,
+    action: FPDF_ACTION,
+) -> Option<Destination> {
+    let doc = bindings.FPDF_LoadDocument(&target.to_string_lossy(), None);
+    if doc.is_null() {
+        return None;
+    }
+    let dest = bindings.FPDFAction_GetDest(doc, action);
+    let result = if dest.is_null() {
+        None
+    } else {
+        raw_destination(bindings, doc, dest)
+    };
+    bindings.FPDF_CloseDocument(doc);
+    result
+}
+
 /// Spawn a pool of render workers sharing a single crossbeam receiver.
 /// Each worker binds its own `Pdfium` instance — `Pdfium` is `!Send + !Sync`,
 /// so sharing a single instance across threads is unsound.
@@ -529,6 +985,7 @@ pub fn run_pool(
     lib_path: std::path::PathBuf,
     generation: Arc<AtomicU64>,
     cache: SharedRenderCache,
+    ocr: OcrQueue,
     worker_count: usize,
 ) -> Vec<std::thread::JoinHandle<()>> {
     (0..worker_count)
@@ -537,6 +994,7 @@ pub fn run_pool(
             let gen = Arc::clone(&generation);
             let cache = Arc::clone(&cache);
             let lib_path = lib_path.clone();
+            let ocr = ocr.clone();
             std::thread::Builder::new()
                 .name(format!("pdf-render-{}", i))
                 .spawn(move || {
@@ -546,7 +1004,7 @@ pub fn run_pool(
                         });
                     let pdfium: &'static Pdfium =
                         Box::leak(Box::new(Pdfium::new(bindings)));
-                    run(rx, pdfium, gen, cache);
+                    run(rx, pdfium, gen, cache, ocr);
                 })
                 .expect("failed to spawn pdf render worker")
         })
@@ -554,8 +1012,14 @@ pub fn run_pool(
 }
 
 /// Main loop for the PDF render thread. Runs until the channel is closed.
-pub fn run(rx: Receiver<PdfRequest>, pdfium: &'static Pdfium, generation: Arc<AtomicU64>, cache: SharedRenderCache) {
-    let mut engine = PdfEngine::new(pdfium, cache);
+pub fn run(
+    rx: Receiver<PdfRequest>,
+    pdfium: &'static Pdfium,
+    generation: Arc<AtomicU64>,
+    cache: SharedRenderCache,
+    ocr: OcrQueue,
+) {
+    let mut engine = PdfEngine::new(pdfium, cache, ocr);
 
     while let Ok(request) = rx.recv() {
         match request {
@@ -578,6 +1042,9 @@ pub fn run(rx: Receiver<PdfRequest>, pdfium: &'static Pdfium, generation: Arc<At
                     let _ = tx.send(engine.render_page(&path, page, width, dpr));
                 }
             }
+            PdfRequest::RenderForOcr { path, page, width, tx } => {
+                let _ = tx.send(engine.render_for_ocr(&path, page, width));
+            }
             PdfRequest::CloseDocument { path, tx } => {
                 engine.close_document(&path);
                 let _ = tx.send(Ok(()));
@@ -588,8 +1055,14 @@ pub fn run(rx: Receiver<PdfRequest>, pdfium: &'static Pdfium, generation: Arc<At
             PdfRequest::GetPageLinks { path, page, tx } => {
                 let _ = tx.send(engine.get_page_links(&path, page));
             }
-            PdfRequest::ExtractPageText { path, page, tx } => {
-                let _ = tx.send(engine.extract_page_text(&path, page));
+            PdfRequest::ResolveDestination { path, name, tx } => {
+                let _ = tx.send(engine.resolve_destination(&path, &name));
+            }
+            PdfRequest::ResolveRemoteLink { path, page, index, tx } => {
+                let _ = tx.send(engine.resolve_remote_link(&path, page, index));
+            }
+            PdfRequest::ExtractPageText { path, page, mode, region, tx } => {
+                let _ = tx.send(engine.extract_page_text(&path, page, mode, region.as_ref()));
             }
             PdfRequest::SearchDocument { path, query, tx } => {
                 let _ = tx.send(engine.search_document(&path, &query));
@@ -603,6 +1076,12 @@ pub fn run(rx: Receiver<PdfRequest>, pdfium: &'static Pdfium, generation: Arc<At
             } => {
                 let _ = tx.send(engine.clip_pdf(&source_path, start_page, end_page, &output_path));
             }
+            PdfRequest::WriteOutline { path, entries, output_path, tx } => {
+                let _ = tx.send(engine.write_outline(&path, &entries, &output_path));
+            }
+            PdfRequest::GetPageLayout { path, page, tx } => {
+                let _ = tx.send(engine.get_page_layout(&path, page));
+            }
             PdfRequest::GetPageTextLayer { path, page, tx } => {
                 let _ = tx.send(engine.get_page_text_layer(&path, page));
             }
@@ -615,6 +1094,61 @@ mod tests {
     use super::*;
     use std::sync::Mutex;
 
+    #[test]
+    fn test_text_in_region() {
+        let span = |text: &str, x: f32, y: f32| TextSpan {
+            text: text.into(),
+            rect: NormalizedRect { x, y, width: 0.1, height: 0.02 },
+            char_rects: vec![],
+        };
+        let layer = PageTextLayer {
+            page: 1,
+            spans: vec![
+                span("Header", 0.1, 0.05),
+                span("Let", 0.1, 0.5),
+                span("x", 0.25, 0.5),
+                span("be", 0.1, 0.53),
+                span("margin", 0.9, 0.5),
+            ],
+        };
+        let region = NormalizedRect { x: 0.05, y: 0.4, width: 0.5, height: 0.2 };
+        assert_eq!(text_in_region(&layer, &region), "Let x\nbe");
+    }
+
+    #[test]
+    fn test_normalized_view() {
+        let letter = Some((612.0, 792.0));
+        let view = normalized_view(Some(306.0), Some(594.0), Some(1.5), letter).unwrap();
+        assert_eq!(view.x, Some(0.5));
+        assert_eq!(view.y, Some(0.25));
+        assert_eq!(view.zoom, Some(1.5));
+
+        // Zoom 0 means "keep current"; off-page coordinates are clamped
+        let view = normalized_view(None, Some(900.0), Some(0.0), letter).unwrap();
+        assert_eq!(view, DestinationView { x: None, y: Some(0.0), zoom: None });
+
+        assert!(normalized_view(None, None, Some(0.0), letter).is_none());
+        assert!(normalized_view(Some(1.0), None, None, None).is_none());
+    }
+
+    #[test]
+    fn test_link_type_serialization() {
+        let internal = LinkType::Internal {
+            page: 4,
+            view: Some(DestinationView { x: None, y: Some(0.3), zoom: None }),
+        };
+        let json = serde_json::to_value(&internal).unwrap();
+        assert_eq!(json["type"], "internal");
+        assert_eq!(json["view"]["y"].as_f64().unwrap() as f32, 0.3);
+
+        // Links saved before views existed still parse
+        let old: LinkType = serde_json::from_str(r#"{"type":"internal","page":2}"#).unwrap();
+        assert!(matches!(old, LinkType::Internal { page: 2, view: None }));
+
+        let remote = LinkType::Remote { file: "/lib/vol2.pdf".into(), index: 2 };
+        assert_eq!(serde_json::to_value(&remote).unwrap()["type"], "remote");
+    }
+
     #[test]
     fn test_render_workers_constant() {
         assert_eq!(RENDER_WORKERS, 4);
@@ -1075,12 +1609,18 @@ mod tests {
                     PdfRequest::RenderPage { tx, .. } => {
                         let _ = tx.send(Ok(vec![0xFF]));
                     }
+                    PdfRequest::RenderForOcr { tx, .. } => {
+                        let _ = tx.send(Ok(vec![0x50]));
+                    }
                     PdfRequest::GetOutline { tx, .. } => {
                         let _ = tx.send(Ok(vec![]));
                     }
                     PdfRequest::GetPageLinks { tx, .. } => {
                         let _ = tx.send(Ok(vec![]));
                     }
+                    PdfRequest::ResolveDestination { tx, .. } | PdfRequest::ResolveRemoteLink { tx, .. } => {
+                        let _ = tx.send(Ok(None));
+                    }
                     PdfRequest::ExtractPageText { tx, .. } => {
                         let _ = tx.send(Ok("text".into()));
                     }
@@ -1090,6 +1630,17 @@ mod tests {
                     PdfRequest::ClipPdf { tx, .. } => {
                         let _ = tx.send(Ok(()));
                     }
+                    PdfRequest::WriteOutline { tx, .. } => {
+                        let _ = tx.send(Ok(()));
+                    }
+                    PdfRequest::GetPageLayout { tx, .. } => {
+                        let _ = tx.send(Ok(PageLayout {
+                            page: 1,
+                            columns: 1,
+                            blocks: vec![],
+                            furniture: vec![],
+                        }));
+                    }
                     PdfRequest::GetPageTextLayer { tx, .. } => {
                         let _ = tx.send(Ok(PageTextLayer {
                             page: 1,
@@ -1132,10 +1683,28 @@ mod tests {
             tx.send(PdfRequest::GetPageLinks { path: "t.pdf".into(), page: 1, tx: reply_tx }).unwrap();
             assert!(reply_rx.recv().unwrap().is_ok());
         }
+        // ResolveDestination
+        {
+            let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
+            tx.send(PdfRequest::ResolveDestination {
+                path: "t.pdf".into(), name: "chap2".into(), tx: reply_tx,
+            }).unwrap();
+            assert!(reply_rx.recv().unwrap().is_ok());
+        }
+        // ResolveRemoteLink
+        {
+            let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
+            tx.send(PdfRequest::ResolveRemoteLink {
+                path: "t.pdf".into(), page: 1, index: 0, tx: reply_tx,
+            }).unwrap();
+            assert!(reply_rx.recv().unwrap().is_ok());
+        }
         // ExtractPageText
         {
             let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
-            tx.send(PdfRequest::ExtractPageText { path: "t.pdf".into(), page: 1, tx: reply_tx }).unwrap();
+            tx.send(PdfRequest::ExtractPageText {
+                path: "t.pdf".into(), page: 1, mode: TextMode::Math, region: None, tx: reply_tx,
+            }).unwrap();
             assert!(reply_rx.recv().unwrap().is_ok());
         }
         // SearchDocument
@@ -1152,6 +1721,20 @@ mod tests {
             }).unwrap();
             assert!(reply_rx.recv().unwrap().is_ok());
         }
+        // WriteOutline
+        {
+            let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
+            tx.send(PdfRequest::WriteOutline {
+                path: "t.pdf".into(), entries: vec![], output_path: "o.pdf".into(), tx: reply_tx,
+            }).unwrap();
+            assert!(reply_rx.recv().unwrap().is_ok());
+        }
+        // GetPageLayout
+        {
+            let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
+            tx.send(PdfRequest::GetPageLayout { path: "t.pdf".into(), page: 1, tx: reply_tx }).unwrap();
+            assert!(reply_rx.recv().unwrap().is_ok());
+        }
         // GetPageTextLayer
         {
             let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
@@ -44,19 +44,62 @@ pub struct NormalizedRect {
     pub height: f32,
 }
 
+/// How `extract_page_text` renders a page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextMode {
+    /// The backend's raw text.
+    #[default]
+    Plain,
+    /// Lines rebuilt from glyph geometry, with formulas as LaTeX `$...$`.
+    Math,
+    /// Layout blocks in reading order, without running headers and footers.
+    Reading,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LinkAnnotation {
     pub rect: NormalizedRect,
     pub link_type: LinkType,
+    /// True for links inferred from textual references ("by Lemma 2.3")
+    /// rather than read from the document's link annotations.
+    #[serde(default)]
+    pub synthetic: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum LinkType {
     #[serde(rename = "internal")]
-    Internal { page: u32 },
+    Internal {
+        page: u32,
+        #[serde(default)]
+        view: Option<DestinationView>,
+    },
     #[serde(rename = "external")]
     External { url: String },
+    /// GoTo into another PDF. `file` is resolved against the linking
+    /// document's directory; `index` numbers the page's remote links, for
+    /// resolving the destination when the link is followed.
+    #[serde(rename = "remote")]
+    Remote { file: String, index: u32 },
+}
+
+/// Where a destination places its page in the window: the point shown at
+/// the top-left, normalized like `NormalizedRect`, and the zoom factor.
+/// `None` values keep the current view, as in the PDF spec.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DestinationView {
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+    pub zoom: Option<f32>,
+}
+
+/// A resolved destination: 1-indexed page and optional view.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Destination {
+    pub page: u32,
+    pub view: Option<DestinationView>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,6 +115,37 @@ pub struct PageTextLayer {
     pub spans: Vec<TextSpan>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Paragraph,
+    Heading,
+    Caption,
+    Footnote,
+    Header,
+    Footer,
+    PageNumber,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutBlock {
+    pub kind: BlockKind,
+    pub text: String,
+    pub rect: NormalizedRect,
+    /// 0-based column, or `None` for blocks spanning all columns.
+    pub column: Option<u32>,
+}
+
+/// Result of layout analysis: body blocks in reading order, plus the running
+/// headers, footers and page numbers that were taken out of the flow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageLayout {
+    pub page: u32,
+    pub columns: u32,
+    pub blocks: Vec<LayoutBlock>,
+    pub furniture: Vec<LayoutBlock>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub page: u32,
@@ -83,5 +157,36 @@ pub struct SearchResult {
 pub struct OutlineEntry {
     pub title: String,
     pub page: Option<u32>,
+    #[serde(default)]
+    pub view: Option<DestinationView>,
+    #[serde(default)]
     pub children: Vec<OutlineEntry>,
 }
+
+/// How a user-defined outline combines with the document's own bookmarks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutlineMode {
+    /// Custom entries are slotted into the PDF outline by page.
+    #[default]
+    Merge,
+    /// Only the custom entries are shown.
+    Replace,
+}
+
+/// A book's user-defined outline, stored per slug in `.axiomatic/outlines.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CustomOutline {
+    #[serde(default)]
+    pub mode: OutlineMode,
+    pub entries: Vec<OutlineEntry>,
+}
+
+/// Recognised text of one scanned page. Stored one file per page under
+/// `.axiomatic/ocr/`, in a folder named after the document identity so it
+/// follows renames.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OcrPage {
+    pub text: String,
+    pub spans: Vec<TextSpan>,
+}
@@ -0,0 +1,462 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::models::{
+    PomodoroConfig, PomodoroPhase, PomodoroState, PomodoroTransition, SessionEntry, StudySession,
+    StudySessionBook, XpSource,
+};
+use crate::session_commands::log_study_session;
+use crate::xp_ledger::record_xp_inner;
+
+/// Timer state in the app data directory, so a running session survives a
+/// reload or restart.
+const STATE_FILE: &str = "pomodoro.json";
+const TICK: Duration = Duration::from_secs(1);
+
+fn format_time(t: DateTime<Utc>) -> String {
+    t.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s).ok().map(|t| t.with_timezone(&Utc))
+}
+
+impl PomodoroConfig {
+    fn phase_seconds(&self, phase: PomodoroPhase) -> i64 {
+        let minutes = match phase {
+            PomodoroPhase::Work => self.work_minutes,
+            PomodoroPhase::Break => self.break_minutes,
+            PomodoroPhase::LongBreak => self.break_minutes * self.long_break_multiplier,
+        };
+        minutes.max(1) * 60
+    }
+
+    /// The break that follows the `completed`-th pomodoro.
+    fn break_after(&self, completed: i64) -> PomodoroPhase {
+        if self.long_break_interval > 0 && completed % self.long_break_interval == 0 {
+            PomodoroPhase::LongBreak
+        } else {
+            PomodoroPhase::Break
+        }
+    }
+}
+
+impl PomodoroState {
+    fn deadline(&self) -> Option<DateTime<Utc>> {
+        self.ends_at.as_deref().and_then(parse_time)
+    }
+
+    /// Bring `seconds_left` up to date with the clock.
+    fn refresh(&mut self, now: DateTime<Utc>) {
+        if let (true, Some(end)) = (self.running, self.deadline()) {
+            let millis = (end - now).num_milliseconds().max(0);
+            self.seconds_left = (millis + 999) / 1000;
+        }
+    }
+
+    fn start(&mut self, now: DateTime<Utc>) {
+        if self.running {
+            return;
+        }
+        if self.phase == PomodoroPhase::Work && self.session_start.is_none() {
+            self.session_start = Some(format_time(now));
+        }
+        self.running = true;
+        self.ends_at = Some(format_time(now + chrono::Duration::seconds(self.seconds_left)));
+    }
+
+    fn pause(&mut self, now: DateTime<Utc>) {
+        self.refresh(now);
+        self.running = false;
+        self.ends_at = None;
+    }
+
+    fn toggle(&mut self, now: DateTime<Utc>) {
+        if self.running {
+            self.pause(now);
+        } else {
+            self.start(now);
+        }
+    }
+
+    /// Back to a fresh work phase, keeping the config and book.
+    fn reset(&mut self) {
+        let config = self.config.clone();
+        *self = PomodoroState {
+            seconds_left: config.phase_seconds(PomodoroPhase::Work),
+            config,
+            book: self.book.take(),
+            ..Default::default()
+        };
+    }
+
+    fn set_config(&mut self, config: PomodoroConfig, reset: bool) {
+        self.config = config;
+        if reset {
+            self.reset();
+        } else if !self.running && self.phase == PomodoroPhase::Work && self.session_start.is_none() {
+            self.seconds_left = self.config.phase_seconds(PomodoroPhase::Work);
+        }
+    }
+
+    fn enter(&mut self, phase: PomodoroPhase) {
+        self.phase = phase;
+        self.seconds_left = self.config.phase_seconds(phase);
+        self.running = false;
+        self.ends_at = None;
+        self.session_start = None;
+    }
+
+    /// Move to the next phase and return the one left. A work phase counts
+    /// towards the long break whether it ran out or was skipped.
+    fn advance(&mut self) -> PomodoroPhase {
+        let from = self.phase;
+        match from {
+            PomodoroPhase::Work => {
+                self.completed_pomodoros += 1;
+                let next = self.config.break_after(self.completed_pomodoros);
+                self.enter(next);
+            }
+            PomodoroPhase::Break => self.enter(PomodoroPhase::Work),
+            PomodoroPhase::LongBreak => {
+                self.completed_pomodoros = 0;
+                self.enter(PomodoroPhase::Work);
+            }
+        }
+        from
+    }
+
+    fn transition(&self, from: PomodoroPhase, completed: bool) -> PomodoroTransition {
+        PomodoroTransition {
+            from,
+            to: self.phase,
+            completed,
+            chime: completed && self.config.audio_enabled,
+            state: self.clone(),
+        }
+    }
+
+    fn skip(&mut self) -> PomodoroTransition {
+        let from = self.advance();
+        self.transition(from, false)
+    }
+
+    /// Finish the running phase if its time is up. A finished work phase
+    /// also yields the study session to log, ending at the deadline rather
+    /// than now, which matters when the app was closed at the time.
+    fn tick(&mut self, now: DateTime<Utc>) -> Option<(PomodoroTransition, Option<StudySession>)> {
+        if !self.running {
+            return None;
+        }
+        self.refresh(now);
+        if self.seconds_left > 0 {
+            return None;
+        }
+        let ended = self.deadline().unwrap_or(now);
+        let session = (self.phase == PomodoroPhase::Work).then(|| {
+            let minutes = self.config.work_minutes;
+            StudySession {
+                id: uuid::Uuid::new_v4().to_string(),
+                started_at: self
+                    .session_start
+                    .clone()
+                    .unwrap_or_else(|| format_time(ended - chrono::Duration::minutes(minutes))),
+                ended_at: format_time(ended),
+                duration_minutes: minutes,
+                books: self.book.iter().cloned().collect(),
+            }
+        });
+        let from = self.advance();
+        if from == PomodoroPhase::Work {
+            self.show_overlay = true;
+        }
+        Some((self.transition(from, true), session))
+    }
+}
+
+fn load_state(path: &Path) -> PomodoroState {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Log a finished work session and its XP against the session's book.
+fn log_finished(session: StudySession) {
+    for book in session.books.clone() {
+        let entry = SessionEntry {
+            dir_path: book.dir_path.clone(),
+            session: session.clone(),
+        };
+        if let Err(e) = log_study_session(vec![entry]) {
+            log::warn!("pomodoro: failed to log session: {}", e);
+        }
+        if let Err(e) = record_xp_inner(&book.dir_path, &book.slug, XpSource::Pomodoro, None) {
+            log::warn!("pomodoro: failed to record XP: {}", e);
+        }
+    }
+}
+
+/// The pomodoro timer. A single background thread drives it and emits
+/// `pomodoro-tick` every second while it runs and `pomodoro-transition`
+/// when a phase ends, so each transition chimes once however many views
+/// are listening.
+pub struct PomodoroService {
+    state: Mutex<PomodoroState>,
+    path: PathBuf,
+}
+
+impl PomodoroService {
+    /// Restore the timer saved in `app_data` and start ticking. A phase
+    /// that ran out while the app was closed finishes on the first tick.
+    pub fn start(app: &AppHandle, app_data: &Path) -> Self {
+        let path = app_data.join(STATE_FILE);
+        let state = load_state(&path);
+
+        let app = app.clone();
+        std::thread::Builder::new()
+            .name("pomodoro".into())
+            .spawn(move || run(app))
+            .expect("failed to spawn pomodoro thread");
+
+        Self {
+            state: Mutex::new(state),
+            path,
+        }
+    }
+
+    fn save(&self, state: &PomodoroState) {
+        let result = serde_json::to_string_pretty(state)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(&self.path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::warn!("pomodoro: failed to save {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn snapshot(&self) -> Result<PomodoroState, String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?.clone();
+        state.refresh(Utc::now());
+        Ok(state)
+    }
+
+    /// Apply a user action, then persist and broadcast the result.
+    fn update(
+        &self,
+        app: &AppHandle,
+        f: impl FnOnce(&mut PomodoroState, DateTime<Utc>) -> Option<PomodoroTransition>,
+    ) -> Result<PomodoroState, String> {
+        let now = Utc::now();
+        let (state, transition) = {
+            let mut guard = self.state.lock().map_err(|e| e.to_string())?;
+            let transition = f(&mut guard, now);
+            guard.refresh(now);
+            self.save(&guard);
+            (guard.clone(), transition)
+        };
+        if let Some(transition) = transition {
+            let _ = app.emit("pomodoro-transition", transition);
+        }
+        let _ = app.emit("pomodoro-tick", &state);
+        Ok(state)
+    }
+
+    fn tick(&self, app: &AppHandle) {
+        let (state, finished) = {
+            let Ok(mut guard) = self.state.lock() else {
+                return;
+            };
+            if !guard.running {
+                return;
+            }
+            let finished = guard.tick(Utc::now());
+            if finished.is_some() {
+                self.save(&guard);
+            }
+            (guard.clone(), finished)
+        };
+        if let Some((transition, session)) = finished {
+            if let Some(session) = session {
+                log_finished(session);
+            }
+            let _ = app.emit("pomodoro-transition", transition);
+        }
+        let _ = app.emit("pomodoro-tick", &state);
+    }
+}
+
+fn run(app: AppHandle) {
+    loop {
+        std::thread::sleep(TICK);
+        if let Some(service) = app.try_state::<PomodoroService>() {
+            service.tick(&app);
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_pomodoro_state(state: State<'_, PomodoroService>) -> Result<PomodoroState, String> {
+    state.snapshot()
+}
+
+/// Start or pause the current phase.
+#[tauri::command]
+pub fn toggle_pomodoro(app: AppHandle, state: State<'_, PomodoroService>) -> Result<PomodoroState, String> {
+    state.update(&app, |s, now| {
+        s.toggle(now);
+        None
+    })
+}
+
+#[tauri::command]
+pub fn reset_pomodoro(app: AppHandle, state: State<'_, PomodoroService>) -> Result<PomodoroState, String> {
+    state.update(&app, |s, _| {
+        s.reset();
+        None
+    })
+}
+
+/// End the current phase early. Skipped work is not logged.
+#[tauri::command]
+pub fn skip_pomodoro_phase(app: AppHandle, state: State<'_, PomodoroService>) -> Result<PomodoroState, String> {
+    state.update(&app, |s, _| Some(s.skip()))
+}
+
+/// Hide the end-of-work overlay and start the break.
+#[tauri::command]
+pub fn dismiss_pomodoro_overlay(
+    app: AppHandle,
+    state: State<'_, PomodoroService>,
+) -> Result<PomodoroState, String> {
+    state.update(&app, |s, now| {
+        s.show_overlay = false;
+        s.start(now);
+        None
+    })
+}
+
+/// Change durations and audio. `reset` restarts from a fresh work phase, as
+/// when picking a preset; otherwise only an unstarted work phase is resized.
+#[tauri::command]
+pub fn set_pomodoro_config(
+    app: AppHandle,
+    state: State<'_, PomodoroService>,
+    config: PomodoroConfig,
+    reset: Option<bool>,
+) -> Result<PomodoroState, String> {
+    state.update(&app, |s, _| {
+        s.set_config(config, reset.unwrap_or(false));
+        None
+    })
+}
+
+/// The book finished work sessions are logged against.
+#[tauri::command]
+pub fn set_pomodoro_book(
+    app: AppHandle,
+    state: State<'_, PomodoroService>,
+    book: Option<StudySessionBook>,
+) -> Result<PomodoroState, String> {
+    state.update(&app, |s, _| {
+        s.book = book;
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        parse_time(s).unwrap()
+    }
+
+    fn timer(work: i64, brk: i64, interval: i64) -> PomodoroState {
+        let mut state = PomodoroState::default();
+        state.set_config(
+            PomodoroConfig {
+                preset: "custom".into(),
+                work_minutes: work,
+                break_minutes: brk,
+                long_break_interval: interval,
+                ..Default::default()
+            },
+            true,
+        );
+        state.book = Some(StudySessionBook {
+            slug: "algebra".into(),
+            dir_path: "/books".into(),
+        });
+        state
+    }
+
+    #[test]
+    fn work_phase_ends_with_a_session_and_a_break() {
+        let mut state = timer(25, 5, 4);
+        state.start(at("2025-01-01T10:00:00Z"));
+        assert!(state.tick(at("2025-01-01T10:10:00Z")).is_none());
+        assert_eq!(state.seconds_left, 15 * 60);
+
+        // Pausing keeps the remaining time and moves the deadline
+        state.pause(at("2025-01-01T10:10:00Z"));
+        assert!(state.tick(at("2025-01-01T11:00:00Z")).is_none());
+        state.start(at("2025-01-01T11:00:00Z"));
+        assert_eq!(state.ends_at.as_deref(), Some("2025-01-01T11:15:00Z"));
+
+        let (transition, session) = state.tick(at("2025-01-01T11:15:00Z")).unwrap();
+        assert_eq!((transition.from, transition.to), (PomodoroPhase::Work, PomodoroPhase::Break));
+        assert!(transition.completed && transition.chime);
+        let session = session.unwrap();
+        assert_eq!(session.started_at, "2025-01-01T10:00:00Z");
+        assert_eq!(session.ended_at, "2025-01-01T11:15:00Z");
+        assert_eq!((session.duration_minutes, session.books[0].slug.as_str()), (25, "algebra"));
+
+        assert!(!state.running && state.show_overlay);
+        assert_eq!((state.completed_pomodoros, state.seconds_left), (1, 5 * 60));
+        // Only one transition per phase
+        assert!(state.tick(at("2025-01-01T11:16:00Z")).is_none());
+    }
+
+    #[test]
+    fn long_break_every_interval() {
+        let mut state = timer(25, 5, 2);
+        state.skip();
+        assert_eq!(state.phase, PomodoroPhase::Break);
+        state.skip();
+        let skipped = state.skip();
+        assert!(!skipped.completed && !skipped.chime);
+        assert_eq!(state.phase, PomodoroPhase::LongBreak);
+        assert_eq!(state.seconds_left, 15 * 60);
+
+        state.start(at("2025-01-01T10:00:00Z"));
+        let (transition, session) = state.tick(at("2025-01-01T10:15:00Z")).unwrap();
+        assert_eq!(transition.to, PomodoroPhase::Work);
+        assert!(session.is_none());
+        assert_eq!((state.completed_pomodoros, state.seconds_left), (0, 25 * 60));
+    }
+
+    #[test]
+    fn running_session_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STATE_FILE);
+        let mut state = timer(25, 5, 4);
+        state.start(at("2025-01-01T10:00:00Z"));
+        std::fs::write(&path, serde_json::to_string(&state).unwrap()).unwrap();
+
+        // Reopened mid-session: the countdown continues from the deadline
+        let mut restored = load_state(&path);
+        assert!(restored.tick(at("2025-01-01T10:20:00Z")).is_none());
+        assert_eq!(restored.seconds_left, 5 * 60);
+
+        // Reopened after the deadline: the session ends when it was due
+        let mut restored = load_state(&path);
+        let (_, session) = restored.tick(at("2025-01-01T18:00:00Z")).unwrap();
+        assert_eq!(session.unwrap().ended_at, "2025-01-01T10:25:00Z");
+
+        assert_eq!(load_state(&dir.path().join("missing.json")).seconds_left, 45 * 60);
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rusqlite::Connection;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::commands::{book_slug, list_directories_inner, title_from_stem, DbState};
+use crate::doc_backend::is_supported_document;
+use crate::doc_identity::{identify_files, known_files, reconcile_identities_inner, record_identities_inner};
+use crate::models::{Directory, LibraryChange, RenamedDocument, SlugMigration, Textbook};
+
+/// Set while a background refresh is running so repeated `list_textbooks`
+/// calls don't pile up walks of the same trees.
+static REFRESHING: AtomicBool = AtomicBool::new(false);
+
+/// Files added to and removed from an index, removals as `(path, slug)`.
+type IndexDiff = (Vec<Textbook>, Vec<(String, String)>);
+
+/// A directory with its cached textbooks, `None` if never indexed.
+type CachedDir = (Directory, Option<Vec<Textbook>>);
+
+/// A document file as recorded in the scan index.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct IndexedFile {
+    pub parent: String,
+    pub slug: String,
+    pub title: String,
+    pub size: i64,
+    pub mtime: i64,
+    pub page_count: Option<i64>,
+    pub doc_id: Option<String>,
+}
+
+/// Scan index of one library directory: every subdirectory with the mtime it
+/// had when listed, and every document file keyed by full path.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DirIndex {
+    pub dirs: HashMap<String, i64>,
+    pub files: HashMap<String, IndexedFile>,
+}
+
+impl DirIndex {
+    /// True for a directory that was never indexed; a scanned tree always
+    /// records at least its root.
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+}
+
+fn mtime_nanos(meta: &std::fs::Metadata) -> i64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or(0)
+}
+
+pub(crate) fn load_index(conn: &Connection, dir_id: i64) -> Result<DirIndex, String> {
+    let mut index = DirIndex::default();
+
+    let mut stmt = conn
+        .prepare("SELECT path, mtime FROM scan_dirs WHERE dir_id = ?1")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([dir_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (path, mtime) = row.map_err(|e| e.to_string())?;
+        index.dirs.insert(path, mtime);
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT full_path, parent, slug, title, file_size, mtime, page_count, doc_id
+             FROM scan_index WHERE dir_id = ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([dir_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                IndexedFile {
+                    parent: row.get(1)?,
+                    slug: row.get(2)?,
+                    title: row.get(3)?,
+                    size: row.get(4)?,
+                    mtime: row.get(5)?,
+                    page_count: row.get(6)?,
+                    doc_id: row.get(7)?,
+                },
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (path, file) = row.map_err(|e| e.to_string())?;
+        index.files.insert(path, file);
+    }
+    Ok(index)
+}
+
+/// Index the document at `path` in directory `parent`, keeping its page
+/// count and identity from `prev` while the file itself is unchanged.
+fn index_file(dir: &Directory, parent: &str, path: &Path, prev: &DirIndex) -> Option<IndexedFile> {
+    let meta = std::fs::metadata(path).ok().filter(|m| m.is_file())?;
+    let full_path = path.to_string_lossy();
+    let size = meta.len() as i64;
+    let mtime = mtime_nanos(&meta);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let (page_count, doc_id) = match prev.files.get(full_path.as_ref()) {
+        Some(old) if old.size == size && old.mtime == mtime => (old.page_count, old.doc_id.clone()),
+        _ => (None, None),
+    };
+    Some(IndexedFile {
+        parent: parent.to_string(),
+        slug: book_slug(dir.id, &stem),
+        title: title_from_stem(&stem),
+        size,
+        mtime,
+        page_count,
+        doc_id,
+    })
+}
+
+/// Walk `dir` against its previous index. Subdirectories whose mtime is
+/// unchanged reuse their recorded listing instead of being read again, so an
+/// unchanged tree costs one `stat` per directory. Files in `touched` (paths
+/// the watcher saw change) are checked on their own, since rewriting a file
+/// in place leaves its directory's mtime alone. Touches only the filesystem.
+pub(crate) fn rescan(dir: &Directory, prev: &DirIndex, touched: &[PathBuf]) -> DirIndex {
+    let mut next = DirIndex::default();
+    if !Path::new(&dir.path).is_dir() {
+        return next;
+    }
+
+    // Recorded children of each directory, for reuse of unchanged listings
+    let mut child_dirs: HashMap<&str, Vec<&str>> = HashMap::new();
+    for path in prev.dirs.keys() {
+        if let Some(parent) = Path::new(path).parent().and_then(|p| p.to_str()) {
+            child_dirs.entry(parent).or_default().push(path);
+        }
+    }
+    let mut child_files: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (path, file) in &prev.files {
+        child_files.entry(file.parent.as_str()).or_default().push(path);
+    }
+
+    let touched: HashSet<String> = touched.iter().map(|p| p.to_string_lossy().to_string()).collect();
+
+    let mut stack = vec![dir.path.clone()];
+    while let Some(current) = stack.pop() {
+        let Ok(meta) = std::fs::metadata(&current) else {
+            continue;
+        };
+        let mtime = mtime_nanos(&meta);
+        next.dirs.insert(current.clone(), mtime);
+
+        if prev.dirs.get(&current) == Some(&mtime) {
+            for path in child_files.get(current.as_str()).into_iter().flatten() {
+                let file = match touched.contains(*path) {
+                    true => index_file(dir, &current, Path::new(path), prev),
+                    false => Some(prev.files[*path].clone()),
+                };
+                next.files.extend(file.map(|f| (path.to_string(), f)));
+            }
+            stack.extend(child_dirs.get(current.as_str()).into_iter().flatten().map(|p| p.to_string()));
+            continue;
+        }
+
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name() == ".axiomatic" {
+                continue;
+            }
+            // Like WalkDir: do not descend into symlinked directories
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                stack.push(path.to_string_lossy().to_string());
+                continue;
+            }
+            if !is_supported_document(&path) {
+                continue;
+            }
+            if let Some(file) = index_file(dir, &current, &path, prev) {
+                next.files.insert(path.to_string_lossy().to_string(), file);
+            }
+        }
+    }
+    next
+}
+
+fn to_textbook(dir: &Directory, full_path: &str, file: &IndexedFile) -> Textbook {
+    Textbook {
+        slug: file.slug.clone(),
+        title: file.title.clone(),
+        file: Path::new(full_path)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        dir_id: dir.id,
+        dir_path: dir.path.clone(),
+        full_path: full_path.to_string(),
+        doc_id: file.doc_id.clone(),
+        page_count: file.page_count,
+    }
+}
+
+/// Textbooks of `dir` in path order.
+pub(crate) fn textbooks_from_index(dir: &Directory, index: &DirIndex) -> Vec<Textbook> {
+    let mut books: Vec<Textbook> = index
+        .files
+        .iter()
+        .map(|(path, file)| to_textbook(dir, path, file))
+        .collect();
+    books.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+    books
+}
+
+/// Replace the stored index of `dir` with `next`, returning the files added
+/// and removed relative to what was stored. The diff is taken against the
+/// database at commit time, so concurrent refreshes never report twice.
+pub(crate) fn commit_index(
+    conn: &Connection,
+    dir: &Directory,
+    next: &DirIndex,
+) -> Result<IndexDiff, String> {
+    let stored = load_index(conn, dir.id)?;
+    let added: Vec<Textbook> = textbooks_from_index(dir, next)
+        .into_iter()
+        .filter(|tb| !stored.files.contains_key(&tb.full_path))
+        .collect();
+    let removed: Vec<(String, String)> = stored
+        .files
+        .iter()
+        .filter(|(path, _)| !next.files.contains_key(*path))
+        .map(|(path, file)| (path.clone(), file.slug.clone()))
+        .collect();
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM scan_dirs WHERE dir_id = ?1", [dir.id])
+        .map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM scan_index WHERE dir_id = ?1", [dir.id])
+        .map_err(|e| e.to_string())?;
+    {
+        let mut insert_dir = tx
+            .prepare("INSERT INTO scan_dirs (dir_id, path, mtime) VALUES (?1, ?2, ?3)")
+            .map_err(|e| e.to_string())?;
+        for (path, mtime) in &next.dirs {
+            insert_dir
+                .execute(rusqlite::params![dir.id, path, mtime])
+                .map_err(|e| e.to_string())?;
+        }
+        let mut insert_file = tx
+            .prepare(
+                "INSERT INTO scan_index
+                 (dir_id, full_path, parent, slug, title, file_size, mtime, page_count, doc_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )
+            .map_err(|e| e.to_string())?;
+        for (path, f) in &next.files {
+            insert_file
+                .execute(rusqlite::params![
+                    dir.id, path, f.parent, f.slug, f.title, f.size, f.mtime, f.page_count, f.doc_id
+                ])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok((added, removed))
+}
+
+/// Store the identities `record_identities_inner` filled in.
+pub(crate) fn store_doc_ids(conn: &Connection, textbooks: &[Textbook]) -> Result<(), String> {
+    for tb in textbooks {
+        if let Some(doc_id) = &tb.doc_id {
+            conn.execute(
+                "UPDATE scan_index SET doc_id = ?1 WHERE dir_id = ?2 AND full_path = ?3",
+                rusqlite::params![doc_id, tb.dir_id, tb.full_path],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Turn raw added/removed files into a `LibraryChange`, pairing removals
+/// with the additions their documents moved to. `added` has been through
+/// the document identity table, which found `migrations`.
+fn build_change(
+    conn: &Connection,
+    mut added: Vec<Textbook>,
+    mut removed: Vec<(String, String)>,
+    migrations: Vec<SlugMigration>,
+) -> Result<LibraryChange, String> {
+    store_doc_ids(conn, &added)?;
+
+    let mut renamed = Vec::new();
+    for m in migrations {
+        let to = added.iter().position(|tb| tb.slug == m.new_slug);
+        let from = removed.iter().position(|(_, slug)| *slug == m.old_slug);
+        if let (Some(to), Some(from)) = (to, from) {
+            let tb = added.remove(to);
+            let (from_path, _) = removed.remove(from);
+            renamed.push(RenamedDocument {
+                from_path,
+                to_path: tb.full_path,
+                old_slug: m.old_slug,
+                new_slug: m.new_slug,
+            });
+        }
+    }
+
+    Ok(LibraryChange {
+        added,
+        removed: removed.into_iter().map(|(path, _)| path).collect(),
+        renamed,
+    })
+}
+
+/// Cached textbooks of every directory, or `None` for a directory that has
+/// never been indexed.
+pub(crate) fn cached_textbooks_inner(
+    conn: &Connection,
+    dirs: &[Directory],
+) -> Result<Vec<CachedDir>, String> {
+    dirs.iter()
+        .map(|dir| {
+            let index = load_index(conn, dir.id)?;
+            let books = (!index.is_empty()).then(|| textbooks_from_index(dir, &index));
+            Ok((dir.clone(), books))
+        })
+        .collect()
+}
+
+/// Record the page count reported when a document was opened.
+pub fn record_page_count_inner(conn: &Connection, full_path: &str, page_count: i64) -> Result<(), String> {
+    conn.execute(
+        "UPDATE scan_index SET page_count = ?1 WHERE full_path = ?2",
+        rusqlite::params![page_count, full_path],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Refresh all directories off the caller's thread. A non-empty result is
+/// emitted as `library-changed`.
+pub(crate) fn spawn_refresh(app: AppHandle) {
+    if REFRESHING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let dirs = {
+            let state = app.state::<DbState>();
+            let conn = state.0.lock().map_err(|e| e.to_string());
+            conn.and_then(|c| list_directories_inner(&c))
+        };
+        let result = dirs.and_then(|dirs| refresh_dirs(&app, &dirs, &[]));
+        REFRESHING.store(false, Ordering::Release);
+        match result {
+            Ok(change) if !change.is_empty() => {
+                let _ = app.emit("library-changed", change);
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("library refresh failed: {}", e),
+        }
+    });
+}
+
+/// Incrementally rescan `dirs` and update their indexes; `touched` is as for
+/// `rescan`. The database lock is only held to load and commit each index,
+/// never during the filesystem walk or while hashing new files, which can be
+/// slow on network mounts.
+pub(crate) fn refresh_dirs(app: &AppHandle, dirs: &[Directory], touched: &[PathBuf]) -> Result<LibraryChange, String> {
+    let state = app.state::<DbState>();
+    let lock = || state.0.lock().map_err(|e| e.to_string());
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for dir in dirs {
+        let prev = load_index(&*lock()?, dir.id)?;
+        let next = rescan(dir, &prev, touched);
+        let (a, r) = commit_index(&*lock()?, dir, &next)?;
+        added.extend(a);
+        removed.extend(r);
+    }
+    let known = known_files(&*lock()?)?;
+    let identities = identify_files(&added, &known);
+    let conn = lock()?;
+    let migrations = record_identities_inner(&conn, &mut added, identities)?;
+    build_change(&conn, added, removed, migrations)
+}
+
+/// Single-connection equivalent of `refresh_dirs`, for callers that own
+/// the connection (the CLI).
+pub(crate) fn refresh_inner(conn: &Connection, dirs: &[Directory]) -> Result<LibraryChange, String> {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for dir in dirs {
+        let next = rescan(dir, &load_index(conn, dir.id)?, &[]);
+        let (a, r) = commit_index(conn, dir, &next)?;
+        added.extend(a);
+        removed.extend(r);
+    }
+    let migrations = reconcile_identities_inner(conn, &mut added)?;
+    build_change(conn, added, removed, migrations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{add_directory_inner, get_note_inner, scan_textbooks, set_note_inner};
+    use crate::db;
+    use crate::doc_backend::DocumentKind;
+    use std::collections::HashSet;
+
+    fn test_db() -> (tempfile::TempDir, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let conn = db::init_db(&db_path).unwrap();
+        (dir, conn)
+    }
+
+    fn indexed_paths(conn: &Connection, dir_id: i64) -> HashSet<String> {
+        load_index(conn, dir_id).unwrap().files.into_keys().collect()
+    }
+
+    fn pdf_with_id(id: &str) -> Vec<u8> {
+        format!("%PDF-1.7\ntrailer\n<< /ID [<{id}> <{id}>] >>\n%%EOF\n").into_bytes()
+    }
+
+    #[test]
+    fn index_matches_full_scan() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let dir = add_directory_inner(&conn, &lib.path().to_string_lossy(), "lib").unwrap();
+        std::fs::create_dir_all(lib.path().join("math/analysis")).unwrap();
+        std::fs::create_dir_all(lib.path().join(".axiomatic")).unwrap();
+        std::fs::write(lib.path().join("a.pdf"), pdf_with_id("aa")).unwrap();
+        std::fs::write(lib.path().join("math/analysis/b.PDF"), pdf_with_id("bb")).unwrap();
+        std::fs::write(lib.path().join(".axiomatic/c.pdf"), b"").unwrap();
+        std::fs::write(lib.path().join("notes.txt"), b"").unwrap();
+        std::fs::write(lib.path().join("math/scan.djvu"), b"AT&TFORM").unwrap();
+        std::fs::write(lib.path().join("novel.epub"), b"PK").unwrap();
+
+        refresh_inner(&conn, std::slice::from_ref(&dir)).unwrap();
+        let cached = cached_textbooks_inner(&conn, std::slice::from_ref(&dir)).unwrap();
+        let cached: Vec<String> = cached[0].1.as_ref().unwrap().iter().map(|t| t.slug.clone()).collect();
+        let mut scanned: Vec<String> = scan_textbooks(std::slice::from_ref(&dir))
+            .into_iter()
+            .map(|t| t.slug)
+            .collect();
+        scanned.sort();
+        let mut sorted = cached.clone();
+        sorted.sort();
+        assert_eq!(sorted, scanned);
+        // DjVu and EPUB are listed only where their tools are installed
+        let optional = [DocumentKind::Djvu, DocumentKind::Epub].iter().filter(|k| k.is_available()).count();
+        assert_eq!(cached.len(), 2 + optional);
+    }
+
+    #[test]
+    fn unindexed_directory_has_no_cache() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let dir = add_directory_inner(&conn, &lib.path().to_string_lossy(), "lib").unwrap();
+        let cached = cached_textbooks_inner(&conn, &[dir]).unwrap();
+        assert!(cached[0].1.is_none());
+    }
+
+    #[test]
+    fn unchanged_subtree_reuses_listing() {
+        let lib = tempfile::tempdir().unwrap();
+        let dir = Directory {
+            id: 1,
+            path: lib.path().to_string_lossy().to_string(),
+            label: "lib".into(),
+            added_at: String::new(),
+        };
+        std::fs::create_dir_all(lib.path().join("sub")).unwrap();
+        std::fs::write(lib.path().join("sub/a.pdf"), b"").unwrap();
+        let first = rescan(&dir, &DirIndex::default(), &[]);
+        assert_eq!(first.files.len(), 1);
+
+        // Forge a recorded file the directory does not contain: it is only
+        // kept if the unchanged directory's listing is reused, not re-read.
+        let mut prev = first.clone();
+        let sub = lib.path().join("sub").to_string_lossy().to_string();
+        let ghost = lib.path().join("sub/ghost.pdf").to_string_lossy().to_string();
+        let mut file = prev.files.values().next().unwrap().clone();
+        file.slug = "1_ghost".into();
+        file.parent = sub.clone();
+        prev.files.insert(ghost.clone(), file);
+        let second = rescan(&dir, &prev, &[]);
+        assert!(second.files.contains_key(&ghost));
+
+        // Once the directory changes its listing is read again
+        prev.dirs.insert(sub, 0);
+        let third = rescan(&dir, &prev, &[]);
+        assert!(!third.files.contains_key(&ghost));
+
+        // A file rewritten in place is checked when the watcher reports it
+        let a = lib.path().join("sub/a.pdf");
+        let mut prev = third.clone();
+        prev.files.get_mut(&*a.to_string_lossy()).unwrap().page_count = Some(12);
+        std::fs::write(&a, b"%PDF-1.7 rewritten").unwrap();
+        assert_eq!(rescan(&dir, &prev, &[]).files[&*a.to_string_lossy()].page_count, Some(12));
+        let fourth = rescan(&dir, &prev, &[a.clone()]);
+        let file = &fourth.files[&*a.to_string_lossy()];
+        assert_eq!((file.size, file.page_count), (18, None));
+    }
+
+    #[test]
+    fn refresh_reports_added_removed_and_keeps_page_count() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let dir = add_directory_inner(&conn, &lib.path().to_string_lossy(), "lib").unwrap();
+        let a = lib.path().join("a.pdf");
+        std::fs::write(&a, pdf_with_id("aa")).unwrap();
+        let change = refresh_inner(&conn, std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(change.added.len(), 1);
+
+        record_page_count_inner(&conn, &a.to_string_lossy(), 42).unwrap();
+        std::fs::write(lib.path().join("b.pdf"), pdf_with_id("bb")).unwrap();
+        let change = refresh_inner(&conn, std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(change.added.len(), 1);
+        assert_eq!(change.added[0].file, "b.pdf");
+
+        let cached = cached_textbooks_inner(&conn, std::slice::from_ref(&dir)).unwrap();
+        let books = cached[0].1.as_ref().unwrap();
+        let a_book = books.iter().find(|t| t.file == "a.pdf").unwrap();
+        assert_eq!(a_book.page_count, Some(42));
+        assert!(a_book.doc_id.as_deref().unwrap().starts_with("pdfid:"));
+
+        std::fs::remove_file(&a).unwrap();
+        let change = refresh_inner(&conn, std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(change.removed, vec![a.to_string_lossy().to_string()]);
+        assert_eq!(indexed_paths(&conn, dir.id).len(), 1);
+    }
+
+    #[test]
+    fn rename_is_reported_and_migrated() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let dir = add_directory_inner(&conn, &lib.path().to_string_lossy(), "lib").unwrap();
+        std::fs::write(lib.path().join("draft.pdf"), pdf_with_id("1234")).unwrap();
+        refresh_inner(&conn, std::slice::from_ref(&dir)).unwrap();
+        let old_slug = format!("{}_draft", dir.id);
+        set_note_inner(&conn, &old_slug, 1, "note", "markdown").unwrap();
+
+        std::fs::rename(lib.path().join("draft.pdf"), lib.path().join("final.pdf")).unwrap();
+        let change = refresh_inner(&conn, std::slice::from_ref(&dir)).unwrap();
+
+        assert!(change.added.is_empty());
+        assert!(change.removed.is_empty());
+        assert_eq!(change.renamed.len(), 1);
+        let new_slug = format!("{}_final", dir.id);
+        assert_eq!(change.renamed[0].old_slug, old_slug);
+        assert_eq!(change.renamed[0].new_slug, new_slug);
+        assert!(get_note_inner(&conn, &new_slug, 1).unwrap().is_some());
+    }
+
+    #[test]
+    fn removing_directory_drops_its_index() {
+        let (_db_dir, conn) = test_db();
+        let lib = tempfile::tempdir().unwrap();
+        let dir = add_directory_inner(&conn, &lib.path().to_string_lossy(), "lib").unwrap();
+        std::fs::write(lib.path().join("a.pdf"), b"").unwrap();
+        refresh_inner(&conn, std::slice::from_ref(&dir)).unwrap();
+        crate::commands::remove_directory_inner(&conn, dir.id).unwrap();
+        assert!(indexed_paths(&conn, dir.id).is_empty());
+    }
+}
@@ -1,10 +1,8 @@
-use std::collections::HashMap;
-
 use crate::json_storage::{read_json, write_json};
-use crate::models::{SessionEntry, StudySession};
+use crate::models::{SessionEntry, StudySession, XpSource};
+use crate::xp_ledger::{book_xp_inner, record_xp_inner};
 
-const SESSIONS_FILE: &str = "sessions.json";
-const POMODORO_XP_FILE: &str = "pomodoro-xp.json";
+pub(crate) const SESSIONS_FILE: &str = "sessions.json";
 
 #[tauri::command]
 pub fn log_study_session(sessions: Vec<SessionEntry>) -> Result<(), String> {
@@ -18,18 +16,14 @@ pub fn log_study_session(sessions: Vec<SessionEntry>) -> Result<(), String> {
 
 #[tauri::command]
 pub fn increment_pomodoro_xp(dir_path: String, slug: String) -> Result<i64, String> {
-    let mut map: HashMap<String, i64> = read_json(&dir_path, POMODORO_XP_FILE);
-    let entry = map.entry(slug).or_insert(0);
-    *entry += 1;
-    let new_value = *entry;
-    write_json(&dir_path, POMODORO_XP_FILE, &map)?;
-    Ok(new_value)
+    record_xp_inner(&dir_path, &slug, XpSource::Pomodoro, None)?;
+    get_pomodoro_xp(dir_path, slug)
 }
 
+/// Pomodoro XP of a book, from the XP ledger.
 #[tauri::command]
 pub fn get_pomodoro_xp(dir_path: String, slug: String) -> Result<i64, String> {
-    let map: HashMap<String, i64> = read_json(&dir_path, POMODORO_XP_FILE);
-    Ok(map.get(&slug).copied().unwrap_or(0))
+    book_xp_inner(&dir_path, &slug, XpSource::Pomodoro)
 }
 
 #[tauri::command]
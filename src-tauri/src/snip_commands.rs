@@ -1,7 +1,7 @@
 use crate::json_storage::{read_json, write_json, update_json};
 use crate::models::{Snip, SnipTagDef};
 
-const SNIPS_FILE: &str = "snips.json";
+pub(crate) const SNIPS_FILE: &str = "snips.json";
 const SNIP_TAG_DEFS_FILE: &str = "snip-tag-defs.json";
 const VALID_SNIP_STATUSES: &[&str] = &["open", "solid", "attention"];
 
@@ -46,6 +46,7 @@ pub fn create_snip(
         created_at: now,
         tags: Vec::new(),
         status: "open".into(),
+        solid_at: None,
     };
     all.push(snip.clone());
     write_json(&dir_path, SNIPS_FILE, &all)?;
@@ -131,13 +132,23 @@ pub fn bulk_remove_snip_tag(dir_path: String, snip_ids: Vec<String>, tag: String
     })
 }
 
+/// Set a snip's status, recording when it became solid.
+fn apply_status(snip: &mut Snip, status: String) {
+    if status != "solid" {
+        snip.solid_at = None;
+    } else if snip.status != "solid" {
+        snip.solid_at = Some(now_iso8601());
+    }
+    snip.status = status;
+}
+
 #[tauri::command]
 pub fn set_snip_status(dir_path: String, snip_id: String, status: String) -> Result<(), String> {
     validate_snip_status(&status)?;
     update_json::<Vec<Snip>, _>(&dir_path, SNIPS_FILE, |all| {
         let snip = all.iter_mut().find(|s| s.id == snip_id)
             .ok_or_else(|| format!("Snip not found: {}", snip_id))?;
-        snip.status = status;
+        apply_status(snip, status);
         Ok(())
     })
 }
@@ -147,7 +158,7 @@ pub fn bulk_set_snip_status(dir_path: String, snip_ids: Vec<String>, status: Str
     validate_snip_status(&status)?;
     update_json::<Vec<Snip>, _>(&dir_path, SNIPS_FILE, |all| {
         for snip in all.iter_mut().filter(|s| snip_ids.contains(&s.id)) {
-            snip.status = status.clone();
+            apply_status(snip, status.clone());
         }
         Ok(())
     })
@@ -238,7 +249,7 @@ pub fn recolor_snip_tag_def(dir_paths: Vec<String>, name: String, color: String)
     Ok(())
 }
 
-fn now_iso8601() -> String {
+pub(crate) fn now_iso8601() -> String {
     chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
 }
 
@@ -518,8 +529,12 @@ mod tests {
 
         set_snip_status(dp.clone(), snip.id.clone(), "solid".into()).unwrap();
 
-        let snips = list_all_snips(dp).unwrap();
+        let snips = list_all_snips(dp.clone()).unwrap();
         assert_eq!(snips[0].status, "solid");
+        assert!(snips[0].solid_at.is_some());
+
+        set_snip_status(dp.clone(), snip.id.clone(), "attention".into()).unwrap();
+        assert!(list_all_snips(dp).unwrap()[0].solid_at.is_none());
     }
 
     #[test]
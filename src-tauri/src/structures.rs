@@ -0,0 +1,686 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crossbeam_channel::Sender;
+use rusqlite::Connection;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::commands::{get_db, DbState};
+use crate::layout;
+use crate::models::{Snip, Structure, StructuresIndexed};
+use crate::pdf_commands::{request, PdfState};
+use crate::pdf_engine::PdfRequest;
+use crate::pdf_models::{
+    BlockKind, DestinationView, LayoutBlock, LinkAnnotation, LinkType, NormalizedRect, PageLayout, PageTextLayer, TextSpan,
+};
+
+/// Labelled environments and the kind they are stored as.
+const STRUCTURE_KINDS: &[(&str, &str)] = &[
+    ("Theorem", "theorem"),
+    ("Lemma", "lemma"),
+    ("Proposition", "proposition"),
+    ("Corollary", "corollary"),
+    ("Definition", "definition"),
+    ("Example", "example"),
+    ("Exercise", "exercise"),
+    ("Problem", "problem"),
+    ("Remark", "remark"),
+];
+
+/// Words that introduce a cross-reference, and the kind they point to.
+const REFERENCE_WORDS: &[(&str, &str)] = &[
+    ("theorem", "theorem"),
+    ("thm.", "theorem"),
+    ("lemma", "lemma"),
+    ("proposition", "proposition"),
+    ("prop.", "proposition"),
+    ("corollary", "corollary"),
+    ("cor.", "corollary"),
+    ("definition", "definition"),
+    ("def.", "definition"),
+    ("example", "example"),
+    ("exercise", "exercise"),
+    ("problem", "problem"),
+    ("remark", "remark"),
+    ("equation", "equation"),
+    ("eq.", "equation"),
+];
+
+/// Extra margin around a structure's text when it becomes a snip.
+const SNIP_PADDING: f64 = 0.01;
+
+/// Slugs currently being indexed, so repeated requests don't pile up.
+static INDEXING: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+/// A structure found on a page, before it is stored.
+#[derive(Debug, Clone)]
+pub(crate) struct Detected {
+    pub kind: String,
+    pub number: String,
+    pub title: Option<String>,
+    pub page: u32,
+    pub rect: NormalizedRect,
+}
+
+/// Split "Theorem 3.2 (Name). Let ..." into kind, number and the rest.
+fn split_label(text: &str) -> Option<(&'static str, String, &str)> {
+    let text = text.trim_start();
+    let (word, rest) = text.split_once(char::is_whitespace)?;
+    let kind = STRUCTURE_KINDS
+        .iter()
+        .find(|(name, _)| word.trim_end_matches('.').eq_ignore_ascii_case(name))
+        .map(|(_, kind)| *kind)?;
+    let rest = rest.trim_start();
+    let (token, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let number = token.trim_end_matches(['.', ':']);
+    if !layout::is_section_number(number) {
+        return None;
+    }
+    Some((kind, number.to_string(), rest.trim_start()))
+}
+
+/// Parse the label that opens a structure. Mentions such as "Theorem 3.2
+/// implies ..." are rejected: the number must be followed by punctuation, a
+/// parenthesised title or a new sentence.
+pub(crate) fn parse_label(text: &str) -> Option<(String, String, Option<String>)> {
+    let first = text.chars().next()?;
+    if !first.is_uppercase() {
+        return None;
+    }
+    let (kind, number, rest) = split_label(text)?;
+    let token_end = text.split_whitespace().nth(1).unwrap_or("");
+    let punctuated = token_end.ends_with('.') || token_end.ends_with(':');
+    let title = rest
+        .strip_prefix('(')
+        .and_then(|r| r.split_once(')'))
+        .map(|(title, _)| title.trim().to_string())
+        .filter(|t| !t.is_empty());
+    let sentence = rest.chars().next().map(char::is_uppercase).unwrap_or(true);
+    if !(punctuated || title.is_some() || sentence) {
+        return None;
+    }
+    Some((kind.to_string(), number, title))
+}
+
+/// "(4.12)" with optional trailing punctuation: an equation number.
+fn paren_number(word: &str) -> Option<String> {
+    let inner = word
+        .trim_end_matches([',', '.', ';', ':'])
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    layout::is_section_number(inner).then(|| inner.to_string())
+}
+
+/// The equation number of a displayed formula, set as a "(4.12)" tag at the
+/// end of its block.
+fn equation_tag(text: &str) -> Option<String> {
+    let last = text.split_whitespace().last()?;
+    if !last.ends_with(')') || text.split_whitespace().count() < 2 {
+        return None;
+    }
+    paren_number(last)
+}
+
+fn union(a: &NormalizedRect, b: &NormalizedRect) -> NormalizedRect {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    NormalizedRect {
+        x,
+        y,
+        width: (a.x + a.width).max(b.x + b.width) - x,
+        height: (a.y + a.height).max(b.y + b.height) - y,
+    }
+}
+
+/// Find the structures on one page. A statement that doesn't end a sentence
+/// (e.g. "... the following are equivalent:") continues into the next
+/// paragraphs of the same column, which are included in its rect.
+pub(crate) fn detect(layout: &PageLayout) -> Vec<Detected> {
+    let blocks = &layout.blocks;
+    let mut found = Vec::new();
+    for (i, block) in blocks.iter().enumerate() {
+        if !matches!(block.kind, BlockKind::Paragraph | BlockKind::Heading) {
+            continue;
+        }
+        if let Some(number) = equation_tag(&block.text) {
+            found.push(Detected {
+                kind: "equation".into(),
+                number,
+                title: None,
+                page: layout.page,
+                rect: block.rect.clone(),
+            });
+        }
+        if block.kind != BlockKind::Paragraph {
+            continue;
+        }
+        let Some((kind, number, title)) = parse_label(&block.text) else { continue };
+        let mut rect = block.rect.clone();
+        let mut last: &LayoutBlock = block;
+        for next in &blocks[i + 1..] {
+            let open = !last.text.trim_end().ends_with(['.', '?', '!']);
+            let continues = next.kind == BlockKind::Paragraph
+                && next.column == block.column
+                && parse_label(&next.text).is_none()
+                && !next.text.starts_with("Proof");
+            if !open || !continues {
+                break;
+            }
+            rect = union(&rect, &next.rect);
+            last = next;
+        }
+        found.push(Detected {
+            kind,
+            number,
+            title,
+            page: layout.page,
+            rect,
+        });
+    }
+    found
+}
+
+/// Replace the stored structures of a book with a fresh scan.
+pub fn replace_structures_inner(
+    conn: &Connection,
+    slug: &str,
+    full_path: &str,
+    found: &[Detected],
+) -> Result<(), String> {
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM structures WHERE slug = ?1", [slug])
+        .map_err(|e| e.to_string())?;
+    for d in found {
+        tx.execute(
+            "INSERT INTO structures (slug, kind, number, title, page, x, y, width, height)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            rusqlite::params![
+                slug,
+                d.kind,
+                d.number,
+                d.title,
+                d.page,
+                d.rect.x,
+                d.rect.y,
+                d.rect.width,
+                d.rect.height
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute(
+        "INSERT INTO structure_scans (slug, full_path, indexed_at) VALUES (?1, ?2, datetime('now'))
+         ON CONFLICT(slug) DO UPDATE SET full_path = excluded.full_path, indexed_at = excluded.indexed_at",
+        [slug, full_path],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+fn row_to_structure(row: &rusqlite::Row) -> rusqlite::Result<Structure> {
+    Ok(Structure {
+        id: row.get(0)?,
+        slug: row.get(1)?,
+        kind: row.get(2)?,
+        number: row.get(3)?,
+        title: row.get(4)?,
+        page: row.get(5)?,
+        x: row.get(6)?,
+        y: row.get(7)?,
+        width: row.get(8)?,
+        height: row.get(9)?,
+    })
+}
+
+const STRUCTURE_COLUMNS: &str = "id, slug, kind, number, title, page, x, y, width, height";
+
+pub fn list_structures_inner(conn: &Connection, slug: &str) -> Result<Vec<Structure>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM structures WHERE slug = ?1 ORDER BY page, y, x",
+            STRUCTURE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([slug], row_to_structure)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+/// Look up a structure by its label as written in the text, e.g.
+/// "Theorem 3.2" or "Exercise 5.7". The first occurrence wins.
+pub fn find_structure_inner(conn: &Connection, slug: &str, label: &str) -> Result<Option<Structure>, String> {
+    let label = label.trim();
+    if let Some(number) = paren_number(label) {
+        return lookup_structure(conn, slug, "equation", &number);
+    }
+    match split_label(&format!("{} ", label)) {
+        Some((kind, number, _)) => lookup_structure(conn, slug, kind, &number),
+        None => Ok(None),
+    }
+}
+
+fn lookup_structure(conn: &Connection, slug: &str, kind: &str, number: &str) -> Result<Option<Structure>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM structures WHERE slug = ?1 AND kind = ?2 AND number = ?3
+             ORDER BY page, y LIMIT 1",
+            STRUCTURE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let mut rows = stmt
+        .query_map([slug, kind, number], row_to_structure)
+        .map_err(|e| e.to_string())?;
+    rows.next().transpose().map_err(|e| e.to_string())
+}
+
+fn get_structure_inner(conn: &Connection, id: i64) -> Result<(Structure, String), String> {
+    conn.query_row(
+        &format!(
+            "SELECT {}, sc.full_path FROM structures s
+             JOIN structure_scans sc ON sc.slug = s.slug WHERE s.id = ?1",
+            STRUCTURE_COLUMNS
+                .split(", ")
+                .map(|c| format!("s.{}", c))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        [id],
+        |row| Ok((row_to_structure(row)?, row.get(10)?)),
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("Structure not found: {}", id),
+        e => e.to_string(),
+    })
+}
+
+/// Snip label for a structure: "Theorem 3.2 (Cauchy–Schwarz)".
+pub fn structure_label(s: &Structure) -> String {
+    let name = STRUCTURE_KINDS
+        .iter()
+        .find(|(_, kind)| *kind == s.kind)
+        .map(|(name, _)| *name)
+        .unwrap_or(&s.kind);
+    match &s.title {
+        Some(title) => format!("{} {} ({})", name, s.number, title),
+        None => format!("{} {}", name, s.number),
+    }
+}
+
+/// A textual reference on a page, e.g. "Lemma 2.3" or "(4.12)".
+#[derive(Debug, Clone)]
+pub(crate) struct Reference {
+    pub kind: String,
+    pub number: String,
+    pub rect: NormalizedRect,
+}
+
+/// Find references in the words of a page's text layer.
+pub(crate) fn find_references(layer: &PageTextLayer) -> Vec<Reference> {
+    let words: Vec<&TextSpan> = layer.spans.iter().filter(|s| !s.text.trim().is_empty()).collect();
+    let mut refs = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i].text.trim();
+        if let Some(number) = paren_number(word) {
+            refs.push(Reference {
+                kind: "equation".into(),
+                number,
+                rect: words[i].rect.clone(),
+            });
+            i += 1;
+            continue;
+        }
+        let lower = word.to_lowercase();
+        let kind = REFERENCE_WORDS.iter().find(|(w, _)| *w == lower).map(|(_, k)| *k);
+        if let (Some(kind), Some(next)) = (kind, words.get(i + 1)) {
+            let token = next
+                .text
+                .trim()
+                .trim_end_matches([',', '.', ';', ':', ')'])
+                .trim_start_matches('(');
+            if layout::is_section_number(token) {
+                let (a, b) = (&words[i].rect, &next.rect);
+                let same_line = (a.y + a.height / 2.0 - (b.y + b.height / 2.0)).abs() < a.height.min(b.height) / 2.0;
+                refs.push(Reference {
+                    kind: kind.into(),
+                    number: token.to_string(),
+                    rect: if same_line { union(a, b) } else { b.clone() },
+                });
+                i += 2;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    refs
+}
+
+pub(crate) fn rects_overlap(a: &NormalizedRect, b: &NormalizedRect) -> bool {
+    a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+}
+
+/// The slug a document was indexed under, if its structures are indexed.
+pub fn indexed_slug_inner(conn: &Connection, full_path: &str) -> Result<Option<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT slug FROM structure_scans WHERE full_path = ?1")
+        .map_err(|e| e.to_string())?;
+    let mut rows = stmt
+        .query_map([full_path], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?;
+    rows.next().transpose().map_err(|e| e.to_string())
+}
+
+/// Turn the references on a page into internal links to their targets.
+/// References that are the target itself (a theorem's own label, an
+/// equation's tag) are skipped, as are labels missing from the index.
+pub fn resolve_references_inner(
+    conn: &Connection,
+    slug: &str,
+    page: u32,
+    layer: &PageTextLayer,
+) -> Result<Vec<LinkAnnotation>, String> {
+    let mut links = Vec::new();
+    for r in find_references(layer) {
+        let Some(target) = lookup_structure(conn, slug, &r.kind, &r.number)? else { continue };
+        let target_rect = NormalizedRect {
+            x: target.x as f32,
+            y: target.y as f32,
+            width: target.width as f32,
+            height: target.height as f32,
+        };
+        if target.page == page as i64 && rects_overlap(&r.rect, &target_rect) {
+            continue;
+        }
+        links.push(LinkAnnotation {
+            rect: r.rect,
+            link_type: LinkType::Internal {
+                page: target.page as u32,
+                view: Some(DestinationView {
+                    x: Some(target_rect.x),
+                    y: Some(target_rect.y),
+                    zoom: None,
+                }),
+            },
+            synthetic: true,
+        });
+    }
+    Ok(links)
+}
+
+fn is_indexed(conn: &Connection, slug: &str, full_path: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM structure_scans WHERE slug = ?1 AND full_path = ?2",
+        [slug, full_path],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|n| n > 0)
+    .map_err(|e| e.to_string())
+}
+
+/// Run layout analysis over every page and collect the structures.
+fn scan_document(sender: &Sender<PdfRequest>, full_path: &str) -> Result<Vec<Detected>, String> {
+    let info = request(sender, |tx| PdfRequest::OpenDocument {
+        path: full_path.to_string(),
+        tx,
+    })?;
+    let mut found = Vec::new();
+    for page in 1..=info.page_count {
+        let layer = request(sender, |tx| PdfRequest::GetPageTextLayer {
+            path: full_path.to_string(),
+            page,
+            tx,
+        })?;
+        found.extend(detect(&layout::analyze(&layer, &[])));
+    }
+    Ok(found)
+}
+
+#[tauri::command]
+pub fn list_structures(slug: String, state: State<'_, DbState>) -> Result<Vec<Structure>, String> {
+    let conn = get_db(&state)?;
+    list_structures_inner(&conn, &slug)
+}
+
+/// Jump target for a label like "Theorem 3.2".
+#[tauri::command]
+pub fn find_structure(slug: String, label: String, state: State<'_, DbState>) -> Result<Option<Structure>, String> {
+    let conn = get_db(&state)?;
+    find_structure_inner(&conn, &slug, &label)
+}
+
+/// Index a book's theorems, definitions, exercises etc. in the background.
+/// Returns false if the book is already indexed (and `force` is not set) or
+/// an indexing run is in progress. Emits `structures-indexed` when done.
+#[tauri::command]
+pub fn index_structures(
+    app: AppHandle,
+    slug: String,
+    full_path: String,
+    force: Option<bool>,
+    state: State<'_, DbState>,
+) -> Result<bool, String> {
+    {
+        let conn = get_db(&state)?;
+        if !force.unwrap_or(false) && is_indexed(&conn, &slug, &full_path)? {
+            return Ok(false);
+        }
+    }
+    {
+        let mut running = INDEXING.lock().map_err(|e| e.to_string())?;
+        if !running.get_or_insert_with(HashSet::new).insert(slug.clone()) {
+            return Ok(false);
+        }
+    }
+
+    std::thread::spawn(move || {
+        let sender = app.state::<PdfState>().sender.clone();
+        let result = scan_document(&sender, &full_path).and_then(|found| {
+            let db = app.state::<DbState>();
+            let conn = db.0.lock().map_err(|e| e.to_string())?;
+            replace_structures_inner(&conn, &slug, &full_path, &found)?;
+            Ok(found.len())
+        });
+        if let Ok(mut running) = INDEXING.lock() {
+            if let Some(set) = running.as_mut() {
+                set.remove(&slug);
+            }
+        }
+        match result {
+            Ok(count) => {
+                let _ = app.emit("structures-indexed", StructuresIndexed { slug, count });
+            }
+            Err(e) => log::warn!("structure indexing failed for {}: {}", full_path, e),
+        }
+    });
+    Ok(true)
+}
+
+/// Create a snip covering a structure, labelled after it.
+#[tauri::command]
+pub fn create_snip_from_structure(
+    dir_path: String,
+    structure_id: i64,
+    state: State<'_, DbState>,
+) -> Result<Snip, String> {
+    let (structure, full_path) = {
+        let conn = get_db(&state)?;
+        get_structure_inner(&conn, structure_id)?
+    };
+    let x = (structure.x - SNIP_PADDING).max(0.0);
+    let y = (structure.y - SNIP_PADDING).max(0.0);
+    let right = (structure.x + structure.width + SNIP_PADDING).min(1.0);
+    let bottom = (structure.y + structure.height + SNIP_PADDING).min(1.0);
+    crate::snip_commands::create_snip(
+        dir_path,
+        structure.slug.clone(),
+        full_path,
+        structure.page,
+        structure_label(&structure),
+        x,
+        y,
+        right - x,
+        bottom - y,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::init_db;
+
+    fn block(text: &str, y: f32, column: Option<u32>) -> LayoutBlock {
+        LayoutBlock {
+            kind: BlockKind::Paragraph,
+            text: text.into(),
+            rect: NormalizedRect { x: 0.1, y, width: 0.8, height: 0.05 },
+            column,
+        }
+    }
+
+    #[test]
+    fn labels_and_mentions() {
+        assert_eq!(
+            parse_label("Theorem 3.2 (Cauchy–Schwarz). For all x"),
+            Some(("theorem".into(), "3.2".into(), Some("Cauchy–Schwarz".into())))
+        );
+        assert_eq!(parse_label("Definition 1.4. A group"), Some(("definition".into(), "1.4".into(), None)));
+        assert_eq!(parse_label("Exercise 5.7 Show that"), Some(("exercise".into(), "5.7".into(), None)));
+        assert_eq!(parse_label("LEMMA 2: trivial"), Some(("lemma".into(), "2".into(), None)));
+        assert_eq!(parse_label("Theorem 3.2 implies the claim."), None);
+        assert_eq!(parse_label("Theorem A. Something"), None);
+        assert_eq!(parse_label("by Theorem 3.2. Then"), None);
+        assert_eq!(parse_label("Theorems are nice"), None);
+    }
+
+    #[test]
+    fn detect_extends_open_statements() {
+        let layout = PageLayout {
+            page: 7,
+            columns: 1,
+            blocks: vec![
+                block("Some introduction.", 0.1, Some(0)),
+                block("Theorem 2.1. The following are equivalent:", 0.2, Some(0)),
+                block("(a) G is abelian;", 0.26, Some(0)),
+                block("(b) every subgroup is normal.", 0.32, Some(0)),
+                block("Proof. Clear.", 0.38, Some(0)),
+                block("Definition 2.2. A ring is", 0.5, Some(0)),
+            ],
+            furniture: vec![],
+        };
+        let found = detect(&layout);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].kind, "theorem");
+        assert_eq!(found[0].page, 7);
+        assert!((found[0].rect.y - 0.2).abs() < 1e-6);
+        assert!((found[0].rect.height - 0.17).abs() < 1e-6);
+        assert_eq!(found[1].number, "2.2");
+    }
+
+    #[test]
+    fn equation_tags_are_indexed() {
+        let layout = PageLayout {
+            page: 3,
+            columns: 1,
+            blocks: vec![
+                block("x^2 + y^2 = 1 (4.12)", 0.3, Some(0)),
+                block("as shown in (4.12).", 0.4, Some(0)),
+            ],
+            furniture: vec![],
+        };
+        let found = detect(&layout);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].kind.as_str(), found[0].number.as_str()), ("equation", "4.12"));
+    }
+
+    #[test]
+    fn references_resolve_to_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = init_db(&dir.path().join("test.db")).unwrap();
+        let rect = NormalizedRect { x: 0.1, y: 0.2, width: 0.8, height: 0.1 };
+        let detected = |kind: &str, number: &str, page: u32| Detected {
+            kind: kind.into(),
+            number: number.into(),
+            title: None,
+            page,
+            rect: rect.clone(),
+        };
+        replace_structures_inner(
+            &conn,
+            "book",
+            "/lib/book.pdf",
+            &[detected("lemma", "2.3", 10), detected("equation", "4.12", 30)],
+        )
+        .unwrap();
+        assert_eq!(indexed_slug_inner(&conn, "/lib/book.pdf").unwrap().as_deref(), Some("book"));
+        assert_eq!(indexed_slug_inner(&conn, "/lib/other.pdf").unwrap(), None);
+
+        let word = |text: &str, x: f32, y: f32| crate::doc_backend::word_span(text, NormalizedRect { x, y, width: 0.05, height: 0.02 });
+        let layer = PageTextLayer {
+            page: 30,
+            spans: vec![
+                word("By", 0.1, 0.5),
+                word("Lemma", 0.16, 0.5),
+                word("2.3,", 0.22, 0.5),
+                word("and", 0.28, 0.5),
+                word("(4.12).", 0.34, 0.5),
+                word("Theorem", 0.1, 0.6),
+                word("9.9", 0.16, 0.6),
+                // The equation's own tag on its page
+                word("(4.12)", 0.8, 0.25),
+            ],
+        };
+        let links = resolve_references_inner(&conn, "book", 30, &layer).unwrap();
+        let targets: Vec<(u32, f32)> = links
+            .iter()
+            .map(|l| match l.link_type {
+                LinkType::Internal { page, .. } => (page, l.rect.x),
+                _ => panic!("expected internal link"),
+            })
+            .collect();
+        assert_eq!(targets, vec![(10, 0.16), (30, 0.34)]);
+        assert!(links.iter().all(|l| l.synthetic));
+        assert!((links[0].rect.width - 0.11).abs() < 1e-6);
+
+        assert_eq!(find_structure_inner(&conn, "book", "(4.12)").unwrap().unwrap().page, 30);
+    }
+
+    #[test]
+    fn store_list_find_and_label() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = init_db(&dir.path().join("test.db")).unwrap();
+        let detected = |kind: &str, number: &str, page: u32, title: Option<&str>| Detected {
+            kind: kind.into(),
+            number: number.into(),
+            title: title.map(Into::into),
+            page,
+            rect: NormalizedRect { x: 0.1, y: 0.2, width: 0.5, height: 0.1 },
+        };
+        replace_structures_inner(
+            &conn,
+            "book",
+            "/lib/book.pdf",
+            &[detected("theorem", "3.2", 40, Some("Fundamental")), detected("exercise", "5.7", 12, None)],
+        )
+        .unwrap();
+
+        let all = list_structures_inner(&conn, "book").unwrap();
+        assert_eq!(all.iter().map(|s| s.page).collect::<Vec<_>>(), vec![12, 40]);
+        assert!(is_indexed(&conn, "book", "/lib/book.pdf").unwrap());
+        assert!(!is_indexed(&conn, "book", "/lib/moved.pdf").unwrap());
+
+        let thm = find_structure_inner(&conn, "book", "Theorem 3.2").unwrap().unwrap();
+        assert_eq!(thm.page, 40);
+        assert_eq!(structure_label(&thm), "Theorem 3.2 (Fundamental)");
+        assert!(find_structure_inner(&conn, "book", "Lemma 1").unwrap().is_none());
+
+        let (s, path) = get_structure_inner(&conn, thm.id).unwrap();
+        assert_eq!(s, thm);
+        assert_eq!(path, "/lib/book.pdf");
+
+        // A rescan replaces the previous results
+        replace_structures_inner(&conn, "book", "/lib/book.pdf", &[]).unwrap();
+        assert!(list_structures_inner(&conn, "book").unwrap().is_empty());
+        assert!(get_structure_inner(&conn, thm.id).is_err());
+    }
+}
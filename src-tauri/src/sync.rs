@@ -0,0 +1,1095 @@
+//! Multi-device sync through a shared folder, such as a Syncthing share.
+//!
+//! Each device appends its changes to its own log, `{folder}/{device_id}.jsonl`,
+//! so no file is ever written by two devices and the folder needs no locking.
+//! Every record (a note, a highlight, a snip, a tag, a book's tag, reading
+//! progress or book status) is a last-writer-wins register versioned by a
+//! vector clock: a change that has seen another supersedes it, and concurrent
+//! changes are settled by timestamp then device id, the same way on every
+//! device. Settled concurrent changes are surfaced as conflicts.
+//!
+//! Books are keyed by document identity rather than slug, since slugs embed
+//! the local directory id. Books without an identity fall back to their file
+//! stem.
+//!
+//! Notes travel with their local references made portable: `[[slug#pN]]`
+//! links name the book key, and image links the image's content hash. The
+//! images themselves are copied to `{folder}/images/{hash}`, where any device
+//! can pick them up.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::commands::{list_directories_inner, set_anchored_note_inner, DbState};
+use crate::json_storage::{read_json, update_json};
+use crate::models::{Directory, NoteAnchor, Snip, SyncConflict, SyncReport, SyncStatus, SyncVersion};
+use crate::note_images;
+use crate::note_links;
+use crate::note_store::{self, IMAGE_SCHEME};
+use crate::snip_commands::{now_iso8601, SNIPS_FILE};
+
+const STATE_FILE: &str = "sync.json";
+const LOG_EXTENSION: &str = "jsonl";
+const SYNC_INTERVAL: Duration = Duration::from_secs(300);
+/// Record kinds in the order they are applied: tags before the book tags
+/// that reference them.
+const KINDS: &[&str] = &["tag", "book-tag", "note", "highlight", "snip", "progress", "status"];
+/// Kinds whose conflicts are settled without being surfaced: progress moves
+/// on every page turn, so concurrent reading would flood the list.
+const QUIET_KINDS: &[&str] = &["progress"];
+/// Image files shared by every device, named by content hash.
+const IMAGES_DIR: &str = "images";
+/// How synced notes link to an image (`axiomatic-image://sha256:{hash}`)
+/// and to a book (`[[book:{key}#pN]]`).
+const SYNCED_IMAGE: &str = "sha256:";
+const SYNCED_BOOK: &str = "book:";
+
+/// Counter per device id.
+type Clock = BTreeMap<String, u64>;
+
+/// One change in a device's log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncOp {
+    key: String,
+    clock: Clock,
+    device: String,
+    #[serde(default)]
+    device_name: String,
+    at: String,
+    value: Option<Value>,
+    /// Set on the change that settled a conflict.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    settles: Option<Settlement>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Settlement {
+    kept: SyncVersion,
+    discarded: Vec<SyncVersion>,
+}
+
+impl SyncOp {
+    fn version(&self) -> SyncVersion {
+        SyncVersion {
+            device: self.device.clone(),
+            device_name: self.device_name.clone(),
+            at: self.at.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+/// The version of a record this device last synced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SyncRecord {
+    clock: Clock,
+    hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SyncState {
+    folder: Option<String>,
+    device_id: String,
+    device_name: String,
+    counter: u64,
+    last_sync: Option<String>,
+    records: BTreeMap<String, SyncRecord>,
+    conflicts: Vec<SyncConflict>,
+}
+
+impl Default for SyncState {
+    fn default() -> Self {
+        Self {
+            folder: None,
+            device_id: uuid::Uuid::new_v4().to_string(),
+            device_name: std::env::var("HOSTNAME")
+                .or_else(|_| std::env::var("COMPUTERNAME"))
+                .unwrap_or_else(|_| std::env::consts::OS.to_string()),
+            counter: 0,
+            last_sync: None,
+            records: BTreeMap::new(),
+            conflicts: Vec::new(),
+        }
+    }
+}
+
+impl SyncState {
+    fn status(&self) -> SyncStatus {
+        SyncStatus {
+            folder: self.folder.clone(),
+            device_id: self.device_id.clone(),
+            device_name: self.device_name.clone(),
+            last_sync: self.last_sync.clone(),
+            conflicts: self.conflicts.clone(),
+        }
+    }
+
+    fn op(&mut self, key: &str, mut clock: Clock, value: Option<Value>, at: &str) -> SyncOp {
+        self.counter += 1;
+        clock.insert(self.device_id.clone(), self.counter);
+        SyncOp {
+            key: key.to_string(),
+            clock,
+            device: self.device_id.clone(),
+            device_name: self.device_name.clone(),
+            at: at.to_string(),
+            value,
+            settles: None,
+        }
+    }
+}
+
+/// Whether `a` has seen everything `b` has, and more.
+fn dominates(a: &Clock, b: &Clock) -> bool {
+    a != b && b.iter().all(|(device, n)| a.get(device).copied().unwrap_or(0) >= *n)
+}
+
+fn hash(value: Option<&Value>) -> Option<String> {
+    value.map(|v| format!("{:x}", Sha256::digest(v.to_string().as_bytes())))
+}
+
+fn kind_rank(key: &str) -> usize {
+    let kind = key.split('/').next().unwrap_or("");
+    KINDS.iter().position(|k| *k == kind).unwrap_or(KINDS.len())
+}
+
+struct Book {
+    slug: String,
+    full_path: String,
+    dir_path: String,
+}
+
+/// The books available on this device, by sync key and by slug.
+#[derive(Default)]
+struct Books {
+    by_key: HashMap<String, Book>,
+    keys: HashMap<String, String>,
+}
+
+impl Books {
+    fn load(conn: &Connection, dirs: &[Directory]) -> Result<Self, String> {
+        let mut stmt = conn
+            .prepare("SELECT dir_id, full_path, slug, doc_id FROM scan_index ORDER BY dir_id, full_path")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get(1)?, row.get::<_, String>(2)?, row.get::<_, Option<String>>(3)?))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        let mut books = Books::default();
+        for (dir_id, full_path, slug, doc_id) in rows {
+            // Books in unavailable directories are left alone, so an
+            // unmounted drive doesn't look like deleted data
+            let Some(dir) = dirs.iter().find(|d| d.id == dir_id && Path::new(&d.path).is_dir()) else {
+                continue;
+            };
+            let key = doc_id.unwrap_or_else(|| {
+                format!("name:{}", slug.split_once('_').map_or(slug.as_str(), |(_, stem)| stem))
+            });
+            // Copies of a book share its key; the first one syncs
+            if books.by_key.contains_key(&key) {
+                continue;
+            }
+            books.keys.insert(slug.clone(), key.clone());
+            books.by_key.insert(key, Book { slug, full_path, dir_path: dir.path.clone() });
+        }
+        Ok(books)
+    }
+
+    fn dir_paths(&self) -> BTreeSet<&str> {
+        self.by_key.values().map(|b| b.dir_path.as_str()).collect()
+    }
+
+    /// Whether this device can read and write the record: tags always,
+    /// per-book records when the book is here.
+    fn has(&self, key: &str) -> bool {
+        match key.split_once('/') {
+            Some(("tag", _)) => true,
+            Some((kind, rest)) if KINDS.contains(&kind) => {
+                self.by_key.contains_key(rest.split('/').next().unwrap_or(rest))
+            }
+            _ => false,
+        }
+    }
+}
+
+fn query_rows<T>(
+    conn: &Connection,
+    sql: &str,
+    f: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+) -> Result<Vec<T>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], f)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string());
+    rows
+}
+
+/// Rewrite the target of every image link in `content` with `f`, which
+/// returns `None` to keep it.
+fn rewrite_images(
+    content: &str,
+    mut f: impl FnMut(&str) -> Result<Option<String>, String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(pos) = rest.find(IMAGE_SCHEME) {
+        let start = pos + IMAGE_SCHEME.len();
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let len = rest.bytes().take_while(|b| b.is_ascii_alphanumeric() || *b == b':').count();
+        match f(&rest[..len])? {
+            Some(target) => out.push_str(&target),
+            None => out.push_str(&rest[..len]),
+        }
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn image_path(folder: &Path, hash: &str) -> PathBuf {
+    folder.join(IMAGES_DIR).join(hash)
+}
+
+/// Hashes of the images already in the folder.
+fn synced_images(folder: &Path) -> HashSet<String> {
+    std::fs::read_dir(folder.join(IMAGES_DIR))
+        .map(|entries| entries.flatten().filter_map(|e| e.file_name().into_string().ok()).collect())
+        .unwrap_or_default()
+}
+
+fn write_image(folder: &Path, hash: &str, data: &[u8]) -> Result<(), String> {
+    let path = image_path(folder, hash);
+    let tmp = path.with_extension("tmp");
+    std::fs::create_dir_all(folder.join(IMAGES_DIR))
+        .and_then(|_| std::fs::write(&tmp, data))
+        .and_then(|_| std::fs::rename(&tmp, &path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// A note's content as synced. Images it links to that the folder lacks
+/// (`present`) are collected in `images`, to be copied there once the
+/// database is unlocked.
+fn portable_note(
+    db: &Connection,
+    books: &Books,
+    present: &HashSet<String>,
+    images: &mut BTreeMap<String, Vec<u8>>,
+    content: &str,
+) -> Result<String, String> {
+    let content = note_links::rewrite_page_links(content, |slug| {
+        books.keys.get(slug).map(|key| format!("{}{}", SYNCED_BOOK, key))
+    })
+    .unwrap_or_else(|| content.to_string());
+    rewrite_images(&content, |target| {
+        let Ok(id) = target.parse::<i64>() else {
+            return Ok(None);
+        };
+        let hash: Option<String> = db
+            .query_row("SELECT hash FROM note_images WHERE id = ?1", [id], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some(hash) = hash else {
+            return Ok(None);
+        };
+        if !present.contains(&hash) && !images.contains_key(&hash) {
+            let data: Vec<u8> = db
+                .query_row("SELECT data FROM note_image_blobs WHERE hash = ?1", [&hash], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            images.insert(hash.clone(), data);
+        }
+        Ok(Some(format!("{}{}", SYNCED_IMAGE, hash)))
+    })
+}
+
+/// A synced note's content with this device's slugs and image ids. Images
+/// not stored here yet are taken from those read from the folder
+/// (`incoming`); one that hasn't arrived fails the note until a later sync.
+fn local_note(
+    db: &Connection,
+    books: &Books,
+    incoming: &HashMap<String, Vec<u8>>,
+    slug: &str,
+    page: i64,
+    content: &str,
+) -> Result<String, String> {
+    let content = note_links::rewrite_page_links(content, |target| {
+        let key = target.strip_prefix(SYNCED_BOOK)?;
+        books.by_key.get(key).map(|book| book.slug.clone())
+    })
+    .unwrap_or_else(|| content.to_string());
+    rewrite_images(&content, |target| {
+        let Some(hash) = target.strip_prefix(SYNCED_IMAGE) else {
+            return Ok(None);
+        };
+        let id: Option<i64> = db
+            .query_row(
+                "SELECT id FROM note_images WHERE note_slug = ?1 AND note_page = ?2 AND hash = ?3 ORDER BY id",
+                rusqlite::params![slug, page, hash],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if let Some(id) = id {
+            return Ok(Some(id.to_string()));
+        }
+        let stored: bool = db
+            .query_row("SELECT EXISTS(SELECT 1 FROM note_image_blobs WHERE hash = ?1)", [hash], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        if !stored {
+            let data = incoming.get(hash).ok_or_else(|| format!("Image {} has not arrived yet", hash))?;
+            if note_images::put_blob(db, data).map_err(|e| e.to_string())? != hash {
+                return Err(format!("Image {} is damaged", hash));
+            }
+        }
+        let id: i64 = db
+            .query_row(
+                "INSERT INTO note_images (note_slug, note_page, filename, hash) VALUES (?1, ?2, ?3, ?3)
+                 ON CONFLICT(note_slug, note_page, filename) DO UPDATE SET hash = excluded.hash
+                 RETURNING id",
+                rusqlite::params![slug, page, hash],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(Some(id.to_string()))
+    })
+}
+
+/// Every synced record on this device, by key. Images the folder lacks are
+/// collected in `images`, as for `portable_note`.
+fn snapshot(
+    conn: &Connection,
+    books: &Books,
+    present: &HashSet<String>,
+    images: &mut BTreeMap<String, Vec<u8>>,
+) -> Result<BTreeMap<String, Value>, String> {
+    let mut values = BTreeMap::new();
+
+    // Notes and highlights of directories keeping their own store live there
+    let stores = note_store::directory_stores(conn)?;
+    for db in std::iter::once(conn).chain(stores.iter()) {
+        let notes = query_rows(db, "SELECT slug, page, anchor, content, format FROM notes", |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+        for (slug, page, anchor, content, format) in notes {
+            if let Some(book) = books.keys.get(&slug) {
+                let key = match anchor.as_str() {
+                    "" => format!("note/{}/{}", book, page),
+                    anchor => format!("note/{}/{}/{}", book, page, anchor),
+                };
+                let content = portable_note(db, books, present, images, &content)?;
+                values.insert(key, json!({ "content": content, "format": format }));
+            }
+        }
+
+        let highlights = query_rows(
+            db,
+            "SELECT slug, page, x, y, width, height, color, note, text, group_id FROM highlights",
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    [row.get::<_, f64>(2)?, row.get(3)?, row.get(4)?, row.get(5)?],
+                    json!({
+                        "color": row.get::<_, String>(6)?,
+                        "note": row.get::<_, String>(7)?,
+                        "text": row.get::<_, String>(8)?,
+                        "groupId": row.get::<_, String>(9)?,
+                    }),
+                ))
+            },
+        )?;
+        for (slug, page, [x, y, w, h], value) in highlights {
+            if let Some(book) = books.keys.get(&slug) {
+                values.insert(format!("highlight/{}/{}/{}:{}:{}:{}", book, page, x, y, w, h), value);
+            }
+        }
+    }
+
+    for (name, color) in query_rows(conn, "SELECT name, color FROM tags", |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })? {
+        values.insert(format!("tag/{}", name), json!({ "color": color }));
+    }
+    for (slug, name) in query_rows(
+        conn,
+        "SELECT bt.book_slug, t.name FROM book_tags bt JOIN tags t ON t.id = bt.tag_id",
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+    )? {
+        if let Some(book) = books.keys.get(&slug) {
+            values.insert(format!("book-tag/{}/{}", book, name), Value::Bool(true));
+        }
+    }
+
+    for dir_path in books.dir_paths() {
+        for (kind, file) in [("progress", "progress.json"), ("status", "book-status.json")] {
+            let map: HashMap<String, Value> = read_json(dir_path, file);
+            for (slug, value) in map {
+                if let Some(book) = books.keys.get(&slug) {
+                    values.insert(format!("{}/{}", kind, book), value);
+                }
+            }
+        }
+        let snips: Vec<Snip> = read_json(dir_path, SNIPS_FILE);
+        for snip in snips {
+            let Some(book) = books.keys.get(&snip.slug) else {
+                continue;
+            };
+            let key = format!("snip/{}/{}", book, snip.id);
+            let mut value = serde_json::to_value(snip).map_err(|e| e.to_string())?;
+            if let Some(map) = value.as_object_mut() {
+                map.remove("slug");
+                map.remove("full_path");
+            }
+            values.insert(key, value);
+        }
+    }
+    Ok(values)
+}
+
+fn str_field<'a>(value: &'a Value, field: &str) -> &'a str {
+    value.get(field).and_then(Value::as_str).unwrap_or_default()
+}
+
+/// Write a record's winning version into the local data.
+fn apply(
+    conn: &Connection,
+    books: &Books,
+    incoming: &HashMap<String, Vec<u8>>,
+    key: &str,
+    value: Option<&Value>,
+) -> Result<(), String> {
+    let (kind, rest) = key.split_once('/').ok_or_else(|| format!("Invalid sync key: {}", key))?;
+    if kind == "tag" {
+        match value {
+            Some(v) => conn.execute(
+                "INSERT INTO tags (name, color) VALUES (?1, ?2) ON CONFLICT(name) DO UPDATE SET color = excluded.color",
+                rusqlite::params![rest, str_field(v, "color")],
+            ),
+            None => conn
+                .execute("PRAGMA foreign_keys = ON", [])
+                .and_then(|_| conn.execute("DELETE FROM tags WHERE name = ?1", [rest])),
+        }
+        .map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+
+    let (book_key, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    let book = books.by_key.get(book_key).ok_or_else(|| format!("Book not in the library: {}", book_key))?;
+    let slug = book.slug.as_str();
+    let invalid = || format!("Invalid sync key: {}", key);
+    match kind {
+        "note" => {
+            let (page, anchor) = rest.split_once('/').unwrap_or((rest, ""));
+            let page: i64 = page.parse().map_err(|_| invalid())?;
+            let anchor = NoteAnchor::from_key(anchor).ok_or_else(invalid)?;
+            let notes = note_store::open(conn, slug)?;
+            match value {
+                Some(v) => {
+                    let content = local_note(&notes, books, incoming, slug, page, str_field(v, "content"))?;
+                    set_anchored_note_inner(&notes, slug, page, &anchor, &content, str_field(v, "format"))?
+                }
+                None => set_anchored_note_inner(&notes, slug, page, &anchor, "", "")?,
+            }
+        }
+        "highlight" => {
+            let (page, rect) = rest.split_once('/').ok_or_else(invalid)?;
+            let page: i64 = page.parse().map_err(|_| invalid())?;
+            let rect = rect.split(':').map(str::parse::<f64>).collect::<Result<Vec<_>, _>>().map_err(|_| invalid())?;
+            let [x, y, w, h] = rect[..] else {
+                return Err(invalid());
+            };
+            let notes = note_store::open(conn, slug)?;
+            notes.execute(
+                "DELETE FROM highlights WHERE slug = ?1 AND page = ?2 AND x = ?3 AND y = ?4 AND width = ?5 AND height = ?6",
+                rusqlite::params![slug, page, x, y, w, h],
+            )
+            .map_err(|e| e.to_string())?;
+            if let Some(v) = value {
+                notes.execute(
+                    "INSERT INTO highlights (slug, page, x, y, width, height, color, note, text, group_id)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    rusqlite::params![
+                        slug,
+                        page,
+                        x,
+                        y,
+                        w,
+                        h,
+                        str_field(v, "color"),
+                        str_field(v, "note"),
+                        str_field(v, "text"),
+                        str_field(v, "groupId")
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+        "book-tag" => {
+            let sql = if value.is_some() {
+                "INSERT OR IGNORE INTO book_tags (book_slug, tag_id) SELECT ?1, id FROM tags WHERE name = ?2"
+            } else {
+                "DELETE FROM book_tags WHERE book_slug = ?1 AND tag_id IN (SELECT id FROM tags WHERE name = ?2)"
+            };
+            conn.execute(sql, [slug, rest]).map_err(|e| e.to_string())?;
+        }
+        "progress" | "status" => {
+            let file = if kind == "progress" { "progress.json" } else { "book-status.json" };
+            update_json::<HashMap<String, Value>, _>(&book.dir_path, file, |map| {
+                match value {
+                    Some(v) => map.insert(slug.to_string(), v.clone()),
+                    None => map.remove(slug),
+                };
+                Ok(())
+            })?;
+        }
+        "snip" => {
+            let snip = value
+                .map(|v| {
+                    let mut v = v.clone();
+                    v["slug"] = json!(slug);
+                    v["full_path"] = json!(book.full_path);
+                    serde_json::from_value::<Snip>(v).map_err(|e| e.to_string())
+                })
+                .transpose()?;
+            update_json::<Vec<Snip>, _>(&book.dir_path, SNIPS_FILE, |snips| {
+                match snips.iter().position(|s| s.id == rest) {
+                    Some(i) => match snip {
+                        Some(snip) => snips[i] = snip,
+                        None => {
+                            snips.remove(i);
+                        }
+                    },
+                    None => snips.extend(snip),
+                }
+                Ok(())
+            })?;
+        }
+        _ => return Err(invalid()),
+    }
+    Ok(())
+}
+
+/// Every op in the folder's logs, grouped by record. Unreadable lines (such
+/// as a log still being transferred) are skipped until the next sync.
+fn read_logs(folder: &Path) -> Result<BTreeMap<String, Vec<SyncOp>>, String> {
+    let entries = std::fs::read_dir(folder)
+        .map_err(|e| format!("Sync folder {} is unavailable: {}", folder.display(), e))?;
+    let mut ops: BTreeMap<String, Vec<SyncOp>> = BTreeMap::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().map_or(true, |e| e != LOG_EXTENSION) {
+            continue;
+        }
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        for op in contents.lines().filter_map(|line| serde_json::from_str::<SyncOp>(line).ok()) {
+            ops.entry(op.key.clone()).or_default().push(op);
+        }
+    }
+    Ok(ops)
+}
+
+fn append_log(path: &Path, ops: &[SyncOp]) -> Result<(), String> {
+    if ops.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for op in ops {
+        lines.push_str(&serde_json::to_string(op).map_err(|e| e.to_string())?);
+        lines.push('\n');
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(lines.as_bytes()))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Drop this device's superseded ops once they make up most of its log.
+/// Each op of a device dominates that device's earlier ops for the record.
+fn compact_log(path: &Path) -> Result<(), String> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Ok(());
+    };
+    let ops: Vec<SyncOp> = contents.lines().filter_map(|l| serde_json::from_str(l).ok()).collect();
+    let mut latest: BTreeMap<&str, &SyncOp> = BTreeMap::new();
+    for op in &ops {
+        latest.insert(&op.key, op);
+    }
+    if ops.len() < 2 * latest.len() + 64 {
+        return Ok(());
+    }
+    let tmp = path.with_extension("tmp");
+    let _ = std::fs::remove_file(&tmp);
+    append_log(&tmp, &latest.into_values().cloned().collect::<Vec<_>>())?;
+    std::fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+/// Sync once, against a copy of `state` that is returned with the report
+/// only after this device's log is written. The database is locked to take
+/// the snapshot and to apply what arrived, never while the folder is read
+/// or written.
+fn sync_inner(db: &Mutex<Connection>, state: &SyncState) -> Result<(SyncState, SyncReport), String> {
+    let lock = || db.lock().map_err(|e| e.to_string());
+    let mut state = state.clone();
+    let folder = PathBuf::from(state.folder.clone().ok_or("Choose a sync folder first")?);
+    let log_path = folder.join(format!("{}.{}", state.device_id, LOG_EXTENSION));
+    let mut ops = read_logs(&folder)?;
+    let mut present = synced_images(&folder);
+    let mut images = BTreeMap::new();
+    let (books, local) = {
+        let conn = lock()?;
+        let books = Books::load(&conn, &list_directories_inner(&conn)?)?;
+        let local = snapshot(&conn, &books, &present, &mut images)?;
+        (books, local)
+    };
+    for (hash, data) in images {
+        write_image(&folder, &hash, &data)?;
+        present.insert(hash);
+    }
+    let now = now_iso8601();
+    let mut report = SyncReport::default();
+
+    // Local changes since the last sync extend the version this device last saw
+    let keys: BTreeSet<String> = local.keys().chain(state.records.keys()).cloned().collect();
+    let mut outgoing = Vec::new();
+    for key in keys.into_iter().filter(|k| books.has(k)) {
+        let value = local.get(&key);
+        let record = state.records.get(&key).cloned().unwrap_or_default();
+        if record.hash == hash(value) {
+            continue;
+        }
+        let op = state.op(&key, record.clock, value.cloned(), &now);
+        state.records.insert(key.clone(), SyncRecord { clock: op.clock.clone(), hash: hash(value) });
+        ops.entry(key).or_default().push(op.clone());
+        outgoing.push(op);
+    }
+    report.sent = outgoing.len();
+
+    // Settle every record: the latest of the changes nobody has superseded
+    let mut keys: Vec<&String> = ops.keys().filter(|k| books.has(k)).collect();
+    keys.sort_by_key(|k| kind_rank(k));
+    let mut arrived = Vec::new();
+    for key in keys {
+        let versions = &ops[key];
+        let mut heads: Vec<&SyncOp> = versions
+            .iter()
+            .filter(|op| !versions.iter().any(|other| dominates(&other.clock, &op.clock)))
+            .collect();
+        heads.sort_by(|a, b| (&b.at, &b.device).cmp(&(&a.at, &a.device)));
+        heads.dedup_by(|a, b| a.clock == b.clock);
+        let winner = heads[0];
+        let clock = heads.iter().fold(Clock::new(), |mut clock, head| {
+            for (device, n) in &head.clock {
+                let entry = clock.entry(device.clone()).or_insert(0);
+                *entry = (*entry).max(*n);
+            }
+            clock
+        });
+        let discarded: Vec<SyncVersion> = heads[1..]
+            .iter()
+            .filter(|h| hash(h.value.as_ref()) != hash(winner.value.as_ref()))
+            .map(|h| h.version())
+            .collect();
+
+        let record = state.records.get(key);
+        if record.is_some_and(|r| r.clock == clock || dominates(&r.clock, &clock)) {
+            continue;
+        }
+        // A conflict is settled with a change that has seen all heads, so it
+        // is detected once; devices receiving the settlement surface it too
+        let settlement = if !discarded.is_empty() {
+            Some(Settlement { kept: winner.version(), discarded })
+        } else if winner.device != state.device_id {
+            winner.settles.clone()
+        } else {
+            None
+        };
+        let mut clock = clock;
+        if heads.len() > 1 && settlement.is_some() {
+            let mut op = state.op(key, clock, winner.value.clone(), &now);
+            op.settles = settlement.clone();
+            clock = op.clock.clone();
+            outgoing.push(op);
+        }
+        if let Some(Settlement { kept, discarded }) = settlement {
+            if !QUIET_KINDS.iter().any(|k| key.starts_with(&format!("{}/", k))) {
+                let book = key.split('/').nth(1).unwrap_or_default();
+                state.conflicts.retain(|c| &c.key != key);
+                state.conflicts.push(SyncConflict {
+                    key: key.clone(),
+                    slug: books.by_key.get(book).map(|b| b.slug.clone()),
+                    kept,
+                    discarded,
+                    detected_at: now.clone(),
+                });
+                report.conflicts += 1;
+            }
+        }
+
+        let winner_hash = hash(winner.value.as_ref());
+        let synced = SyncRecord { clock, hash: winner_hash.clone() };
+        if hash(local.get(key)) == winner_hash {
+            state.records.insert(key.clone(), synced);
+        } else {
+            arrived.push((key.clone(), winner.value.clone(), synced));
+        }
+    }
+
+    // Images of arriving notes are read from the folder before locking
+    let mut incoming = HashMap::new();
+    for (key, value, _) in &arrived {
+        let Some(value) = value.as_ref().filter(|_| key.starts_with("note/")) else {
+            continue;
+        };
+        rewrite_images(str_field(value, "content"), |target| {
+            if let Some(hash) = target.strip_prefix(SYNCED_IMAGE) {
+                if !incoming.contains_key(hash) {
+                    if let Ok(data) = std::fs::read(image_path(&folder, hash)) {
+                        incoming.insert(hash.to_string(), data);
+                    }
+                }
+            }
+            Ok(None)
+        })?;
+    }
+
+    let mut errors = Vec::new();
+    if !arrived.is_empty() {
+        let conn = lock()?;
+        // A record edited here since the snapshot keeps the edit, which the
+        // next sync sends
+        let current = snapshot(&conn, &books, &present, &mut BTreeMap::new())?;
+        for (key, value, synced) in arrived {
+            if hash(current.get(&key)) != hash(local.get(&key)) {
+                continue;
+            }
+            if let Err(e) = apply(&conn, &books, &incoming, &key, value.as_ref()) {
+                errors.push(e);
+                continue;
+            }
+            report.received += 1;
+            state.records.insert(key, synced);
+        }
+    }
+
+    append_log(&log_path, &outgoing)?;
+    if let Err(e) = compact_log(&log_path) {
+        log::warn!("sync: {}", e);
+    }
+    for e in errors {
+        log::warn!("sync: {}", e);
+    }
+    state.last_sync = Some(now);
+    Ok((state, report))
+}
+
+fn save_state(path: &Path, state: &SyncState) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(state).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| format!("Failed to save sync state: {}", e))
+}
+
+/// Sync settings and bookkeeping, persisted in the app data dir. A sync runs
+/// every few minutes while a folder is set, and on demand.
+pub struct SyncService {
+    path: PathBuf,
+    state: Mutex<SyncState>,
+}
+
+impl SyncService {
+    pub fn start(app: &AppHandle, app_data: &Path) -> Self {
+        let path = app_data.join(STATE_FILE);
+        let state: SyncState = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        // Persist right away so the device id is stable
+        if let Err(e) = save_state(&path, &state) {
+            log::warn!("sync: {}", e);
+        }
+        let service = Self { path, state: Mutex::new(state) };
+
+        let app = app.clone();
+        let spawned = std::thread::Builder::new().name("sync".into()).spawn(move || loop {
+            std::thread::sleep(SYNC_INTERVAL);
+            let (Some(service), Some(db)) = (app.try_state::<SyncService>(), app.try_state::<DbState>()) else {
+                continue;
+            };
+            if service.state.lock().map_or(true, |s| s.folder.is_none()) {
+                continue;
+            }
+            match service.sync(&db) {
+                Ok(report) => {
+                    let _ = app.emit("sync-completed", &report);
+                }
+                Err(e) => log::warn!("sync: {}", e),
+            }
+        });
+        if let Err(e) = spawned {
+            log::warn!("sync: failed to start background sync: {}", e);
+        }
+        service
+    }
+
+    fn sync(&self, db: &DbState) -> Result<SyncReport, String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        let (synced, report) = sync_inner(&db.0, &state)?;
+        // The log is written: keep the new clocks even if saving fails
+        *state = synced;
+        save_state(&self.path, &state)?;
+        Ok(report)
+    }
+
+    fn update<F: FnOnce(&mut SyncState)>(&self, f: F) -> Result<SyncStatus, String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        f(&mut state);
+        save_state(&self.path, &state)?;
+        Ok(state.status())
+    }
+}
+
+#[tauri::command]
+pub fn get_sync_status(state: State<'_, SyncService>) -> Result<SyncStatus, String> {
+    Ok(state.state.lock().map_err(|e| e.to_string())?.status())
+}
+
+/// Set the shared folder (`None` turns sync off) and this device's display
+/// name. Moving to another folder resends everything on the next sync.
+#[tauri::command]
+pub fn set_sync_config(
+    folder: Option<String>,
+    device_name: Option<String>,
+    state: State<'_, SyncService>,
+) -> Result<SyncStatus, String> {
+    if let Some(folder) = folder.as_deref() {
+        if !Path::new(folder).is_dir() {
+            return Err(format!("Sync folder {} does not exist", folder));
+        }
+    }
+    state.update(|s| {
+        if s.folder != folder {
+            s.records.clear();
+            s.conflicts.clear();
+            s.folder = folder;
+        }
+        if let Some(name) = device_name.filter(|n| !n.trim().is_empty()) {
+            s.device_name = name;
+        }
+    })
+}
+
+#[tauri::command]
+pub fn sync_now(
+    app: AppHandle,
+    state: State<'_, SyncService>,
+    db: State<'_, DbState>,
+) -> Result<SyncReport, String> {
+    let report = state.sync(&db)?;
+    let _ = app.emit("sync-completed", &report);
+    Ok(report)
+}
+
+/// Remove a conflict from the list once the user has looked at it.
+#[tauri::command]
+pub fn dismiss_sync_conflict(key: String, state: State<'_, SyncService>) -> Result<SyncStatus, String> {
+    state.update(|s| s.conflicts.retain(|c| c.key != key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{add_directory_inner, get_anchored_note_inner, get_note_inner, set_note_inner};
+    use crate::json_storage::write_json;
+
+    struct Device {
+        _tmp: tempfile::TempDir,
+        db: Mutex<Connection>,
+        library: String,
+        slug: String,
+        state: SyncState,
+    }
+
+    /// A device whose library holds one book with identity `doc`, registered
+    /// after `offset` other directories so slugs differ between devices.
+    fn device(folder: &Path, offset: usize) -> Device {
+        let tmp = tempfile::tempdir().unwrap();
+        let conn = crate::db::init_db(&tmp.path().join("axiomatic.db")).unwrap();
+        for i in 0..offset {
+            let other = tmp.path().join(format!("other{}", i));
+            std::fs::create_dir_all(&other).unwrap();
+            add_directory_inner(&conn, &other.to_string_lossy(), "other").unwrap();
+        }
+        let library = tmp.path().join("library");
+        std::fs::create_dir_all(&library).unwrap();
+        let library = library.to_string_lossy().to_string();
+        let dir = add_directory_inner(&conn, &library, "library").unwrap();
+        let slug = format!("{}_algebra", dir.id);
+        conn.execute(
+            "INSERT INTO scan_index (dir_id, full_path, parent, slug, title, file_size, mtime, doc_id)
+             VALUES (?1, ?2, '', ?3, 'Algebra', 1, 1, 'pdfid:abc')",
+            rusqlite::params![dir.id, format!("{}/algebra.pdf", library), slug],
+        )
+        .unwrap();
+        let state = SyncState { folder: Some(folder.to_string_lossy().to_string()), ..Default::default() };
+        Device { _tmp: tmp, db: Mutex::new(conn), library, slug, state }
+    }
+
+    impl Device {
+        fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+            self.db.lock().unwrap()
+        }
+    }
+
+    fn sync(d: &mut Device) -> SyncReport {
+        let (state, report) = sync_inner(&d.db, &d.state).unwrap();
+        d.state = state;
+        report
+    }
+
+    fn note(d: &Device, page: i64) -> Option<String> {
+        get_note_inner(&d.conn(), &d.slug, page).unwrap().map(|n| n.content)
+    }
+
+    #[test]
+    fn changes_flow_between_devices_under_their_own_slugs() {
+        let folder = tempfile::tempdir().unwrap();
+        let (mut a, mut b) = (device(folder.path(), 0), device(folder.path(), 2));
+        assert_ne!(a.slug, b.slug);
+
+        set_note_inner(&a.conn(), &a.slug, 3, "From A", "markdown").unwrap();
+        update_json::<HashMap<String, String>, _>(&a.library, "book-status.json", |m| {
+            m.insert(a.slug.clone(), "in-progress".into());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sync(&mut a).sent, 2);
+        assert_eq!(sync(&mut b).received, 2);
+        assert_eq!(note(&b, 3).as_deref(), Some("From A"));
+        let status: HashMap<String, String> = read_json(&b.library, "book-status.json");
+        assert_eq!(status[&b.slug], "in-progress");
+
+        // B edits what it received; A picks it up without a conflict
+        set_note_inner(&b.conn(), &b.slug, 3, "Edited on B", "markdown").unwrap();
+        set_note_inner(&b.conn(), &b.slug, 3, "", "").unwrap();
+        set_note_inner(&b.conn(), &b.slug, 4, "New on B", "markdown").unwrap();
+        assert_eq!(sync(&mut b), SyncReport { sent: 2, received: 0, conflicts: 0 });
+        assert_eq!(sync(&mut a), SyncReport { sent: 0, received: 2, conflicts: 0 });
+        assert_eq!(note(&a, 3), None);
+        assert_eq!(note(&a, 4).as_deref(), Some("New on B"));
+
+        // Anchored notes sync beside the page's own note
+        let anchor = NoteAnchor::Highlight { group_id: "g/1".into() };
+        set_anchored_note_inner(&a.conn(), &a.slug, 4, &anchor, "On the highlight", "markdown").unwrap();
+        assert_eq!(sync(&mut a).sent, 1);
+        assert_eq!(sync(&mut b).received, 1);
+        let anchored = get_anchored_note_inner(&b.conn(), &b.slug, 4, &anchor).unwrap().unwrap();
+        assert_eq!(anchored.content, "On the highlight");
+        assert_eq!(note(&b, 4).as_deref(), Some("New on B"));
+
+        // Nothing changed: nothing to send or apply
+        assert_eq!(sync(&mut a), SyncReport::default());
+        assert_eq!(sync(&mut b), SyncReport::default());
+    }
+
+    #[test]
+    fn notes_bring_their_images_and_links_along() {
+        let folder = tempfile::tempdir().unwrap();
+        let (mut a, mut b) = (device(folder.path(), 0), device(folder.path(), 2));
+        let image = crate::commands::save_note_image_inner(&a.conn(), &a.slug, 1, "fig.png", b"figure").unwrap();
+        // B already has an unrelated image under that id
+        crate::commands::save_note_image_inner(&b.conn(), "9_other", 1, "x.png", b"other").unwrap();
+        let content = format!("![fig](axiomatic-image://{}) see [[{}#p7]]", image, a.slug);
+        set_note_inner(&a.conn(), &a.slug, 1, &content, "markdown").unwrap();
+        sync(&mut a);
+        assert_eq!(sync(&mut b).received, 1);
+
+        let received = note(&b, 1).unwrap();
+        assert!(received.ends_with(&format!("see [[{}#p7]]", b.slug)));
+        let id = received.split("axiomatic-image://").nth(1).unwrap().split(')').next().unwrap();
+        assert_eq!(crate::commands::get_note_image_inner(&b.conn(), id.parse().unwrap()).unwrap(), b"figure");
+
+        // Both sides see the same record, so nothing bounces back
+        assert_eq!(sync(&mut b), SyncReport::default());
+        assert_eq!(sync(&mut a), SyncReport::default());
+    }
+
+    #[test]
+    fn concurrent_edits_converge_and_are_surfaced_once() {
+        let folder = tempfile::tempdir().unwrap();
+        let (mut a, mut b) = (device(folder.path(), 0), device(folder.path(), 1));
+        set_note_inner(&a.conn(), &a.slug, 1, "Base", "markdown").unwrap();
+        sync(&mut a);
+        sync(&mut b);
+
+        set_note_inner(&a.conn(), &a.slug, 1, "A's take", "markdown").unwrap();
+        set_note_inner(&b.conn(), &b.slug, 1, "B's take", "markdown").unwrap();
+        assert_eq!(sync(&mut a).conflicts, 0);
+        assert_eq!(sync(&mut b).conflicts, 1);
+        assert_eq!(sync(&mut a).conflicts, 1);
+        assert_eq!(note(&a, 1), note(&b, 1));
+        assert_eq!(a.state.conflicts.len(), 1);
+        assert_eq!(b.state.conflicts[0].key, "note/pdfid:abc/1");
+        assert_eq!(b.state.conflicts[0].slug.as_deref(), Some(b.slug.as_str()));
+        assert_eq!(b.state.conflicts[0].discarded.len(), 1);
+
+        // Settled: later syncs neither flip the value nor report it again
+        for _ in 0..2 {
+            assert_eq!(sync(&mut a).conflicts + sync(&mut b).conflicts, 0);
+        }
+        assert_eq!(note(&a, 1), note(&b, 1));
+    }
+
+    #[test]
+    fn snips_and_tags_are_rewritten_for_the_receiving_library() {
+        let folder = tempfile::tempdir().unwrap();
+        let (mut a, mut b) = (device(folder.path(), 0), device(folder.path(), 3));
+        let snip: Snip = serde_json::from_value(json!({
+            "id": "s1", "slug": a.slug, "full_path": format!("{}/algebra.pdf", a.library), "page": 2,
+            "label": "Lemma", "x": 0.1, "y": 0.2, "width": 0.3, "height": 0.4, "created_at": ""
+        }))
+        .unwrap();
+        write_json(&a.library, SNIPS_FILE, &vec![snip]).unwrap();
+        let tag = crate::commands::create_tag_inner(&a.conn(), "exam", "#f00").unwrap();
+        crate::commands::tag_book_inner(&a.conn(), &a.slug, tag.id).unwrap();
+        sync(&mut a);
+        sync(&mut b);
+
+        let snips: Vec<Snip> = read_json(&b.library, SNIPS_FILE);
+        assert_eq!((snips[0].slug.as_str(), snips[0].label.as_str()), (b.slug.as_str(), "Lemma"));
+        assert_eq!(snips[0].full_path, format!("{}/algebra.pdf", b.library));
+        let tags = crate::commands::list_book_tags_all_inner(&b.conn()).unwrap();
+        assert_eq!(tags[0].book_slug, b.slug);
+
+        // An unavailable library is out of scope, not deleted
+        std::fs::rename(&b.library, format!("{}-unmounted", b.library)).unwrap();
+        assert_eq!(sync(&mut b).sent, 0);
+        assert!(dominates(&Clock::from([("a".into(), 2)]), &Clock::from([("a".into(), 1)])));
+        assert!(!dominates(&Clock::from([("a".into(), 1)]), &Clock::from([("b".into(), 1)])));
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::history_commands::MAX_PAGE_STEP;
+use crate::json_storage::{read_json, update_json, write_json};
+use crate::models::{BookProgress, DirXp, PageMark, XpEvent, XpSource, XpSummary};
+use crate::snip_commands::now_iso8601;
+
+pub(crate) const XP_EVENTS_FILE: &str = "xp-events.json";
+pub(crate) const PAGE_MARKS_FILE: &str = "page-marks.json";
+/// Counters kept before the ledger, and the source their XP came from.
+const LEGACY_COUNTERS: &[(&str, XpSource)] = &[
+    ("xp.json", XpSource::SnipReview),
+    ("pomodoro-xp.json", XpSource::Pomodoro),
+];
+/// Reaching level `n + 1` from level `n` takes `n * XP_PER_LEVEL` XP.
+const XP_PER_LEVEL: i64 = 10;
+
+impl XpSource {
+    /// XP awarded when the caller doesn't give an amount.
+    pub fn default_amount(self) -> i64 {
+        match self {
+            XpSource::Pomodoro | XpSource::SnipReview | XpSource::PageRead => 1,
+            XpSource::BookFinished => 50,
+        }
+    }
+}
+
+/// Fold the old per-book counters into the ledger, one event per book and
+/// counter. The counter files are renamed to `*.migrated` so this runs once.
+/// Page marks start where each book was left, so pages read before the
+/// ledger earn nothing.
+fn migrate_legacy_counters(dir_path: &str) -> Result<(), String> {
+    let axiomatic = Path::new(dir_path).join(".axiomatic");
+    if !axiomatic.join(PAGE_MARKS_FILE).is_file() && axiomatic.join("progress.json").is_file() {
+        let progress: HashMap<String, BookProgress> = read_json(dir_path, "progress.json");
+        let marks: HashMap<String, PageMark> = progress
+            .into_iter()
+            .map(|(slug, p)| (slug, PageMark { last: p.current_page, furthest: p.current_page }))
+            .collect();
+        write_json(dir_path, PAGE_MARKS_FILE, &marks)?;
+    }
+    for &(filename, source) in LEGACY_COUNTERS {
+        let path = axiomatic.join(filename);
+        if !path.is_file() {
+            continue;
+        }
+        let counts: HashMap<String, i64> = read_json(dir_path, filename);
+        let timestamp = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .map(|t| chrono::DateTime::<chrono::Utc>::from(t).format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            .unwrap_or_else(|_| now_iso8601());
+        update_json::<Vec<XpEvent>, _>(dir_path, XP_EVENTS_FILE, |events| {
+            let mut slugs: Vec<_> = counts.into_iter().filter(|(_, n)| *n != 0).collect();
+            slugs.sort();
+            events.extend(slugs.into_iter().map(|(slug, amount)| XpEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                timestamp: timestamp.clone(),
+                source,
+                slug,
+                amount,
+                reverts: None,
+            }));
+            Ok(())
+        })?;
+        let mut migrated = path.clone().into_os_string();
+        migrated.push(".migrated");
+        std::fs::rename(&path, &migrated).map_err(|e| format!("Failed to retire {}: {}", filename, e))?;
+    }
+    Ok(())
+}
+
+fn read_events(dir_path: &str) -> Result<Vec<XpEvent>, String> {
+    migrate_legacy_counters(dir_path)?;
+    Ok(read_json(dir_path, XP_EVENTS_FILE))
+}
+
+/// Sum of the events matching `filter`.
+fn sum(events: &[XpEvent], filter: impl Fn(&XpEvent) -> bool) -> i64 {
+    events.iter().filter(|e| filter(e)).map(|e| e.amount).sum()
+}
+
+/// Level reached with `total` XP, XP into that level, and XP the level takes.
+pub fn level_for(total: i64) -> (i64, i64, i64) {
+    let mut level = 1;
+    let mut remaining = total.max(0);
+    while remaining >= level * XP_PER_LEVEL {
+        remaining -= level * XP_PER_LEVEL;
+        level += 1;
+    }
+    (level, remaining, level * XP_PER_LEVEL)
+}
+
+pub(crate) fn record_xp_inner(
+    dir_path: &str,
+    slug: &str,
+    source: XpSource,
+    amount: Option<i64>,
+) -> Result<XpEvent, String> {
+    migrate_legacy_counters(dir_path)?;
+    let event = XpEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        timestamp: now_iso8601(),
+        source,
+        slug: slug.to_string(),
+        amount: amount.unwrap_or_else(|| source.default_amount()),
+        reverts: None,
+    };
+    update_json::<Vec<XpEvent>, _>(dir_path, XP_EVENTS_FILE, |events| {
+        events.push(event.clone());
+        Ok(())
+    })?;
+    Ok(event)
+}
+
+/// XP a book has from one source.
+pub(crate) fn book_xp_inner(dir_path: &str, slug: &str, source: XpSource) -> Result<i64, String> {
+    let events = read_events(dir_path)?;
+    Ok(sum(&events, |e| e.slug == slug && e.source == source))
+}
+
+/// Credit the pages read in a book since the page last reported. Only
+/// forward steps of at most `MAX_PAGE_STEP` pages count, so jumps through
+/// links or the outline earn nothing, and only pages beyond the furthest
+/// credited, so paging back and reading again earns nothing either.
+pub(crate) fn record_pages_read_inner(dir_path: &str, slug: &str, page: i64) -> Result<Option<XpEvent>, String> {
+    migrate_legacy_counters(dir_path)?;
+    let mut pages = 0;
+    update_json::<HashMap<String, PageMark>, _>(dir_path, PAGE_MARKS_FILE, |marks| {
+        let mark = marks.entry(slug.to_string()).or_insert(PageMark { last: page, furthest: page });
+        let step = page - mark.last;
+        if step > 0 && step as f64 <= MAX_PAGE_STEP {
+            pages = (page - mark.last.max(mark.furthest)).max(0);
+            mark.furthest = mark.furthest.max(page);
+        }
+        mark.last = page;
+        Ok(())
+    })?;
+    if pages == 0 {
+        return Ok(None);
+    }
+    let amount = pages * XpSource::PageRead.default_amount();
+    record_xp_inner(dir_path, slug, XpSource::PageRead, Some(amount)).map(Some)
+}
+
+/// Award the finishing bonus of a book, once however often it is marked done.
+pub(crate) fn record_book_finished_inner(dir_path: &str, slug: &str) -> Result<Option<XpEvent>, String> {
+    if book_xp_inner(dir_path, slug, XpSource::BookFinished)? > 0 {
+        return Ok(None);
+    }
+    record_xp_inner(dir_path, slug, XpSource::BookFinished, None).map(Some)
+}
+
+/// Record XP for a book. `amount` defaults to the source's usual award.
+#[tauri::command]
+pub fn record_xp(
+    dir_path: String,
+    slug: String,
+    source: XpSource,
+    amount: Option<i64>,
+) -> Result<XpEvent, String> {
+    record_xp_inner(&dir_path, &slug, source, amount)
+}
+
+/// Undo an event by appending its negation. Each event can be undone once.
+#[tauri::command]
+pub fn revert_xp_event(dir_path: String, event_id: String) -> Result<XpEvent, String> {
+    migrate_legacy_counters(&dir_path)?;
+    let mut reverted = None;
+    update_json::<Vec<XpEvent>, _>(&dir_path, XP_EVENTS_FILE, |events| {
+        let original = events
+            .iter()
+            .find(|e| e.id == event_id)
+            .ok_or_else(|| format!("XP event not found: {}", event_id))?;
+        if original.reverts.is_some() {
+            return Err("Cannot revert a reversal".to_string());
+        }
+        if events.iter().any(|e| e.reverts.as_deref() == Some(event_id.as_str())) {
+            return Err(format!("XP event {} was already reverted", event_id));
+        }
+        let event = XpEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: now_iso8601(),
+            source: original.source,
+            slug: original.slug.clone(),
+            amount: -original.amount,
+            reverts: Some(event_id.clone()),
+        };
+        events.push(event.clone());
+        reverted = Some(event);
+        Ok(())
+    })?;
+    Ok(reverted.unwrap())
+}
+
+/// The ledger of a directory, optionally for one book, oldest first.
+#[tauri::command]
+pub fn list_xp_events(dir_path: String, slug: Option<String>) -> Result<Vec<XpEvent>, String> {
+    let events = read_events(&dir_path)?;
+    Ok(match slug {
+        Some(slug) => events.into_iter().filter(|e| e.slug == slug).collect(),
+        None => events,
+    })
+}
+
+/// Totals, level and rollups per source, book and directory over the
+/// ledgers of `dir_paths`.
+#[tauri::command]
+pub fn get_xp_summary(dir_paths: Vec<String>) -> Result<XpSummary, String> {
+    let mut summary = XpSummary::default();
+    for dir_path in dir_paths {
+        let events = read_events(&dir_path)?;
+        let total = sum(&events, |_| true);
+        for e in &events {
+            *summary.by_source.entry(e.source).or_default() += e.amount;
+            *summary.by_book.entry(e.slug.clone()).or_default() += e.amount;
+        }
+        summary.total += total;
+        summary.by_dir.push(DirXp { dir_path, total });
+    }
+    (summary.level, summary.level_xp, summary.next_level_xp) = level_for(summary.total);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let dp = dir.path().to_string_lossy().to_string();
+        (dir, dp)
+    }
+
+    #[test]
+    fn legacy_counters_are_migrated_once() {
+        let (dir, dp) = temp_dir();
+        let axiomatic = dir.path().join(".axiomatic");
+        std::fs::create_dir_all(&axiomatic).unwrap();
+        std::fs::write(axiomatic.join("xp.json"), r#"{"algebra": 7, "topology": 0}"#).unwrap();
+        std::fs::write(axiomatic.join("pomodoro-xp.json"), r#"{"algebra": 3}"#).unwrap();
+
+        let events = list_xp_events(dp.clone(), None).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(book_xp_inner(&dp, "algebra", XpSource::SnipReview).unwrap(), 7);
+        assert_eq!(book_xp_inner(&dp, "algebra", XpSource::Pomodoro).unwrap(), 3);
+        assert!(!axiomatic.join("xp.json").exists());
+        assert!(axiomatic.join("xp.json.migrated").exists());
+
+        // Reading again does not migrate twice
+        assert_eq!(list_xp_events(dp, None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn revert_appends_negation() {
+        let (_dir, dp) = temp_dir();
+        let event = record_xp(dp.clone(), "algebra".into(), XpSource::BookFinished, None).unwrap();
+        assert_eq!(event.amount, 50);
+        record_xp(dp.clone(), "algebra".into(), XpSource::PageRead, Some(4)).unwrap();
+
+        let undo = revert_xp_event(dp.clone(), event.id.clone()).unwrap();
+        assert_eq!((undo.amount, undo.reverts.as_deref()), (-50, Some(event.id.as_str())));
+        assert!(revert_xp_event(dp.clone(), event.id.clone()).unwrap_err().contains("already"));
+        assert!(revert_xp_event(dp.clone(), undo.id).is_err());
+        assert!(revert_xp_event(dp.clone(), "missing".into()).is_err());
+
+        let events = list_xp_events(dp.clone(), Some("algebra".into())).unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(get_xp_summary(vec![dp]).unwrap().total, 4);
+    }
+
+    #[test]
+    fn progress_and_finishing_earn_xp() {
+        let (_dir, dp) = temp_dir();
+        let progress = |page: i64| crate::models::BookProgress {
+            current_page: page,
+            total_pages: 100,
+            last_read_at: String::new(),
+        };
+        let read = |page: i64| crate::commands::save_progress(dp.clone(), "algebra".into(), progress(page)).unwrap();
+        read(1);
+        assert_eq!(book_xp_inner(&dp, "algebra", XpSource::PageRead).unwrap(), 0);
+        read(4);
+        read(8);
+        assert_eq!(book_xp_inner(&dp, "algebra", XpSource::PageRead).unwrap(), 7);
+        // A jump earns nothing, reading on from there does
+        read(60);
+        read(61);
+        assert_eq!(book_xp_inner(&dp, "algebra", XpSource::PageRead).unwrap(), 8);
+        // Going back and reading the same pages again earns nothing
+        read(5);
+        read(9);
+        assert_eq!(book_xp_inner(&dp, "algebra", XpSource::PageRead).unwrap(), 8);
+        assert_eq!(list_xp_events(dp.clone(), None).unwrap().len(), 3);
+
+        crate::commands::set_book_status(dp.clone(), "algebra".into(), "in-progress".into()).unwrap();
+        assert_eq!(book_xp_inner(&dp, "algebra", XpSource::BookFinished).unwrap(), 0);
+        crate::commands::set_book_status(dp.clone(), "algebra".into(), "done".into()).unwrap();
+        crate::commands::set_book_status(dp.clone(), "algebra".into(), "need-revisit".into()).unwrap();
+        crate::commands::set_book_status(dp.clone(), "algebra".into(), "done".into()).unwrap();
+        assert_eq!(book_xp_inner(&dp, "algebra", XpSource::BookFinished).unwrap(), 50);
+    }
+
+    #[test]
+    fn pages_read_before_the_ledger_earn_nothing() {
+        let (dir, dp) = temp_dir();
+        let axiomatic = dir.path().join(".axiomatic");
+        std::fs::create_dir_all(&axiomatic).unwrap();
+        std::fs::write(
+            axiomatic.join("progress.json"),
+            r#"{"algebra": {"currentPage": 300, "totalPages": 400, "lastReadAt": ""}}"#,
+        )
+        .unwrap();
+
+        let progress = crate::models::BookProgress {
+            current_page: 302,
+            total_pages: 400,
+            last_read_at: String::new(),
+        };
+        crate::commands::save_progress(dp.clone(), "algebra".into(), progress).unwrap();
+        assert_eq!(book_xp_inner(&dp, "algebra", XpSource::PageRead).unwrap(), 2);
+    }
+
+    #[test]
+    fn summary_rolls_up_dirs_books_and_levels() {
+        let (_a, math) = temp_dir();
+        let (_b, physics) = temp_dir();
+        record_xp(math.clone(), "algebra".into(), XpSource::SnipReview, Some(12)).unwrap();
+        record_xp(math.clone(), "topology".into(), XpSource::Pomodoro, Some(5)).unwrap();
+        record_xp(physics.clone(), "mechanics".into(), XpSource::Pomodoro, Some(20)).unwrap();
+
+        let summary = get_xp_summary(vec![math.clone(), physics.clone()]).unwrap();
+        assert_eq!(summary.total, 37);
+        assert_eq!(summary.by_source[&XpSource::Pomodoro], 25);
+        assert_eq!(summary.by_book["algebra"], 12);
+        assert_eq!(summary.by_dir[0].total, 17);
+        assert_eq!(summary.by_dir[1].total, 20);
+        // 10 for level 2, 20 more for level 3
+        assert_eq!((summary.level, summary.level_xp, summary.next_level_xp), (3, 7, 30));
+        assert_eq!(level_for(0), (1, 0, 10));
+    }
+}
@@ -3,6 +3,9 @@ import type { Highlight } from '../hooks/useHighlights'
 
 interface Props {
   items: Highlight[]
+  /** Read-only items of the book a clip was cut from, in the clip's page numbers. */
+  sourceItems?: Highlight[]
+  sourceTitle?: string
   variant: 'highlights' | 'bookmarks'
   width: number
   onNavigate: (page: number) => void
@@ -15,86 +18,93 @@ const CONFIG = {
   bookmarks: { title: 'Bookmarks', empty: 'No bookmarks yet. Select text and right-click to bookmark.' },
 } as const
 
-export function AnnotationPanel({
-  items,
-  variant,
-  width,
-  onNavigate,
-  onDeleteHighlight,
-  onDeleteHighlightGroup,
-}: Props) {
-  const { title, empty } = CONFIG[variant]
+interface Entry {
+  key: string
+  page: number
+  color: string
+  text: string
+  groupId?: string
+  id?: number
+}
 
-  // Group by group_id (multi-rect selections share one entry)
-  const entries = useMemo(() => {
-    const grouped = new Map<
-      string,
-      { page: number; color: string; text: string; ids: number[]; groupId: string }
-    >()
-    const singles: { page: number; color: string; text: string; id: number }[] = []
+/** One entry per highlight, multi-rect selections (sharing a group_id) merged, by page. */
+function toEntries(items: Highlight[]): Entry[] {
+  const grouped = new Map<
+    string,
+    { page: number; color: string; text: string; ids: number[]; groupId: string }
+  >()
+  const singles: { page: number; color: string; text: string; id: number }[] = []
 
-    for (const h of items) {
-      if (h.group_id) {
-        const existing = grouped.get(h.group_id)
-        if (existing) {
-          existing.ids.push(h.id)
-        } else {
-          grouped.set(h.group_id, {
-            page: h.page,
-            color: h.color,
-            text: h.text,
-            ids: [h.id],
-            groupId: h.group_id,
-          })
-        }
+  for (const h of items) {
+    if (h.group_id) {
+      const existing = grouped.get(h.group_id)
+      if (existing) {
+        existing.ids.push(h.id)
       } else {
-        singles.push({ page: h.page, color: h.color, text: h.text || h.note, id: h.id })
+        grouped.set(h.group_id, {
+          page: h.page,
+          color: h.color,
+          text: h.text,
+          ids: [h.id],
+          groupId: h.group_id,
+        })
       }
+    } else {
+      singles.push({ page: h.page, color: h.color, text: h.text || h.note, id: h.id })
     }
+  }
 
-    const result: {
-      key: string
-      page: number
-      color: string
-      text: string
-      groupId?: string
-      id?: number
-    }[] = []
-
-    for (const [gid, g] of grouped) {
-      result.push({ key: gid, page: g.page, color: g.color, text: g.text, groupId: g.groupId })
-    }
-    for (const s of singles) {
-      result.push({ key: `single-${s.id}`, page: s.page, color: s.color, text: s.text, id: s.id })
-    }
+  const result: Entry[] = []
+  for (const [gid, g] of grouped) {
+    result.push({ key: gid, page: g.page, color: g.color, text: g.text, groupId: g.groupId })
+  }
+  for (const s of singles) {
+    result.push({ key: `single-${s.id}`, page: s.page, color: s.color, text: s.text, id: s.id })
+  }
 
-    result.sort((a, b) => a.page - b.page)
-    return result
-  }, [items])
+  result.sort((a, b) => a.page - b.page)
+  return result
+}
 
-  // Group by page for display
-  const pageGroups = useMemo(() => {
-    const groups: { page: number; items: typeof entries }[] = []
-    let currentPage = -1
-    let currentItems: typeof entries = []
-    for (const entry of entries) {
-      if (entry.page !== currentPage) {
-        if (currentItems.length > 0) {
-          groups.push({ page: currentPage, items: currentItems })
-        }
-        currentPage = entry.page
-        currentItems = [entry]
-      } else {
-        currentItems.push(entry)
+/** Consecutive entries on the same page, for display. */
+function byPage(entries: Entry[]): { page: number; items: Entry[] }[] {
+  const groups: { page: number; items: Entry[] }[] = []
+  let currentPage = -1
+  let currentItems: Entry[] = []
+  for (const entry of entries) {
+    if (entry.page !== currentPage) {
+      if (currentItems.length > 0) {
+        groups.push({ page: currentPage, items: currentItems })
       }
+      currentPage = entry.page
+      currentItems = [entry]
+    } else {
+      currentItems.push(entry)
     }
-    if (currentItems.length > 0) {
-      groups.push({ page: currentPage, items: currentItems })
-    }
-    return groups
-  }, [entries])
+  }
+  if (currentItems.length > 0) {
+    groups.push({ page: currentPage, items: currentItems })
+  }
+  return groups
+}
 
-  const handleDelete = (entry: (typeof entries)[0]) => {
+export function AnnotationPanel({
+  items,
+  sourceItems,
+  sourceTitle,
+  variant,
+  width,
+  onNavigate,
+  onDeleteHighlight,
+  onDeleteHighlightGroup,
+}: Props) {
+  const { title, empty } = CONFIG[variant]
+
+  const entries = useMemo(() => toEntries(items), [items])
+  const pageGroups = useMemo(() => byPage(entries), [entries])
+  const sourceGroups = useMemo(() => byPage(toEntries(sourceItems ?? [])), [sourceItems])
+
+  const handleDelete = (entry: Entry) => {
     if (entry.groupId) {
       onDeleteHighlightGroup(entry.groupId)
     } else if (entry.id != null) {
@@ -102,6 +112,66 @@ export function AnnotationPanel({
     }
   }
 
+  const renderGroups = (groups: { page: number; items: Entry[] }[], readOnly: boolean) =>
+    groups.map((group) => (
+      <div key={group.page}>
+        <div className="sticky top-0 border-b border-[#eee8d5] bg-[#fdf6e3]/90 px-3 py-1 backdrop-blur-sm dark:border-[#073642] dark:bg-[#002b36]/90">
+          <span className="text-xs font-medium text-[#93a1a1] dark:text-[#657b83]">
+            Page {group.page}
+          </span>
+        </div>
+        {group.items.map((entry) => (
+          <div
+            key={entry.key}
+            className="group flex cursor-pointer items-start gap-2 border-b border-[#eee8d5]/50 px-3 py-2 hover:bg-[#eee8d5] dark:border-[#073642]/50 dark:hover:bg-[#073642]"
+            onClick={() => onNavigate(entry.page)}
+          >
+            {variant === 'highlights' ? (
+              <span
+                className="mt-1 inline-block h-2.5 w-2.5 shrink-0 rounded-full"
+                style={{ backgroundColor: entry.color }}
+              />
+            ) : (
+              <svg
+                className="mt-0.5 shrink-0 text-[#93a1a1] dark:text-[#657b83]"
+                width="12"
+                height="12"
+                viewBox="0 0 24 24"
+                fill="none"
+                stroke="currentColor"
+                strokeWidth="2"
+                strokeLinecap="round"
+                strokeLinejoin="round"
+              >
+                <path d="M19 21l-7-5-7 5V5a2 2 0 0 1 2-2h10a2 2 0 0 1 2 2z" />
+              </svg>
+            )}
+            <span className="mr-2 line-clamp-3 min-w-0 flex-1 overflow-hidden text-xs leading-relaxed text-[#586e75] dark:text-[#93a1a1]">
+              {entry.text || '(no text)'}
+            </span>
+            {!readOnly && (
+              <button
+                className="shrink-0 rounded p-1 text-[#93a1a1] opacity-0 hover:bg-[#eee8d5] hover:text-[#dc322f] group-hover:opacity-100 dark:text-[#657b83] dark:hover:bg-[#073642] dark:hover:text-[#dc322f]"
+                onClick={(e) => {
+                  e.stopPropagation()
+                  handleDelete(entry)
+                }}
+                aria-label={`Delete ${variant === 'highlights' ? 'highlight' : 'bookmark'}`}
+              >
+                <svg width="14" height="14" viewBox="0 0 24 24" fill="none" stroke="currentColor" strokeWidth="2" strokeLinecap="round" strokeLinejoin="round">
+                  <polyline points="3 6 5 6 21 6" />
+                  <path d="M19 6l-1 14a2 2 0 0 1-2 2H8a2 2 0 0 1-2-2L5 6" />
+                  <path d="M10 11v6" />
+                  <path d="M14 11v6" />
+                  <path d="M9 6V4a1 1 0 0 1 1-1h4a1 1 0 0 1 1 1v2" />
+                </svg>
+              </button>
+            )}
+          </div>
+        ))}
+      </div>
+    ))
+
   return (
     <div className="flex h-full flex-col overflow-hidden" style={{ width }}>
       <div className="flex h-8 shrink-0 items-center justify-between border-b border-[#eee8d5] bg-[#fdf6e3] px-3 dark:border-[#073642] dark:bg-[#002b36]">
@@ -113,67 +183,22 @@ export function AnnotationPanel({
         </span>
       </div>
       <div className="flex-1 overflow-y-auto bg-[#fdf6e3] dark:bg-[#002b36]">
-        {pageGroups.length === 0 && (
+        {pageGroups.length === 0 && sourceGroups.length === 0 && (
           <p className="p-4 text-center text-xs text-[#93a1a1] dark:text-[#657b83]">
             {empty}
           </p>
         )}
-        {pageGroups.map((group) => (
-          <div key={group.page}>
-            <div className="sticky top-0 border-b border-[#eee8d5] bg-[#fdf6e3]/90 px-3 py-1 backdrop-blur-sm dark:border-[#073642] dark:bg-[#002b36]/90">
-              <span className="text-xs font-medium text-[#93a1a1] dark:text-[#657b83]">
-                Page {group.page}
+        {renderGroups(pageGroups, false)}
+        {sourceGroups.length > 0 && (
+          <>
+            <div className="border-b border-[#eee8d5] px-3 py-1.5 dark:border-[#073642]">
+              <span className="text-xs font-medium text-[#586e75] dark:text-[#93a1a1]">
+                From {sourceTitle ?? 'the source book'}
               </span>
             </div>
-            {group.items.map((entry) => (
-              <div
-                key={entry.key}
-                className="group flex cursor-pointer items-start gap-2 border-b border-[#eee8d5]/50 px-3 py-2 hover:bg-[#eee8d5] dark:border-[#073642]/50 dark:hover:bg-[#073642]"
-                onClick={() => onNavigate(entry.page)}
-              >
-                {variant === 'highlights' ? (
-                  <span
-                    className="mt-1 inline-block h-2.5 w-2.5 shrink-0 rounded-full"
-                    style={{ backgroundColor: entry.color }}
-                  />
-                ) : (
-                  <svg
-                    className="mt-0.5 shrink-0 text-[#93a1a1] dark:text-[#657b83]"
-                    width="12"
-                    height="12"
-                    viewBox="0 0 24 24"
-                    fill="none"
-                    stroke="currentColor"
-                    strokeWidth="2"
-                    strokeLinecap="round"
-                    strokeLinejoin="round"
-                  >
-                    <path d="M19 21l-7-5-7 5V5a2 2 0 0 1 2-2h10a2 2 0 0 1 2 2z" />
-                  </svg>
-                )}
-                <span className="mr-2 line-clamp-3 min-w-0 flex-1 overflow-hidden text-xs leading-relaxed text-[#586e75] dark:text-[#93a1a1]">
-                  {entry.text || '(no text)'}
-                </span>
-                <button
-                  className="shrink-0 rounded p-1 text-[#93a1a1] opacity-0 hover:bg-[#eee8d5] hover:text-[#dc322f] group-hover:opacity-100 dark:text-[#657b83] dark:hover:bg-[#073642] dark:hover:text-[#dc322f]"
-                  onClick={(e) => {
-                    e.stopPropagation()
-                    handleDelete(entry)
-                  }}
-                  aria-label={`Delete ${variant === 'highlights' ? 'highlight' : 'bookmark'}`}
-                >
-                  <svg width="14" height="14" viewBox="0 0 24 24" fill="none" stroke="currentColor" strokeWidth="2" strokeLinecap="round" strokeLinejoin="round">
-                    <polyline points="3 6 5 6 21 6" />
-                    <path d="M19 6l-1 14a2 2 0 0 1-2 2H8a2 2 0 0 1-2-2L5 6" />
-                    <path d="M10 11v6" />
-                    <path d="M14 11v6" />
-                    <path d="M9 6V4a1 1 0 0 1 1-1h4a1 1 0 0 1 1 1v2" />
-                  </svg>
-                </button>
-              </div>
-            ))}
-          </div>
-        ))}
+            {renderGroups(sourceGroups, true)}
+          </>
+        )}
       </div>
     </div>
   )
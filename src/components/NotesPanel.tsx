@@ -106,7 +106,7 @@ export function NotesPanel({ slug, page, content, onUpdate, externalEditorRef, w
         keymap.of([...defaultKeymap, ...historyKeymap]),
         markdown({ codeLanguages: languages }),
         mathDecoration,
-        imageDecoration,
+        imageDecoration(() => contextRef.current.slug),
         imagePasteHandler(() => contextRef.current),
         updateListener,
         vimModeListener,
@@ -4,6 +4,7 @@ import type { DocumentInfo } from '../hooks/useDocument'
 import { usePageLinks, type LinkAnnotation } from '../hooks/usePageLinks'
 import { usePageTextLayer, type PageTextLayer } from '../hooks/usePageTextLayer'
 import type { Highlight } from '../hooks/useHighlights'
+import type { ReadingPosition } from '../hooks/useReadingHistory'
 import { TextLayer } from './TextLayer'
 import { SnipOverlay } from './SnipOverlay'
 import { invoke } from '@tauri-apps/api/core'
@@ -24,6 +25,7 @@ const HIGHLIGHT_COLORS = [
 
 export interface PdfViewerHandle {
   applyZoom: (zoom: number) => void
+  getPosition: () => ReadingPosition | null
 }
 
 interface Props {
@@ -32,7 +34,13 @@ interface Props {
   initialPage?: number
   onPageChange?: (page: number) => void
   containerRef?: RefObject<HTMLDivElement | null>
-  scrollRequest?: { page: number; seq: number } | null
+  /** `offset` is the fraction of the page's height to scroll past. */
+  scrollRequest?: { page: number; offset?: number; seq: number } | null
+  onPositionChange?: (position: ReadingPosition) => void
+  /** An internal link was followed from `from` to `to`. */
+  onLinkJump?: (from: ReadingPosition, to: ReadingPosition) => void
+  /** A link into another file was followed; `page` is where it leads, if found. */
+  onRemoteLink?: (file: string, page: number | null) => void
   highlightsForPage?: (page: number) => Highlight[]
   onDeleteHighlight?: (id: number) => void
   onDeleteHighlightGroup?: (groupId: string) => void
@@ -79,6 +87,9 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
   onPageChange,
   containerRef: externalContainerRef,
   scrollRequest,
+  onPositionChange,
+  onLinkJump,
+  onRemoteLink,
   highlightsForPage,
   onDeleteHighlight,
   onDeleteHighlightGroup,
@@ -93,7 +104,7 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
   const trackingEnabled = useRef(false)
   const hasRestored = useRef(false)
   const rafId = useRef(0)
-  const { getLinks } = usePageLinks(fullPath)
+  const { getLinks, resolveRemote } = usePageLinks(fullPath)
   const { getTextLayer, getCachedTextLayer } = usePageTextLayer(fullPath)
   const pageLinksRef = useRef<Map<number, LinkAnnotation[]>>(new Map())
   const pageTextLayersRef = useRef<Map<number, PageTextLayer>>(new Map())
@@ -143,9 +154,24 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
   const totalHeight = pageOffsets[numPages] - PAGE_GAP
   const totalHeightRef = useRef(totalHeight)
   totalHeightRef.current = totalHeight
+  const onPositionChangeRef = useRef(onPositionChange)
+  onPositionChangeRef.current = onPositionChange
+
+  // Page at the top of the view and how far into it the view starts
+  const getPosition = useCallback((): ReadingPosition | null => {
+    const container = containerRef.current
+    if (!container || numPages === 0) return null
+    const offsets = pageOffsetsRef.current
+    const scrollTop = container.scrollTop / scaleRef.current
+    const page = pageAtOffset(offsets, scrollTop)
+    const height = offsets[page] - PAGE_GAP - offsets[page - 1]
+    const scrollOffset = Math.min(1, Math.max(0, (scrollTop - offsets[page - 1]) / height))
+    return { page, scrollOffset, zoom: currentZoomRef.current }
+  }, [numPages])
 
   // Expose imperative applyZoom — called directly, no React re-render.
   useImperativeHandle(ref, () => ({
+    getPosition,
     applyZoom: (newZoom: number) => {
       currentZoomRef.current = newZoom
       const th = totalHeightRef.current
@@ -202,7 +228,7 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
         startTransition(() => setCommittedZoom(newZoom))
       }, 300)
     },
-  }), [])
+  }), [getPosition])
 
   // After committedZoom catches up, sync transform state
   useLayoutEffect(() => {
@@ -268,6 +294,8 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
         const page = pageAtOffset(offsets, centerY)
         clearTimeout(pageDebounceRef.current)
         pageDebounceRef.current = setTimeout(() => setCurrentPage(page), 150)
+        const position = getPosition()
+        if (position) onPositionChangeRef.current?.(position)
       }
     }
 
@@ -286,7 +314,7 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
       if (rafId.current) cancelAnimationFrame(rafId.current)
       clearTimeout(pageDebounceRef.current)
     }
-  }, [numPages])
+  }, [numPages, getPosition])
 
   // Pre-warm cache for newly visible pages via IPC (runs on spawn_blocking,
   // not the main thread). Pages show a placeholder until warm.
@@ -338,7 +366,9 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
     const container = containerRef.current
     if (!container) return
     const targetPage = Math.min(scrollRequest.page, numPages)
-    container.scrollTop = pageOffsets[targetPage - 1] * scaleRef.current
+    const top = pageOffsets[targetPage - 1]
+    const height = pageOffsets[targetPage] - PAGE_GAP - top
+    container.scrollTop = (top + (scrollRequest.offset ?? 0) * height) * scaleRef.current
   }, [scrollRequest, pageOffsets, numPages])
 
   // Track which pages have been fetched so the effect doesn't re-fetch.
@@ -401,18 +431,30 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
   }, [visibleRange, getLinks, getTextLayer])
 
   const handleLinkClick = useCallback(
-    (link: LinkAnnotation) => {
+    (link: LinkAnnotation, pageNum: number) => {
       if (link.link_type.type === 'internal') {
         const container = containerRef.current
         if (container) {
+          const from = getPosition()
           const targetPage = Math.min(link.link_type.page, numPages)
-          container.scrollTop = pageOffsets[targetPage - 1] * scaleRef.current
+          const top = pageOffsets[targetPage - 1]
+          const height = pageOffsets[targetPage] - PAGE_GAP - top
+          const y = link.link_type.view?.y ?? 0
+          container.scrollTop = (top + y * height) * scaleRef.current
+          if (from) onLinkJump?.(from, { page: targetPage, scrollOffset: y, zoom: currentZoomRef.current })
+        }
+      } else if (link.link_type.type === 'remote') {
+        const { file, index } = link.link_type
+        if (onRemoteLink) {
+          resolveRemote(pageNum, index).then((page) => onRemoteLink(file, page))
+        } else {
+          invoke('open_url', { url: file }).catch(() => {})
         }
       } else {
         invoke('open_url', { url: link.link_type.url }).catch(() => {})
       }
     },
-    [numPages, pageOffsets],
+    [numPages, pageOffsets, getPosition, onLinkJump, onRemoteLink, resolveRemote],
   )
 
   // Close context menu on click elsewhere or scroll
@@ -591,6 +633,22 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
     setContextMenu(null)
   }, [contextMenu, onCreateHighlight])
 
+  const handleCopyPageText = useCallback(async () => {
+    if (!contextMenu || contextMenu.type !== 'page' || !contextMenu.pageNum) return
+    setContextMenu(null)
+    try {
+      // Math mode keeps formulas as LaTeX instead of scrambled glyphs
+      const text = await invoke<string>('extract_page_text', {
+        path: fullPath,
+        page: contextMenu.pageNum,
+        mode: 'math',
+      })
+      await navigator.clipboard.writeText(text)
+    } catch (err) {
+      console.error('Failed to copy page text:', err)
+    }
+  }, [contextMenu, fullPath])
+
   const handleDeleteFromMenu = useCallback(() => {
     if (!contextMenu || contextMenu.type !== 'highlight') return
     if (contextMenu.highlightGroupId) {
@@ -718,7 +776,7 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
                       height: `${link.rect.height * 100}%`,
                       zIndex: 3,
                     }}
-                    onClick={() => handleLinkClick(link)}
+                    onClick={() => handleLinkClick(link, pageNum)}
                   />
                 ))}
               {snipMode && onSnipRegion && (
@@ -803,6 +861,16 @@ const PdfViewerInner = React.forwardRef<PdfViewerHandle, Props>(function PdfView
                 </svg>
                 Bookmark page
               </button>
+              <button
+                className="flex w-full items-center gap-2 px-3 py-1.5 text-left text-sm text-[#586e75] hover:bg-[#eee8d5] dark:text-[#93a1a1] dark:hover:bg-[#073642]"
+                onClick={handleCopyPageText}
+              >
+                <svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" strokeWidth="2" strokeLinecap="round" strokeLinejoin="round">
+                  <rect x="9" y="9" width="13" height="13" rx="2" ry="2" />
+                  <path d="M5 15H4a2 2 0 0 1-2-2V4a2 2 0 0 1 2-2h9a2 2 0 0 1 2 2v1" />
+                </svg>
+                Copy page text
+              </button>
               <div className="my-1 h-px bg-[#eee8d5] dark:bg-[#073642]" />
               <button
                 className="flex w-full items-center gap-2 px-3 py-1.5 text-left text-sm text-[#586e75] hover:bg-[#eee8d5] dark:text-[#93a1a1] dark:hover:bg-[#073642]"
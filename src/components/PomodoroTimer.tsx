@@ -48,8 +48,8 @@ export function PomodoroTimer({ zenMode, activeSlug, activeDirPath }: Props) {
   }, [config.workMinutes, config.breakMinutes])
 
   useEffect(() => {
-    applyNewDuration(config.workMinutes)
-  }, [config.workMinutes])
+    applyNewDuration(config)
+  }, [config])
 
   useEffect(() => {
     if (!popoverOpen) return
@@ -76,7 +76,7 @@ export function PomodoroTimer({ zenMode, activeSlug, activeDirPath }: Props) {
     (preset: PomodoroPreset) => {
       const next = applyPreset(preset, config)
       savePomodoroConfig(next)
-      resetToPreset(next.workMinutes)
+      resetToPreset(next)
     },
     [config],
   )
@@ -87,7 +87,7 @@ export function PomodoroTimer({ zenMode, activeSlug, activeDirPath }: Props) {
     if (!w || w < 1 || !b || b < 1) return
     const next: PomodoroConfig = { ...config, preset: 'custom', workMinutes: w, breakMinutes: b }
     savePomodoroConfig(next)
-    resetToPreset(w)
+    resetToPreset(next)
   }, [config, customWork, customBreak])
 
   const handleToggleAudio = useCallback(() => {
@@ -11,14 +11,20 @@ const blobCache = new Map<string, string>()
 
 class ImageWidget extends WidgetType {
   id: string
+  slug: string
 
-  constructor(id: string) {
+  constructor(id: string, slug: string) {
     super()
     this.id = id
+    this.slug = slug
+  }
+
+  get cacheKey() {
+    return `${this.slug}/${this.id}`
   }
 
   eq(other: ImageWidget) {
-    return this.id === other.id
+    return this.cacheKey === other.cacheKey
   }
 
   toDOM() {
@@ -31,7 +37,7 @@ class ImageWidget extends WidgetType {
     img.style.margin = '4px 0'
     img.alt = 'pasted image'
 
-    const cached = blobCache.get(this.id)
+    const cached = blobCache.get(this.cacheKey)
     if (cached) {
       img.src = cached
     } else {
@@ -39,10 +45,10 @@ class ImageWidget extends WidgetType {
       img.style.background = '#e5e7eb'
       const numId = parseInt(this.id, 10)
       if (!isNaN(numId)) {
-        getNoteImage(numId).then((data) => {
+        getNoteImage(numId, this.slug).then((data) => {
           const blob = new Blob([data])
           const url = URL.createObjectURL(blob)
-          blobCache.set(this.id, url)
+          blobCache.set(this.cacheKey, url)
           img.src = url
           img.style.height = ''
           img.style.background = ''
@@ -61,7 +67,7 @@ class ImageWidget extends WidgetType {
 
 const IMAGE_RE = /!\[([^\]]*)\]\(axiomatic-image:\/\/(\d+)\)/g
 
-function buildImageDecorations(view: EditorView): DecorationSet {
+function buildImageDecorations(view: EditorView, slug: string): DecorationSet {
   const decorations: { from: number; to: number; deco: Decoration }[] = []
   const { from: selFrom, to: selTo } = view.state.selection.main
   const text = view.state.doc.toString()
@@ -77,7 +83,7 @@ function buildImageDecorations(view: EditorView): DecorationSet {
       from,
       to,
       deco: Decoration.replace({
-        widget: new ImageWidget(m[2]),
+        widget: new ImageWidget(m[2], slug),
       }),
     })
   }
@@ -86,24 +92,27 @@ function buildImageDecorations(view: EditorView): DecorationSet {
   return Decoration.set(decorations.map((d) => d.deco.range(d.from, d.to)))
 }
 
-export const imageDecoration = ViewPlugin.fromClass(
-  class {
-    decorations: DecorationSet
+/** Images are looked up in the store of the book `getSlug` returns. */
+export function imageDecoration(getSlug: () => string) {
+  return ViewPlugin.fromClass(
+    class {
+      decorations: DecorationSet
 
-    constructor(view: EditorView) {
-      this.decorations = buildImageDecorations(view)
-    }
+      constructor(view: EditorView) {
+        this.decorations = buildImageDecorations(view, getSlug())
+      }
 
-    update(update: ViewUpdate) {
-      if (update.docChanged || update.selectionSet) {
-        this.decorations = buildImageDecorations(update.view)
+      update(update: ViewUpdate) {
+        if (update.docChanged || update.selectionSet) {
+          this.decorations = buildImageDecorations(update.view, getSlug())
+        }
       }
-    }
-  },
-  {
-    decorations: (v) => v.decorations,
-  },
-)
+    },
+    {
+      decorations: (v) => v.decorations,
+    },
+  )
+}
 
 export function imagePasteHandler(getContext: () => { slug: string; page: number }) {
   return EditorView.domEventHandlers({
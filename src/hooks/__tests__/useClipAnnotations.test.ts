@@ -0,0 +1,47 @@
+import { describe, it, expect, vi, beforeEach } from 'vitest'
+import { renderHook, waitFor } from '@testing-library/react'
+import { mockInvoke, resetMockInvoke, getInvokeCallsFor } from '../../../__mocks__/@tauri-apps/api/core'
+
+vi.mock('@tauri-apps/api/core')
+
+import { useClipAnnotations } from '../useClipAnnotations'
+import type { ClipAnnotations } from '../useClipAnnotations'
+
+const annotations: ClipAnnotations = {
+  provenance: {
+    sourceSlug: '1_algebra',
+    sourceDirPath: '/lib',
+    sourcePath: '/lib/algebra.pdf',
+    pageOffset: 9,
+    pageCount: 5,
+    createdAt: '2026-01-01T00:00:00Z',
+  },
+  notes: [],
+  highlights: [],
+  snips: [],
+}
+
+beforeEach(() => {
+  resetMockInvoke()
+})
+
+describe('useClipAnnotations', () => {
+  it('loads the source annotations of a clip', async () => {
+    mockInvoke('get_clip_annotations', annotations)
+
+    const { result } = renderHook(() => useClipAnnotations('/lib', '1_algebra-ch2'))
+
+    await waitFor(() => {
+      expect(result.current?.provenance.sourceSlug).toBe('1_algebra')
+    })
+    const calls = getInvokeCallsFor('get_clip_annotations')
+    expect(calls[0].args).toEqual({ dirPath: '/lib', slug: '1_algebra-ch2' })
+  })
+
+  it('stays null without a book', () => {
+    const { result } = renderHook(() => useClipAnnotations(undefined, undefined))
+
+    expect(result.current).toBeNull()
+    expect(getInvokeCallsFor('get_clip_annotations').length).toBe(0)
+  })
+})
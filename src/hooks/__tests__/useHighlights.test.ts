@@ -92,6 +92,7 @@ describe('useHighlights', () => {
     const calls = getInvokeCallsFor('delete_highlight')
     expect(calls.length).toBe(1)
     expect(calls[0].args?.id).toBe(1)
+    expect(calls[0].args?.slug).toBe('test_book')
   })
 
   it('deleteHighlightGroup removes all highlights with the given group_id', async () => {
@@ -117,6 +118,7 @@ describe('useHighlights', () => {
     const calls = getInvokeCallsFor('delete_highlight_group')
     expect(calls.length).toBe(1)
     expect(calls[0].args?.groupId).toBe('grp-A')
+    expect(calls[0].args?.slug).toBe('test_book')
   })
 
   it('filters bookmarks (color="bookmark") into bookmarkHighlights', async () => {
@@ -79,6 +79,26 @@ describe('usePageLinks', () => {
     expect(getInvokeCallsFor('get_page_links')).toHaveLength(1)
   })
 
+  it('resolves a remote link only when asked', async () => {
+    mockInvoke('get_page_links', [
+      { rect: internalLink.rect, link_type: { type: 'remote', file: '/books/vol2.pdf', index: 0 } },
+    ])
+    mockInvoke('resolve_remote_link', { page: 42, view: null })
+
+    const { result } = renderHook(() => usePageLinks('/books/remote.pdf'))
+    await act(async () => {
+      await result.current.getLinks(2)
+    })
+    expect(getInvokeCallsFor('resolve_remote_link')).toHaveLength(0)
+
+    let page: number | null = null
+    await act(async () => {
+      page = await result.current.resolveRemote(2, 0)
+    })
+    expect(page).toBe(42)
+    expect(getInvokeCallsFor('resolve_remote_link')[0].args).toEqual({ path: '/books/remote.pdf', page: 2, index: 0 })
+  })
+
   it('returns empty array when IPC call fails', async () => {
     mockInvoke('get_page_links', () => { throw new Error('pdf error') })
 
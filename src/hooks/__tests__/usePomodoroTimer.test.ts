@@ -1,54 +1,70 @@
-import { describe, it, expect, vi, beforeEach, afterEach } from 'vitest'
+import { describe, it, expect, vi, beforeEach } from 'vitest'
 import { renderHook, act } from '@testing-library/react'
 
+const handlers = new Map<string, (e: { payload: unknown }) => void>()
+
 vi.mock('@tauri-apps/api/core', () => ({
-  invoke: vi.fn().mockResolvedValue(undefined),
+  invoke: vi.fn().mockResolvedValue(null),
 }))
 
-import { usePomodoroTimer, toggleTimer, resetTimer } from '../usePomodoroTimer'
+vi.mock('@tauri-apps/api/event', () => ({
+  listen: vi.fn(async (event: string, handler: (e: { payload: unknown }) => void) => {
+    handlers.set(event, handler)
+    return () => handlers.delete(event)
+  }),
+}))
 
-beforeEach(() => {
-  localStorage.clear()
-  vi.useFakeTimers()
-  act(() => { resetTimer() })
+import { invoke } from '@tauri-apps/api/core'
+import { usePomodoroTimer, toggleTimer } from '../usePomodoroTimer'
+
+const backendState = (overrides: Record<string, unknown> = {}) => ({
+  phase: 'work',
+  running: false,
+  secondsLeft: 2700,
+  completedPomodoros: 0,
+  showOverlay: false,
+  sessionStart: null,
+  ...overrides,
 })
 
-afterEach(() => {
-  act(() => { resetTimer() })
-  vi.useRealTimers()
+beforeEach(() => {
+  vi.mocked(invoke).mockClear()
 })
 
 describe('usePomodoroTimer', () => {
-  it('timer ticks while a subscriber is mounted', () => {
+  it('follows backend tick events', () => {
     const { result } = renderHook(() => usePomodoroTimer())
-    const initial = result.current.secondsLeft
 
-    act(() => { toggleTimer() })
+    act(() => { handlers.get('pomodoro-tick')?.({ payload: backendState({ running: true, secondsLeft: 2697 }) }) })
     expect(result.current.running).toBe(true)
-
-    act(() => { vi.advanceTimersByTime(3000) })
-    expect(result.current.secondsLeft).toBe(initial - 3)
+    expect(result.current.secondsLeft).toBe(2697)
   })
 
-  it('interval pauses when all subscribers unmount and resumes on remount', () => {
-    const { result, unmount } = renderHook(() => usePomodoroTimer())
-    const initial = result.current.secondsLeft
-
-    act(() => { toggleTimer() })
-    act(() => { vi.advanceTimersByTime(2000) })
-    expect(result.current.secondsLeft).toBe(initial - 2)
+  it('maps long breaks and plays no chime when muted', () => {
+    const { result } = renderHook(() => usePomodoroTimer())
 
-    // Unmount — interval should pause
-    unmount()
-    vi.advanceTimersByTime(5000)
+    act(() => {
+      handlers.get('pomodoro-transition')?.({
+        payload: {
+          from: 'work',
+          to: 'long_break',
+          completed: true,
+          chime: false,
+          state: backendState({ phase: 'long_break', completedPomodoros: 4, showOverlay: true }),
+        },
+      })
+    })
+    expect(result.current.phase).toBe('break')
+    expect(result.current.isLongBreak).toBe(true)
+    expect(result.current.showOverlay).toBe(true)
+  })
 
-    // Remount — should resume from where it paused
-    const { result: result2 } = renderHook(() => usePomodoroTimer())
-    expect(result2.current.secondsLeft).toBe(initial - 2) // not initial - 7
-    expect(result2.current.running).toBe(true)
+  it('sends actions and the active book to the backend', () => {
+    renderHook(() => usePomodoroTimer('algebra', '/books'))
+    toggleTimer()
 
-    // Timer should be ticking again
-    act(() => { vi.advanceTimersByTime(1000) })
-    expect(result2.current.secondsLeft).toBe(initial - 3)
+    const commands = vi.mocked(invoke).mock.calls.map(([command]) => command)
+    expect(commands).toContain('toggle_pomodoro')
+    expect(vi.mocked(invoke)).toHaveBeenCalledWith('set_pomodoro_book', { book: { slug: 'algebra', dirPath: '/books' } })
   })
 })
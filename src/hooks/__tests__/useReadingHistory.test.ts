@@ -0,0 +1,71 @@
+import { describe, it, expect, vi, beforeEach, afterEach } from 'vitest'
+import { renderHook, act } from '@testing-library/react'
+import { mockInvoke, resetMockInvoke, getInvokeCallsFor } from '../../../__mocks__/@tauri-apps/api/core'
+
+vi.mock('@tauri-apps/api/core')
+
+import { useReadingHistory } from '../useReadingHistory'
+
+const at = (page: number, scrollOffset = 0) => ({ page, scrollOffset, zoom: 1 })
+
+beforeEach(() => {
+  resetMockInvoke()
+  vi.useFakeTimers({ shouldAdvanceTime: true })
+})
+
+afterEach(() => {
+  vi.useRealTimers()
+})
+
+describe('useReadingHistory', () => {
+  it('writes only the latest scroll position per interval', async () => {
+    const { result } = renderHook(() => useReadingHistory('/lib', '1_algebra'))
+
+    act(() => {
+      result.current.recordPosition(at(3, 0.1))
+      result.current.recordPosition(at(3, 0.6))
+      result.current.recordPosition(at(4, 0.2))
+    })
+    expect(getInvokeCallsFor('record_reading_position').length).toBe(0)
+
+    await act(async () => {
+      vi.advanceTimersByTime(5000)
+    })
+    const calls = getInvokeCallsFor('record_reading_position')
+    expect(calls.length).toBe(1)
+    expect(calls[0].args).toEqual({ dirPath: '/lib', slug: '1_algebra', position: at(4, 0.2) })
+  })
+
+  it('writes the pending position when the book closes', () => {
+    const { result, unmount } = renderHook(() => useReadingHistory('/lib', '1_algebra'))
+
+    act(() => {
+      result.current.recordPosition(at(9, 0.5))
+    })
+    unmount()
+
+    expect(getInvokeCallsFor('record_reading_position')[0].args?.position).toEqual(at(9, 0.5))
+  })
+
+  it('records jumps and steps back through them', async () => {
+    mockInvoke('navigate_back', at(12, 0.4))
+    const { result } = renderHook(() => useReadingHistory('/lib', '1_algebra'))
+
+    act(() => {
+      result.current.recordJump(at(12, 0.4), at(80))
+    })
+    expect(getInvokeCallsFor('record_jump')[0].args).toEqual({
+      dirPath: '/lib',
+      slug: '1_algebra',
+      from: at(12, 0.4),
+      to: at(80),
+    })
+
+    let target = null
+    await act(async () => {
+      target = await result.current.back(at(80, 0.3))
+    })
+    expect(target).toEqual(at(12, 0.4))
+    expect(getInvokeCallsFor('navigate_back')[0].args?.current).toEqual(at(80, 0.3))
+  })
+})
@@ -0,0 +1,42 @@
+import { useCallback, useEffect, useState } from 'react'
+import { invoke } from '@tauri-apps/api/core'
+
+export interface AgentServerConfig {
+  enabled: boolean
+  port: number
+  allowedTools: string[]
+}
+
+export interface AgentToolInfo {
+  name: string
+  description: string
+  writes: boolean
+}
+
+export interface AgentServerStatus {
+  config: AgentServerConfig
+  /** MCP endpoint while the server is listening. */
+  url: string | null
+  tools: AgentToolInfo[]
+}
+
+/** Settings and state of the local MCP server for coding agents. */
+export function useAgentServer() {
+  const [status, setStatus] = useState<AgentServerStatus | null>(null)
+  const [error, setError] = useState<string | null>(null)
+
+  useEffect(() => {
+    invoke<AgentServerStatus>('get_agent_server').then(setStatus).catch(() => {})
+  }, [])
+
+  const saveConfig = useCallback(async (config: AgentServerConfig) => {
+    try {
+      setStatus(await invoke<AgentServerStatus>('set_agent_server_config', { config }))
+      setError(null)
+    } catch (e) {
+      setError(String(e))
+    }
+  }, [])
+
+  return { status, error, saveConfig }
+}
@@ -0,0 +1,69 @@
+import { useCallback, useState } from 'react'
+import { invoke } from '@tauri-apps/api/core'
+
+export interface BackupDirectory {
+  id: number
+  path: string
+  label: string
+}
+
+export interface BackupManifest {
+  format: string
+  version: number
+  appVersion: string
+  createdAt: string
+  schemaVersion: number
+  directories: BackupDirectory[]
+  notes: number
+  noteImages: number
+  noteRevisions: number
+  files: number
+}
+
+/** `replace` wipes local notes, highlights and tags first; `merge` keeps the newer of each. */
+export type BackupMode = 'replace' | 'merge'
+
+export interface BackupReport {
+  directoriesAdded: string[]
+  /** Backed-up directories that don't exist on this machine. */
+  directoriesSkipped: string[]
+  slugsRenamed: number
+  notes: number
+  noteImages: number
+  noteRevisions: number
+  highlights: number
+  files: number
+}
+
+/** Export and restore full backups of the library data. */
+export function useBackup() {
+  const [busy, setBusy] = useState(false)
+  const [error, setError] = useState<string | null>(null)
+
+  const run = useCallback(async <T,>(command: string, args: Record<string, unknown>) => {
+    setBusy(true)
+    try {
+      const result = await invoke<T>(command, args)
+      setError(null)
+      return result
+    } catch (e) {
+      setError(String(e))
+      return null
+    } finally {
+      setBusy(false)
+    }
+  }, [])
+
+  const exportBackup = useCallback(
+    (outputPath: string) => run<BackupManifest>('export_backup', { outputPath }),
+    [run],
+  )
+
+  const importBackup = useCallback(
+    (path: string, mode: BackupMode, dirMap?: Record<string, string>) =>
+      run<BackupReport>('import_backup', { path, mode, dirMap: dirMap ?? null }),
+    [run],
+  )
+
+  return { busy, error, exportBackup, importBackup }
+}
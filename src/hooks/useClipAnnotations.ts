@@ -0,0 +1,44 @@
+import { useEffect, useState } from 'react'
+import { invoke } from '@tauri-apps/api/core'
+import type { Highlight } from './useHighlights'
+import type { Snip } from './useSnips'
+import type { NoteRecord } from '../lib/notes'
+
+/** Where a clipped PDF came from: clip page `n` is source page `n + pageOffset`. */
+export interface ClipProvenance {
+  sourceSlug: string
+  sourceDirPath: string
+  sourcePath: string
+  pageOffset: number
+  pageCount: number
+  createdAt: string
+}
+
+/** The source book's annotations on the clipped pages, in clip page numbers. */
+export interface ClipAnnotations {
+  provenance: ClipProvenance
+  notes: NoteRecord[]
+  highlights: Highlight[]
+  snips: Snip[]
+}
+
+/** Annotations carried over from the book a clip was cut from; `null` for books that aren't clips. */
+export function useClipAnnotations(dirPath: string | undefined, slug: string | undefined) {
+  const [annotations, setAnnotations] = useState<ClipAnnotations | null>(null)
+
+  useEffect(() => {
+    setAnnotations(null)
+    if (!dirPath || !slug) return
+    let cancelled = false
+    invoke<ClipAnnotations | null>('get_clip_annotations', { dirPath, slug })
+      .then((a) => {
+        if (!cancelled) setAnnotations(a)
+      })
+      .catch((err) => console.error('get_clip_annotations failed:', err))
+    return () => {
+      cancelled = true
+    }
+  }, [dirPath, slug])
+
+  return annotations
+}
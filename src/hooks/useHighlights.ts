@@ -65,19 +65,20 @@ export function useHighlights(slug: string | undefined) {
 
   const deleteHighlight = useCallback(
     async (id: number) => {
-      await invoke('delete_highlight', { id })
+      if (!slug) return
+      await invoke('delete_highlight', { id, slug })
       setHighlights((prev) => prev.filter((h) => h.id !== id))
     },
-    [],
+    [slug],
   )
 
   const deleteHighlightGroup = useCallback(
     async (groupId: string) => {
-      if (!groupId) return
-      await invoke('delete_highlight_group', { groupId })
+      if (!groupId || !slug) return
+      await invoke('delete_highlight_group', { groupId, slug })
       setHighlights((prev) => prev.filter((h) => h.group_id !== groupId))
     },
-    [],
+    [slug],
   )
 
   const colorHighlights = useMemo(
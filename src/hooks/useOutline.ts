@@ -1,20 +1,35 @@
 import { useEffect, useState } from 'react'
 import { invoke } from '@tauri-apps/api/core'
+import type { DestinationView } from './usePageLinks'
 
 export interface OutlineEntry {
   title: string
   page: number | null
+  view?: DestinationView | null
   children: OutlineEntry[]
 }
 
-export function useOutline(fullPath: string | undefined) {
+/** A user-defined outline, merged into or replacing the PDF's bookmarks. */
+export interface CustomOutline {
+  mode: 'merge' | 'replace'
+  entries: OutlineEntry[]
+}
+
+/** Pass the library dir and slug to include the book's custom outline. */
+export function useOutline(
+  fullPath: string | undefined,
+  book?: { dirPath: string; slug: string },
+) {
   const [outline, setOutline] = useState<OutlineEntry[]>([])
 
   useEffect(() => {
     if (!fullPath) return
     let cancelled = false
 
-    invoke<OutlineEntry[]>('get_outline', { path: fullPath })
+    const request = book
+      ? invoke<OutlineEntry[]>('get_book_outline', { dirPath: book.dirPath, slug: book.slug, path: fullPath })
+      : invoke<OutlineEntry[]>('get_outline', { path: fullPath })
+    request
       .then((entries) => {
         if (!cancelled) setOutline(entries)
       })
@@ -23,7 +38,7 @@ export function useOutline(fullPath: string | undefined) {
     return () => {
       cancelled = true
     }
-  }, [fullPath])
+  }, [fullPath, book?.dirPath, book?.slug])
 
   return outline
 }
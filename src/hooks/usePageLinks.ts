@@ -8,11 +8,22 @@ interface NormalizedRect {
   height: number
 }
 
+/** Point shown at the top-left (0..1, top-down) and zoom; null keeps the current value. */
+export interface DestinationView {
+  x: number | null
+  y: number | null
+  zoom: number | null
+}
+
 export interface LinkAnnotation {
   rect: NormalizedRect
   link_type:
-    | { type: 'internal'; page: number }
+    | { type: 'internal'; page: number; view?: DestinationView | null }
     | { type: 'external'; url: string }
+    /** `index` numbers the page's remote links; see `resolveRemote`. */
+    | { type: 'remote'; file: string; index: number }
+  /** Inferred from a textual reference such as "by Lemma 2.3". */
+  synthetic?: boolean
 }
 
 const cache = new Map<string, LinkAnnotation[]>()
@@ -35,5 +46,19 @@ export function usePageLinks(fullPath: string | undefined) {
     [fullPath],
   )
 
-  return { getLinks }
+  /** Page a remote link on `page` leads to. Resolving opens the target file, so it waits until the link is followed. */
+  const resolveRemote = useCallback(
+    async (page: number, index: number): Promise<number | null> => {
+      if (!fullPath) return null
+      try {
+        const dest = await invoke<{ page: number } | null>('resolve_remote_link', { path: fullPath, page, index })
+        return dest?.page ?? null
+      } catch {
+        return null
+      }
+    },
+    [fullPath],
+  )
+
+  return { getLinks, resolveRemote }
 }
@@ -1,5 +1,6 @@
 import { useEffect, useSyncExternalStore } from 'react'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import type { PomodoroConfig } from './usePomodoroConfig'
 
 type Phase = 'work' | 'break'
@@ -14,41 +15,75 @@ export interface TimerState {
   sessionStart: string | null
 }
 
-let _state: TimerState = {
+/** Timer state as owned by the backend `pomodoro` service. */
+interface BackendState {
+  phase: 'work' | 'break' | 'long_break'
+  running: boolean
+  secondsLeft: number
+  completedPomodoros: number
+  showOverlay: boolean
+  sessionStart: string | null
+}
+
+interface Transition {
+  from: BackendState['phase']
+  to: BackendState['phase']
+  completed: boolean
+  chime: boolean
+  state: BackendState
+}
+
+const INITIAL_STATE: TimerState = {
   running: false,
   phase: 'work',
-  secondsLeft: 25 * 60,
+  secondsLeft: 45 * 60,
   completedPomodoros: 0,
   showOverlay: false,
   isLongBreak: false,
   sessionStart: null,
 }
 
-let _snapshot = { ..._state }
-let _intervalId: ReturnType<typeof setInterval> | undefined
+let _snapshot: TimerState = INITIAL_STATE
 const _listeners = new Set<() => void>()
-let _activeSlug: string | undefined
-let _activeDirPath: string | undefined
-
-function notify() {
-  _snapshot = { ..._state }
+let _unlisten: Promise<Array<() => void>> | undefined
+
+function apply(state: BackendState | null | undefined) {
+  if (!state) return
+  _snapshot = {
+    running: state.running,
+    phase: state.phase === 'work' ? 'work' : 'break',
+    secondsLeft: state.secondsLeft,
+    completedPomodoros: state.completedPomodoros,
+    showOverlay: state.showOverlay,
+    isLongBreak: state.phase === 'long_break',
+    sessionStart: state.sessionStart,
+  }
   _listeners.forEach((fn) => fn())
 }
 
+function call(command: string, args?: Record<string, unknown>) {
+  invoke<BackendState>(command, args).then(apply).catch(() => {})
+}
+
+// The backend emits ticks and transitions from a single timer thread, so
+// listening once per webview is enough to get exactly one chime each.
+function ensureListening() {
+  if (_unlisten) return
+  _unlisten = Promise.all([
+    listen<BackendState>('pomodoro-tick', (e) => apply(e.payload)),
+    listen<Transition>('pomodoro-transition', (e) => {
+      if (e.payload.chime) playChime()
+      apply(e.payload.state)
+    }),
+  ])
+  call('get_pomodoro_state')
+}
+
 function subscribe(fn: () => void): () => void {
-  const wasEmpty = _listeners.size === 0
+  ensureListening()
   _listeners.add(fn)
-  // Resume interval if timer was running when all components had unmounted
-  if (wasEmpty && _state.running && _intervalId == null) {
-    _intervalId = setInterval(tick, 1000)
-  }
   return () => {
     _listeners.delete(fn)
-    // Pause interval when no components are listening (timer stays "running" in state)
-    if (_listeners.size === 0 && _intervalId != null) {
-      clearInterval(_intervalId)
-      _intervalId = undefined
-    }
   }
 }
 
@@ -76,129 +111,38 @@ function playChime() {
   } catch { /* Audio not available */ }
 }
 
-function logSession(startedAt: string, endedAt: string, durationMinutes: number) {
-  const id = crypto.randomUUID()
-  const books = _activeSlug && _activeDirPath ? [{ slug: _activeSlug, dirPath: _activeDirPath }] : []
-  invoke('log_study_session', { sessions: { id, startedAt, endedAt, durationMinutes, books } }).catch(() => {})
-  if (_activeSlug && _activeDirPath) {
-    invoke('increment_pomodoro_xp', { dirPath: _activeDirPath, slug: _activeSlug }).catch(() => {})
-  }
-}
-
-function readConfig(): PomodoroConfig {
-  try {
-    const raw = localStorage.getItem('axiomatic:pomodoro-config')
-    if (!raw) return { preset: '45/10', workMinutes: 45, breakMinutes: 10, audioEnabled: true, longBreakMultiplier: 3, longBreakInterval: 4 }
-    return JSON.parse(raw)
-  } catch {
-    return { preset: '45/10', workMinutes: 45, breakMinutes: 10, audioEnabled: true, longBreakMultiplier: 3, longBreakInterval: 4 }
-  }
-}
-
-function stopInterval() {
-  if (_intervalId != null) {
-    clearInterval(_intervalId)
-    _intervalId = undefined
-  }
-}
-
-function handlePhaseComplete(phase: Phase) {
-  stopInterval()
-  const cfg = readConfig()
-  if (cfg.audioEnabled && _listeners.size > 0) playChime()
-
-  if (phase === 'work') {
-    const endedAt = new Date().toISOString()
-    logSession(_state.sessionStart ?? endedAt, endedAt, cfg.workMinutes)
-    const newCompleted = _state.completedPomodoros + 1
-    const isLong = cfg.longBreakInterval > 0 && newCompleted % cfg.longBreakInterval === 0
-    const breakDuration = isLong ? cfg.breakMinutes * cfg.longBreakMultiplier : cfg.breakMinutes
-    _state = { ..._state, running: false, phase: 'break', secondsLeft: breakDuration * 60, completedPomodoros: newCompleted, showOverlay: true, isLongBreak: isLong, sessionStart: null }
-  } else {
-    const isLong = cfg.longBreakInterval > 0 && _state.completedPomodoros % cfg.longBreakInterval === 0
-    _state = { ..._state, running: false, phase: 'work', secondsLeft: cfg.workMinutes * 60, completedPomodoros: isLong ? 0 : _state.completedPomodoros, sessionStart: null }
-  }
-  notify()
-}
-
-function tick() {
-  if (_state.secondsLeft <= 1) {
-    _state.secondsLeft = 0
-    handlePhaseComplete(_state.phase)
-    return
-  }
-  _state = { ..._state, secondsLeft: _state.secondsLeft - 1 }
-  notify()
-}
-
 export function toggleTimer() {
-  if (_state.running) {
-    stopInterval()
-    _state = { ..._state, running: false }
-  } else {
-    if (_state.phase === 'work' && !_state.sessionStart) {
-      _state.sessionStart = new Date().toISOString()
-    }
-    _state = { ..._state, running: true }
-    stopInterval()
-    _intervalId = setInterval(tick, 1000)
-  }
-  notify()
+  call('toggle_pomodoro')
 }
 
 export function resetTimer() {
-  stopInterval()
-  const cfg = readConfig()
-  _state = { running: false, phase: 'work', secondsLeft: cfg.workMinutes * 60, completedPomodoros: 0, showOverlay: false, isLongBreak: false, sessionStart: null }
-  notify()
+  call('reset_pomodoro')
 }
 
 export function skipPhase() {
-  stopInterval()
-  const cfg = readConfig()
-  if (_state.phase === 'work') {
-    const isLong = cfg.longBreakInterval > 0 && (_state.completedPomodoros + 1) % cfg.longBreakInterval === 0
-    const breakDuration = isLong ? cfg.breakMinutes * cfg.longBreakMultiplier : cfg.breakMinutes
-    _state = { ..._state, running: false, phase: 'break', secondsLeft: breakDuration * 60, completedPomodoros: _state.completedPomodoros + 1, isLongBreak: isLong, sessionStart: null }
-  } else {
-    _state = { ..._state, running: false, phase: 'work', secondsLeft: cfg.workMinutes * 60, sessionStart: null }
-  }
-  notify()
+  call('skip_pomodoro_phase')
 }
 
 export function dismissOverlay() {
-  _state = { ..._state, showOverlay: false, running: true }
-  stopInterval()
-  _intervalId = setInterval(tick, 1000)
-  notify()
-}
-
-export function applyNewDuration(workMinutes: number) {
-  if (!_state.running && _state.phase === 'work') {
-    _state = { ..._state, secondsLeft: workMinutes * 60 }
-    notify()
-  }
+  call('dismiss_pomodoro_overlay')
 }
 
-export function resetToPreset(workMinutes: number) {
-  stopInterval()
-  _state = { running: false, phase: 'work', secondsLeft: workMinutes * 60, completedPomodoros: 0, showOverlay: false, isLongBreak: false, sessionStart: null }
-  notify()
+/** Send changed settings; an unstarted work phase picks up the new length. */
+export function applyNewDuration(config: PomodoroConfig) {
+  call('set_pomodoro_config', { config, reset: false })
 }
 
-// Sync initial secondsLeft with persisted config
-{
-  const cfg = readConfig()
-  _state.secondsLeft = cfg.workMinutes * 60
-  _snapshot = { ..._state }
+/** Send new settings and restart from a fresh work phase. */
+export function resetToPreset(config: PomodoroConfig) {
+  call('set_pomodoro_config', { config, reset: true })
 }
 
 if (import.meta.hot) {
   import.meta.hot.dispose(() => {
-    stopInterval()
+    _unlisten?.then((fns) => fns.forEach((fn) => fn()))
+    _unlisten = undefined
     _listeners.clear()
-    _state = { running: false, phase: 'work', secondsLeft: 45 * 60, completedPomodoros: 0, showOverlay: false, isLongBreak: false, sessionStart: null }
-    _snapshot = { ..._state }
+    _snapshot = INITIAL_STATE
   })
 }
 
@@ -206,8 +150,8 @@ export function usePomodoroTimer(activeSlug?: string, activeDirPath?: string) {
   const timer = useSyncExternalStore(subscribe, getSnapshot)
 
   useEffect(() => {
-    _activeSlug = activeSlug
-    _activeDirPath = activeDirPath
+    const book = activeSlug && activeDirPath ? { slug: activeSlug, dirPath: activeDirPath } : null
+    call('set_pomodoro_book', { book })
   }, [activeSlug, activeDirPath])
 
   return timer
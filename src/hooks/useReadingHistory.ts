@@ -0,0 +1,91 @@
+import { useCallback, useEffect, useRef, useState } from 'react'
+import { invoke } from '@tauri-apps/api/core'
+
+/** Where the reader is: page, offset into it (0..1 of its height) and zoom. */
+export interface ReadingPosition {
+  page: number
+  scrollOffset: number
+  zoom: number
+  timestamp?: string
+}
+
+/** Scrolling is written to the reading log at most this often. */
+const POSITION_INTERVAL_MS = 5000
+
+/** A book's reading log: scroll positions, link jumps and back/forward. */
+export function useReadingHistory(dirPath: string | undefined, slug: string | undefined) {
+  // The latest position not yet written, with the book it belongs to
+  const pendingRef = useRef<{ dirPath: string; slug: string; position: ReadingPosition } | null>(null)
+  const timerRef = useRef<ReturnType<typeof setTimeout>>(undefined)
+
+  const flush = useCallback(() => {
+    clearTimeout(timerRef.current)
+    timerRef.current = undefined
+    const pending = pendingRef.current
+    pendingRef.current = null
+    if (!pending) return
+    invoke('record_reading_position', pending).catch((err) =>
+      console.error('record_reading_position failed:', err),
+    )
+  }, [])
+
+  // Write the last position of a book before switching away from it
+  useEffect(() => flush, [dirPath, slug, flush])
+
+  // Where the book was left, to restore the offset into the page
+  const [lastPosition, setLastPosition] = useState<ReadingPosition | null>(null)
+  useEffect(() => {
+    setLastPosition(null)
+    if (!dirPath || !slug) return
+    let cancelled = false
+    invoke<ReadingPosition | null>('get_last_position', { dirPath, slug })
+      .then((p) => {
+        if (!cancelled) setLastPosition(p)
+      })
+      .catch((err) => console.error('get_last_position failed:', err))
+    return () => {
+      cancelled = true
+    }
+  }, [dirPath, slug])
+
+  const recordPosition = useCallback(
+    (position: ReadingPosition) => {
+      if (!dirPath || !slug) return
+      pendingRef.current = { dirPath, slug, position }
+      if (timerRef.current === undefined) {
+        timerRef.current = setTimeout(flush, POSITION_INTERVAL_MS)
+      }
+    },
+    [dirPath, slug, flush],
+  )
+
+  const recordJump = useCallback(
+    (from: ReadingPosition, to: ReadingPosition) => {
+      if (!dirPath || !slug) return
+      pendingRef.current = null
+      clearTimeout(timerRef.current)
+      timerRef.current = undefined
+      invoke('record_jump', { dirPath, slug, from, to }).catch((err) => console.error('record_jump failed:', err))
+    },
+    [dirPath, slug],
+  )
+
+  const navigate = useCallback(
+    async (command: 'navigate_back' | 'navigate_forward', current: ReadingPosition) => {
+      if (!dirPath || !slug) return null
+      flush()
+      try {
+        return await invoke<ReadingPosition | null>(command, { dirPath, slug, current })
+      } catch (err) {
+        console.error(`${command} failed:`, err)
+        return null
+      }
+    },
+    [dirPath, slug, flush],
+  )
+
+  const back = useCallback((current: ReadingPosition) => navigate('navigate_back', current), [navigate])
+  const forward = useCallback((current: ReadingPosition) => navigate('navigate_forward', current), [navigate])
+
+  return { lastPosition, recordPosition, recordJump, back, forward }
+}
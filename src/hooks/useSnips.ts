@@ -15,6 +15,7 @@ export interface Snip {
   created_at: string
   tags: string[]
   status: 'open' | 'solid' | 'attention'
+  solid_at?: string
 }
 
 export function useSnips(slug: string | undefined, dirPath: string | undefined) {
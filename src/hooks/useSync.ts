@@ -0,0 +1,86 @@
+import { useCallback, useEffect, useState } from 'react'
+import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
+
+export interface SyncVersion {
+  device: string
+  deviceName: string
+  at: string
+  /** `null` when this version deleted the record. */
+  value: unknown
+}
+
+export interface SyncConflict {
+  /** Record key, e.g. `note/{book}/{page}`. */
+  key: string
+  slug: string | null
+  kept: SyncVersion
+  discarded: SyncVersion[]
+  detectedAt: string
+}
+
+export interface SyncStatus {
+  folder: string | null
+  deviceId: string
+  deviceName: string
+  lastSync: string | null
+  conflicts: SyncConflict[]
+}
+
+export interface SyncReport {
+  sent: number
+  received: number
+  conflicts: number
+}
+
+/**
+ * Folder sync between devices. `onReceived` runs after a sync applied
+ * changes from another device, so views can reload.
+ */
+export function useSync(onReceived?: () => void) {
+  const [status, setStatus] = useState<SyncStatus | null>(null)
+  const [syncing, setSyncing] = useState(false)
+  const [error, setError] = useState<string | null>(null)
+
+  const refresh = useCallback(() => {
+    invoke<SyncStatus>('get_sync_status').then(setStatus).catch(() => {})
+  }, [])
+
+  useEffect(() => {
+    refresh()
+    const unlisten = listen<SyncReport>('sync-completed', (event) => {
+      refresh()
+      if (event.payload.received > 0) onReceived?.()
+    })
+    return () => {
+      unlisten.then((fn) => fn())
+    }
+  }, [refresh, onReceived])
+
+  const configure = useCallback(async (folder: string | null, deviceName?: string) => {
+    try {
+      setStatus(await invoke<SyncStatus>('set_sync_config', { folder, deviceName: deviceName ?? null }))
+      setError(null)
+    } catch (e) {
+      setError(String(e))
+    }
+  }, [])
+
+  const syncNow = useCallback(async () => {
+    setSyncing(true)
+    try {
+      await invoke<SyncReport>('sync_now')
+      setError(null)
+    } catch (e) {
+      setError(String(e))
+    } finally {
+      setSyncing(false)
+    }
+  }, [])
+
+  const dismissConflict = useCallback(async (key: string) => {
+    setStatus(await invoke<SyncStatus>('dismiss_sync_conflict', { key }))
+  }, [])
+
+  return { status, syncing, error, configure, syncNow, dismissConflict }
+}
@@ -1,16 +1,24 @@
 import { invoke } from '@tauri-apps/api/core'
 
+/** What a note is attached to on its page. Omitted, a note belongs to the page itself. */
+export type NoteAnchor =
+  | { kind: 'page' }
+  | { kind: 'rect'; x: number; y: number; width: number; height: number }
+  | { kind: 'highlight'; group_id: string }
+  | { kind: 'snip'; id: string }
+
 export interface NoteRecord {
   id: number
   slug: string
   page: number
+  anchor: NoteAnchor
   content: string
   format: string
   updated_at: string
 }
 
-export async function getNote(slug: string, page: number): Promise<NoteRecord | null> {
-  return invoke<NoteRecord | null>('get_note', { slug, page })
+export async function getNote(slug: string, page: number, anchor?: NoteAnchor): Promise<NoteRecord | null> {
+  return invoke<NoteRecord | null>('get_note', { slug, page, anchor })
 }
 
 export async function setNote(
@@ -18,16 +26,22 @@ export async function setNote(
   page: number,
   content: string,
   format: string = 'markdown',
+  anchor?: NoteAnchor,
 ): Promise<void> {
-  await invoke('set_note', { slug, page, content, format })
+  await invoke('set_note', { slug, page, content, format, anchor })
+}
+
+/** Every note on a page, the page's own note first. */
+export async function listNotesForPage(slug: string, page: number): Promise<NoteRecord[]> {
+  return invoke<NoteRecord[]>('list_notes_for_page', { slug, page })
 }
 
 export async function listNotesForBook(slug: string): Promise<NoteRecord[]> {
   return invoke<NoteRecord[]>('list_notes_for_book', { slug })
 }
 
-export async function deleteNote(slug: string, page: number): Promise<void> {
-  await invoke('delete_note', { slug, page })
+export async function deleteNote(slug: string, page: number, anchor?: NoteAnchor): Promise<void> {
+  await invoke('delete_note', { slug, page, anchor })
 }
 
 export async function saveNoteImage(
@@ -39,8 +53,8 @@ export async function saveNoteImage(
   return invoke<number>('save_note_image', { slug, page, filename, data })
 }
 
-export async function getNoteImage(id: number): Promise<ArrayBuffer> {
-  return invoke<ArrayBuffer>('get_note_image', { id })
+export async function getNoteImage(id: number, slug: string): Promise<ArrayBuffer> {
+  return invoke<ArrayBuffer>('get_note_image', { id, slug })
 }
 
 export async function exportNotesForBook(slug: string): Promise<string> {
@@ -50,3 +64,100 @@ export async function exportNotesForBook(slug: string): Promise<string> {
 export async function migrateNotesFromJson(jsonData: string): Promise<number> {
   return invoke<number>('migrate_notes_from_json', { jsonData })
 }
+
+/** Where a library directory keeps its notes, note images and highlights. */
+export type NoteStorage = 'app' | 'directory'
+
+export async function getNoteStorage(dirPath: string): Promise<NoteStorage> {
+  return invoke<NoteStorage>('get_note_storage', { dirPath })
+}
+
+/** Move a directory's notes into its `.axiomatic/` folder or back into the app database. */
+export async function setNoteStorage(dirPath: string, storage: NoteStorage): Promise<void> {
+  await invoke('set_note_storage', { dirPath, storage })
+}
+
+export interface NoteRevision {
+  id: number
+  slug: string
+  page: number
+  anchor: NoteAnchor
+  format: string
+  content: string
+  created_at: string
+}
+
+/** Earlier versions of a note, newest first. */
+export async function listNoteRevisions(slug: string, page: number, anchor?: NoteAnchor): Promise<NoteRevision[]> {
+  return invoke<NoteRevision[]>('list_note_revisions', { slug, page, anchor })
+}
+
+/** Put a revision back as the note's content; the replaced content becomes a revision itself. */
+export async function restoreNoteRevision(
+  slug: string,
+  page: number,
+  id: number,
+  anchor?: NoteAnchor,
+): Promise<NoteRecord> {
+  return invoke<NoteRecord>('restore_note_revision', { slug, page, anchor, id })
+}
+
+/** How long note revisions are kept. `null` means no limit. */
+export interface NoteRevisionConfig {
+  maxAgeDays: number | null
+  maxPerNote: number | null
+}
+
+export async function getNoteRevisionConfig(): Promise<NoteRevisionConfig> {
+  return invoke<NoteRevisionConfig>('get_note_revision_config')
+}
+
+export async function setNoteRevisionConfig(config: NoteRevisionConfig): Promise<NoteRevisionConfig> {
+  return invoke<NoteRevisionConfig>('set_note_revision_config', { config })
+}
+
+/** What a `[[...]]` link in a note points at: `[[slug#p42]]`, `[[snip:id]]` or `[[tag]]`. */
+export type LinkTarget =
+  | { kind: 'page'; slug: string; page: number }
+  | { kind: 'snip'; id: string }
+  | { kind: 'tag'; name: string }
+
+/** Notes linking to `target`, across every book. */
+export async function getBacklinks(target: LinkTarget): Promise<NoteRecord[]> {
+  return invoke<NoteRecord[]>('get_backlinks', { target })
+}
+
+export interface ImageGcReport {
+  imagesRemoved: number
+  blobsRemoved: number
+  bytesFreed: number
+}
+
+export interface BookStorage {
+  slug: string
+  notes: number
+  noteBytes: number
+  images: number
+  imageBytes: number
+}
+
+export interface VacuumReport {
+  gc: ImageGcReport
+  bytesBefore: number
+  bytesAfter: number
+}
+
+/** Delete pasted images no note or note revision links to any more. */
+export async function collectNoteImages(): Promise<ImageGcReport> {
+  return invoke<ImageGcReport>('collect_note_images')
+}
+
+/** Space taken by each book's notes and images, largest first. */
+export async function getStorageReport(): Promise<BookStorage[]> {
+  return invoke<BookStorage[]>('get_storage_report')
+}
+
+/** Collect unlinked images, then compact the note databases. */
+export async function vacuum(): Promise<VacuumReport> {
+  return invoke<VacuumReport>('vacuum')
+}
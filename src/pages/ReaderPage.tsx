@@ -1,5 +1,6 @@
 import { useCallback, useEffect, useMemo, useRef, useState } from 'react'
-import { useParams, useNavigate } from 'react-router-dom'
+import { useParams, useNavigate, useLocation } from 'react-router-dom'
+import { invoke } from '@tauri-apps/api/core'
 import type { EditorView } from '@codemirror/view'
 import { useTextbooks } from '../hooks/useTextbooks'
 import { useProgress } from '../hooks/useProgress'
@@ -8,6 +9,8 @@ import { useVimReader } from '../hooks/useVimReader'
 import { useSearch } from '../hooks/useSearch'
 import { useDocument } from '../hooks/useDocument'
 import { useHighlights } from '../hooks/useHighlights'
+import { useClipAnnotations } from '../hooks/useClipAnnotations'
+import { useReadingHistory, type ReadingPosition } from '../hooks/useReadingHistory'
 import { useSnips } from '../hooks/useSnips'
 import { useBookStatus } from '../hooks/useBookStatus'
 import { useTabNavigation } from '../hooks/useTabs'
@@ -25,6 +28,8 @@ import { makeResizeHandler } from '../lib/makeResizeHandler'
 export function ReaderPage() {
   const { slug } = useParams<{ slug: string }>()
   const navigate = useNavigate()
+  // Page to open at when arriving through a link from another book
+  const linkedPage = (useLocation().state as { page?: number } | null)?.page
   const { textbooks, loading } = useTextbooks()
   const { ensureNote, setNote } = useNotes()
   const book = textbooks.find((b) => b.slug === slug)
@@ -39,7 +44,22 @@ export function ReaderPage() {
   const { docInfo, loading: docLoading, error: docError } = useDocument(book?.full_path)
   const { colorHighlights, bookmarkHighlights, highlightsForPage, createHighlight, deleteHighlight, deleteHighlightGroup } = useHighlights(slug)
   const { snips, addSnip } = useSnips(slug, book?.dir_path)
+  const clipAnnotations = useClipAnnotations(book?.dir_path, slug)
+  const sourceColorHighlights = useMemo(
+    () => clipAnnotations?.highlights.filter((h) => h.color !== 'bookmark'),
+    [clipAnnotations],
+  )
+  const sourceBookmarkHighlights = useMemo(
+    () => clipAnnotations?.highlights.filter((h) => h.color === 'bookmark'),
+    [clipAnnotations],
+  )
+  const sourceTitle = useMemo(() => {
+    const sourceSlug = clipAnnotations?.provenance.sourceSlug
+    if (!sourceSlug) return undefined
+    return textbooks.find((b) => b.slug === sourceSlug)?.title ?? sourceSlug
+  }, [clipAnnotations, textbooks])
   const { getStatus: getBookStatus, setStatus: setBookStatus } = useBookStatus(dirPaths, progress)
+  const { lastPosition, recordPosition, recordJump, back: historyBack, forward: historyForward } = useReadingHistory(book?.dir_path, slug)
   const { tabs, openTab, reopenTab, tabsRef, selectTab, closeTabAndNavigate, closeOtherTabsAndNavigate, closeTabsToLeftAndNavigate, closeTabsToRightAndNavigate } = useTabNavigation(slug)
 
   const [snipMode, setSnipMode] = useState(false)
@@ -71,9 +91,9 @@ export function ReaderPage() {
   // Capture initial page once per document — don't re-evaluate when progress
   // updates during scroll, as that would bust PdfViewer's React.memo every 300ms.
   // eslint-disable-next-line react-hooks/exhaustive-deps
-  const stableInitialPage = useMemo(() => bookProgress?.currentPage ?? 1, [slug, progressLoaded])
+  const stableInitialPage = useMemo(() => linkedPage ?? bookProgress?.currentPage ?? 1, [slug, progressLoaded])
 
-  const [currentPage, setCurrentPage] = useState(bookProgress?.currentPage ?? 1)
+  const [currentPage, setCurrentPage] = useState(linkedPage ?? bookProgress?.currentPage ?? 1)
   const noteContent = useNoteContent(slug, currentPage)
   const totalPages = docInfo?.page_count ?? bookProgress?.totalPages ?? 0
   const [zoom, setZoom] = useState(1)
@@ -86,7 +106,7 @@ export function ReaderPage() {
   const [highlightsPaneWidth, setHighlightsPaneWidth] = useState(280)
   const [bookmarksOpen, setBookmarksOpen] = useState(false)
   const [bookmarksPaneWidth, setBookmarksPaneWidth] = useState(280)
-  const [scrollRequest, setScrollRequest] = useState<{ page: number; seq: number } | null>(null)
+  const [scrollRequest, setScrollRequest] = useState<{ page: number; offset?: number; seq: number } | null>(null)
   const [savedProgressPage, setSavedProgressPage] = useState<number | null>(null)
   const [outlinePaneWidth, setOutlinePaneWidth] = useState(200)
   const [notesPaneWidth, setNotesPaneWidth] = useState(384)
@@ -200,6 +220,33 @@ export function ReaderPage() {
     }
   }, [search.currentMatchPage, search.currentIndex])
 
+  // Reopen at the offset into the page the book was left at
+  const restoredRef = useRef(false)
+  useEffect(() => {
+    restoredRef.current = false
+  }, [slug])
+  useEffect(() => {
+    if (restoredRef.current || !docInfo || !lastPosition) return
+    restoredRef.current = true
+    // The log holds the page at the top of the view, progress the one in the middle
+    if (Math.abs(lastPosition.page - stableInitialPage) > 1 || lastPosition.scrollOffset <= 0) return
+    scrollSeq.current += 1
+    setScrollRequest({ page: lastPosition.page, offset: lastPosition.scrollOffset, seq: scrollSeq.current })
+  }, [docInfo, lastPosition, stableInitialPage])
+
+  // Return to the positions left by link and outline jumps
+  const handleHistoryStep = useCallback(
+    async (step: (current: ReadingPosition) => Promise<ReadingPosition | null>) => {
+      const current = pdfViewerRef.current?.getPosition()
+      if (!current) return
+      const target = await step(current)
+      if (!target) return
+      scrollSeq.current += 1
+      setScrollRequest({ page: target.page, offset: target.scrollOffset, seq: scrollSeq.current })
+    },
+    [],
+  )
+
   // Keyboard shortcuts
   useEffect(() => {
     const handleKeyDown = (e: KeyboardEvent) => {
@@ -230,6 +277,12 @@ export function ReaderPage() {
         const currentTabs = tabsRef.current
         const idx = currentTabs.findIndex((t) => t.slug === slug)
         if (idx >= 0 && idx < currentTabs.length - 1) selectTab(currentTabs[idx + 1].slug)
+      } else if (e.altKey && !e.shiftKey && e.key === 'ArrowLeft') {
+        e.preventDefault()
+        handleHistoryStep(historyBack)
+      } else if (e.altKey && !e.shiftKey && e.key === 'ArrowRight') {
+        e.preventDefault()
+        handleHistoryStep(historyForward)
       } else if (mod && e.key === 'PageUp') {
         e.preventDefault()
         const currentTabs = tabsRef.current
@@ -244,7 +297,7 @@ export function ReaderPage() {
     }
     window.addEventListener('keydown', handleKeyDown)
     return () => window.removeEventListener('keydown', handleKeyDown)
-  }, [slug, closeTabAndNavigate, selectTab, reopenTab, navigate])
+  }, [slug, closeTabAndNavigate, selectTab, reopenTab, navigate, handleHistoryStep, historyBack, historyForward])
 
   // Listen for command palette custom events
   useEffect(() => {
@@ -323,13 +376,36 @@ export function ReaderPage() {
   const handleHighlightsResize = useMemo(() => makeResizeHandler(setHighlightsPaneWidth, 180, 500, 'right'), [])
   const handleBookmarksResize = useMemo(() => makeResizeHandler(setBookmarksPaneWidth, 180, 500, 'right'), [])
 
-  const handlePaneNavigate = useCallback(
+  // Jumps within the book are logged so back can return from them
+  const jumpTo = useCallback(
     (page: number) => {
-      setSavedProgressPage((prev) => prev ?? currentPageRef.current)
+      const from = pdfViewerRef.current?.getPosition()
+      if (from) recordJump(from, { page, scrollOffset: 0, zoom: zoomRef.current })
       scrollSeq.current += 1
       setScrollRequest({ page, seq: scrollSeq.current })
     },
-    [],
+    [recordJump],
+  )
+
+  // A link into another file opens that book at the page it points to
+  const handleRemoteLink = useCallback(
+    async (file: string, page: number | null) => {
+      try {
+        const target = await invoke<string>('open_file', { filePath: file })
+        navigate(`/read/${target}`, { state: page ? { page } : null })
+      } catch (err) {
+        console.error('open_file failed:', err)
+      }
+    },
+    [navigate],
+  )
+
+  const handlePaneNavigate = useCallback(
+    (page: number) => {
+      setSavedProgressPage((prev) => prev ?? currentPageRef.current)
+      jumpTo(page)
+    },
+    [jumpTo],
   )
 
   const handlePageChange = useCallback(
@@ -347,14 +423,6 @@ export function ReaderPage() {
     [slug, update, savedProgressPage],
   )
 
-  const handleOutlineNavigate = useCallback(
-    (page: number) => {
-      scrollSeq.current += 1
-      setScrollRequest({ page, seq: scrollSeq.current })
-    },
-    [],
-  )
-
   if (!book) {
     if (loading) {
       return (
@@ -453,7 +521,7 @@ export function ReaderPage() {
                 docInfo={docInfo}
                 fullPath={book.full_path}
                 currentPage={currentPage}
-                onNavigate={handleOutlineNavigate}
+                onNavigate={jumpTo}
               />
             </div>
             <div
@@ -481,6 +549,9 @@ export function ReaderPage() {
             onPageChange={handlePageChange}
             containerRef={pdfContainerRef}
             scrollRequest={scrollRequest}
+            onPositionChange={recordPosition}
+            onLinkJump={recordJump}
+            onRemoteLink={handleRemoteLink}
             highlightsForPage={highlightsForPage}
             onDeleteHighlight={deleteHighlight}
             onDeleteHighlightGroup={deleteHighlightGroup}
@@ -523,6 +594,8 @@ export function ReaderPage() {
             <div className="flex h-full min-h-0 flex-col border-t-2 border-[#eee8d5] dark:border-[#073642]">
               <AnnotationPanel
                 items={bookmarkHighlights}
+                sourceItems={sourceBookmarkHighlights}
+                sourceTitle={sourceTitle}
                 variant="bookmarks"
                 width={bookmarksPaneWidth}
                 onNavigate={handlePaneNavigate}
@@ -541,6 +614,8 @@ export function ReaderPage() {
             <div className="flex h-full min-h-0 flex-col border-t-2 border-[#eee8d5] dark:border-[#073642]">
               <AnnotationPanel
                 items={colorHighlights}
+                sourceItems={sourceColorHighlights}
+                sourceTitle={sourceTitle}
                 variant="highlights"
                 width={highlightsPaneWidth}
                 onNavigate={handlePaneNavigate}
@@ -412,6 +412,21 @@ export function SnipsPage() {
     setRenamingId(null)
   }, [renamingId, renameValue, snips, renameSnip])
 
+  // Copy the snipped region's text, formulas as LaTeX
+  const copySnipText = useCallback(async (snip: SnipWithDir) => {
+    try {
+      const text = await invoke<string>('extract_page_text', {
+        path: snip.full_path,
+        page: snip.page,
+        mode: 'math',
+        region: { x: snip.x, y: snip.y, width: snip.width, height: snip.height },
+      })
+      await navigator.clipboard.writeText(text)
+    } catch (err) {
+      console.error('Failed to copy snip text:', err)
+    }
+  }, [])
+
   // Delete selected snips
   const handleDeleteSelected = useCallback(async () => {
     const toDelete = snips.filter((s) => selectedIds.has(s.id))
@@ -880,6 +895,7 @@ export function SnipsPage() {
           }}
           onNavigate={() => { navigateToSnip(contextMenu.snip); setContextMenu(null) }}
           onRename={() => { startRename(contextMenu.snip); setContextMenu(null) }}
+          onCopyText={() => { copySnipText(contextMenu.snip); setContextMenu(null) }}
           onDelete={async () => {
             await deleteSnip(contextMenu.snip.dirPath, contextMenu.snip.id)
             setContextMenu(null)
@@ -1049,7 +1065,7 @@ export function SnipsPage() {
 // Extracted context menu with tag checkboxes, rename, delete
 function ContextMenu({
   x, y, snip, tagDefs, bulkSnips,
-  onView, onExpand, onNavigate, onRename, onDelete, onAddTag, onRemoveTag, onCreateTag, onSetStatus, onClose,
+  onView, onExpand, onNavigate, onRename, onCopyText, onDelete, onAddTag, onRemoveTag, onCreateTag, onSetStatus, onClose,
 }: {
   x: number
   y: number
@@ -1060,6 +1076,7 @@ function ContextMenu({
   onExpand: () => void
   onNavigate: () => void
   onRename: () => void
+  onCopyText: () => void
   onDelete: () => void
   onAddTag: (tag: string) => void
   onRemoveTag: (tag: string) => void
@@ -1127,6 +1144,12 @@ function ContextMenu({
       >
         Rename
       </button>
+      <button
+        onClick={onCopyText}
+        className="block w-full px-3 py-1.5 text-left text-sm text-[#586e75] hover:bg-[#eee8d5] dark:text-[#93a1a1] dark:hover:bg-[#002b36]/50"
+      >
+        Copy text
+      </button>
       <button
         onClick={onDelete}
         className="block w-full px-3 py-1.5 text-left text-sm text-[#dc322f] hover:bg-[#eee8d5] dark:hover:bg-[#002b36]/50"
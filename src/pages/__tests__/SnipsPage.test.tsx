@@ -2,6 +2,9 @@ import { describe, it, expect, vi, beforeEach } from 'vitest'
 import { render, screen, fireEvent, within } from '@testing-library/react'
 import { MemoryRouter } from 'react-router-dom'
 import type { SnipWithDir } from '../../hooks/useSnips'
+import { mockInvoke, resetMockInvoke, getInvokeCallsFor } from '../../../__mocks__/@tauri-apps/api/core'
+
+vi.mock('@tauri-apps/api/core')
 
 vi.mock('../../lib/palette', () => ({ togglePalette: vi.fn() }))
 
@@ -318,6 +321,25 @@ describe('SnipsPage', () => {
     expect(screen.queryByText('Go to page')).not.toBeInTheDocument()
   })
 
+  it('"Copy text" copies the snip region as math text', async () => {
+    resetMockInvoke()
+    mockInvoke('extract_page_text', 'Let $x \\in V$.')
+    const writeText = vi.fn().mockResolvedValue(undefined)
+    Object.assign(navigator, { clipboard: { writeText } })
+    renderPage()
+
+    const row = screen.getByText('Definition 1.1').closest('tr')!
+    fireEvent.contextMenu(row)
+    fireEvent.click(screen.getByText('Copy text'))
+
+    await vi.waitFor(() => expect(writeText).toHaveBeenCalledWith('Let $x \\in V$.'))
+    const calls = getInvokeCallsFor('extract_page_text')
+    expect(calls.length).toBe(1)
+    expect(calls[0].args?.mode).toBe('math')
+    expect(calls[0].args?.page).toBe(4)
+    expect(calls[0].args?.region).toEqual({ x: 0.1, y: 0.2, width: 0.5, height: 0.3 })
+  })
+
   it('"Open in reader" navigates to the snip page', () => {
     renderPage()
 